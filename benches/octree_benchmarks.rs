@@ -1,15 +1,12 @@
 use avian3d::math::Vector;
-use bevy::prelude::Entity;
 use criterion::BenchmarkId;
 use criterion::Criterion;
 use rand::Rng;
 use rand::SeedableRng;
 use rand_chacha::ChaCha8Rng;
+use stardrift::physics;
 use std::hint::black_box;
 
-#[path = "../src/physics/mod.rs"]
-pub mod physics;
-
 fn generate_test_bodies(count: usize, seed: u64) -> Vec<physics::octree::OctreeBody> {
     let mut rng = ChaCha8Rng::seed_from_u64(seed);
     let mut bodies = Vec::with_capacity(count);
@@ -29,8 +26,9 @@ fn generate_test_bodies(count: usize, seed: u64) -> Vec<physics::octree::OctreeB
         let mass = rng.random_range(1.0..100.0);
 
         bodies.push(physics::octree::OctreeBody {
-            entity: Entity::from_raw(i as u32),
+            id: physics::BodyId(i as u64),
             position,
+            velocity: Vector::ZERO,
             mass,
         });
     }
@@ -0,0 +1,93 @@
+//! Apps that run the simulation, for the binary, sweeps and tests alike.
+
+use crate::StardriftSimulationPlugin;
+use crate::config::SimulationConfig;
+use bevy::app::TaskPoolThreadAssignmentPolicy;
+#[cfg(feature = "diagnostics")]
+use bevy::diagnostic::DiagnosticsPlugin;
+use bevy::log::DEFAULT_FILTER;
+use bevy::log::Level;
+use bevy::log::tracing;
+use bevy::log::tracing_subscriber::EnvFilter;
+use bevy::log::tracing_subscriber::Registry;
+use bevy::log::tracing_subscriber::fmt;
+use bevy::log::tracing_subscriber::layer::SubscriberExt;
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use bevy::tasks::available_parallelism;
+use bevy::time::TimeUpdateStrategy;
+use std::sync::Once;
+use tracing_log::LogTracer;
+
+/// Bevy's task pools with every available thread for computation.
+///
+/// The pools are shared by every app in the process and set up by the first one, so this only
+/// has an effect if no other app has been built yet.
+pub fn task_pool_plugin() -> TaskPoolPlugin {
+    TaskPoolPlugin {
+        task_pool_options: TaskPoolOptions {
+            compute: TaskPoolThreadAssignmentPolicy {
+                // set the minimum # of compute threads
+                // to the total number of available threads
+                min_threads: available_parallelism(),
+                max_threads: usize::MAX, // unlimited max threads
+                percent: 1.0,            // this value is irrelevant in this case
+                on_thread_spawn: None,
+                on_thread_destroy: None,
+            },
+            ..default()
+        },
+    }
+}
+
+/// Sets up the global logger as Bevy's `LogPlugin` would, unless something else already has.
+///
+/// `RUST_LOG` overrides the default filter. Call this before loading the configuration, so
+/// problems with the configuration file are logged too.
+pub fn init_logging() {
+    static LOGGING: Once = Once::new();
+
+    LOGGING.call_once(|| {
+        if tracing::dispatcher::has_been_set() {
+            return;
+        }
+        let filter = EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new(format!("{},{DEFAULT_FILTER}", Level::INFO)));
+        let subscriber = Registry::default()
+            .with(filter)
+            .with(fmt::Layer::default().with_writer(std::io::stderr));
+        if tracing::subscriber::set_global_default(subscriber).is_ok() {
+            // Forwards records of the `log` crate, which the physics core logs with
+            let _ = LogTracer::init();
+        }
+    });
+}
+
+/// An app that runs the simulation configured by `config` with no window or renderer, ready to
+/// be updated by hand or run.
+///
+/// Time advances by exactly one fixed timestep every update, so each update simulates one fixed
+/// step and the app loop runs as fast as it can. Logging is set up with [`init_logging`].
+pub fn headless_app(config: SimulationConfig) -> App {
+    init_logging();
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins.set(task_pool_plugin()));
+    app.add_plugins((
+        TransformPlugin,
+        StatesPlugin,
+        #[cfg(feature = "diagnostics")]
+        DiagnosticsPlugin,
+    ));
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(
+        Time::<Fixed>::default().timestep(),
+    ));
+    app.add_plugins(StardriftSimulationPlugin {
+        config,
+        #[cfg(feature = "graphics")]
+        graphics: false,
+    });
+    app.finish();
+    app.cleanup();
+    app
+}
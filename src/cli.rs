@@ -0,0 +1,408 @@
+//! Command-line interface of the stardrift binary.
+//!
+//! The configuration is read from `--config` or else the user configuration file, and every
+//! `--set key=value` and shorthand such as `--seed` is then applied on top, in that order. So
+//! command-line values override the file, and the file overrides the defaults.
+//!
+//! The `sweep` subcommand runs a parameter sweep instead, starting every run from that same
+//! configuration.
+
+use clap::Arg;
+use clap::ArgAction;
+use clap::ArgMatches;
+use clap::Command;
+use clap::value_parser;
+use stardrift::config::SimulationConfig;
+use std::num::NonZeroU64;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CliOptions {
+    pub config_path: Option<PathBuf>,
+    /// Configuration keys and the values they are set to, in the order they are applied.
+    pub overrides: Vec<(String, toml::Value)>,
+    /// Seed of the random number generator, applied after the overrides.
+    pub seed: Option<u64>,
+    /// Fixed steps to simulate before quitting.
+    pub steps: Option<NonZeroU64>,
+    /// Export the bodies once `steps` have been simulated.
+    pub export: bool,
+    /// Run without a window or renderer, which builds without the `graphics` feature always do.
+    pub headless: bool,
+    pub dump_config: bool,
+    /// Trajectory to replay once loaded, instead of simulating.
+    pub replay_path: Option<PathBuf>,
+    /// Run a parameter sweep instead of the simulation.
+    pub sweep: Option<SweepOptions>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepOptions {
+    pub spec_path: PathBuf,
+    /// CSV file the summary is written to, standard output if not given.
+    pub output_path: Option<PathBuf>,
+    /// Runs to run at the same time, one per core if not given.
+    pub jobs: Option<usize>,
+}
+
+/// Reads a `--set` value as TOML, or as a bare string if it is not valid TOML, so strings need
+/// no quotes.
+fn parse_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+fn parse_override(assignment: &str) -> Result<(String, toml::Value), String> {
+    let (key, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{assignment}`"))?;
+    Ok((key.trim().to_string(), parse_value(value.trim())))
+}
+
+pub fn command() -> Command {
+    let command = Command::new("stardrift")
+        .about("Gravitational N-body simulation")
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .short('c')
+                .value_name("PATH")
+                .value_parser(value_parser!(PathBuf))
+                .help("Configuration file to use instead of the user configuration"),
+        )
+        .arg(
+            Arg::new("set")
+                .long("set")
+                .value_name("KEY=VALUE")
+                .value_parser(parse_override)
+                .action(ArgAction::Append)
+                .help("Override a configuration key, e.g. physics.body_count=5000"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("SEED")
+                .value_parser(value_parser!(u64))
+                .help("Seed of the random number generator"),
+        )
+        .arg(
+            Arg::new("scenario")
+                .long("scenario")
+                .value_name("SCENARIO")
+                .help("random_sphere, planetary_system or stellar_population"),
+        )
+        .arg(
+            Arg::new("integrator")
+                .long("integrator")
+                .value_name("INTEGRATOR")
+                .help("leapfrog_kdk, velocity_verlet, yoshida4, hermite4 or wisdom_holman"),
+        )
+        .arg(
+            Arg::new("initial-conditions")
+                .long("initial-conditions")
+                .value_name("PATH")
+                .help("CSV or JSON Lines table of bodies to start from"),
+        )
+        .arg(
+            Arg::new("steps")
+                .long("steps")
+                .value_name("STEPS")
+                .value_parser(value_parser!(NonZeroU64))
+                .help("Quit after simulating this many fixed steps"),
+        )
+        .arg(
+            Arg::new("export")
+                .long("export")
+                .action(ArgAction::SetTrue)
+                .requires("steps")
+                .help("Export the bodies to CSV and JSON Lines before quitting"),
+        )
+        .arg(
+            Arg::new("headless")
+                .long("headless")
+                .action(ArgAction::SetTrue)
+                .help("Run without a window or renderer"),
+        )
+        .arg(
+            Arg::new("dump-config")
+                .long("dump-config")
+                .action(ArgAction::SetTrue)
+                .help("Print the effective configuration as TOML and quit"),
+        )
+        .subcommand(
+            Command::new("sweep")
+                .about("Run every configuration of a sweep spec headless and summarize them as CSV")
+                .arg(
+                    Arg::new("spec")
+                        .value_name("SPEC")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("TOML file listing the parameter values, seeds and duration"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .value_name("PATH")
+                        .value_parser(value_parser!(PathBuf))
+                        .help("CSV file to write the summary to instead of standard output"),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .long("jobs")
+                        .short('j')
+                        .value_name("JOBS")
+                        .value_parser(value_parser!(u64).range(1..))
+                        .help("Runs to run at the same time, one per core by default"),
+                ),
+        );
+
+    // Builds without the `graphics` feature always run headless
+    if cfg!(feature = "graphics") {
+        command
+            .arg(
+                Arg::new("windowed")
+                    .long("windowed")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with("headless")
+                    .help("Run in a window (the default)"),
+            )
+            .arg(
+                Arg::new("replay")
+                    .long("replay")
+                    .value_name("PATH")
+                    .value_parser(value_parser!(PathBuf))
+                    .conflicts_with_all(["headless", "steps"])
+                    .help("Replay this trajectory file instead of simulating"),
+            )
+    } else {
+        command
+    }
+}
+
+impl CliOptions {
+    fn from_matches(matches: &ArgMatches) -> Self {
+        let mut overrides: Vec<(String, toml::Value)> = matches
+            .get_many::<(String, toml::Value)>("set")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        [
+            ("scenario", "physics.scenario"),
+            ("integrator", "physics.integrator"),
+            ("initial-conditions", "physics.initial_conditions_path"),
+        ]
+        .into_iter()
+        .for_each(|(arg, key)| {
+            if let Some(value) = matches.get_one::<String>(arg) {
+                overrides.push((key.into(), toml::Value::String(value.clone())));
+            }
+        });
+
+        Self {
+            config_path: matches.get_one::<PathBuf>("config").cloned(),
+            overrides,
+            seed: matches.get_one::<u64>("seed").copied(),
+            steps: matches.get_one::<NonZeroU64>("steps").copied(),
+            export: matches.get_flag("export"),
+            headless: matches.get_flag("headless") || !cfg!(feature = "graphics"),
+            dump_config: matches.get_flag("dump-config"),
+            replay_path: matches
+                .try_get_one::<PathBuf>("replay")
+                .ok()
+                .flatten()
+                .cloned(),
+            sweep: matches
+                .subcommand_matches("sweep")
+                .map(|matches| SweepOptions {
+                    spec_path: matches
+                        .get_one::<PathBuf>("spec")
+                        .cloned()
+                        .unwrap_or_default(),
+                    output_path: matches.get_one::<PathBuf>("output").cloned(),
+                    jobs: matches.get_one::<u64>("jobs").map(|&jobs| jobs as usize),
+                }),
+        }
+    }
+
+    /// Parses the process arguments, printing help or errors and quitting if asked for help or
+    /// they are invalid.
+    pub fn parse() -> Self {
+        Self::try_parse_from(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    pub fn try_parse_from<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        Ok(Self::from_matches(&command().try_get_matches_from(args)?))
+    }
+
+    /// The configuration file with the command-line overrides applied.
+    pub fn effective_config(&self) -> Result<SimulationConfig, Box<dyn std::error::Error>> {
+        let mut config = match &self.config_path {
+            Some(path) => SimulationConfig::load_or_default(path),
+            None => SimulationConfig::load_from_user_config(),
+        };
+        self.apply_overrides(&mut config)?;
+        Ok(config)
+    }
+
+    pub fn apply_overrides(
+        &self,
+        config: &mut SimulationConfig,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.overrides
+            .iter()
+            .try_for_each(|(key, value)| config.set_value(key, value.clone()))?;
+        // Set directly, as TOML integers cannot hold seeds above `i64::MAX`
+        if let Some(seed) = self.seed {
+            config.physics.initial_seed = Some(seed);
+        }
+        if let Some(path) = &self.replay_path {
+            config.recording.replay_path = Some(path.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stardrift::config::Scenario;
+    use stardrift::physics::integrator::IntegratorKind;
+
+    #[test]
+    fn test_command_is_valid() {
+        command().debug_assert();
+    }
+
+    #[test]
+    fn test_no_arguments_change_nothing() {
+        let options = CliOptions::try_parse_from(["stardrift"]).unwrap();
+        assert_eq!(
+            options,
+            CliOptions {
+                headless: !cfg!(feature = "graphics"),
+                ..CliOptions::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_overrides_apply_in_order_over_the_file() {
+        let options = CliOptions::try_parse_from([
+            "stardrift",
+            "--set",
+            "physics.body_count=5000",
+            "--set",
+            "physics.scenario=stellar_population",
+            "--scenario",
+            "planetary_system",
+            "--integrator",
+            "hermite4",
+            "--seed",
+            "42",
+        ])
+        .unwrap();
+
+        let mut config = SimulationConfig::default();
+        config.physics.body_count = 10;
+        options.apply_overrides(&mut config).unwrap();
+
+        assert_eq!(config.physics.body_count, 5000);
+        assert_eq!(config.physics.scenario, Scenario::PlanetarySystem);
+        assert_eq!(config.physics.integrator, IntegratorKind::Hermite4);
+        assert_eq!(config.physics.initial_seed, Some(42));
+    }
+
+    #[test]
+    fn test_seed_takes_any_u64() {
+        let options =
+            CliOptions::try_parse_from(["stardrift", "--seed", "18446744073709551615"]).unwrap();
+        let mut config = SimulationConfig::default();
+        options.apply_overrides(&mut config).unwrap();
+
+        assert_eq!(config.physics.initial_seed, Some(u64::MAX));
+        assert!(CliOptions::try_parse_from(["stardrift", "--seed", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_arguments_are_refused() {
+        assert!(CliOptions::try_parse_from(["stardrift", "--set", "physics.body_count"]).is_err());
+        assert!(CliOptions::try_parse_from(["stardrift", "--export"]).is_err());
+        assert!(CliOptions::try_parse_from(["stardrift", "--steps", "0"]).is_err());
+        assert!(CliOptions::try_parse_from(["stardrift", "--headless", "--windowed"]).is_err());
+
+        let options =
+            CliOptions::try_parse_from(["stardrift", "--integrator", "runge_kutta"]).unwrap();
+        assert!(
+            options
+                .apply_overrides(&mut SimulationConfig::default())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_sweep_subcommand() {
+        let options = CliOptions::try_parse_from([
+            "stardrift",
+            "--seed",
+            "3",
+            "sweep",
+            "theta.toml",
+            "-o",
+            "summary.csv",
+            "--jobs",
+            "4",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            options.sweep,
+            Some(SweepOptions {
+                spec_path: PathBuf::from("theta.toml"),
+                output_path: Some(PathBuf::from("summary.csv")),
+                jobs: Some(4),
+            })
+        );
+        assert_eq!(options.seed, Some(3));
+
+        assert!(CliOptions::try_parse_from(["stardrift", "sweep"]).is_err());
+        assert!(CliOptions::try_parse_from(["stardrift", "sweep", "a.toml", "-j", "0"]).is_err());
+    }
+
+    #[cfg(feature = "graphics")]
+    #[test]
+    fn test_replay_path_sets_the_replayed_file() {
+        let options = CliOptions::try_parse_from(["stardrift", "--replay", "run.strj"]).unwrap();
+        let mut config = SimulationConfig::default();
+        options.apply_overrides(&mut config).unwrap();
+
+        assert_eq!(options.replay_path, Some(PathBuf::from("run.strj")));
+        assert_eq!(
+            config.recording.replay_path,
+            Some(PathBuf::from("run.strj"))
+        );
+        assert!(CliOptions::try_parse_from(["stardrift", "--replay", "a", "--headless"]).is_err());
+    }
+
+    #[test]
+    fn test_set_values_are_read_as_toml() {
+        assert_eq!(parse_value("5000"), toml::Value::Integer(5000));
+        assert_eq!(parse_value("0.5"), toml::Value::Float(0.5));
+        assert_eq!(parse_value("true"), toml::Value::Boolean(true));
+        assert_eq!(
+            parse_value("\"quoted\""),
+            toml::Value::String("quoted".to_string())
+        );
+        assert_eq!(
+            parse_value("planetary_system"),
+            toml::Value::String("planetary_system".to_string())
+        );
+    }
+}
@@ -1,40 +1,95 @@
 use crate::config;
+use crate::initial_conditions;
+use crate::physics;
 use crate::resources;
 use crate::utils;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
 use avian3d::prelude::*;
 use bevy::prelude::*;
+#[cfg(feature = "graphics")]
 use bevy::render::mesh::SphereKind;
 use rand::Rng;
 
+/// Gravitational acceleration of a body from the most recent force evaluation.
+///
+/// The integrator keeps this in step with the body's `Position`, so it can be reused as the
+/// opening kick of the next step instead of evaluating the forces twice.
+#[derive(Component, Deref, DerefMut, Copy, Clone, Default, PartialEq, Debug)]
+pub struct Acceleration(pub Vector);
+
+/// Temperature in kelvin, which sets the colour of a body's material.
+#[derive(Component, Deref, DerefMut, Copy, Clone, Default, PartialEq, Debug)]
+pub struct Temperature(pub Scalar);
+
+/// Friends-of-friends group of a body, indexing into [`resources::GroupCatalog`].
+#[derive(Component, Deref, DerefMut, Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct GroupId(pub usize);
+
+/// Marks a body whose energy relative to the barycenter is positive, so it will escape.
+#[derive(Component, Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Unbound;
+
+/// Debris of a shattered or tidally disrupted body, which neither merges, shatters nor is torn
+/// apart again for this many more fixed steps, while it moves clear of its siblings.
+#[derive(Component, Deref, DerefMut, Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Fragment(pub u32);
+
+/// Evolutionary state of a body that is a star, which ages with simulated time.
+#[derive(Component, Deref, DerefMut, Copy, Clone, PartialEq, Debug)]
+pub struct StellarProperties(pub physics::stars::Star);
+
+/// Exact mass of a body, applied once Avian has computed the body's mass from its collider and
+/// its density, which Avian only holds to `f32` precision.
+#[derive(Component, Deref, DerefMut, Copy, Clone, PartialEq, Debug)]
+pub struct RestoredMass(pub Scalar);
+
+/// Shows a body of the trajectory being replayed, which has no physics of its own.
+#[derive(Component, Deref, DerefMut, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ReplayBody(pub physics::BodyId);
+
+/// A body's own temperature material, kept while it is shown in its group's colour.
+#[cfg(feature = "graphics")]
+#[derive(Component, Deref, DerefMut, Clone, Default, PartialEq, Debug)]
+pub struct TemperatureMaterial(pub Handle<StandardMaterial>);
+
+/// Radius and temperature a body's mesh and material were made for.
+#[cfg(feature = "graphics")]
+#[derive(Component, Copy, Clone, PartialEq, Debug)]
+pub struct BodyAppearance {
+    pub radius: Scalar,
+    pub temperature: Scalar,
+}
+
 /// Bundle containing all components needed for a celestial body.
 ///
 /// This bundle follows idiomatic Bevy patterns by focusing solely on component organization.
-/// Asset creation and complex calculations are handled by separate factory functions that
-/// can properly utilize system resources and existing utilities.
+/// Complex calculations are handled by separate factory functions, and the mesh and material a
+/// body is drawn with are added by `systems::visualization::update_body_appearance` when
+/// graphics are enabled.
 #[derive(Bundle)]
 pub struct BodyBundle {
     pub transform: Transform,
     pub collider: Collider,
     pub gravity_scale: GravityScale,
     pub rigid_body: RigidBody,
-    pub external_force: ExternalForce,
+    pub acceleration: Acceleration,
+    pub temperature: Temperature,
+    pub collision_events: CollisionEventsEnabled,
     pub restitution: Restitution,
     pub friction: Friction,
-    pub mesh_material: MeshMaterial3d<StandardMaterial>,
-    pub mesh: Mesh3d,
 }
 
 impl BodyBundle {
     /// Creates a new celestial body bundle with the provided components.
     ///
-    /// This constructor follows idiomatic Bevy patterns by accepting pre-created
-    /// components rather than performing complex calculations or asset creation.
-    /// Use the factory functions in this module to create the necessary components.
+    /// This constructor follows idiomatic Bevy patterns by accepting pre-computed values
+    /// rather than performing complex calculations. Use the factory functions in this module
+    /// to compute them.
     pub fn new(
         position: Vec3,
         radius: f64,
-        material: Handle<StandardMaterial>,
-        mesh: Handle<Mesh>,
+        temperature: f64,
         config: &config::SimulationConfig,
     ) -> Self {
         Self {
@@ -42,11 +97,11 @@ impl BodyBundle {
             collider: Collider::sphere(radius),
             gravity_scale: GravityScale(0.0),
             rigid_body: RigidBody::Dynamic,
-            external_force: ExternalForce::ZERO,
+            acceleration: Acceleration::default(),
+            temperature: Temperature(temperature),
+            collision_events: CollisionEventsEnabled,
             restitution: Restitution::new(config.physics.collision_restitution),
             friction: Friction::new(config.physics.collision_friction),
-            mesh_material: MeshMaterial3d(material),
-            mesh: Mesh3d(mesh),
         }
     }
 }
@@ -89,6 +144,7 @@ pub mod factory {
     }
 
     /// Creates a detailed mesh for a celestial body with high-quality subdivisions.
+    #[cfg(feature = "graphics")]
     pub fn create_detailed_mesh(meshes: &mut Assets<Mesh>, radius: f64) -> Handle<Mesh> {
         meshes.add(
             Sphere::new(radius as f32)
@@ -105,8 +161,6 @@ pub mod factory {
     /// This function demonstrates the idiomatic way to create bodies in systems,
     /// using existing utilities and proper resource access patterns.
     pub fn create_random_body(
-        meshes: &mut ResMut<Assets<Mesh>>,
-        materials: &mut ResMut<Assets<StandardMaterial>>,
         rng: &mut ResMut<resources::SharedRng>,
         config: &config::SimulationConfig,
         total_body_count: usize,
@@ -115,16 +169,165 @@ pub mod factory {
         let radius = random_radius(rng, config);
         let temperature = calculate_temperature(radius, config);
 
-        let material = utils::color::emissive_material_for_temp(
-            materials,
-            temperature,
-            config.rendering.bloom_intensity,
-            config.rendering.saturation_intensity,
+        BodyBundle::new(position, radius, temperature, config)
+    }
+
+    /// Creates a star and a disc of bodies on circular orbits around it.
+    ///
+    /// The orbiting bodies keep Avian's default unit density, and the star's density is chosen
+    /// so it outweighs all of them together by `central_body_mass_ratio`. The star moves
+    /// opposite to the disc's total momentum, so the barycenter stays at rest. Masses are applied
+    /// exactly once Avian has computed them, so that holds to the last bit.
+    pub fn create_planetary_system(
+        rng: &mut ResMut<resources::SharedRng>,
+        config: &config::SimulationConfig,
+        total_body_count: usize,
+    ) -> Vec<(BodyBundle, ColliderDensity, LinearVelocity, RestoredMass)> {
+        let sphere_volume = |radius: f64| 4.0 / 3.0 * std::f64::consts::PI * radius.powi(3);
+        let outer_orbit = utils::math::min_sphere_radius_for_surface_distribution(
+            total_body_count,
+            config.physics.body_distribution_sphere_radius_multiplier,
+            config.physics.body_distribution_min_distance,
         );
+        let star_radius = 4.0 * config.physics.max_body_radius;
+        let inner_orbit = (4.0 * star_radius).min(0.5 * outer_orbit);
+
+        let planets: Vec<(Vector, f64)> = (1..total_body_count)
+            .map(|_| {
+                let orbit = rng.random_range(inner_orbit..=outer_orbit);
+                let angle = rng.random_range(0.0..std::f64::consts::TAU);
+                let height = rng.random_range(-0.02..=0.02) * orbit;
+                let position = Vector::new(orbit * angle.cos(), height, orbit * angle.sin());
+                (position, random_radius(rng, config))
+            })
+            .collect();
 
-        let mesh = create_detailed_mesh(meshes, radius);
+        let planets_mass: f64 = planets
+            .iter()
+            .map(|&(_, radius)| sphere_volume(radius))
+            .sum();
+        let star_mass = config.physics.central_body_mass_ratio * planets_mass.max(1.0);
+        let g = config.physics.gravitational_constant;
 
-        BodyBundle::new(position, radius, material, mesh, config)
+        let mut star_momentum = Vector::ZERO;
+        let mut bodies: Vec<(BodyBundle, ColliderDensity, LinearVelocity, RestoredMass)> = planets
+            .into_iter()
+            .map(|(position, radius)| {
+                let orbit = Vector::new(position.x, 0.0, position.z);
+                let speed = libm::sqrt(g * star_mass / orbit.length());
+                let velocity = Vector::new(-orbit.z, 0.0, orbit.x).normalize() * speed;
+                star_momentum -= velocity * sphere_volume(radius);
+
+                let temperature = calculate_temperature(radius, config);
+                (
+                    BodyBundle::new(position.as_vec3(), radius, temperature, config),
+                    ColliderDensity(1.0),
+                    LinearVelocity(velocity),
+                    RestoredMass(sphere_volume(radius)),
+                )
+            })
+            .collect();
+
+        bodies.push((
+            BodyBundle::new(
+                Vec3::ZERO,
+                star_radius,
+                config.rendering.max_temperature,
+                config,
+            ),
+            ColliderDensity((star_mass / sphere_volume(star_radius)) as f32),
+            LinearVelocity(star_momentum / star_mass),
+            RestoredMass(star_mass),
+        ));
+
+        bodies
+    }
+
+    /// Creates an emissive material for a star, glowing brighter the more luminous it is.
+    #[cfg(feature = "graphics")]
+    pub fn create_stellar_material(
+        materials: &mut ResMut<Assets<StandardMaterial>>,
+        star: &physics::stars::Star,
+    ) -> Handle<StandardMaterial> {
+        let bloom_intensity = (star.luminosity * 100.0).clamp(100.0, 10000.0);
+        let saturation_intensity = match star.phase {
+            physics::stars::StellarPhase::MainSequence => 2.0,
+            _ => 1.0,
+        };
+        utils::color::emissive_material_for_temp(
+            materials,
+            star.temperature,
+            bloom_intensity,
+            saturation_intensity,
+        )
+    }
+
+    /// Creates stars of random initial mass and age, scattered like [`create_random_body`].
+    ///
+    /// Each star's radius and mass are its solar values scaled by
+    /// [`physics::stars::StellarParameters`], with the density chosen to match and the mass
+    /// applied exactly once Avian has computed it from the collider.
+    pub fn create_stellar_population(
+        rng: &mut ResMut<resources::SharedRng>,
+        config: &config::SimulationConfig,
+        total_body_count: usize,
+    ) -> Vec<(BodyBundle, ColliderDensity, RestoredMass, StellarProperties)> {
+        let parameters = physics::stars::StellarParameters::default();
+
+        (0..total_body_count)
+            .map(|_| {
+                let position = random_position(rng, total_body_count, config);
+                let initial_mass = config
+                    .physics
+                    .stellar_initial_mass_function
+                    .sample(&mut rng.0);
+                let age_gyr = rng.random_range(0.1..config.physics.stellar_max_age_gyr.max(0.2));
+                let star = physics::stars::Star::new(initial_mass, age_gyr);
+
+                let radius = star.radius * parameters.solar_radius;
+                let mass = star.mass * parameters.solar_mass;
+                let volume = 4.0 / 3.0 * std::f64::consts::PI * radius.powi(3);
+                (
+                    BodyBundle::new(position, radius, star.temperature, config),
+                    ColliderDensity((mass / volume) as f32),
+                    RestoredMass(mass),
+                    StellarProperties(star),
+                )
+            })
+            .collect()
+    }
+
+    /// Creates exactly the bodies of an imported table of initial conditions.
+    ///
+    /// Bodies without a temperature are given the one [`calculate_temperature`] gives their
+    /// radius. Their positions are set exactly rather than through the `Transform`, and their
+    /// masses are applied exactly once Avian has computed them from the collider.
+    pub fn create_imported_bodies(
+        bodies: &[initial_conditions::InitialBody],
+        config: &config::SimulationConfig,
+    ) -> Vec<(
+        BodyBundle,
+        Position,
+        LinearVelocity,
+        ColliderDensity,
+        RestoredMass,
+    )> {
+        bodies
+            .iter()
+            .map(|body| {
+                let temperature = body
+                    .temperature
+                    .unwrap_or_else(|| calculate_temperature(body.radius, config));
+                let volume = 4.0 / 3.0 * std::f64::consts::PI * body.radius.powi(3);
+                (
+                    BodyBundle::new(body.position.as_vec3(), body.radius, temperature, config),
+                    Position(body.position),
+                    LinearVelocity(body.velocity),
+                    ColliderDensity((body.mass / volume) as f32),
+                    RestoredMass(body.mass),
+                )
+            })
+            .collect()
     }
 }
 
@@ -132,10 +335,14 @@ pub mod factory {
 mod tests {
     use super::*;
     use crate::config::SimulationConfig;
+    #[cfg(feature = "graphics")]
     use bevy::app::App;
+    #[cfg(feature = "graphics")]
     use bevy::asset::AssetPlugin;
+    #[cfg(feature = "graphics")]
     use bevy::render::RenderPlugin;
 
+    #[cfg(feature = "graphics")]
     fn setup_test_app() -> (App, Handle<StandardMaterial>, Handle<Mesh>) {
         let mut app = App::new();
         app.add_plugins((AssetPlugin::default(), RenderPlugin::default()));
@@ -179,6 +386,7 @@ mod tests {
         assert!((temp_mid_radius - expected_mid_temp).abs() < 0.001);
     }
 
+    #[cfg(feature = "graphics")]
     #[test]
     fn test_mesh_creation() {
         let (mut app, _, _) = setup_test_app();
@@ -1,3 +1,15 @@
 pub mod body;
 
+pub use body::Acceleration;
+#[cfg(feature = "graphics")]
+pub use body::BodyAppearance;
 pub use body::BodyBundle;
+pub use body::Fragment;
+pub use body::GroupId;
+pub use body::ReplayBody;
+pub use body::RestoredMass;
+pub use body::StellarProperties;
+pub use body::Temperature;
+#[cfg(feature = "graphics")]
+pub use body::TemperatureMaterial;
+pub use body::Unbound;
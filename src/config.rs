@@ -1,3 +1,6 @@
+use crate::physics::collisions::EjectaDistribution;
+use crate::physics::integrator::IntegratorKind;
+use crate::physics::stars::InitialMassFunction;
 use avian3d::math::Scalar;
 use bevy::prelude::*;
 use config::Config;
@@ -6,6 +9,7 @@ use config::File;
 #[cfg(not(target_arch = "wasm32"))]
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 use std::path::PathBuf;
 
 #[derive(Resource, Serialize, Deserialize, Clone, Debug)]
@@ -13,6 +17,7 @@ pub struct SimulationConfig {
     pub version: u32,
     pub physics: PhysicsConfig,
     pub rendering: RenderingConfig,
+    pub recording: RecordingConfig,
 }
 
 impl Default for SimulationConfig {
@@ -21,11 +26,50 @@ impl Default for SimulationConfig {
             version: 2,
             physics: PhysicsConfig::default(),
             rendering: RenderingConfig::default(),
+            recording: RecordingConfig::default(),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Initial conditions the simulation starts from.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scenario {
+    /// Bodies at rest, scattered over the surface of a sphere.
+    #[default]
+    RandomSphere,
+    /// One heavy central star orbited by the remaining bodies on near-circular orbits in a disc.
+    PlanetarySystem,
+    /// Stars with masses drawn from `stellar_initial_mass_function` and random ages, which
+    /// evolve as simulated time passes.
+    StellarPopulation,
+}
+
+/// How bodies respond when they collide.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionMode {
+    /// Contacts are resolved by Avian using `collision_restitution` and `collision_friction`.
+    #[default]
+    Bounce,
+    /// Colliding bodies merge into one, as in a perfectly inelastic collision.
+    Merge,
+}
+
+/// What the conservation monitor does once drift exceeds its threshold.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConservationDriftAction {
+    /// Only report drift through the diagnostics.
+    Ignore,
+    /// Log a warning the first time drift exceeds the threshold.
+    #[default]
+    Warn,
+    /// Log a warning and pause the simulation.
+    Pause,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct PhysicsConfig {
     pub gravitational_constant: Scalar,
     pub body_count: usize,
@@ -40,6 +84,55 @@ pub struct PhysicsConfig {
     pub initial_seed: Option<u64>,
     pub collision_restitution: Scalar,
     pub collision_friction: Scalar,
+    pub collision_mode: CollisionMode,
+    pub merge_conserves_spin: bool,
+    /// Specific impact energy above which a body shatters; `None` never shatters bodies.
+    pub fragmentation_threshold: Option<Scalar>,
+    pub fragment_count: usize,
+    /// Mean ejecta speed of fragments as a fraction of the impact speed.
+    pub fragment_ejecta_speed_ratio: Scalar,
+    pub fragment_ejecta_distribution: EjectaDistribution,
+    pub fragment_min_radius: Scalar,
+    /// Fixed steps after a body breaks up during which its fragments neither merge, shatter nor
+    /// are torn apart again.
+    pub fragment_grace_steps: u32,
+    pub tidal_disruption: bool,
+    /// Minimum mass of a body relative to another for it to tear the other apart.
+    pub tidal_disruption_mass_ratio: Scalar,
+    pub tidal_fragment_count: usize,
+    /// Gyr of stellar evolution per unit of simulated time.
+    pub stellar_time_scale: Scalar,
+    pub stellar_evolution_interval: u32,
+    pub stellar_initial_mass_function: InitialMassFunction,
+    pub stellar_max_age_gyr: Scalar,
+    /// Reimers wind efficiency, where zero turns stellar winds off.
+    pub stellar_wind_efficiency: Scalar,
+    pub supernova_kick_speed: Scalar,
+    pub integrator: IntegratorKind,
+    pub adaptive_timesteps: bool,
+    pub adaptive_timestep_min: Scalar,
+    pub adaptive_timestep_max: Scalar,
+    pub adaptive_timestep_accuracy: Scalar,
+    pub hermite_accuracy: Scalar,
+    pub regularization: bool,
+    pub regularization_max_energy_ratio: Scalar,
+    /// Fraction of the total mass one body must hold for the Wisdom-Holman map to take over from
+    /// the configured integrator; `None` never switches.
+    pub wisdom_holman_mass_fraction: Option<Scalar>,
+    pub scenario: Scenario,
+    /// CSV or JSON Lines table of bodies to start from instead of the scenario.
+    pub initial_conditions_path: Option<PathBuf>,
+    pub central_body_mass_ratio: Scalar,
+    pub time_scale: Scalar,
+    pub conservation_check_interval: u32,
+    pub conservation_drift_threshold: Scalar,
+    pub conservation_drift_action: ConservationDriftAction,
+    pub group_linking_length: Scalar,
+    pub group_min_members: usize,
+    pub group_finding_interval: u32,
+    pub bound_classification_interval: u32,
+    pub escaper_removal: bool,
+    pub escaper_removal_radius: Scalar,
 }
 
 impl Default for PhysicsConfig {
@@ -59,6 +152,45 @@ impl Default for PhysicsConfig {
                 initial_seed: None,
                 collision_restitution: 0.8,
                 collision_friction: 0.1,
+                collision_mode: CollisionMode::default(),
+                merge_conserves_spin: true,
+                fragmentation_threshold: None,
+                fragment_count: 8,
+                fragment_ejecta_speed_ratio: 0.5,
+                fragment_ejecta_distribution: EjectaDistribution::default(),
+                fragment_min_radius: 1.0,
+                fragment_grace_steps: 30,
+                tidal_disruption: false,
+                tidal_disruption_mass_ratio: 10.0,
+                tidal_fragment_count: 6,
+                stellar_time_scale: 0.01,
+                stellar_evolution_interval: 10,
+                stellar_initial_mass_function: InitialMassFunction::default(),
+                stellar_max_age_gyr: 10.0,
+                stellar_wind_efficiency: 0.5,
+                supernova_kick_speed: 50.0,
+                integrator: IntegratorKind::default(),
+                adaptive_timesteps: false,
+                adaptive_timestep_min: 1e-5,
+                adaptive_timestep_max: 1.0 / 64.0,
+                adaptive_timestep_accuracy: 0.025,
+                hermite_accuracy: 0.02,
+                regularization: false,
+                regularization_max_energy_ratio: 0.9,
+                wisdom_holman_mass_fraction: None,
+                scenario: Scenario::default(),
+                initial_conditions_path: None,
+                central_body_mass_ratio: 1000.0,
+                time_scale: 1.0,
+                conservation_check_interval: 60,
+                conservation_drift_threshold: 1e-2,
+                conservation_drift_action: ConservationDriftAction::default(),
+                group_linking_length: 30.0,
+                group_min_members: 3,
+                group_finding_interval: 120,
+                bound_classification_interval: 60,
+                escaper_removal: false,
+                escaper_removal_radius: 2e4,
             }
         } else {
             Self {
@@ -75,6 +207,45 @@ impl Default for PhysicsConfig {
                 initial_seed: None,
                 collision_restitution: 0.8,
                 collision_friction: 0.1,
+                collision_mode: CollisionMode::default(),
+                merge_conserves_spin: true,
+                fragmentation_threshold: None,
+                fragment_count: 8,
+                fragment_ejecta_speed_ratio: 0.5,
+                fragment_ejecta_distribution: EjectaDistribution::default(),
+                fragment_min_radius: 1.0,
+                fragment_grace_steps: 30,
+                tidal_disruption: false,
+                tidal_disruption_mass_ratio: 10.0,
+                tidal_fragment_count: 6,
+                stellar_time_scale: 0.01,
+                stellar_evolution_interval: 10,
+                stellar_initial_mass_function: InitialMassFunction::default(),
+                stellar_max_age_gyr: 10.0,
+                stellar_wind_efficiency: 0.5,
+                supernova_kick_speed: 50.0,
+                integrator: IntegratorKind::default(),
+                adaptive_timesteps: false,
+                adaptive_timestep_min: 1e-5,
+                adaptive_timestep_max: 1.0 / 64.0,
+                adaptive_timestep_accuracy: 0.025,
+                hermite_accuracy: 0.02,
+                regularization: false,
+                regularization_max_energy_ratio: 0.9,
+                wisdom_holman_mass_fraction: None,
+                scenario: Scenario::default(),
+                initial_conditions_path: None,
+                central_body_mass_ratio: 1000.0,
+                time_scale: 1.0,
+                conservation_check_interval: 60,
+                conservation_drift_threshold: 1e-2,
+                conservation_drift_action: ConservationDriftAction::default(),
+                group_linking_length: 30.0,
+                group_min_members: 3,
+                group_finding_interval: 120,
+                bound_classification_interval: 60,
+                escaper_removal: false,
+                escaper_removal_radius: 2e4,
             }
         }
     }
@@ -101,6 +272,36 @@ impl Default for RenderingConfig {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordingConfig {
+    pub trajectory_recording: bool,
+    /// Fixed steps between recorded trajectory frames.
+    pub trajectory_interval: u32,
+    pub trajectory_compression: bool,
+    /// File the trajectory is written to, next to the user configuration if unset.
+    pub trajectory_path: Option<PathBuf>,
+    /// File replayed with F8, the latest recording if unset.
+    pub replay_path: Option<PathBuf>,
+    /// Directory body tables are exported to, `exports` next to the user configuration if unset.
+    pub export_directory: Option<PathBuf>,
+    /// File snapshots are saved to and loaded from, next to the user configuration if unset.
+    pub snapshot_path: Option<PathBuf>,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            trajectory_recording: false,
+            trajectory_interval: 10,
+            trajectory_compression: true,
+            trajectory_path: None,
+            replay_path: None,
+            export_directory: None,
+            snapshot_path: None,
+        }
+    }
+}
+
 impl SimulationConfig {
     fn get_config_path() -> Result<PathBuf, ConfigError> {
         #[cfg(not(target_arch = "wasm32"))]
@@ -126,6 +327,11 @@ impl SimulationConfig {
         }
     }
 
+    /// Path of a file with the given name next to the user configuration file.
+    pub fn get_user_file_path(file_name: &str) -> Result<PathBuf, ConfigError> {
+        Self::get_config_path().map(|path| path.with_file_name(file_name))
+    }
+
     fn load_config_with_source(source: File<config::FileSourceFile, config::FileFormat>) -> Self {
         let config_result = Config::builder()
             .add_source(config::File::from_str(
@@ -179,33 +385,74 @@ impl SimulationConfig {
         }
     }
 
-    pub fn load_or_default(path: &str) -> Self {
-        info!("Attempting to load configuration from: {}", path);
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        info!("Attempting to load configuration from: {}", path.display());
         match std::fs::read_to_string(path) {
             Ok(content) => {
                 info!("Configuration file exists and was read successfully");
                 match toml::from_str::<toml::Value>(&content) {
                     Ok(value) => {
                         if value.get("version").is_none() {
-                            warn!("Config file {path} missing version field. Using defaults.");
+                            warn!(
+                                "Config file {} missing version field. Using defaults.",
+                                path.display()
+                            );
                             Self::default()
                         } else {
-                            Self::load_config_with_source(File::with_name(path).required(false))
+                            Self::load_config_with_source(File::from(path).required(false))
                         }
                     }
                     Err(e) => {
-                        warn!("Failed to parse config file {path}: {e}. Using defaults.",);
+                        warn!(
+                            "Failed to parse config file {}: {e}. Using defaults.",
+                            path.display()
+                        );
                         Self::default()
                     }
                 }
             }
             Err(_) => {
-                warn!("Config file {path} not found. Using defaults.");
+                warn!("Config file {} not found. Using defaults.", path.display());
                 Self::default()
             }
         }
     }
 
+    /// Sets the value at a dotted `key` such as `physics.body_count`.
+    ///
+    /// The value is checked by deserializing the changed configuration, and keys the
+    /// configuration does not have are refused rather than ignored.
+    pub fn set_value(
+        &mut self,
+        key: &str,
+        value: toml::Value,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let unknown_key = || format!("Unknown configuration key `{key}`");
+        let (parents, name) = key.rsplit_once('.').unwrap_or(("", key));
+        let parents = || parents.split('.').filter(|part| !part.is_empty());
+
+        let mut root = toml::Table::try_from(&*self)?;
+        parents()
+            .try_fold(&mut root, |table, part| table.get_mut(part)?.as_table_mut())
+            .ok_or_else(unknown_key)?
+            .insert(name.to_string(), value);
+
+        let config: Self = toml::Value::Table(root)
+            .try_into()
+            .map_err(|e| format!("Invalid value for `{key}`: {e}"))?;
+
+        // Deserializing drops keys the configuration does not have
+        let round_trip = toml::Table::try_from(&config)?;
+        parents()
+            .try_fold(&round_trip, |table, part| table.get(part)?.as_table())
+            .and_then(|table| table.get(name))
+            .ok_or_else(unknown_key)?;
+
+        *self = config;
+        Ok(())
+    }
+
     pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
         let content = toml::to_string_pretty(self)?;
         std::fs::write(path, content)?;
@@ -264,6 +511,68 @@ mod tests {
         assert!(error_message.contains("Configuration saving not supported on WebAssembly"));
     }
 
+    #[test]
+    fn test_set_value_overrides_a_single_key() {
+        let mut config = SimulationConfig::default();
+
+        config
+            .set_value("physics.body_count", toml::Value::Integer(5000))
+            .unwrap();
+        config
+            .set_value("physics.gravitational_constant", toml::Value::Integer(3))
+            .unwrap();
+        config
+            .set_value(
+                "physics.scenario",
+                toml::Value::String("planetary_system".to_string()),
+            )
+            .unwrap();
+        config
+            .set_value("physics.initial_seed", toml::Value::Integer(7))
+            .unwrap();
+
+        assert_eq!(config.physics.body_count, 5000);
+        assert_eq!(config.physics.gravitational_constant, 3.0);
+        assert_eq!(config.physics.scenario, Scenario::PlanetarySystem);
+        assert_eq!(config.physics.initial_seed, Some(7));
+        assert_eq!(
+            config.rendering.bloom_intensity,
+            SimulationConfig::default().rendering.bloom_intensity
+        );
+    }
+
+    #[test]
+    fn test_set_value_refuses_unknown_keys_and_invalid_values() {
+        let mut config = SimulationConfig::default();
+
+        let error = config
+            .set_value("physics.bodycount", toml::Value::Integer(5000))
+            .unwrap_err();
+        assert!(error.to_string().contains("Unknown configuration key"));
+
+        let error = config
+            .set_value("nonsense.body_count", toml::Value::Integer(5000))
+            .unwrap_err();
+        assert!(error.to_string().contains("Unknown configuration key"));
+
+        let error = config
+            .set_value(
+                "physics.body_count",
+                toml::Value::String("many".to_string()),
+            )
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("Invalid value for `physics.body_count`")
+        );
+
+        assert_eq!(
+            config.physics.body_count,
+            SimulationConfig::default().physics.body_count
+        );
+    }
+
     #[test]
     fn test_save_and_load_config() {
         use std::fs;
@@ -0,0 +1,146 @@
+//! Tables of body states written out for analysis in other tools.
+//!
+//! Each export writes the same rows to a CSV file and a JSON Lines file. Vectors are split into
+//! one column per component so both formats have the same flat columns, and columns for group
+//! data are left empty for bodies that have none.
+
+use avian3d::math::Scalar;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Name of the directory exports are written to next to the user configuration by default.
+pub const EXPORT_DIRECTORY_NAME: &str = "exports";
+
+/// One body's row of the exported table.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BodyRecord {
+    pub entity: u64,
+    pub mass: Scalar,
+    pub radius: Scalar,
+    pub position_x: Scalar,
+    pub position_y: Scalar,
+    pub position_z: Scalar,
+    pub velocity_x: Scalar,
+    pub velocity_y: Scalar,
+    pub velocity_z: Scalar,
+    pub force_x: Scalar,
+    pub force_y: Scalar,
+    pub force_z: Scalar,
+    pub temperature: Scalar,
+    pub kinetic_energy: Scalar,
+    /// Potential energy of the body in the field of all the others.
+    pub potential_energy: Scalar,
+    /// Friends-of-friends group, once groups have been found.
+    pub group: Option<usize>,
+    pub group_member_count: Option<usize>,
+}
+
+pub fn write_csv(records: &[BodyRecord], writer: impl Write) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_json_lines(records: &[BodyRecord], mut writer: impl Write) -> io::Result<()> {
+    for record in records {
+        serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/// Writes `records` to `<name>.csv` and `<name>.jsonl` in `directory`, creating it if needed,
+/// and returns the paths written.
+pub fn export(
+    records: &[BodyRecord],
+    directory: &Path,
+    name: &str,
+) -> Result<[PathBuf; 2], Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(directory)?;
+
+    let csv_path = directory.join(format!("{name}.csv"));
+    write_csv(records, BufWriter::new(File::create(&csv_path)?))?;
+
+    let json_lines_path = directory.join(format!("{name}.jsonl"));
+    write_json_lines(records, BufWriter::new(File::create(&json_lines_path)?))?;
+
+    Ok([csv_path, json_lines_path])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(entity: u64, group: Option<usize>) -> BodyRecord {
+        BodyRecord {
+            entity,
+            mass: 2.5,
+            radius: 0.75,
+            position_x: 1.0,
+            position_y: -2.0,
+            position_z: 1.0 / 3.0,
+            velocity_x: 0.0,
+            velocity_y: 0.5,
+            velocity_z: -0.5,
+            force_x: 1e-12,
+            force_y: 0.0,
+            force_z: 3.0,
+            temperature: 5772.0,
+            kinetic_energy: 0.3125,
+            potential_energy: -4.0,
+            group,
+            group_member_count: group.map(|_| 12),
+        }
+    }
+
+    #[test]
+    fn test_csv_has_one_flat_column_per_field() {
+        let mut csv = Vec::new();
+        write_csv(&[record(7, Some(3)), record(8, None)], &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("entity,mass,radius,position_x,position_y,position_z,"));
+        assert!(lines[0].ends_with(",group,group_member_count"));
+        assert!(lines[1].starts_with("7,2.5,0.75,"));
+        assert!(lines[1].ends_with(",3,12"));
+        assert!(lines[2].ends_with(",,"));
+    }
+
+    #[test]
+    fn test_json_lines_read_back_exactly() {
+        let records = vec![record(7, Some(3)), record(8, None)];
+        let mut json_lines = Vec::new();
+        write_json_lines(&records, &mut json_lines).unwrap();
+
+        let read: Vec<BodyRecord> = String::from_utf8(json_lines)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(read, records);
+    }
+
+    #[test]
+    fn test_export_writes_both_files() {
+        let directory = std::env::temp_dir().join("stardrift_test_export");
+        let paths = export(&[record(1, None)], &directory, "bodies").unwrap();
+
+        let csv = std::fs::read_to_string(&paths[0]);
+        let json_lines = std::fs::read_to_string(&paths[1]);
+        let _ = std::fs::remove_dir_all(&directory);
+
+        assert_eq!(csv.unwrap().lines().count(), 2);
+        assert_eq!(json_lines.unwrap().lines().count(), 1);
+    }
+}
@@ -0,0 +1,329 @@
+//! Initial conditions read from a table of bodies made in another tool.
+//!
+//! The table is CSV with a header row, or JSON Lines with one object per body, chosen by the
+//! file extension. Each body needs `mass`, `radius`, `position_x`, `position_y`, `position_z`,
+//! `velocity_x`, `velocity_y` and `velocity_z`, and may give a `temperature`. Other columns are
+//! ignored, so tables written by [`crate::export`] can be read back.
+
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use std::fmt;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+const REQUIRED_COLUMNS: [&str; 8] = [
+    "mass",
+    "radius",
+    "position_x",
+    "position_y",
+    "position_z",
+    "velocity_x",
+    "velocity_y",
+    "velocity_z",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InitialBody {
+    pub mass: Scalar,
+    pub radius: Scalar,
+    pub position: Vector,
+    pub velocity: Vector,
+    pub temperature: Option<Scalar>,
+}
+
+/// Why a table of bodies could not be read, and where in which file.
+#[derive(Debug)]
+pub struct ImportError {
+    pub path: PathBuf,
+    /// Line of the file the error is on, counting from one, if it is on a particular line.
+    pub line: Option<u64>,
+    pub message: String,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: {}", self.path.display(), self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Builds a body from the values of one row, looked up by column name.
+fn body_from_row(
+    value: impl Fn(&str) -> Result<Option<Scalar>, String>,
+) -> Result<InitialBody, String> {
+    let required = |column: &str| {
+        let value = value(column)?.ok_or_else(|| format!("missing value for `{column}`"))?;
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(format!("`{column}` is {value}, not a finite number"))
+        }
+    };
+
+    let mass = required("mass")?;
+    if mass <= 0.0 {
+        return Err(format!("`mass` is {mass}, but must be positive"));
+    }
+    let radius = required("radius")?;
+    if radius <= 0.0 {
+        return Err(format!("`radius` is {radius}, but must be positive"));
+    }
+    let position = Vector::new(
+        required("position_x")?,
+        required("position_y")?,
+        required("position_z")?,
+    );
+    let velocity = Vector::new(
+        required("velocity_x")?,
+        required("velocity_y")?,
+        required("velocity_z")?,
+    );
+    let temperature = match value("temperature")? {
+        Some(temperature) if !temperature.is_finite() || temperature <= 0.0 => {
+            return Err(format!(
+                "`temperature` is {temperature}, but must be positive"
+            ));
+        }
+        temperature => temperature,
+    };
+
+    Ok(InitialBody {
+        mass,
+        radius,
+        position,
+        velocity,
+        temperature,
+    })
+}
+
+pub fn read_csv(source: impl Read, path: &Path) -> Result<Vec<InitialBody>, ImportError> {
+    let error = |line: Option<u64>, message: String| ImportError {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+
+    let mut reader = csv::Reader::from_reader(source);
+    let headers = reader
+        .headers()
+        .map_err(|e| error(Some(1), e.to_string()))?
+        .clone();
+    if let Some(column) = REQUIRED_COLUMNS
+        .iter()
+        .find(|&&column| !headers.iter().any(|header| header.trim() == column))
+    {
+        return Err(error(Some(1), format!("missing column `{column}`")));
+    }
+
+    reader
+        .records()
+        .map(|record| {
+            let record = record
+                .map_err(|e| error(e.position().map(|position| position.line()), e.to_string()))?;
+            let line = record.position().map(|position| position.line());
+            body_from_row(|column| {
+                let Some(index) = headers.iter().position(|header| header.trim() == column) else {
+                    return Ok(None);
+                };
+                match record.get(index).map(str::trim) {
+                    None | Some("") => Ok(None),
+                    Some(field) => field
+                        .parse()
+                        .map(Some)
+                        .map_err(|_| format!("`{column}` is `{field}`, not a number")),
+                }
+            })
+            .map_err(|message| error(line, message))
+        })
+        .collect()
+}
+
+pub fn read_json_lines(source: impl BufRead, path: &Path) -> Result<Vec<InitialBody>, ImportError> {
+    let error = |line: usize, message: String| ImportError {
+        path: path.to_path_buf(),
+        line: Some(line as u64 + 1),
+        message,
+    };
+
+    let mut bodies = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.map_err(|e| error(index, e.to_string()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let row: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&line).map_err(|e| error(index, e.to_string()))?;
+        let body = body_from_row(|column| match row.get(column) {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(value) => value
+                .as_f64()
+                .map(Some)
+                .ok_or_else(|| format!("`{column}` is `{value}`, not a number")),
+        })
+        .map_err(|message| error(index, message))?;
+        bodies.push(body);
+    }
+    Ok(bodies)
+}
+
+/// Reads the bodies in `path`, as CSV or JSON Lines depending on its extension.
+pub fn read_bodies(path: &Path) -> Result<Vec<InitialBody>, ImportError> {
+    let error = |message: String| ImportError {
+        path: path.to_path_buf(),
+        line: None,
+        message,
+    };
+
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    let file = File::open(path).map_err(|e| error(e.to_string()))?;
+    let bodies = match extension.as_deref() {
+        Some("csv") => read_csv(file, path)?,
+        Some("jsonl" | "ndjson") => read_json_lines(BufReader::new(file), path)?,
+        _ => {
+            return Err(error("expected a .csv, .jsonl or .ndjson file".to_string()));
+        }
+    };
+
+    if bodies.is_empty() {
+        return Err(error("no bodies".to_string()));
+    }
+    Ok(bodies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export;
+
+    fn path() -> PathBuf {
+        PathBuf::from("bodies.csv")
+    }
+
+    #[test]
+    fn test_csv_bodies_are_read_in_order() {
+        let csv = "\
+mass,radius,position_x,position_y,position_z,velocity_x,velocity_y,velocity_z,temperature
+1.5,0.5,1,2,3,0.1,0.2,0.3,4000
+2,1, -1 ,0,0,0,0,0,
+";
+
+        let bodies = read_csv(csv.as_bytes(), &path()).unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0].mass, 1.5);
+        assert_eq!(bodies[0].position, Vector::new(1.0, 2.0, 3.0));
+        assert_eq!(bodies[0].velocity, Vector::new(0.1, 0.2, 0.3));
+        assert_eq!(bodies[0].temperature, Some(4000.0));
+        assert_eq!(bodies[1].position, Vector::new(-1.0, 0.0, 0.0));
+        assert_eq!(bodies[1].temperature, None);
+    }
+
+    #[test]
+    fn test_csv_errors_name_the_file_line_and_column() {
+        let missing_column = "mass,radius,position_x,position_y,position_z,velocity_x,velocity_y\n";
+        let error = read_csv(missing_column.as_bytes(), &path()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "bodies.csv:1: missing column `velocity_z`"
+        );
+
+        let nan = "\
+mass,radius,position_x,position_y,position_z,velocity_x,velocity_y,velocity_z
+1,1,0,0,0,0,0,0
+1,1,0,NaN,0,0,0,0
+";
+        let error = read_csv(nan.as_bytes(), &path()).unwrap_err();
+        assert_eq!(error.line, Some(3));
+        assert!(error.to_string().contains("`position_y` is NaN"));
+
+        let zero_mass = "\
+mass,radius,position_x,position_y,position_z,velocity_x,velocity_y,velocity_z
+0,1,0,0,0,0,0,0
+";
+        let error = read_csv(zero_mass.as_bytes(), &path()).unwrap_err();
+        assert_eq!(error.line, Some(2));
+        assert!(error.message.contains("must be positive"));
+
+        let not_a_number = "\
+mass,radius,position_x,position_y,position_z,velocity_x,velocity_y,velocity_z
+1,1,0,0,zero,0,0,0
+";
+        let error = read_csv(not_a_number.as_bytes(), &path()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "bodies.csv:2: `position_z` is `zero`, not a number"
+        );
+    }
+
+    #[test]
+    fn test_json_lines_bodies_are_read_and_validated() {
+        let path = PathBuf::from("bodies.jsonl");
+        let json_lines = r#"{"mass": 1, "radius": 0.5, "position_x": 1, "position_y": 2, "position_z": 3, "velocity_x": 0, "velocity_y": 0, "velocity_z": 1}
+
+{"mass": 2, "radius": 1, "position_x": 0, "position_y": 0, "position_z": 0, "velocity_x": 0, "velocity_y": 0, "velocity_z": 0, "temperature": 6000}
+"#;
+        let bodies = read_json_lines(json_lines.as_bytes(), &path).unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0].velocity, Vector::Z);
+        assert_eq!(bodies[1].temperature, Some(6000.0));
+
+        let negative_mass = r#"{"mass": -1, "radius": 0.5, "position_x": 1, "position_y": 2, "position_z": 3, "velocity_x": 0, "velocity_y": 0, "velocity_z": 1}"#;
+        let error = read_json_lines(negative_mass.as_bytes(), &path).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "bodies.jsonl:1: `mass` is -1, but must be positive"
+        );
+
+        let missing_value = r#"{"mass": 1, "radius": 0.5, "position_x": 1, "position_z": 3, "velocity_x": 0, "velocity_y": 0, "velocity_z": 1}"#;
+        let error = read_json_lines(missing_value.as_bytes(), &path).unwrap_err();
+        assert!(error.message.contains("missing value for `position_y`"));
+    }
+
+    #[test]
+    fn test_exported_bodies_read_back() {
+        let record = export::BodyRecord {
+            entity: 3,
+            mass: 2.5,
+            radius: 0.75,
+            position_x: 1.0,
+            position_y: -2.0,
+            position_z: 1.0 / 3.0,
+            velocity_x: 0.0,
+            velocity_y: 0.5,
+            velocity_z: -0.5,
+            force_x: 0.0,
+            force_y: 0.0,
+            force_z: 0.0,
+            temperature: 5772.0,
+            kinetic_energy: 0.3125,
+            potential_energy: -4.0,
+            group: None,
+            group_member_count: None,
+        };
+        let mut csv = Vec::new();
+        export::write_csv(&[record], &mut csv).unwrap();
+
+        let bodies = read_csv(csv.as_slice(), &path()).unwrap();
+        assert_eq!(
+            bodies,
+            vec![InitialBody {
+                mass: 2.5,
+                radius: 0.75,
+                position: Vector::new(1.0, -2.0, 1.0 / 3.0),
+                velocity: Vector::new(0.0, 0.5, -0.5),
+                temperature: Some(5772.0),
+            }]
+        );
+    }
+}
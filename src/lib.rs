@@ -0,0 +1,25 @@
+//! Gravitational N-body simulation built on Bevy and Avian.
+//!
+//! [`StardriftSimulationPlugin`] adds the whole simulation to a Bevy app. The octree, force
+//! solvers and integrators in [`physics`], the [`config`] and the [`initial_conditions`] reader
+//! can also be used on their own, and [`physics::simulation::Simulation`] steps bodies without
+//! an app.
+
+pub mod app;
+pub mod components;
+pub mod config;
+pub mod export;
+pub mod initial_conditions;
+pub mod physics;
+pub mod plugins;
+pub mod resources;
+pub mod snapshot;
+pub mod states;
+pub mod sweep;
+pub mod systems;
+pub mod trajectory;
+pub mod utils;
+
+pub use app::headless_app;
+pub use app::init_logging;
+pub use plugins::stardrift_simulation::StardriftSimulationPlugin;
@@ -1,32 +1,101 @@
 #![cfg_attr(target_os = "windows", windows_subsystem = "windows")]
-#![allow(dead_code)]
-
-mod components;
-mod config;
-mod physics;
-mod plugins;
-mod resources;
-mod states;
-mod systems;
-mod utils;
-
-use avian3d::prelude::*;
-use bevy::app::TaskPoolThreadAssignmentPolicy;
-#[cfg(feature = "diagnostics")]
-use bevy::diagnostic::EntityCountDiagnosticsPlugin;
-#[cfg(feature = "diagnostics")]
-use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
-#[cfg(feature = "diagnostics")]
-use bevy::diagnostic::SystemInformationDiagnosticsPlugin;
+
+mod cli;
+
 use bevy::prelude::*;
-use bevy::tasks::available_parallelism;
+#[cfg(feature = "graphics")]
 use bevy::window::WindowMode;
-use bevy_panorbit_camera::PanOrbitCameraPlugin;
+#[cfg(feature = "graphics")]
+use stardrift::StardriftSimulationPlugin;
+use stardrift::config;
+use stardrift::initial_conditions;
+use stardrift::resources;
+use stardrift::sweep;
+use std::fs::File;
+use std::io::BufWriter;
 
-fn main() {
-    let mut app = App::new();
+/// The effective configuration, or quits with the reason it could not be made.
+fn effective_config_or_exit(options: &cli::CliOptions) -> config::SimulationConfig {
+    options.effective_config().unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(2);
+    })
+}
+
+/// Quits with the reason the bodies to start from cannot be imported, rather than simulating the
+/// scenario instead as the windowed app does.
+fn check_initial_conditions_or_exit(config: &config::SimulationConfig) {
+    if let Some(path) = &config.physics.initial_conditions_path
+        && let Err(e) = initial_conditions::read_bodies(path)
+    {
+        eprintln!("error: {e}");
+        std::process::exit(2);
+    }
+}
+
+/// Runs every configuration of the sweep, starting from `base`, and writes the summary.
+///
+/// Progress and failed runs are reported on standard error, so the summary can be written to
+/// standard output.
+fn run_sweep(
+    options: &cli::SweepOptions,
+    base: &config::SimulationConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let spec = sweep::SweepSpec::load(&options.spec_path)?;
+    let runs = spec.runs()?;
+    let configs = runs
+        .iter()
+        .map(|run| run.config(base))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    eprintln!("Running {} configurations", runs.len());
+    let results = sweep::run_all(&configs, spec.duration, options.jobs, |index, result| {
+        let run = &runs[index];
+        match result {
+            Ok(summary) => eprintln!(
+                "Finished run {} of {} (seed {}) in {:.1} s",
+                index + 1,
+                runs.len(),
+                run.seed,
+                summary.wall_time
+            ),
+            Err(e) => eprintln!(
+                "Run {} of {} (seed {}, {:?}) failed: {e}",
+                index + 1,
+                runs.len(),
+                run.seed,
+                run.parameters
+            ),
+        }
+    })?;
+
+    match &options.output_path {
+        Some(path) => {
+            sweep::write_summary_csv(&spec, &runs, &results, BufWriter::new(File::create(path)?))?;
+            eprintln!("Wrote the summary to {}", path.display());
+        }
+        None => sweep::write_summary_csv(&spec, &runs, &results, std::io::stdout().lock())?,
+    }
+
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    if failed > 0 {
+        return Err(format!("{failed} of {} runs failed", runs.len()).into());
+    }
+    Ok(())
+}
 
-    app.add_plugins((
+/// The simulation with no window or renderer.
+fn headless_app(options: &cli::CliOptions) -> App {
+    stardrift::init_logging();
+    let config = effective_config_or_exit(options);
+    check_initial_conditions_or_exit(&config);
+    stardrift::headless_app(config)
+}
+
+#[cfg(feature = "graphics")]
+fn windowed_app(options: &cli::CliOptions) -> App {
+    let mut app = App::new();
+    app.add_plugins(
         DefaultPlugins
             .set(WindowPlugin {
                 primary_window: Some(Window {
@@ -38,39 +107,67 @@ fn main() {
                 }),
                 ..default()
             })
-            .set(TaskPoolPlugin {
-                task_pool_options: TaskPoolOptions {
-                    compute: TaskPoolThreadAssignmentPolicy {
-                        // set the minimum # of compute threads
-                        // to the total number of available threads
-                        min_threads: available_parallelism(),
-                        max_threads: usize::MAX, // unlimited max threads
-                        percent: 1.0,            // this value is irrelevant in this case
-                        on_thread_spawn: None,
-                        on_thread_destroy: None,
-                    },
-                    ..default()
-                },
-            }),
-        plugins::embedded_assets::EmbeddedAssetsPlugin,
-        #[cfg(feature = "diagnostics")]
-        plugins::diagnostics_hud::DiagnosticsHudPlugin,
-        #[cfg(feature = "diagnostics")]
-        EntityCountDiagnosticsPlugin,
-        #[cfg(feature = "diagnostics")]
-        FrameTimeDiagnosticsPlugin::default(),
-        PanOrbitCameraPlugin,
-        PhysicsPlugins::default(),
-        #[cfg(feature = "diagnostics")]
-        plugins::simulation_diagnostics::SimulationDiagnosticsPlugin::default(),
-        #[cfg(feature = "diagnostics")]
-        SystemInformationDiagnosticsPlugin,
-        plugins::simulation::SimulationPlugin,
-    ));
-
-    // Initialize app states after DefaultPlugins (which includes StatesPlugin)
-    app.init_state::<states::AppState>();
-    app.add_sub_state::<states::LoadingState>();
+            .set(stardrift::app::task_pool_plugin()),
+    );
+
+    // Loaded once logging is set up, so problems with the configuration file are logged
+    let config = effective_config_or_exit(options);
+    app.add_plugins(StardriftSimulationPlugin {
+        config,
+        graphics: true,
+    });
+    if options.replay_path.is_some() {
+        app.add_systems(
+            OnEnter(stardrift::states::AppState::Running),
+            start_replay.run_if(run_once),
+        );
+    }
+    app
+}
+
+/// Replays the trajectory given on the command line as soon as the first simulation has loaded.
+#[cfg(feature = "graphics")]
+fn start_replay(
+    mut start_replay_events: EventWriter<stardrift::systems::simulation_actions::StartReplayEvent>,
+) {
+    start_replay_events.write(stardrift::systems::simulation_actions::StartReplayEvent);
+}
+
+fn main() {
+    let options = cli::CliOptions::parse();
+    if options.dump_config {
+        let config = effective_config_or_exit(&options);
+        match toml::to_string_pretty(&config) {
+            Ok(toml) => print!("{toml}"),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(sweep_options) = &options.sweep {
+        let config = effective_config_or_exit(&options);
+        if let Err(e) = run_sweep(sweep_options, &config) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    #[cfg(feature = "graphics")]
+    let mut app = if options.headless {
+        headless_app(&options)
+    } else {
+        windowed_app(&options)
+    };
+    #[cfg(not(feature = "graphics"))]
+    let mut app = headless_app(&options);
+
+    app.insert_resource(resources::StepLimit {
+        steps: options.steps,
+        export: options.export,
+    });
 
     app.run();
 }
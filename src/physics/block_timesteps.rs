@@ -0,0 +1,311 @@
+//! Hierarchical power-of-two block timesteps for the leapfrog integrator.
+//!
+//! Each fixed tick of length `dt` is divided into levels: a body on level `l` takes steps of
+//! `dt / 2^l`. Bodies in dense regions or close encounters sit on deep levels while the slow
+//! outskirts take a single step per tick. Positions of all bodies drift together, but forces are
+//! only evaluated for the bodies whose step ends at the current substep. Every level boundary
+//! coincides with the end of the tick, so all bodies are synchronized again when it finishes.
+
+use super::integrator::GravitySource;
+use super::integrator::IntegratedBody;
+use super::integrator::drift;
+use avian3d::math::Scalar;
+use rayon::iter::IntoParallelRefMutIterator;
+use rayon::iter::ParallelIterator;
+
+/// Deepest level that can be represented in the integer timeline.
+const MAX_LEVEL: u32 = 62;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockTimesteps {
+    /// Shortest step any body may take.
+    pub min_step: Scalar,
+    /// Longest step any body may take.
+    pub max_step: Scalar,
+    /// Dimensionless accuracy parameter of the step criterion.
+    pub accuracy: Scalar,
+    /// Length scale of the step criterion, usually the force softening distance.
+    pub softening: Scalar,
+}
+
+impl BlockTimesteps {
+    /// Step size a body with the given acceleration would like to take.
+    ///
+    /// This is the acceleration-based criterion `sqrt(2 η ε / |a|)` from Aarseth's and
+    /// Springel's codes: a body may move roughly `η ε` before its force must be refreshed.
+    pub fn desired_step(&self, body: &IntegratedBody) -> Scalar {
+        let acceleration = body.acceleration.length();
+
+        if acceleration <= 0.0 {
+            return Scalar::INFINITY;
+        }
+
+        libm::sqrt(2.0 * self.accuracy * self.softening / acceleration)
+    }
+
+    /// Shallowest level usable within a tick of length `dt`.
+    pub fn min_level(&self, dt: Scalar) -> u32 {
+        if self.max_step >= dt {
+            return 0;
+        }
+
+        (libm::ceil(libm::log2(dt / self.max_step)) as u32).min(MAX_LEVEL)
+    }
+
+    /// Deepest level usable within a tick of length `dt`.
+    pub fn max_level(&self, dt: Scalar) -> u32 {
+        if self.min_step >= dt {
+            return self.min_level(dt);
+        }
+
+        (libm::floor(libm::log2(dt / self.min_step)) as u32)
+            .min(MAX_LEVEL)
+            .max(self.min_level(dt))
+    }
+
+    /// Level whose step is the longest one not exceeding the body's desired step.
+    pub fn level_for(&self, body: &IntegratedBody, dt: Scalar) -> u32 {
+        self.level_for_step(self.desired_step(body), dt)
+    }
+
+    /// Level whose step is the longest one not exceeding `desired_step`.
+    pub fn level_for_step(&self, desired_step: Scalar, dt: Scalar) -> u32 {
+        let level = if desired_step >= dt {
+            0
+        } else {
+            libm::ceil(libm::log2(dt / desired_step)) as u32
+        };
+
+        level.clamp(self.min_level(dt), self.max_level(dt))
+    }
+
+    /// Advances all bodies by `dt` with individual kick-drift-kick steps.
+    ///
+    /// As with the shared-step integrators, accelerations must be valid on entry and are valid
+    /// again on exit. Returns the number of bodies that started the tick on each level.
+    pub fn step(
+        &self,
+        bodies: &mut [IntegratedBody],
+        dt: Scalar,
+        source: &mut impl GravitySource,
+    ) -> Vec<usize> {
+        let max_level = self.max_level(dt);
+        let tick_length = 1_u64 << max_level;
+        let unit = dt / tick_length as Scalar;
+
+        let mut levels: Vec<u32> = bodies.iter().map(|body| self.level_for(body, dt)).collect();
+        let mut step_ends: Vec<u64> = levels.iter().map(|&level| tick_length >> level).collect();
+
+        let mut histogram = vec![0; max_level as usize + 1];
+        levels
+            .iter()
+            .for_each(|&level| histogram[level as usize] += 1);
+
+        bodies.iter_mut().zip(&levels).for_each(|(body, &level)| {
+            let step = unit * (tick_length >> level) as Scalar;
+            body.velocity += body.acceleration * (0.5 * step);
+        });
+
+        let mut now = 0;
+        let mut active = Vec::with_capacity(bodies.len());
+
+        while now < tick_length {
+            let next = step_ends.iter().copied().min().unwrap_or(tick_length);
+            drift(bodies, (next - now) as Scalar * unit);
+            now = next;
+
+            active.clear();
+            active.extend((0..bodies.len()).filter(|&index| step_ends[index] == now));
+            update_active_accelerations(source, bodies, &active);
+
+            for &index in &active {
+                let body = &mut bodies[index];
+                let step = unit * (tick_length >> levels[index]) as Scalar;
+                body.velocity += body.acceleration * (0.5 * step);
+
+                if now == tick_length {
+                    continue;
+                }
+
+                // A body may only move to a longer step once the timeline lines up with it
+                let mut level = self.level_for(body, dt).min(max_level);
+                while level < levels[index] && now % (tick_length >> level) != 0 {
+                    level += 1;
+                }
+
+                levels[index] = level;
+                step_ends[index] = now + (tick_length >> level);
+
+                let step = unit * (tick_length >> level) as Scalar;
+                body.velocity += body.acceleration * (0.5 * step);
+            }
+        }
+
+        histogram
+    }
+}
+
+/// Prepares `source` for all bodies' current positions and refreshes the accelerations of the
+/// bodies at `active` indices only.
+fn update_active_accelerations(
+    source: &mut impl GravitySource,
+    bodies: &mut [IntegratedBody],
+    active: &[usize],
+) {
+    if active.is_empty() {
+        return;
+    }
+
+    source.prepare(bodies);
+
+    let mut active_bodies: Vec<IntegratedBody> =
+        active.iter().map(|&index| bodies[index]).collect();
+    let source = &*source;
+    active_bodies
+        .par_iter_mut()
+        .for_each(|body| body.acceleration = source.acceleration(body));
+
+    active
+        .iter()
+        .zip(active_bodies)
+        .for_each(|(&index, body)| bodies[index].acceleration = body.acceleration);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::integrator::update_accelerations;
+    use crate::physics::test_support::G;
+    use crate::physics::test_support::body;
+    use crate::physics::test_support::total_energy;
+    use avian3d::math::Vector;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    /// Exact pairwise gravity that counts how many accelerations it has evaluated.
+    #[derive(Default)]
+    struct CountingGravity {
+        bodies: Vec<IntegratedBody>,
+        evaluations: AtomicUsize,
+    }
+
+    impl GravitySource for CountingGravity {
+        fn prepare(&mut self, bodies: &[IntegratedBody]) {
+            self.bodies = bodies.to_vec();
+        }
+
+        fn gravitational_constant(&self) -> Scalar {
+            G
+        }
+
+        fn acceleration(&self, body: &IntegratedBody) -> Vector {
+            self.evaluations.fetch_add(1, Ordering::Relaxed);
+            self.bodies
+                .iter()
+                .filter(|other| other.id != body.id)
+                .map(|other| {
+                    let direction = other.position - body.position;
+                    let distance = direction.length();
+                    direction * (G * other.mass / (distance * distance * distance))
+                })
+                .sum()
+        }
+
+        fn acceleration_and_jerk(&self, body: &IntegratedBody) -> (Vector, Vector) {
+            (self.acceleration(body), Vector::ZERO)
+        }
+    }
+
+    /// A tight binary orbited by a distant, slow third body.
+    fn hierarchical_triple() -> Vec<IntegratedBody> {
+        let inner_speed = libm::sqrt(G * 2.0 / 0.1) / 2.0;
+        let outer_speed = libm::sqrt(G * 2.0 / 100.0);
+
+        vec![
+            body(
+                0,
+                Vector::new(0.05, 0.0, 0.0),
+                Vector::new(0.0, inner_speed, 0.0),
+                1.0,
+            ),
+            body(
+                1,
+                Vector::new(-0.05, 0.0, 0.0),
+                Vector::new(0.0, -inner_speed, 0.0),
+                1.0,
+            ),
+            body(
+                2,
+                Vector::new(100.0, 0.0, 0.0),
+                Vector::new(0.0, outer_speed, 0.0),
+                1e-6,
+            ),
+        ]
+    }
+
+    fn block_timesteps() -> BlockTimesteps {
+        BlockTimesteps {
+            min_step: 1e-5,
+            max_step: 1.0,
+            accuracy: 0.01,
+            softening: 0.01,
+        }
+    }
+
+    #[test]
+    fn test_levels_follow_acceleration() {
+        let mut bodies = hierarchical_triple();
+        update_accelerations(&mut CountingGravity::default(), &mut bodies);
+        let timesteps = block_timesteps();
+
+        let binary_level = timesteps.level_for(&bodies[0], 0.1);
+        let outer_level = timesteps.level_for(&bodies[2], 0.1);
+
+        assert!(binary_level > outer_level);
+        assert!(binary_level <= timesteps.max_level(0.1));
+    }
+
+    #[test]
+    fn test_level_bounds_respect_min_and_max_step() {
+        let timesteps = BlockTimesteps {
+            min_step: 0.01,
+            max_step: 0.25,
+            ..block_timesteps()
+        };
+
+        // 1.0 / 2^2 = 0.25 is the longest step allowed, 1.0 / 2^6 ≈ 0.0156 the shortest
+        assert_eq!(timesteps.min_level(1.0), 2);
+        assert_eq!(timesteps.max_level(1.0), 6);
+    }
+
+    #[test]
+    fn test_only_active_bodies_are_evaluated() {
+        let mut bodies = hierarchical_triple();
+        let mut gravity = CountingGravity::default();
+        update_accelerations(&mut gravity, &mut bodies);
+        gravity.evaluations.store(0, Ordering::Relaxed);
+
+        let histogram = block_timesteps().step(&mut bodies, 0.1, &mut gravity);
+        let deepest_level = histogram.iter().rposition(|&count| count > 0).unwrap();
+
+        // The binary is evaluated every substep, the outer body only once per tick
+        assert_eq!(histogram.iter().sum::<usize>(), 3);
+        assert!(deepest_level > 0);
+        assert!(gravity.evaluations.load(Ordering::Relaxed) < 3 * (1 << deepest_level));
+    }
+
+    #[test]
+    fn test_block_timesteps_conserve_energy() {
+        let mut bodies = hierarchical_triple();
+        let mut gravity = CountingGravity::default();
+        update_accelerations(&mut gravity, &mut bodies);
+        let initial_energy = total_energy(&bodies);
+
+        (0..100).for_each(|_| {
+            block_timesteps().step(&mut bodies, 0.1, &mut gravity);
+        });
+
+        let error = ((total_energy(&bodies) - initial_energy) / initial_energy).abs();
+        assert!(error < 1e-3, "energy error too large: {error:e}");
+    }
+}
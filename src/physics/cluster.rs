@@ -0,0 +1,210 @@
+//! Structural measures of a star cluster.
+//!
+//! Lagrangian radii are the radii of spheres around the centre that enclose fixed fractions of
+//! the total mass. Following them over time shows core collapse as the inner radii shrink and
+//! evaporation as the outer radii grow.
+
+use super::octree::Octree;
+use super::octree::OctreeBody;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+
+/// Mass fractions enclosed by the reported Lagrangian radii.
+pub const LAGRANGIAN_MASS_FRACTIONS: [Scalar; 5] = [0.1, 0.25, 0.5, 0.75, 0.9];
+
+/// Index of the half-mass radius within [`LAGRANGIAN_MASS_FRACTIONS`].
+pub const HALF_MASS_INDEX: usize = 2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClusterStructure {
+    pub virial_ratio: Scalar,
+    /// Radii enclosing each of [`LAGRANGIAN_MASS_FRACTIONS`] of the mass.
+    pub lagrangian_radii: [Scalar; LAGRANGIAN_MASS_FRACTIONS.len()],
+}
+
+impl ClusterStructure {
+    pub fn half_mass_radius(&self) -> Scalar {
+        self.lagrangian_radii[HALF_MASS_INDEX]
+    }
+}
+
+/// Radii around `center` enclosing each of [`LAGRANGIAN_MASS_FRACTIONS`] of the total mass.
+///
+/// Each radius is the distance of the body at which the enclosed mass first reaches the
+/// fraction, with bodies sorted by distance.
+pub fn lagrangian_radii(
+    bodies: &[OctreeBody],
+    center: Vector,
+) -> [Scalar; LAGRANGIAN_MASS_FRACTIONS.len()] {
+    let mut distances: Vec<(Scalar, Scalar)> = bodies
+        .iter()
+        .map(|body| (body.position.distance(center), body.mass))
+        .collect();
+    distances.sort_unstable_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let total_mass: Scalar = distances.iter().map(|(_, mass)| mass).sum();
+    let mut radii = [0.0; LAGRANGIAN_MASS_FRACTIONS.len()];
+    if total_mass <= 0.0 {
+        return radii;
+    }
+
+    let mut enclosed_mass = 0.0;
+    let outermost = distances.last().map_or(0.0, |(distance, _)| *distance);
+    let mut fractions = LAGRANGIAN_MASS_FRACTIONS.iter().zip(&mut radii).peekable();
+    for (distance, mass) in distances {
+        enclosed_mass += mass;
+        while let Some((_, radius)) =
+            fractions.next_if(|(fraction, _)| enclosed_mass >= **fraction * total_mass)
+        {
+            *radius = distance;
+        }
+    }
+
+    // Rounding can leave the outermost fractions just out of reach
+    fractions.for_each(|(_, radius)| *radius = outermost);
+
+    radii
+}
+
+/// Energy of each body in the barycentric frame: its kinetic energy relative to the centre of
+/// mass plus its potential energy in the field of all other bodies.
+///
+/// Bodies with negative energy are bound to the system. `octree` must have been built from
+/// `bodies`.
+pub fn binding_energies(bodies: &[OctreeBody], octree: &Octree, g: Scalar) -> Vec<Scalar> {
+    let total_mass: Scalar = bodies.iter().map(|body| body.mass).sum();
+    let center_of_mass_velocity = if total_mass > 0.0 {
+        bodies
+            .iter()
+            .map(|body| body.velocity * body.mass)
+            .sum::<Vector>()
+            / total_mass
+    } else {
+        Vector::ZERO
+    };
+
+    bodies
+        .iter()
+        .map(|body| {
+            0.5 * body.mass * body.velocity.distance_squared(center_of_mass_velocity)
+                + octree.calculate_potential(body, octree.root.as_ref(), g)
+        })
+        .collect()
+}
+
+/// Mass-weighted mean position of `bodies`.
+pub fn center_of_mass(bodies: &[OctreeBody]) -> Option<Vector> {
+    let total_mass: Scalar = bodies.iter().map(|body| body.mass).sum();
+    (total_mass > 0.0).then(|| {
+        bodies
+            .iter()
+            .map(|body| body.position * body.mass)
+            .sum::<Vector>()
+            / total_mass
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::BodyId;
+
+    fn shell(count: u32, radius: Scalar) -> impl Iterator<Item = OctreeBody> {
+        (0..count).map(move |index| OctreeBody {
+            id: BodyId(u64::from(index)),
+            position: Vector::new(radius, 0.0, 0.0),
+            velocity: Vector::ZERO,
+            mass: 1.0,
+        })
+    }
+
+    #[test]
+    fn test_lagrangian_radii_of_equal_masses() {
+        // Ten unit masses at radii 1 to 10
+        let bodies: Vec<OctreeBody> = (1..=10)
+            .flat_map(|radius| shell(1, radius as Scalar))
+            .collect();
+
+        let radii = lagrangian_radii(&bodies, Vector::ZERO);
+
+        assert_eq!(radii, [1.0, 3.0, 5.0, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn test_lagrangian_radii_are_measured_from_center() {
+        let center = Vector::new(100.0, -50.0, 20.0);
+        let bodies: Vec<OctreeBody> = shell(4, 2.0)
+            .map(|body| OctreeBody {
+                position: body.position + center,
+                ..body
+            })
+            .collect();
+
+        let radii = lagrangian_radii(&bodies, center);
+
+        assert!(radii.iter().all(|radius| (radius - 2.0).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_heavy_body_sets_inner_radii() {
+        let mut bodies: Vec<OctreeBody> = shell(9, 10.0).collect();
+        bodies.push(OctreeBody {
+            id: BodyId(9),
+            position: Vector::new(0.0, 1.0, 0.0),
+            velocity: Vector::ZERO,
+            mass: 91.0,
+        });
+
+        let structure = ClusterStructure {
+            virial_ratio: 0.0,
+            lagrangian_radii: lagrangian_radii(&bodies, Vector::ZERO),
+        };
+
+        assert_eq!(structure.half_mass_radius(), 1.0);
+        assert_eq!(structure.lagrangian_radii[4], 1.0);
+        assert!(
+            lagrangian_radii(&[], Vector::ZERO)
+                .iter()
+                .all(|r| *r == 0.0)
+        );
+    }
+
+    #[test]
+    fn test_binding_energies_separate_escapers() {
+        let g = 1.0;
+        let mut bodies: Vec<OctreeBody> = shell(1, 0.0)
+            .map(|body| OctreeBody {
+                mass: 100.0,
+                ..body
+            })
+            .collect();
+        // Circular speed at radius 10 is sqrt(10) and escape speed is sqrt(20)
+        bodies.push(OctreeBody {
+            id: BodyId(1),
+            position: Vector::new(10.0, 0.0, 0.0),
+            velocity: Vector::new(0.0, 3.0, 0.0),
+            mass: 1e-6,
+        });
+        bodies.push(OctreeBody {
+            id: BodyId(2),
+            position: Vector::new(-10.0, 0.0, 0.0),
+            velocity: Vector::new(0.0, 5.0, 0.0),
+            mass: 1e-6,
+        });
+        let mut octree = Octree::new(0.0, 1e-3, 1e12);
+        octree.build(bodies.iter().copied());
+
+        let energies = binding_energies(&bodies, &octree, g);
+
+        assert!(energies[0] < 0.0);
+        assert!(energies[1] < 0.0);
+        assert!(energies[2] > 0.0);
+    }
+
+    #[test]
+    fn test_center_of_mass() {
+        let bodies: Vec<OctreeBody> = shell(1, 3.0).chain(shell(2, 0.0)).collect();
+        assert_eq!(center_of_mass(&bodies), Some(Vector::new(1.0, 0.0, 0.0)));
+        assert_eq!(center_of_mass(&[]), None);
+    }
+}
@@ -0,0 +1,476 @@
+//! Outcomes of collisions between bodies, independent of how they are stored.
+//!
+//! Bodies are treated as uniform spheres, so a body's moment of inertia is `2/5 m r²` and its
+//! volume follows from its radius.
+
+use avian3d::math::Quaternion;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use rand::Rng;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Gap left between neighbouring fragments when a body breaks up, as a fraction of the distance
+/// at which they would touch.
+const FRAGMENT_CLEARANCE: Scalar = 0.1;
+
+/// How the speeds of fragments relative to their parent's centre of mass are distributed.
+///
+/// Directions are isotropic in every case, and speeds are scaled so their mean is the
+/// requested ejecta speed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EjectaDistribution {
+    /// Every fragment leaves at the same speed.
+    Fixed,
+    /// Speeds are uniform between zero and twice the mean.
+    #[default]
+    Uniform,
+    /// Speeds are exponentially distributed, so a few fragments leave much faster than most.
+    Exponential,
+}
+
+impl EjectaDistribution {
+    fn sample(self, mean_speed: Scalar, rng: &mut impl Rng) -> Scalar {
+        match self {
+            EjectaDistribution::Fixed => mean_speed,
+            EjectaDistribution::Uniform => rng.random_range(0.0..=2.0 * mean_speed),
+            EjectaDistribution::Exponential => -mean_speed * (1.0 - rng.random::<Scalar>()).ln(),
+        }
+    }
+}
+
+/// The state of a colliding body that a collision outcome depends on.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CollidingBody {
+    pub position: Vector,
+    pub velocity: Vector,
+    pub angular_velocity: Vector,
+    pub mass: Scalar,
+    pub radius: Scalar,
+    pub temperature: Scalar,
+}
+
+impl CollidingBody {
+    pub fn momentum(&self) -> Vector {
+        self.velocity * self.mass
+    }
+
+    pub fn density(&self) -> Scalar {
+        self.mass / (4.0 / 3.0 * core::f64::consts::PI * self.radius.powi(3))
+    }
+
+    pub fn moment_of_inertia(&self) -> Scalar {
+        0.4 * self.mass * self.radius * self.radius
+    }
+
+    /// Spin and orbital angular momentum about `origin`.
+    pub fn angular_momentum_about(&self, origin: Vector) -> Vector {
+        (self.position - origin).cross(self.momentum())
+            + self.angular_velocity * self.moment_of_inertia()
+    }
+}
+
+/// Combines two bodies into one, as in a perfectly inelastic collision.
+///
+/// Mass, momentum and volume are conserved, and the temperature is the mass-weighted mean. With
+/// `conserve_spin`, the angular momentum of the pair about their centre of mass becomes the spin
+/// of the merged body; otherwise the merged body does not spin.
+pub fn merge(a: &CollidingBody, b: &CollidingBody, conserve_spin: bool) -> CollidingBody {
+    let mass = a.mass + b.mass;
+    if mass <= 0.0 {
+        return *a;
+    }
+
+    let position = (a.position * a.mass + b.position * b.mass) / mass;
+    let mut merged = CollidingBody {
+        position,
+        velocity: (a.momentum() + b.momentum()) / mass,
+        angular_velocity: Vector::ZERO,
+        mass,
+        radius: (a.radius.powi(3) + b.radius.powi(3)).cbrt(),
+        temperature: (a.temperature * a.mass + b.temperature * b.mass) / mass,
+    };
+
+    if conserve_spin && merged.moment_of_inertia() > 0.0 {
+        let angular_momentum =
+            a.angular_momentum_about(position) + b.angular_momentum_about(position);
+        merged.angular_velocity = angular_momentum / merged.moment_of_inertia();
+    }
+
+    merged
+}
+
+/// Kinetic energy of the impact per unit mass of each body, as `(a, b)`.
+///
+/// The impact energy is that of the relative motion, `μ v² / 2` with the reduced mass `μ`, so
+/// the smaller body always takes the larger share.
+pub fn impact_specific_energies(a: &CollidingBody, b: &CollidingBody) -> (Scalar, Scalar) {
+    let mass = a.mass + b.mass;
+    if a.mass <= 0.0 || b.mass <= 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let reduced_mass = a.mass * b.mass / mass;
+    let energy = 0.5 * reduced_mass * (a.velocity - b.velocity).length_squared();
+    (energy / a.mass, energy / b.mass)
+}
+
+/// Shatters a body into `count` fragments of random mass.
+///
+/// The fragments have the parent's density and start spread evenly over a shell around its
+/// centre, just far enough out that no two of them touch, each ejected straight away from the
+/// centre. Their positions and velocities are shifted so that the centre of mass and the momentum
+/// are exactly those of the parent, which leaves the spread of ejecta speeds as set by
+/// `distribution` around `ejecta_speed` only approximately.
+pub fn fragment(
+    body: &CollidingBody,
+    count: usize,
+    ejecta_speed: Scalar,
+    distribution: EjectaDistribution,
+    rng: &mut impl Rng,
+) -> Vec<CollidingBody> {
+    if count < 2 || body.mass <= 0.0 {
+        return vec![*body];
+    }
+
+    let weights: Vec<Scalar> = (0..count).map(|_| rng.random_range(0.5..=1.5)).collect();
+    let total_weight: Scalar = weights.iter().sum();
+    let radii: Vec<Scalar> = weights
+        .iter()
+        .map(|weight| body.radius * (weight / total_weight).cbrt())
+        .collect();
+    let directions = spread_directions(count, rng);
+    let shell_radius = packed_shell_radius(&directions, &radii);
+
+    let mut fragments: Vec<CollidingBody> = weights
+        .iter()
+        .zip(&radii)
+        .zip(&directions)
+        .map(|((weight, &radius), &direction)| CollidingBody {
+            position: body.position + direction * shell_radius,
+            velocity: body.velocity + direction * distribution.sample(ejecta_speed, rng),
+            angular_velocity: body.angular_velocity,
+            mass: body.mass * weight / total_weight,
+            radius,
+            temperature: body.temperature,
+        })
+        .collect();
+
+    recentre(&mut fragments, body);
+    fragments
+}
+
+/// Fluid Roche limit of `secondary` around `primary`, `2.44 R (ρ / ρ')^(1/3)` with the primary's
+/// radius `R` and density `ρ` and the secondary's density `ρ'`.
+///
+/// Within this distance of the primary's centre, the tidal pull across a self-gravitating
+/// secondary exceeds its own gravity and tears it apart.
+pub fn roche_radius(primary: &CollidingBody, secondary: &CollidingBody) -> Scalar {
+    let secondary_density = secondary.density();
+    if secondary_density <= 0.0 {
+        return 0.0;
+    }
+    2.44 * primary.radius * (primary.density() / secondary_density).cbrt()
+}
+
+/// Tears a body into a string of `count` fragments of random mass along its tidal axis, the
+/// line through the centre of `primary_position`.
+///
+/// The fragments have the parent's density and lie in a row, [`FRAGMENT_CLEARANCE`] further apart
+/// than touching and centred where the parent was.
+/// Each moves with the parent's velocity plus its share of the parent's spin, shifted so the
+/// centre of mass and the momentum are exactly those of the parent.
+pub fn tidal_stream(
+    body: &CollidingBody,
+    primary_position: Vector,
+    count: usize,
+    rng: &mut impl Rng,
+) -> Vec<CollidingBody> {
+    if count < 2 || body.mass <= 0.0 {
+        return vec![*body];
+    }
+
+    let axis = (body.position - primary_position).normalize_or(Vector::X);
+    let weights: Vec<Scalar> = (0..count).map(|_| rng.random_range(0.5..=1.5)).collect();
+    let total_weight: Scalar = weights.iter().sum();
+    let radii: Vec<Scalar> = weights
+        .iter()
+        .map(|weight| body.radius * (weight / total_weight).cbrt())
+        .collect();
+
+    let spacing = 1.0 + FRAGMENT_CLEARANCE;
+    let mut offset = -radii.iter().sum::<Scalar>() * spacing;
+    let mut fragments: Vec<CollidingBody> = weights
+        .iter()
+        .zip(&radii)
+        .map(|(weight, &radius)| {
+            offset += radius * spacing;
+            let position = body.position + axis * offset;
+            offset += radius * spacing;
+            CollidingBody {
+                position,
+                velocity: body.velocity + body.angular_velocity.cross(position - body.position),
+                angular_velocity: body.angular_velocity,
+                mass: body.mass * weight / total_weight,
+                radius,
+                temperature: body.temperature,
+            }
+        })
+        .collect();
+
+    recentre(&mut fragments, body);
+    fragments
+}
+
+/// Shifts fragments so their total mass, centre of mass and momentum match `body`.
+fn recentre(fragments: &mut [CollidingBody], body: &CollidingBody) {
+    let mass: Scalar = fragments.iter().map(|fragment| fragment.mass).sum();
+    let position_offset = fragments
+        .iter()
+        .map(|fragment| fragment.position * fragment.mass)
+        .sum::<Vector>()
+        / mass
+        - body.position;
+    let velocity_offset = fragments
+        .iter()
+        .map(CollidingBody::momentum)
+        .sum::<Vector>()
+        / mass
+        - body.velocity;
+    for fragment in fragments {
+        fragment.position -= position_offset;
+        fragment.velocity -= velocity_offset;
+        fragment.mass *= body.mass / mass;
+    }
+}
+
+/// `count` directions spread evenly over the sphere along a golden spiral, turned by a random
+/// rotation.
+fn spread_directions(count: usize, rng: &mut impl Rng) -> Vec<Vector> {
+    let rotation = Quaternion::from_axis_angle(
+        random_unit_vector(rng),
+        rng.random_range(0.0..core::f64::consts::TAU),
+    );
+    let golden_angle = core::f64::consts::PI * (3.0 - Scalar::sqrt(5.0));
+
+    (0..count)
+        .map(|index| {
+            let z = 1.0 - (2 * index + 1) as Scalar / count as Scalar;
+            let planar = (1.0 - z * z).max(0.0).sqrt();
+            let azimuth = golden_angle * index as Scalar;
+            rotation * Vector::new(planar * azimuth.cos(), planar * azimuth.sin(), z)
+        })
+        .collect()
+}
+
+/// Smallest radius of a shell on which spheres of the given radii, centred along the given
+/// directions, are [`FRAGMENT_CLEARANCE`] further apart than touching.
+fn packed_shell_radius(directions: &[Vector], radii: &[Scalar]) -> Scalar {
+    let mut shell_radius: Scalar = 0.0;
+    for (index, (direction, radius)) in directions.iter().zip(radii).enumerate() {
+        for (other_direction, other_radius) in directions.iter().zip(radii).skip(index + 1) {
+            let separation = (radius + other_radius) * (1.0 + FRAGMENT_CLEARANCE);
+            shell_radius = shell_radius.max(separation / direction.distance(*other_direction));
+        }
+    }
+    shell_radius
+}
+
+fn random_unit_vector(rng: &mut impl Rng) -> Vector {
+    let z: Scalar = rng.random_range(-1.0..=1.0);
+    let azimuth: Scalar = rng.random_range(0.0..core::f64::consts::TAU);
+    let planar = (1.0 - z * z).max(0.0).sqrt();
+    Vector::new(planar * azimuth.cos(), planar * azimuth.sin(), z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn pair() -> (CollidingBody, CollidingBody) {
+        (
+            CollidingBody {
+                position: Vector::new(-1.0, 0.0, 0.0),
+                velocity: Vector::new(0.5, 1.0, 0.0),
+                angular_velocity: Vector::new(0.0, 0.0, 0.2),
+                mass: 3.0,
+                radius: 1.0,
+                temperature: 3000.0,
+            },
+            CollidingBody {
+                position: Vector::new(1.0, 0.5, 0.0),
+                velocity: Vector::new(-1.0, -2.0, 0.5),
+                angular_velocity: Vector::new(0.1, 0.0, 0.0),
+                mass: 1.0,
+                radius: 0.5,
+                temperature: 9000.0,
+            },
+        )
+    }
+
+    #[test]
+    fn test_merge_conserves_mass_momentum_and_volume() {
+        let (a, b) = pair();
+
+        let merged = merge(&a, &b, false);
+
+        assert_eq!(merged.mass, 4.0);
+        assert!(merged.momentum().distance(a.momentum() + b.momentum()) < 1e-12);
+        assert!((merged.radius.powi(3) - 1.125).abs() < 1e-12);
+        assert!(merged.position.distance(Vector::new(-0.5, 0.125, 0.0)) < 1e-12);
+        assert_eq!(merged.temperature, 4500.0);
+        assert_eq!(merged.angular_velocity, Vector::ZERO);
+    }
+
+    #[test]
+    fn test_merge_conserves_angular_momentum_as_spin() {
+        let (a, b) = pair();
+        let origin = Vector::new(3.0, -2.0, 1.0);
+
+        let merged = merge(&a, &b, true);
+
+        let before = a.angular_momentum_about(origin) + b.angular_momentum_about(origin);
+        assert!(merged.angular_momentum_about(origin).distance(before) < 1e-12);
+        assert!(merged.angular_velocity.length() > 0.0);
+    }
+
+    #[test]
+    fn test_smaller_body_takes_larger_specific_impact_energy() {
+        let (a, b) = pair();
+
+        let (a_energy, b_energy) = impact_specific_energies(&a, &b);
+
+        let reduced_mass = 0.75;
+        let energy = 0.5 * reduced_mass * (a.velocity - b.velocity).length_squared();
+        assert!((a_energy * a.mass - energy).abs() < 1e-12);
+        assert!((b_energy * b.mass - energy).abs() < 1e-12);
+        assert!(b_energy > a_energy);
+    }
+
+    #[test]
+    fn test_fragments_conserve_mass_momentum_and_centre_of_mass() {
+        let (body, _) = pair();
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+
+        for distribution in [
+            EjectaDistribution::Fixed,
+            EjectaDistribution::Uniform,
+            EjectaDistribution::Exponential,
+        ] {
+            let fragments = fragment(&body, 6, 2.0, distribution, &mut rng);
+
+            assert_eq!(fragments.len(), 6);
+            let mass: Scalar = fragments.iter().map(|fragment| fragment.mass).sum();
+            let momentum: Vector = fragments.iter().map(CollidingBody::momentum).sum();
+            let first_moment: Vector = fragments
+                .iter()
+                .map(|fragment| fragment.position * fragment.mass)
+                .sum();
+            let volume: Scalar = fragments
+                .iter()
+                .map(|fragment| fragment.radius.powi(3))
+                .sum();
+            assert!((mass - body.mass).abs() < 1e-12);
+            assert!(momentum.distance(body.momentum()) < 1e-12);
+            assert!((first_moment / mass).distance(body.position) < 1e-12);
+            assert!((volume - body.radius.powi(3)).abs() < 1e-12);
+            assert!(
+                fragments
+                    .iter()
+                    .all(|fragment| fragment.velocity.distance(body.velocity) > 0.0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_fragments_start_clear_of_each_other() {
+        let (body, _) = pair();
+        let mut rng = ChaCha8Rng::seed_from_u64(11);
+
+        for count in [2, 3, 8, 20] {
+            let fragments = fragment(&body, count, 1.0, EjectaDistribution::Uniform, &mut rng);
+
+            for (index, a) in fragments.iter().enumerate() {
+                for b in &fragments[index + 1..] {
+                    let gap = a.position.distance(b.position) - a.radius - b.radius;
+                    assert!(gap > 0.0, "fragments of {count} overlap by {}", -gap);
+                    // Moving straight out from a shared centre, no two fragments approach
+                    let approach = (b.position - a.position).dot(b.velocity - a.velocity);
+                    assert!(approach >= -1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_roche_radius_scales_with_density_ratio() {
+        let primary = CollidingBody {
+            mass: 8.0,
+            radius: 2.0,
+            ..Default::default()
+        };
+        let secondary = CollidingBody {
+            mass: 1.0,
+            radius: 1.0,
+            ..Default::default()
+        };
+
+        assert!((roche_radius(&primary, &secondary) - 4.88).abs() < 1e-12);
+        assert!((roche_radius(&primary, &primary) - 4.88).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_tidal_stream_lies_along_axis_and_conserves_momentum() {
+        let (mut body, _) = pair();
+        body.angular_velocity = Vector::ZERO;
+        let primary_position = Vector::new(-1.0, 10.0, 0.0);
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+
+        let fragments = tidal_stream(&body, primary_position, 5, &mut rng);
+
+        assert_eq!(fragments.len(), 5);
+        let mass: Scalar = fragments.iter().map(|fragment| fragment.mass).sum();
+        let momentum: Vector = fragments.iter().map(CollidingBody::momentum).sum();
+        let first_moment: Vector = fragments
+            .iter()
+            .map(|fragment| fragment.position * fragment.mass)
+            .sum();
+        assert!((mass - body.mass).abs() < 1e-12);
+        assert!(momentum.distance(body.momentum()) < 1e-12);
+        assert!((first_moment / mass).distance(body.position) < 1e-12);
+        for fragment in &fragments {
+            let offset = fragment.position - body.position;
+            assert!(offset.cross(Vector::Y).length() < 1e-12);
+        }
+        for pair in fragments.windows(2) {
+            let distance = pair[0].position.distance(pair[1].position);
+            let touching = pair[0].radius + pair[1].radius;
+            assert!((distance - touching * (1.0 + FRAGMENT_CLEARANCE)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_fragments_are_reproducible_from_the_seed() {
+        let (body, _) = pair();
+
+        let first = fragment(
+            &body,
+            4,
+            1.0,
+            EjectaDistribution::Uniform,
+            &mut ChaCha8Rng::seed_from_u64(42),
+        );
+        let second = fragment(
+            &body,
+            4,
+            1.0,
+            EjectaDistribution::Uniform,
+            &mut ChaCha8Rng::seed_from_u64(42),
+        );
+
+        assert_eq!(first, second);
+    }
+}
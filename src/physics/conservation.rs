@@ -0,0 +1,308 @@
+//! Global quantities that gravity alone conserves.
+//!
+//! Integration error, force softening and the Barnes-Hut approximation all make these drift
+//! slowly, and inelastic collisions remove energy outright. Comparing against the values at the
+//! start of a run shows how far the simulation has strayed.
+
+use super::octree::Octree;
+use super::octree::OctreeBody;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use rayon::iter::ParallelIterator;
+use rayon::slice::ParallelSlice;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Bodies measured together on one thread.
+const MEASURE_CHUNK_SIZE: usize = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConservedQuantities {
+    pub kinetic_energy: Scalar,
+    pub potential_energy: Scalar,
+    pub linear_momentum: Vector,
+    /// Angular momentum about the origin.
+    pub angular_momentum: Vector,
+    /// Sum of the momentum magnitudes, against which drift in the total is measured.
+    pub momentum_scale: Scalar,
+    /// Sum of the angular momentum magnitudes, against which drift in the total is measured.
+    pub angular_momentum_scale: Scalar,
+    pub total_mass: Scalar,
+}
+
+impl ConservedQuantities {
+    /// Measures `bodies`, taking the potential energy from `octree`, which must have been built
+    /// from the same bodies.
+    pub fn measure(bodies: &[OctreeBody], octree: &Octree, g: Scalar) -> Self {
+        // Chunks of a fixed size are summed in a fixed order, so the result does not depend on
+        // the number of threads
+        bodies
+            .par_chunks(MEASURE_CHUNK_SIZE)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .map(|body| {
+                        // Each pair's potential energy is shared between its two bodies
+                        let potential_energy =
+                            octree.calculate_potential(body, octree.root.as_ref(), g);
+                        Self::of_body(body, 0.5 * potential_energy)
+                    })
+                    .fold(Self::default(), Self::combine)
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .fold(Self::default(), Self::combine)
+    }
+
+    /// The contribution of a single body with the given share of the potential energy.
+    pub fn of_body(body: &OctreeBody, potential_energy: Scalar) -> Self {
+        let momentum = body.velocity * body.mass;
+        let angular_momentum = body.position.cross(momentum);
+        Self {
+            kinetic_energy: 0.5 * body.mass * body.velocity.length_squared(),
+            potential_energy,
+            linear_momentum: momentum,
+            angular_momentum,
+            momentum_scale: momentum.length(),
+            angular_momentum_scale: angular_momentum.length(),
+            total_mass: body.mass,
+        }
+    }
+
+    /// These quantities less those carried away by `removed` bodies.
+    ///
+    /// The scales are kept, so drift stays relative to the system as it was at the start.
+    pub fn without(&self, removed: &Self) -> Self {
+        Self {
+            kinetic_energy: self.kinetic_energy - removed.kinetic_energy,
+            potential_energy: self.potential_energy - removed.potential_energy,
+            linear_momentum: self.linear_momentum - removed.linear_momentum,
+            angular_momentum: self.angular_momentum - removed.angular_momentum,
+            total_mass: self.total_mass - removed.total_mass,
+            ..*self
+        }
+    }
+
+    pub fn total_energy(&self) -> Scalar {
+        self.kinetic_energy + self.potential_energy
+    }
+
+    /// The virial ratio `2K/|W|`, which is one for a system in virial equilibrium.
+    ///
+    /// Only the kinetic energy of motion relative to the centre of mass counts towards `K`.
+    pub fn virial_ratio(&self) -> Scalar {
+        if self.potential_energy == 0.0 || self.total_mass <= 0.0 {
+            return 0.0;
+        }
+
+        let internal_kinetic_energy =
+            self.kinetic_energy - 0.5 * self.linear_momentum.length_squared() / self.total_mass;
+        2.0 * internal_kinetic_energy / self.potential_energy.abs()
+    }
+
+    /// Relative drift of every quantity since `initial`.
+    ///
+    /// Energy drift is relative to the initial total energy. The totals of the momenta are
+    /// often close to zero, so their drift is relative to the sum of magnitudes instead.
+    pub fn drift_from(&self, initial: &Self) -> ConservationDrift {
+        let relative = |change: Scalar, scale: Scalar| {
+            if scale > 0.0 { change / scale } else { 0.0 }
+        };
+
+        ConservationDrift {
+            energy: relative(
+                (self.total_energy() - initial.total_energy()).abs(),
+                initial.total_energy().abs(),
+            ),
+            linear_momentum: relative(
+                self.linear_momentum.distance(initial.linear_momentum),
+                initial.momentum_scale,
+            ),
+            angular_momentum: relative(
+                self.angular_momentum.distance(initial.angular_momentum),
+                initial.angular_momentum_scale,
+            ),
+        }
+    }
+
+    pub fn combine(self, other: Self) -> Self {
+        Self {
+            kinetic_energy: self.kinetic_energy + other.kinetic_energy,
+            potential_energy: self.potential_energy + other.potential_energy,
+            linear_momentum: self.linear_momentum + other.linear_momentum,
+            angular_momentum: self.angular_momentum + other.angular_momentum,
+            momentum_scale: self.momentum_scale + other.momentum_scale,
+            angular_momentum_scale: self.angular_momentum_scale + other.angular_momentum_scale,
+            total_mass: self.total_mass + other.total_mass,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConservationDrift {
+    pub energy: Scalar,
+    pub linear_momentum: Scalar,
+    pub angular_momentum: Scalar,
+}
+
+impl ConservationDrift {
+    /// The largest of the individual drifts.
+    pub fn max(&self) -> Scalar {
+        self.energy
+            .max(self.linear_momentum)
+            .max(self.angular_momentum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::test_support::octree_body;
+
+    fn measure(bodies: &[OctreeBody]) -> ConservedQuantities {
+        let mut octree = Octree::new(0.0, 1e-6, 1e12);
+        octree.build(bodies.iter().copied());
+        ConservedQuantities::measure(bodies, &octree, 2.0)
+    }
+
+    #[test]
+    fn test_measures_pair_exactly() {
+        let bodies = [
+            octree_body(
+                0,
+                Vector::new(1.0, 0.0, 0.0),
+                Vector::new(0.0, 1.0, 0.0),
+                3.0,
+            ),
+            octree_body(
+                1,
+                Vector::new(-1.0, 0.0, 0.0),
+                Vector::new(0.0, -3.0, 0.0),
+                1.0,
+            ),
+        ];
+
+        let quantities = measure(&bodies);
+
+        assert!((quantities.kinetic_energy - 6.0).abs() < 1e-12);
+        assert!((quantities.potential_energy + 3.0).abs() < 1e-12);
+        assert!(quantities.linear_momentum.length() < 1e-12);
+        assert!(
+            quantities
+                .angular_momentum
+                .distance(Vector::new(0.0, 0.0, 6.0))
+                < 1e-12
+        );
+        assert!((quantities.momentum_scale - 6.0).abs() < 1e-12);
+        assert!((quantities.virial_ratio() - 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_virial_ratio_ignores_bulk_motion() {
+        let drift = Vector::new(5.0, 0.0, 0.0);
+        let bodies = [
+            octree_body(
+                0,
+                Vector::new(1.0, 0.0, 0.0),
+                Vector::new(0.0, 1.0, 0.0),
+                3.0,
+            ),
+            octree_body(
+                1,
+                Vector::new(-1.0, 0.0, 0.0),
+                Vector::new(0.0, -3.0, 0.0),
+                1.0,
+            ),
+        ];
+        let moving_bodies = bodies.map(|body| OctreeBody {
+            velocity: body.velocity + drift,
+            ..body
+        });
+
+        let ratio = measure(&bodies).virial_ratio();
+        let moving_ratio = measure(&moving_bodies).virial_ratio();
+
+        assert!((ratio - moving_ratio).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_potential_is_held_inside_min_distance() {
+        let bodies = [
+            octree_body(0, Vector::ZERO, Vector::ZERO, 1.0),
+            octree_body(1, Vector::new(0.1, 0.0, 0.0), Vector::ZERO, 1.0),
+        ];
+        let mut octree = Octree::new(0.0, 0.5, 1e12);
+        octree.build(bodies.iter().copied());
+
+        let quantities = ConservedQuantities::measure(&bodies, &octree, 1.0);
+
+        assert!((quantities.potential_energy + 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_without_removed_body_matches_remaining_bodies() {
+        let bodies = [
+            octree_body(
+                0,
+                Vector::new(1.0, 0.0, 0.0),
+                Vector::new(0.0, 1.0, 0.0),
+                3.0,
+            ),
+            octree_body(
+                1,
+                Vector::new(-1.0, 0.0, 0.0),
+                Vector::new(0.0, -3.0, 0.0),
+                1.0,
+            ),
+            octree_body(
+                2,
+                Vector::new(0.0, 4.0, 1.0),
+                Vector::new(0.5, 0.0, 2.0),
+                2.0,
+            ),
+        ];
+        let mut octree = Octree::new(0.0, 1e-6, 1e12);
+        octree.build(bodies.iter().copied());
+        let removed_potential = octree.calculate_potential(&bodies[2], octree.root.as_ref(), 2.0);
+
+        let remaining =
+            measure(&bodies).without(&ConservedQuantities::of_body(&bodies[2], removed_potential));
+        let expected = measure(&bodies[..2]);
+
+        assert!((remaining.total_energy() - expected.total_energy()).abs() < 1e-12);
+        assert!(remaining.linear_momentum.distance(expected.linear_momentum) < 1e-12);
+        assert!(
+            remaining
+                .angular_momentum
+                .distance(expected.angular_momentum)
+                < 1e-12
+        );
+        assert!((remaining.total_mass - expected.total_mass).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_drift_is_relative_to_initial_values() {
+        let initial = ConservedQuantities {
+            kinetic_energy: 1.0,
+            potential_energy: -3.0,
+            linear_momentum: Vector::ZERO,
+            angular_momentum: Vector::new(0.0, 0.0, 4.0),
+            momentum_scale: 10.0,
+            angular_momentum_scale: 8.0,
+            total_mass: 1.0,
+        };
+        let current = ConservedQuantities {
+            kinetic_energy: 1.5,
+            linear_momentum: Vector::new(0.5, 0.0, 0.0),
+            angular_momentum: Vector::new(0.0, 0.0, 3.0),
+            ..initial
+        };
+
+        let drift = current.drift_from(&initial);
+
+        assert!((drift.energy - 0.25).abs() < 1e-12);
+        assert!((drift.linear_momentum - 0.05).abs() < 1e-12);
+        assert!((drift.angular_momentum - 0.125).abs() < 1e-12);
+        assert_eq!(drift.max(), drift.energy);
+    }
+}
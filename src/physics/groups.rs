@@ -0,0 +1,206 @@
+//! Friends-of-friends group finding.
+//!
+//! Two bodies closer than the linking length are friends, and a group is everything reachable
+//! through a chain of friends. With a linking length well below the mean separation this picks
+//! out the clumps that formed in a collapse without assuming anything about their shape.
+
+use super::BodyId;
+use super::octree::Octree;
+use super::octree::OctreeBody;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Group {
+    pub member_count: usize,
+    pub mass: Scalar,
+    pub center_of_mass: Vector,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Groups {
+    /// Index into `groups` of each body's group, in the order the bodies were given.
+    pub assignments: Vec<usize>,
+    /// Groups ordered by decreasing mass, so the most massive group has index zero.
+    pub groups: Vec<Group>,
+}
+
+impl Groups {
+    /// Groups with at least `min_members` members.
+    pub fn with_min_members(&self, min_members: usize) -> impl Iterator<Item = &Group> {
+        self.groups
+            .iter()
+            .filter(move |group| group.member_count >= min_members)
+    }
+}
+
+/// Links every pair of bodies closer than `linking_length` and returns the resulting groups.
+///
+/// `octree` must have been built from `bodies`; it supplies the candidate neighbours of each
+/// body, so the cost stays close to `O(n log n)` for linking lengths well below the mean
+/// separation.
+pub fn friends_of_friends(
+    bodies: &[OctreeBody],
+    octree: &Octree,
+    linking_length: Scalar,
+) -> Groups {
+    let indices: HashMap<BodyId, usize> = bodies
+        .iter()
+        .enumerate()
+        .map(|(index, body)| (body.id, index))
+        .collect();
+
+    let mut parents: Vec<usize> = (0..bodies.len()).collect();
+    bodies.iter().enumerate().for_each(|(index, body)| {
+        octree.for_each_body_within(body.position, linking_length, |neighbour| {
+            if let Some(&neighbour_index) = indices.get(&neighbour.id) {
+                union(&mut parents, index, neighbour_index);
+            }
+        });
+    });
+
+    let mut root_groups: HashMap<usize, usize> = HashMap::new();
+    let mut groups: Vec<Group> = Vec::new();
+    let mut weighted_positions: Vec<Vector> = Vec::new();
+    let assignments: Vec<usize> = (0..bodies.len())
+        .map(|index| {
+            let root = find(&mut parents, index);
+            let group = *root_groups.entry(root).or_insert_with(|| {
+                groups.push(Group::default());
+                weighted_positions.push(Vector::ZERO);
+                groups.len() - 1
+            });
+
+            let body = &bodies[index];
+            groups[group].member_count += 1;
+            groups[group].mass += body.mass;
+            weighted_positions[group] += body.position * body.mass;
+            group
+        })
+        .collect();
+
+    groups
+        .iter_mut()
+        .zip(&weighted_positions)
+        .for_each(|(group, weighted_position)| {
+            group.center_of_mass = if group.mass > 0.0 {
+                *weighted_position / group.mass
+            } else {
+                Vector::ZERO
+            };
+        });
+
+    // Order groups by mass, keeping the order of first appearance among equals
+    let mut order: Vec<usize> = (0..groups.len()).collect();
+    order.sort_by(|&a, &b| groups[b].mass.total_cmp(&groups[a].mass));
+    let mut ranks = vec![0; groups.len()];
+    order
+        .iter()
+        .enumerate()
+        .for_each(|(rank, &group)| ranks[group] = rank);
+
+    Groups {
+        assignments: assignments.into_iter().map(|group| ranks[group]).collect(),
+        groups: order.into_iter().map(|group| groups[group]).collect(),
+    }
+}
+
+fn find(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let a = find(parents, a);
+    let b = find(parents, b);
+    if a != b {
+        parents[a.max(b)] = a.min(b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::test_support::octree_body;
+
+    fn find_groups(bodies: &[OctreeBody], linking_length: Scalar) -> Groups {
+        let mut octree = Octree::new(0.5, 1.0, 1e4).with_leaf_threshold(1);
+        octree.build(bodies.iter().copied());
+        friends_of_friends(bodies, &octree, linking_length)
+    }
+
+    #[test]
+    fn test_chains_link_through_friends() {
+        // A chain of bodies 1 apart, with both ends further than the linking length apart
+        let chain: Vec<OctreeBody> = (0..5)
+            .map(|index| {
+                octree_body(
+                    index,
+                    Vector::new(index as Scalar, 0.0, 0.0),
+                    Vector::ZERO,
+                    1.0,
+                )
+            })
+            .chain([octree_body(
+                5,
+                Vector::new(10.0, 0.0, 0.0),
+                Vector::ZERO,
+                1.0,
+            )])
+            .collect();
+
+        let groups = find_groups(&chain, 1.5);
+
+        assert_eq!(groups.groups.len(), 2);
+        assert_eq!(groups.assignments, vec![0, 0, 0, 0, 0, 1]);
+        assert_eq!(groups.groups[0].member_count, 5);
+        assert!(
+            groups.groups[0]
+                .center_of_mass
+                .distance(Vector::new(2.0, 0.0, 0.0))
+                < 1e-12
+        );
+    }
+
+    #[test]
+    fn test_groups_are_ordered_by_mass() {
+        let bodies = [
+            octree_body(0, Vector::new(0.0, 0.0, 0.0), Vector::ZERO, 1.0),
+            octree_body(1, Vector::new(0.5, 0.0, 0.0), Vector::ZERO, 1.0),
+            octree_body(2, Vector::new(20.0, 0.0, 0.0), Vector::ZERO, 5.0),
+            octree_body(3, Vector::new(20.0, 0.5, 0.0), Vector::ZERO, 3.0),
+            octree_body(4, Vector::new(-20.0, 0.0, 0.0), Vector::ZERO, 0.5),
+        ];
+
+        let groups = find_groups(&bodies, 1.0);
+
+        assert_eq!(groups.assignments, vec![1, 1, 0, 0, 2]);
+        assert_eq!(groups.groups[0].mass, 8.0);
+        assert_eq!(groups.groups[1].mass, 2.0);
+        assert_eq!(groups.with_min_members(2).count(), 2);
+    }
+
+    #[test]
+    fn test_isolated_bodies_form_their_own_groups() {
+        let bodies: Vec<OctreeBody> = (0..4)
+            .map(|index| {
+                octree_body(
+                    index,
+                    Vector::new(0.0, 10.0 * index as Scalar, 0.0),
+                    Vector::ZERO,
+                    1.0,
+                )
+            })
+            .collect();
+
+        let groups = find_groups(&bodies, 1.0);
+
+        assert_eq!(groups.groups.len(), 4);
+        assert_eq!(groups.with_min_members(2).count(), 0);
+        assert!(find_groups(&[], 1.0).groups.is_empty());
+    }
+}
@@ -0,0 +1,338 @@
+//! Fourth-order Hermite predictor-corrector integration.
+//!
+//! Each step predicts positions and velocities from the acceleration and jerk at the start of
+//! the step, evaluates acceleration and jerk again at the predicted state, and then corrects the
+//! prediction with the Hermite interpolant through both ends. It is the standard scheme for
+//! collisional N-body work: fourth order for the price of a single force evaluation per step,
+//! and it pairs naturally with individual block timesteps chosen by Aarseth's criterion.
+//!
+//! Jerk depends on velocities, which Avian changes when it resolves collisions, so unlike the
+//! symplectic integrators these functions refresh accelerations and jerks on entry.
+
+use super::block_timesteps::BlockTimesteps;
+use super::integrator::GravitySource;
+use super::integrator::IntegratedBody;
+use super::integrator::update_accelerations_and_jerks;
+use avian3d::math::Scalar;
+use rayon::iter::IntoParallelRefMutIterator;
+use rayon::iter::ParallelIterator;
+
+/// Advances all bodies by `dt` with one shared Hermite step.
+pub fn shared_step(bodies: &mut [IntegratedBody], dt: Scalar, source: &mut impl GravitySource) {
+    update_accelerations_and_jerks(source, bodies);
+
+    let starts = bodies.to_vec();
+    bodies.iter_mut().for_each(|body| predict(body, dt));
+
+    update_accelerations_and_jerks(source, bodies);
+
+    bodies
+        .iter_mut()
+        .zip(&starts)
+        .for_each(|(body, start)| correct(start, body, dt));
+}
+
+/// Hierarchical power-of-two block timesteps driven by Hermite steps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockHermite {
+    /// Shortest step any body may take.
+    pub min_step: Scalar,
+    /// Longest step any body may take.
+    pub max_step: Scalar,
+    /// The `η` of Aarseth's criterion; 0.01 to 0.02 is typical.
+    pub accuracy: Scalar,
+}
+
+impl BlockHermite {
+    fn timesteps(&self) -> BlockTimesteps {
+        BlockTimesteps {
+            min_step: self.min_step,
+            max_step: self.max_step,
+            accuracy: self.accuracy,
+            softening: 0.0,
+        }
+    }
+
+    /// Step size for a body with no step history yet, `η |a| / |j|`.
+    pub fn startup_step(&self, body: &IntegratedBody) -> Scalar {
+        let jerk = body.jerk.length();
+
+        if jerk <= 0.0 {
+            return Scalar::INFINITY;
+        }
+
+        self.accuracy * body.acceleration.length() / jerk
+    }
+
+    /// Aarseth's criterion from the accelerations and jerks at both ends of a step of length `h`.
+    ///
+    /// The second and third derivatives of the acceleration are recovered from the Hermite
+    /// interpolant, and the step is `sqrt(η (|a||a⁽²⁾| + |a⁽¹⁾|²) / (|a⁽¹⁾||a⁽³⁾| + |a⁽²⁾|²))`
+    /// evaluated at the end of the step.
+    pub fn aarseth_step(&self, start: &IntegratedBody, end: &IntegratedBody, h: Scalar) -> Scalar {
+        let acceleration_change = start.acceleration - end.acceleration;
+        let snap = (acceleration_change * -6.0 - (start.jerk * 4.0 + end.jerk * 2.0) * h) / (h * h);
+        let crackle =
+            (acceleration_change * 12.0 + (start.jerk + end.jerk) * (6.0 * h)) / (h * h * h);
+        let snap = snap + crackle * h;
+
+        let acceleration = end.acceleration.length();
+        let jerk = end.jerk.length();
+        let snap = snap.length();
+        let crackle = crackle.length();
+
+        let denominator = jerk * crackle + snap * snap;
+        if denominator <= 0.0 {
+            return Scalar::INFINITY;
+        }
+
+        libm::sqrt(self.accuracy * (acceleration * snap + jerk * jerk) / denominator)
+    }
+
+    /// Advances all bodies by `dt` with individual Hermite steps.
+    ///
+    /// Inactive bodies are predicted to every substep so active ones feel them at the right
+    /// place. Returns the number of bodies that started the tick on each level.
+    pub fn step(
+        &self,
+        bodies: &mut [IntegratedBody],
+        dt: Scalar,
+        source: &mut impl GravitySource,
+    ) -> Vec<usize> {
+        update_accelerations_and_jerks(source, bodies);
+
+        let timesteps = self.timesteps();
+        let max_level = timesteps.max_level(dt);
+        let tick_length = 1_u64 << max_level;
+        let unit = dt / tick_length as Scalar;
+
+        let mut levels: Vec<u32> = bodies
+            .iter()
+            .map(|body| timesteps.level_for_step(self.startup_step(body), dt))
+            .collect();
+        let mut step_starts = vec![0_u64; bodies.len()];
+        let mut step_ends: Vec<u64> = levels.iter().map(|&level| tick_length >> level).collect();
+        let mut starts = bodies.to_vec();
+
+        let mut histogram = vec![0; max_level as usize + 1];
+        levels
+            .iter()
+            .for_each(|&level| histogram[level as usize] += 1);
+
+        let mut now = 0;
+        let mut active = Vec::with_capacity(bodies.len());
+
+        while now < tick_length {
+            now = step_ends.iter().copied().min().unwrap_or(tick_length);
+
+            bodies.iter_mut().zip(&starts).zip(&step_starts).for_each(
+                |((body, start), &step_start)| {
+                    *body = *start;
+                    predict(body, (now - step_start) as Scalar * unit);
+                },
+            );
+
+            active.clear();
+            active.extend((0..bodies.len()).filter(|&index| step_ends[index] == now));
+            update_active_accelerations_and_jerks(source, bodies, &active);
+
+            for &index in &active {
+                let h = (now - step_starts[index]) as Scalar * unit;
+                correct(&starts[index], &mut bodies[index], h);
+
+                let desired_step = self.aarseth_step(&starts[index], &bodies[index], h);
+                starts[index] = bodies[index];
+                step_starts[index] = now;
+
+                if now == tick_length {
+                    continue;
+                }
+
+                // A body may only move to a longer step once the timeline lines up with it
+                let mut level = timesteps.level_for_step(desired_step, dt).min(max_level);
+                while level < levels[index] && now % (tick_length >> level) != 0 {
+                    level += 1;
+                }
+
+                levels[index] = level;
+                step_ends[index] = now + (tick_length >> level);
+            }
+        }
+
+        histogram
+    }
+}
+
+/// Taylor-expands position and velocity over `dt` from the body's acceleration and jerk.
+#[inline]
+fn predict(body: &mut IntegratedBody, dt: Scalar) {
+    body.position +=
+        body.velocity * dt + body.acceleration * (dt * dt / 2.0) + body.jerk * (dt * dt * dt / 6.0);
+    body.velocity += body.acceleration * dt + body.jerk * (dt * dt / 2.0);
+}
+
+/// Replaces the predicted position and velocity of `end`, which must hold the acceleration and
+/// jerk evaluated at the prediction, with the Hermite-corrected ones.
+#[inline]
+fn correct(start: &IntegratedBody, end: &mut IntegratedBody, h: Scalar) {
+    let velocity = start.velocity
+        + (start.acceleration + end.acceleration) * (h / 2.0)
+        + (start.jerk - end.jerk) * (h * h / 12.0);
+    let position = start.position
+        + (start.velocity + velocity) * (h / 2.0)
+        + (start.acceleration - end.acceleration) * (h * h / 12.0);
+
+    end.position = position;
+    end.velocity = velocity;
+}
+
+/// Prepares `source` for all bodies' predicted states and refreshes the accelerations and jerks
+/// of the bodies at `active` indices only.
+fn update_active_accelerations_and_jerks(
+    source: &mut impl GravitySource,
+    bodies: &mut [IntegratedBody],
+    active: &[usize],
+) {
+    if active.is_empty() {
+        return;
+    }
+
+    source.prepare(bodies);
+
+    let mut active_bodies: Vec<IntegratedBody> =
+        active.iter().map(|&index| bodies[index]).collect();
+    let source = &*source;
+    active_bodies.par_iter_mut().for_each(|body| {
+        (body.acceleration, body.jerk) = source.acceleration_and_jerk(body);
+    });
+
+    active.iter().zip(active_bodies).for_each(|(&index, body)| {
+        bodies[index].acceleration = body.acceleration;
+        bodies[index].jerk = body.jerk;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::integrator::OctreeGravity;
+    use crate::physics::octree::Octree;
+    use crate::physics::test_support::DirectGravity;
+    use crate::physics::test_support::G;
+    use crate::physics::test_support::body;
+    use crate::physics::test_support::total_energy;
+    use avian3d::math::Vector;
+
+    /// An equal-mass binary on an eccentric orbit, starting at apocentre.
+    fn eccentric_binary(eccentricity: Scalar) -> Vec<IntegratedBody> {
+        // Relative speed at apocentre of an orbit with semi-major axis 1
+        let speed = libm::sqrt(G * 2.0 * (1.0 - eccentricity) / (1.0 + eccentricity));
+        let separation = 1.0 + eccentricity;
+
+        vec![
+            body(
+                0,
+                Vector::new(separation / 2.0, 0.0, 0.0),
+                Vector::new(0.0, speed / 2.0, 0.0),
+                1.0,
+            ),
+            body(
+                1,
+                Vector::new(-separation / 2.0, 0.0, 0.0),
+                Vector::new(0.0, -speed / 2.0, 0.0),
+                1.0,
+            ),
+        ]
+    }
+
+    fn shared_step_energy_error(steps: usize, dt: Scalar) -> Scalar {
+        let mut bodies = eccentric_binary(0.5);
+        let mut gravity = DirectGravity::default();
+        let initial_energy = total_energy(&bodies);
+
+        (0..steps).for_each(|_| shared_step(&mut bodies, dt, &mut gravity));
+
+        ((total_energy(&bodies) - initial_energy) / initial_energy).abs()
+    }
+
+    #[test]
+    fn test_jerk_matches_finite_difference_of_acceleration() {
+        let mut bodies = eccentric_binary(0.5);
+        let mut gravity = DirectGravity::default();
+        update_accelerations_and_jerks(&mut gravity, &mut bodies);
+        let jerk = bodies[0].jerk;
+
+        let h = 1e-6;
+        let mut later = bodies.clone();
+        later
+            .iter_mut()
+            .for_each(|body| body.position += body.velocity * h);
+        update_accelerations_and_jerks(&mut gravity, &mut later);
+        let finite_difference = (later[0].acceleration - bodies[0].acceleration) / h;
+
+        assert!(
+            jerk.distance(finite_difference) < 1e-4 * jerk.length(),
+            "jerk {jerk:?} should match finite difference {finite_difference:?}"
+        );
+    }
+
+    #[test]
+    fn test_octree_jerk_matches_direct_jerk() {
+        let mut bodies = eccentric_binary(0.5);
+        let mut direct_bodies = bodies.clone();
+        let mut octree = Octree::new(0.5, 0.01, 1e9);
+
+        update_accelerations_and_jerks(
+            &mut OctreeGravity {
+                octree: &mut octree,
+                g: G,
+            },
+            &mut bodies,
+        );
+        update_accelerations_and_jerks(&mut DirectGravity::default(), &mut direct_bodies);
+
+        for (octree_body, direct_body) in bodies.iter().zip(&direct_bodies) {
+            assert!(octree_body.jerk.distance(direct_body.jerk) < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_shared_step_is_fourth_order() {
+        // One orbit of the binary takes 2π / sqrt(2) time units
+        let coarse_error = shared_step_energy_error(1_000, 0.004);
+        let fine_error = shared_step_energy_error(2_000, 0.002);
+
+        // Halving the step should shrink the error about sixteenfold
+        assert!(
+            coarse_error / fine_error > 10.0,
+            "coarse {coarse_error:e} / fine {fine_error:e} should be near 16"
+        );
+    }
+
+    #[test]
+    fn test_block_steps_conserve_energy_of_eccentric_binary() {
+        let block_hermite = BlockHermite {
+            min_step: 1e-6,
+            max_step: 0.1,
+            accuracy: 0.02,
+        };
+        let mut bodies = eccentric_binary(0.9);
+        let mut gravity = DirectGravity::default();
+        let initial_energy = total_energy(&bodies);
+
+        // Roughly five orbits, passing through pericentre each time
+        let mut deepest_level = 0;
+        for _ in 0..250 {
+            let histogram = block_hermite.step(&mut bodies, 0.1, &mut gravity);
+            deepest_level =
+                deepest_level.max(histogram.iter().rposition(|&count| count > 0).unwrap());
+        }
+
+        let error = ((total_energy(&bodies) - initial_energy) / initial_energy).abs();
+        assert!(error < 1e-4, "energy error too large: {error:e}");
+        assert!(
+            deepest_level > 0,
+            "pericentre passages should shorten the step"
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Symplectic integrators for advancing bodies under their mutual gravitation.
+//!
+//! Avian's own integrator is a first-order semi-implicit Euler scheme, which lets the total
+//! energy of an orbiting system wander over long runs. The integrators here work directly on
+//! positions and velocities and are time-symmetric, so energy errors stay bounded instead.
+//! Every step assumes each body's `acceleration` is valid for its current position on entry
+//! and leaves it valid for the final position on exit, so the accelerations from the end of
+//! one step can be reused at the start of the next. The Hermite scheme in [`super::hermite`]
+//! also needs each body's jerk, which depends on velocities and is refreshed on entry instead.
+
+use super::BodyId;
+use super::hermite;
+use super::octree::Octree;
+use super::octree::OctreeBody;
+use super::wisdom_holman;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use rayon::iter::IntoParallelRefMutIterator;
+use rayon::iter::ParallelIterator;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Coefficients of Yoshida's fourth-order "triple jump" composition.
+///
+/// Three leapfrog steps of `w1 * dt`, `w0 * dt` and `w1 * dt` cancel the leading error term of
+/// the second-order scheme. The middle step runs backwards in time.
+const YOSHIDA_W1: Scalar = 1.351_207_191_959_657_8; // 1 / (2 - 2^(1/3))
+const YOSHIDA_W0: Scalar = -1.702_414_383_919_315_3; // -2^(1/3) / (2 - 2^(1/3))
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegratorKind {
+    /// Second-order kick-drift-kick leapfrog, one force evaluation per step.
+    #[default]
+    LeapfrogKdk,
+    /// Second-order velocity Verlet, one force evaluation per step.
+    VelocityVerlet,
+    /// Fourth-order Yoshida (Forest-Ruth) composition, three force evaluations per step.
+    Yoshida4,
+    /// Fourth-order Hermite predictor-corrector, two force and jerk evaluations per step.
+    Hermite4,
+    /// Wisdom-Holman map around the most massive body, two force evaluations per step.
+    WisdomHolman,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IntegratedBody {
+    pub id: BodyId,
+    pub position: Vector,
+    pub velocity: Vector,
+    pub acceleration: Vector,
+    pub jerk: Vector,
+    pub mass: Scalar,
+}
+
+impl IntegratedBody {
+    pub fn as_octree_body(&self) -> OctreeBody {
+        OctreeBody {
+            id: self.id,
+            position: self.position,
+            velocity: self.velocity,
+            mass: self.mass,
+        }
+    }
+}
+
+/// Something that produces gravitational accelerations at the bodies' positions.
+pub trait GravitySource: Sync {
+    /// Prepares the source for evaluations at the given bodies' current positions.
+    fn prepare(&mut self, bodies: &[IntegratedBody]);
+
+    /// Gravitational constant the accelerations are computed with.
+    fn gravitational_constant(&self) -> Scalar;
+
+    /// Gravitational acceleration on `body` from every other body.
+    fn acceleration(&self, body: &IntegratedBody) -> Vector;
+
+    /// Gravitational acceleration on `body` and its time derivative, the jerk.
+    fn acceleration_and_jerk(&self, body: &IntegratedBody) -> (Vector, Vector);
+}
+
+/// Barnes-Hut gravity from an octree rebuilt every time the bodies move.
+pub struct OctreeGravity<'a> {
+    pub octree: &'a mut Octree,
+    pub g: Scalar,
+}
+
+impl GravitySource for OctreeGravity<'_> {
+    fn prepare(&mut self, bodies: &[IntegratedBody]) {
+        self.octree
+            .build(bodies.iter().map(IntegratedBody::as_octree_body));
+    }
+
+    fn gravitational_constant(&self) -> Scalar {
+        self.g
+    }
+
+    fn acceleration(&self, body: &IntegratedBody) -> Vector {
+        if body.mass <= 0.0 {
+            return Vector::ZERO;
+        }
+
+        self.octree
+            .calculate_force(&body.as_octree_body(), self.octree.root.as_ref(), self.g)
+            / body.mass
+    }
+
+    fn acceleration_and_jerk(&self, body: &IntegratedBody) -> (Vector, Vector) {
+        if body.mass <= 0.0 {
+            return (Vector::ZERO, Vector::ZERO);
+        }
+
+        let (force, force_derivative) = self.octree.calculate_force_and_jerk(
+            &body.as_octree_body(),
+            self.octree.root.as_ref(),
+            self.g,
+        );
+        (force / body.mass, force_derivative / body.mass)
+    }
+}
+
+/// Prepares `source` for the bodies' current positions and updates every body's acceleration.
+pub fn update_accelerations(source: &mut impl GravitySource, bodies: &mut [IntegratedBody]) {
+    source.prepare(bodies);
+
+    let source = &*source;
+    bodies
+        .par_iter_mut()
+        .for_each(|body| body.acceleration = source.acceleration(body));
+}
+
+/// Prepares `source` for the bodies' current positions and velocities and updates every body's
+/// acceleration and jerk.
+pub fn update_accelerations_and_jerks(
+    source: &mut impl GravitySource,
+    bodies: &mut [IntegratedBody],
+) {
+    source.prepare(bodies);
+
+    let source = &*source;
+    bodies.par_iter_mut().for_each(|body| {
+        (body.acceleration, body.jerk) = source.acceleration_and_jerk(body);
+    });
+}
+
+impl IntegratorKind {
+    pub fn force_evaluations_per_step(self) -> usize {
+        match self {
+            IntegratorKind::LeapfrogKdk | IntegratorKind::VelocityVerlet => 1,
+            IntegratorKind::Yoshida4 => 3,
+            IntegratorKind::Hermite4 | IntegratorKind::WisdomHolman => 2,
+        }
+    }
+
+    /// Advances all bodies by `dt`, evaluating `source` whenever the positions have moved.
+    pub fn step(self, bodies: &mut [IntegratedBody], dt: Scalar, source: &mut impl GravitySource) {
+        match self {
+            IntegratorKind::LeapfrogKdk => leapfrog_kdk(bodies, dt, source),
+            IntegratorKind::VelocityVerlet => velocity_verlet(bodies, dt, source),
+            IntegratorKind::Yoshida4 => {
+                leapfrog_kdk(bodies, YOSHIDA_W1 * dt, source);
+                leapfrog_kdk(bodies, YOSHIDA_W0 * dt, source);
+                leapfrog_kdk(bodies, YOSHIDA_W1 * dt, source);
+            }
+            IntegratorKind::Hermite4 => hermite::shared_step(bodies, dt, source),
+            IntegratorKind::WisdomHolman => wisdom_holman::step(bodies, dt, source),
+        }
+    }
+}
+
+#[inline]
+pub fn kick(bodies: &mut [IntegratedBody], dt: Scalar) {
+    bodies
+        .iter_mut()
+        .for_each(|body| body.velocity += body.acceleration * dt);
+}
+
+#[inline]
+pub fn drift(bodies: &mut [IntegratedBody], dt: Scalar) {
+    bodies
+        .iter_mut()
+        .for_each(|body| body.position += body.velocity * dt);
+}
+
+fn leapfrog_kdk(bodies: &mut [IntegratedBody], dt: Scalar, source: &mut impl GravitySource) {
+    kick(bodies, 0.5 * dt);
+    drift(bodies, dt);
+    update_accelerations(source, bodies);
+    kick(bodies, 0.5 * dt);
+}
+
+fn velocity_verlet(bodies: &mut [IntegratedBody], dt: Scalar, source: &mut impl GravitySource) {
+    let previous_accelerations: Vec<Vector> = bodies.iter().map(|body| body.acceleration).collect();
+
+    bodies.iter_mut().for_each(|body| {
+        body.position += body.velocity * dt + body.acceleration * (0.5 * dt * dt);
+    });
+
+    update_accelerations(source, bodies);
+
+    bodies
+        .iter_mut()
+        .zip(previous_accelerations)
+        .for_each(|(body, previous_acceleration)| {
+            body.velocity += (previous_acceleration + body.acceleration) * (0.5 * dt);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::test_support::DirectGravity;
+    use crate::physics::test_support::G;
+    use crate::physics::test_support::total_energy;
+
+    /// A light body on a circular orbit of radius 1 around a heavy one.
+    fn circular_binary() -> Vec<IntegratedBody> {
+        let central_mass = 1.0;
+        let orbiting_mass = 1e-3;
+        let speed = libm::sqrt(G * (central_mass + orbiting_mass));
+
+        vec![
+            IntegratedBody {
+                id: BodyId(0),
+                position: Vector::ZERO,
+                velocity: Vector::new(0.0, -speed * orbiting_mass / central_mass, 0.0),
+                acceleration: Vector::ZERO,
+                jerk: Vector::ZERO,
+                mass: central_mass,
+            },
+            IntegratedBody {
+                id: BodyId(1),
+                position: Vector::new(1.0, 0.0, 0.0),
+                velocity: Vector::new(0.0, speed, 0.0),
+                acceleration: Vector::ZERO,
+                jerk: Vector::ZERO,
+                mass: orbiting_mass,
+            },
+        ]
+    }
+
+    fn relative_energy_error(kind: IntegratorKind, steps: usize, dt: Scalar) -> Scalar {
+        let mut bodies = circular_binary();
+        let mut gravity = DirectGravity::default();
+        update_accelerations(&mut gravity, &mut bodies);
+        let initial_energy = total_energy(&bodies);
+
+        (0..steps).for_each(|_| kind.step(&mut bodies, dt, &mut gravity));
+
+        ((total_energy(&bodies) - initial_energy) / initial_energy).abs()
+    }
+
+    #[test]
+    fn test_integrators_conserve_energy_over_many_orbits() {
+        // ~16 orbits at ~100 steps per orbit
+        for kind in [
+            IntegratorKind::LeapfrogKdk,
+            IntegratorKind::VelocityVerlet,
+            IntegratorKind::Yoshida4,
+            IntegratorKind::Hermite4,
+        ] {
+            let error = relative_energy_error(kind, 10_000, 0.01);
+            assert!(error < 1e-4, "{kind:?} energy error too large: {error:e}");
+        }
+    }
+
+    #[test]
+    fn test_yoshida_is_more_accurate_than_leapfrog() {
+        let leapfrog_error = relative_energy_error(IntegratorKind::LeapfrogKdk, 1_000, 0.05);
+        let yoshida_error = relative_energy_error(IntegratorKind::Yoshida4, 1_000, 0.05);
+
+        assert!(
+            yoshida_error < leapfrog_error,
+            "Yoshida error {yoshida_error:e} should be below leapfrog error {leapfrog_error:e}"
+        );
+    }
+
+    #[test]
+    fn test_leapfrog_and_velocity_verlet_agree() {
+        let mut leapfrog_bodies = circular_binary();
+        let mut verlet_bodies = circular_binary();
+        let mut gravity = DirectGravity::default();
+        update_accelerations(&mut gravity, &mut leapfrog_bodies);
+        update_accelerations(&mut gravity, &mut verlet_bodies);
+
+        for _ in 0..100 {
+            IntegratorKind::LeapfrogKdk.step(&mut leapfrog_bodies, 0.01, &mut gravity);
+            IntegratorKind::VelocityVerlet.step(&mut verlet_bodies, 0.01, &mut gravity);
+        }
+
+        for (a, b) in leapfrog_bodies.iter().zip(&verlet_bodies) {
+            assert!(a.position.distance(b.position) < 1e-10);
+            assert!(a.velocity.distance(b.velocity) < 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_symplectic_integrators_retrace_steps_after_velocity_reversal() {
+        for kind in [
+            IntegratorKind::LeapfrogKdk,
+            IntegratorKind::VelocityVerlet,
+            IntegratorKind::Yoshida4,
+        ] {
+            let mut bodies = circular_binary();
+            let mut gravity = DirectGravity::default();
+            update_accelerations(&mut gravity, &mut bodies);
+            let initial = bodies.clone();
+
+            (0..500).for_each(|_| kind.step(&mut bodies, 0.01, &mut gravity));
+            bodies
+                .iter_mut()
+                .for_each(|body| body.velocity = -body.velocity);
+            (0..500).for_each(|_| kind.step(&mut bodies, 0.01, &mut gravity));
+
+            for (body, initial) in bodies.iter().zip(&initial) {
+                let deviation = body.position.distance(initial.position);
+                assert!(
+                    deviation < 1e-10,
+                    "{kind:?} deviation too large: {deviation:e}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_octree_accelerations_point_towards_other_body() {
+        let mut octree = Octree::new(0.5, 0.1, 1e9);
+        let mut bodies = circular_binary();
+
+        update_accelerations(
+            &mut OctreeGravity {
+                octree: &mut octree,
+                g: G,
+            },
+            &mut bodies,
+        );
+
+        assert!(bodies[0].acceleration.x > 0.0);
+        assert!(bodies[1].acceleration.x < 0.0);
+        // Newton's third law: m1 a1 = -m2 a2
+        let net_force =
+            bodies[0].acceleration * bodies[0].mass + bodies[1].acceleration * bodies[1].mass;
+        assert!(net_force.length() < 1e-12);
+    }
+}
@@ -0,0 +1,200 @@
+//! Universal-variable solution of the two-body problem.
+//!
+//! Advancing an orbit by a time step means solving Kepler's equation. Written in terms of the
+//! universal anomaly `χ` and the Stumpff functions, one equation covers elliptic, parabolic and
+//! hyperbolic orbits alike, and the new state follows from the Lagrange `f` and `g` functions.
+
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+
+/// Iteration limit of the solver for the universal anomaly.
+const MAX_ITERATIONS: usize = 50;
+
+/// Order of Laguerre's method, as recommended by Conway for Kepler's equation.
+const LAGUERRE_ORDER: Scalar = 5.0;
+
+/// The Stumpff functions `c2(ψ)` and `c3(ψ)`.
+///
+/// Near zero the closed forms cancel catastrophically, so their power series is summed instead.
+pub fn stumpff(psi: Scalar) -> (Scalar, Scalar) {
+    if psi.abs() < 1.0 {
+        let mut c2 = 0.0;
+        let mut c3 = 0.0;
+        let mut term2 = 0.5;
+        let mut term3 = 1.0 / 6.0;
+        for k in 1..20 {
+            c2 += term2;
+            c3 += term3;
+            let k = k as Scalar;
+            term2 *= -psi / ((2.0 * k + 1.0) * (2.0 * k + 2.0));
+            term3 *= -psi / ((2.0 * k + 2.0) * (2.0 * k + 3.0));
+        }
+        (c2, c3)
+    } else if psi > 0.0 {
+        let root = libm::sqrt(psi);
+        let half_sin = libm::sin(0.5 * root);
+        (
+            2.0 * half_sin * half_sin / psi,
+            (root - libm::sin(root)) / (psi * root),
+        )
+    } else {
+        let root = libm::sqrt(-psi);
+        (
+            (libm::cosh(root) - 1.0) / -psi,
+            (libm::sinh(root) - root) / (-psi * root),
+        )
+    }
+}
+
+/// Advances a relative position and velocity along their Kepler orbit by `dt`.
+///
+/// `gm` is the gravitational parameter of the orbit. A body sitting exactly on the centre has no
+/// orbit and moves in a straight line.
+pub fn drift(position: Vector, velocity: Vector, gm: Scalar, dt: Scalar) -> (Vector, Vector) {
+    let r0 = position.length();
+    if r0 <= 0.0 || gm <= 0.0 || dt == 0.0 {
+        return (position + velocity * dt, velocity);
+    }
+
+    let sqrt_gm = libm::sqrt(gm);
+    let sigma0 = position.dot(velocity) / sqrt_gm;
+    let alpha = 2.0 / r0 - velocity.length_squared() / gm;
+
+    // Universal functions U0..U3 of χ, where dUn/dχ = Un-1 and dU0/dχ = -α U1
+    let universal = |chi: Scalar| {
+        let psi = alpha * chi * chi;
+        let (c2, c3) = stumpff(psi);
+        let u2 = chi * chi * c2;
+        let u3 = chi * chi * chi * c3;
+        let u1 = chi - alpha * u3;
+        let u0 = 1.0 - alpha * u2;
+        (u0, u1, u2, u3)
+    };
+
+    let target = sqrt_gm * dt;
+    let mut chi = target / r0;
+    for _ in 0..MAX_ITERATIONS {
+        let (u0, u1, u2, u3) = universal(chi);
+        let f = u3 + sigma0 * u2 + r0 * u1 - target;
+        let f_prime = u2 + sigma0 * u1 + r0 * u0;
+        let f_second = sigma0 * u0 + (1.0 - alpha * r0) * u1;
+
+        let n = LAGUERRE_ORDER;
+        let discriminant = ((n - 1.0) * (n - 1.0) * f_prime * f_prime
+            - n * (n - 1.0) * f * f_second)
+            .abs()
+            .sqrt();
+        let delta = n * f / (f_prime + discriminant.copysign(f_prime));
+        chi -= delta;
+
+        if delta.abs() <= 4.0 * Scalar::EPSILON * chi.abs() {
+            break;
+        }
+    }
+
+    let (u0, u1, u2, u3) = universal(chi);
+    let r = u2 + sigma0 * u1 + r0 * u0;
+
+    let f = 1.0 - u2 / r0;
+    let g = dt - u3 / sqrt_gm;
+    let f_dot = -sqrt_gm * u1 / (r * r0);
+    let g_dot = 1.0 - u2 / r;
+
+    (
+        position * f + velocity * g,
+        position * f_dot + velocity * g_dot,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::regularization::KsOrbit;
+    use core::f64::consts::PI;
+
+    fn energy(position: Vector, velocity: Vector, gm: Scalar) -> Scalar {
+        0.5 * velocity.length_squared() - gm / position.length()
+    }
+
+    #[test]
+    fn test_stumpff_series_matches_closed_form() {
+        for psi in [-0.999, -0.5, 0.3, 0.999] {
+            let (c2, c3) = stumpff(psi);
+            let (c2_closed, c3_closed) = if psi > 0.0 {
+                let root = libm::sqrt(psi);
+                (
+                    (1.0 - libm::cos(root)) / psi,
+                    (root - libm::sin(root)) / (psi * root),
+                )
+            } else {
+                let root = libm::sqrt(-psi);
+                (
+                    (libm::cosh(root) - 1.0) / -psi,
+                    (libm::sinh(root) - root) / (-psi * root),
+                )
+            };
+            assert!((c2 - c2_closed).abs() < 1e-12);
+            assert!((c3 - c3_closed).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_circular_orbit_quarter_period() {
+        let gm = 4.0;
+        let position = Vector::new(2.0, 0.0, 0.0);
+        let velocity = Vector::new(0.0, libm::sqrt(gm / 2.0), 0.0);
+        let period = 2.0 * PI * libm::sqrt(8.0 / gm);
+
+        let (position, velocity) = drift(position, velocity, gm, 0.25 * period);
+
+        assert!(position.distance(Vector::new(0.0, 2.0, 0.0)) < 1e-12);
+        assert!(velocity.distance(Vector::new(-libm::sqrt(gm / 2.0), 0.0, 0.0)) < 1e-12);
+    }
+
+    #[test]
+    fn test_eccentric_orbit_returns_after_period() {
+        let gm = 1.0;
+        let position = Vector::new(0.1, 0.02, -0.03);
+        let velocity = Vector::new(0.5, 4.0, 0.7);
+        let semi_major_axis = -0.5 * gm / energy(position, velocity, gm);
+        let period = 2.0 * PI * libm::sqrt(semi_major_axis.powi(3) / gm);
+
+        let (final_position, final_velocity) = drift(position, velocity, gm, period);
+
+        assert!(final_position.distance(position) < 1e-9 * position.length());
+        assert!(final_velocity.distance(velocity) < 1e-9 * velocity.length());
+    }
+
+    #[test]
+    fn test_hyperbolic_orbit_conserves_energy_and_angular_momentum() {
+        let gm = 1.0;
+        let position = Vector::new(1.0, 0.0, 0.0);
+        let velocity = Vector::new(-0.5, 2.0, 0.1);
+
+        let (final_position, final_velocity) = drift(position, velocity, gm, 7.5);
+
+        let initial_energy = energy(position, velocity, gm);
+        assert!(initial_energy > 0.0);
+        assert!((energy(final_position, final_velocity, gm) - initial_energy).abs() < 1e-12);
+        assert!(
+            final_position
+                .cross(final_velocity)
+                .distance(position.cross(velocity))
+                < 1e-12
+        );
+    }
+
+    #[test]
+    fn test_agrees_with_ks_regularized_orbit() {
+        let gm = 2.0;
+        let position = Vector::new(-0.3, 0.4, 0.1);
+        let velocity = Vector::new(1.2, 0.9, -0.4);
+        let mut orbit = KsOrbit::from_relative(position, velocity, gm);
+
+        orbit.advance(1.3);
+        let (final_position, final_velocity) = drift(position, velocity, gm, 1.3);
+
+        assert!(orbit.relative_position().distance(final_position) < 1e-10);
+        assert!(orbit.relative_velocity().distance(final_velocity) < 1e-10);
+    }
+}
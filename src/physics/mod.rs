@@ -1 +1,25 @@
+pub mod block_timesteps;
+pub mod cluster;
+pub mod collisions;
+pub mod conservation;
+pub mod groups;
+pub mod hermite;
+pub mod integrator;
+pub mod kepler;
 pub mod octree;
+pub mod regularization;
+pub mod simulation;
+pub mod stars;
+#[cfg(test)]
+mod test_support;
+pub mod wisdom_holman;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Identifies a body independently of how it is stored.
+///
+/// The Bevy app uses the bits of the body's entity, see [`bevy::prelude::Entity::to_bits`], while
+/// a [`simulation::Simulation`] numbers its bodies in the order they are added.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BodyId(pub u64);
@@ -1,8 +1,8 @@
 //! Spatial octree for approximating gravitational forces using the Barnes-Hut algorithm.
 
+use super::BodyId;
 use avian3d::math::Scalar;
 use avian3d::math::Vector;
-use bevy::prelude::*;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 
@@ -117,6 +117,12 @@ impl Aabb3d {
         self.max - self.min
     }
 
+    /// Squared distance from `point` to the nearest point of the box, zero inside it.
+    #[inline]
+    pub fn distance_squared_to(&self, point: Vector) -> Scalar {
+        point.clamp(self.min, self.max).distance_squared(point)
+    }
+
     pub fn subdivide_into_children(&self) -> [Aabb3d; 8] {
         let center = self.center();
         [
@@ -243,6 +249,26 @@ impl Octree {
         bounds
     }
 
+    /// Calls `f` with every body within `radius` of `position`, skipping nodes out of reach.
+    pub fn for_each_body_within(
+        &self,
+        position: Vector,
+        radius: Scalar,
+        mut f: impl FnMut(&OctreeBody),
+    ) {
+        if let Some(root) = &self.root {
+            root.for_each_body_within(position, radius * radius, &mut f);
+        }
+    }
+
+    /// Bodies in the order the leaves hold them, from which [`Octree::build`] builds the same
+    /// leaves again.
+    pub fn bodies(&self) -> Vec<OctreeBody> {
+        let mut bodies = Vec::new();
+        self.for_each_body_within(Vector::ZERO, Scalar::INFINITY, |body| bodies.push(*body));
+        bodies
+    }
+
     pub fn build(&mut self, bodies: impl IntoIterator<Item = OctreeBody>) {
         if let Some(old_root) = self.root.take() {
             self.octree_node_pool.return_node(old_root);
@@ -348,20 +374,29 @@ impl Octree {
             }
         }
 
-        let (total_mass, weighted_sum) = bodies
-            .iter()
-            .fold((0.0, Vector::ZERO), |(mass_acc, pos_acc), body| {
-                (mass_acc + body.mass, pos_acc + body.position * body.mass)
-            });
-        let center_of_mass = if total_mass > 0.0 {
-            weighted_sum / total_mass
+        let (total_mass, weighted_sum, weighted_velocity_sum) = bodies.iter().fold(
+            (0.0, Vector::ZERO, Vector::ZERO),
+            |(mass_acc, pos_acc, vel_acc), body| {
+                (
+                    mass_acc + body.mass,
+                    pos_acc + body.position * body.mass,
+                    vel_acc + body.velocity * body.mass,
+                )
+            },
+        );
+        let (center_of_mass, center_of_mass_velocity) = if total_mass > 0.0 {
+            (
+                weighted_sum / total_mass,
+                weighted_velocity_sum / total_mass,
+            )
         } else {
-            bounds.center()
+            (bounds.center(), Vector::ZERO)
         };
 
         OctreeNode::Internal {
             bounds,
             center_of_mass,
+            center_of_mass_velocity,
             total_mass,
             children,
         }
@@ -405,7 +440,7 @@ impl Octree {
             Some(OctreeNode::External { bodies, .. }) => {
                 let mut force = Vector::ZERO;
                 bodies.iter().for_each(|other_body| {
-                    if other_body.entity != body.entity {
+                    if other_body.id != body.id {
                         force += self.calculate_direct_force(body, other_body, g);
                     }
                 });
@@ -444,12 +479,180 @@ impl Octree {
     fn calculate_direct_force(&self, body1: &OctreeBody, body2: &OctreeBody, g: Scalar) -> Vector {
         self.calculate_force_from_point(body1, body2.position, body2.mass, g)
     }
+
+    /// Potential energy of `body` in the field of every other body, using the same opening
+    /// criterion as [`Self::calculate_force`].
+    ///
+    /// Forces vanish inside `min_distance`, so the potential is held at its value there. Halving
+    /// the sum over all bodies gives the total potential energy.
+    pub fn calculate_potential(
+        &self,
+        body: &OctreeBody,
+        node: Option<&OctreeNode>,
+        g: Scalar,
+    ) -> Scalar {
+        match node {
+            Some(OctreeNode::Internal {
+                bounds,
+                center_of_mass,
+                total_mass,
+                children,
+                ..
+            }) => {
+                let distance_squared = body.position.distance_squared(*center_of_mass);
+                let size_squared = bounds.min.distance_squared(bounds.max);
+
+                if size_squared < distance_squared * self.theta * self.theta {
+                    self.calculate_potential_from_point(body, *center_of_mass, *total_mass, g)
+                } else {
+                    children
+                        .iter()
+                        .map(|child| self.calculate_potential(body, child.as_deref(), g))
+                        .sum()
+                }
+            }
+            Some(OctreeNode::External { bodies, .. }) => bodies
+                .iter()
+                .filter(|other_body| other_body.id != body.id)
+                .map(|other_body| {
+                    self.calculate_potential_from_point(
+                        body,
+                        other_body.position,
+                        other_body.mass,
+                        g,
+                    )
+                })
+                .sum(),
+            None => 0.0,
+        }
+    }
+
+    #[inline]
+    fn calculate_potential_from_point(
+        &self,
+        body: &OctreeBody,
+        point_position: Vector,
+        point_mass: Scalar,
+        g: Scalar,
+    ) -> Scalar {
+        let distance = body
+            .position
+            .distance(point_position)
+            .max(self.min_distance);
+
+        -g * body.mass * point_mass / distance
+    }
+
+    /// Like [`Self::calculate_force`], but also returns the force's time derivative.
+    ///
+    /// Dividing both by the body's mass gives the acceleration and jerk needed by Hermite
+    /// integration. Nodes accepted by the opening criterion are treated as a point mass moving
+    /// with their centre-of-mass velocity.
+    pub fn calculate_force_and_jerk(
+        &self,
+        body: &OctreeBody,
+        node: Option<&OctreeNode>,
+        g: Scalar,
+    ) -> (Vector, Vector) {
+        match node {
+            Some(OctreeNode::Internal {
+                bounds,
+                center_of_mass,
+                center_of_mass_velocity,
+                total_mass,
+                children,
+            }) => {
+                let distance_squared = body.position.distance_squared(*center_of_mass);
+                let size_squared = bounds.min.distance_squared(bounds.max);
+
+                if size_squared < distance_squared * self.theta * self.theta {
+                    self.calculate_force_and_jerk_from_point(
+                        body,
+                        *center_of_mass,
+                        *center_of_mass_velocity,
+                        *total_mass,
+                        g,
+                    )
+                } else {
+                    children
+                        .iter()
+                        .map(|child| {
+                            self.calculate_force_and_jerk(body, child.as_ref().map(|v| &**v), g)
+                        })
+                        .fold((Vector::ZERO, Vector::ZERO), |(f, j), (df, dj)| {
+                            (f + df, j + dj)
+                        })
+                }
+            }
+            Some(OctreeNode::External { bodies, .. }) => bodies
+                .iter()
+                .filter(|other_body| other_body.id != body.id)
+                .map(|other_body| self.calculate_direct_force_and_jerk(body, other_body, g))
+                .fold((Vector::ZERO, Vector::ZERO), |(f, j), (df, dj)| {
+                    (f + df, j + dj)
+                }),
+            None => (Vector::ZERO, Vector::ZERO),
+        }
+    }
+
+    #[inline]
+    fn calculate_force_and_jerk_from_point(
+        &self,
+        body: &OctreeBody,
+        point_position: Vector,
+        point_velocity: Vector,
+        point_mass: Scalar,
+        g: Scalar,
+    ) -> (Vector, Vector) {
+        let direction = point_position - body.position;
+        let distance_squared = direction.length_squared();
+
+        if distance_squared < self.min_distance_squared {
+            return (Vector::ZERO, Vector::ZERO);
+        }
+
+        self.force_calculation_count.fetch_add(1, Ordering::Relaxed);
+
+        let relative_velocity = point_velocity - body.velocity;
+        let distance = distance_squared.sqrt();
+        let strength = g * body.mass * point_mass / (distance_squared * distance);
+        let force = direction * strength;
+        let jerk = (relative_velocity
+            - direction * (3.0 * direction.dot(relative_velocity) / distance_squared))
+            * strength;
+
+        // Clamp like `calculate_force_from_point`, scaling the jerk by the same factor
+        let force_magnitude = strength * distance;
+        if force_magnitude > self.max_force {
+            let scale = self.max_force / force_magnitude;
+            (force * scale, jerk * scale)
+        } else {
+            (force, jerk)
+        }
+    }
+
+    #[inline]
+    fn calculate_direct_force_and_jerk(
+        &self,
+        body1: &OctreeBody,
+        body2: &OctreeBody,
+        g: Scalar,
+    ) -> (Vector, Vector) {
+        self.calculate_force_and_jerk_from_point(
+            body1,
+            body2.position,
+            body2.velocity,
+            body2.mass,
+            g,
+        )
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct OctreeBody {
-    pub entity: Entity,
+    pub id: BodyId,
     pub position: Vector,
+    pub velocity: Vector,
     pub mass: Scalar,
 }
 
@@ -458,6 +661,7 @@ pub enum OctreeNode {
     Internal {
         bounds: Aabb3d,
         center_of_mass: Vector,
+        center_of_mass_velocity: Vector,
         total_mass: Scalar,
         children: [Option<Box<OctreeNode>>; 8],
     },
@@ -481,10 +685,10 @@ impl OctreeNode {
         current_depth: usize,
         max_depth: Option<usize>,
     ) {
-        if let Some(max_depth) = max_depth {
-            if current_depth > max_depth {
-                return;
-            }
+        if let Some(max_depth) = max_depth
+            && current_depth > max_depth
+        {
+            return;
         }
 
         bounds.push(self.bounds());
@@ -496,6 +700,29 @@ impl OctreeNode {
         }
     }
 
+    pub fn for_each_body_within(
+        &self,
+        position: Vector,
+        radius_squared: Scalar,
+        f: &mut impl FnMut(&OctreeBody),
+    ) {
+        if self.bounds().distance_squared_to(position) > radius_squared {
+            return;
+        }
+
+        match self {
+            OctreeNode::External { bodies, .. } => bodies
+                .iter()
+                .filter(|body| body.position.distance_squared(position) <= radius_squared)
+                .for_each(f),
+            OctreeNode::Internal { children, .. } => {
+                children.iter().flatten().for_each(|child| {
+                    child.for_each_body_within(position, radius_squared, f);
+                });
+            }
+        }
+    }
+
     pub fn count_bodies(&self) -> usize {
         match self {
             OctreeNode::External { bodies, .. } => bodies.len(),
@@ -558,21 +785,23 @@ impl OctreeNode {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bevy::prelude::Entity;
+    use crate::physics::BodyId;
 
     #[test]
     fn test_octree_force_calculation() {
         let mut octree = Octree::new(0.5, 10.0, 1e4);
 
         let body1 = OctreeBody {
-            entity: Entity::from_raw(0),
+            id: BodyId(0),
             position: Vector::new(0.0, 0.0, 0.0),
+            velocity: Vector::ZERO,
             mass: 1000.0,
         };
 
         let body2 = OctreeBody {
-            entity: Entity::from_raw(1),
+            id: BodyId(1),
             position: Vector::new(10.0, 0.0, 0.0),
+            velocity: Vector::ZERO,
             mass: 1000.0,
         };
 
@@ -595,21 +824,24 @@ mod tests {
 
         // Create a body exactly at the center (boundary of all octants)
         let center_body = OctreeBody {
-            entity: Entity::from_raw(0),
+            id: BodyId(0),
             position: Vector::new(0.0, 0.0, 0.0),
+            velocity: Vector::ZERO,
             mass: 1000.0,
         };
 
         // Create bodies in different octants
         let body1 = OctreeBody {
-            entity: Entity::from_raw(1),
+            id: BodyId(1),
             position: Vector::new(-1.0, -1.0, -1.0),
+            velocity: Vector::ZERO,
             mass: 1000.0,
         };
 
         let body2 = OctreeBody {
-            entity: Entity::from_raw(2),
+            id: BodyId(2),
             position: Vector::new(1.0, 1.0, 1.0),
+            velocity: Vector::ZERO,
             mass: 1000.0,
         };
 
@@ -626,6 +858,38 @@ mod tests {
         assert!(force.is_finite(), "Force should be finite");
     }
 
+    #[test]
+    fn test_for_each_body_within_matches_brute_force() {
+        let mut octree = Octree::new(0.5, 10.0, 1e4).with_leaf_threshold(2);
+        let bodies: Vec<OctreeBody> = (0..125)
+            .map(|index| OctreeBody {
+                id: BodyId(index),
+                position: Vector::new(
+                    (index % 5) as Scalar,
+                    (index / 5 % 5) as Scalar,
+                    (index / 25) as Scalar,
+                ) * 3.0,
+                velocity: Vector::ZERO,
+                mass: 1.0,
+            })
+            .collect();
+        octree.build(bodies.clone());
+
+        let position = Vector::new(5.0, 6.5, 4.0);
+        let radius = 4.0;
+        let mut found = Vec::new();
+        octree.for_each_body_within(position, radius, |body| found.push(body.id));
+        found.sort();
+
+        let expected: Vec<BodyId> = bodies
+            .iter()
+            .filter(|body| body.position.distance(position) <= radius)
+            .map(|body| body.id)
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(found, expected);
+    }
+
     #[test]
     fn test_octree_no_body_duplication() {
         let mut octree = Octree::new(0.5, 10.0, 1e4);
@@ -633,18 +897,21 @@ mod tests {
         // Create bodies, including one exactly on octant boundary
         let bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(0.0, 0.0, 0.0), // Exactly at center
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(-2.0, -2.0, -2.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(2),
+                id: BodyId(2),
                 position: Vector::new(2.0, 2.0, 2.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
         ];
@@ -708,33 +975,39 @@ mod tests {
         // Create enough bodies to force tree structure creation
         let bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(-5.0, -5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(5.0, 5.0, 5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(2),
+                id: BodyId(2),
                 position: Vector::new(-5.0, 5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(3),
+                id: BodyId(3),
                 position: Vector::new(5.0, -5.0, 5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(4),
+                id: BodyId(4),
                 position: Vector::new(-5.0, -5.0, 5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(5),
+                id: BodyId(5),
                 position: Vector::new(5.0, 5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
         ];
@@ -748,13 +1021,15 @@ mod tests {
         // Build again with fewer bodies - should return old nodes to pool
         let new_bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(6),
+                id: BodyId(6),
                 position: Vector::new(0.0, 0.0, 0.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(7),
+                id: BodyId(7),
                 position: Vector::new(1.0, 1.0, 1.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
         ];
@@ -784,13 +1059,15 @@ mod tests {
         // Build and rebuild to populate the pool
         let bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(-1.0, -1.0, -1.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(1.0, 1.0, 1.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
         ];
@@ -828,8 +1105,9 @@ mod tests {
 
         // Test with single body (external node)
         let single_body = vec![OctreeBody {
-            entity: Entity::from_raw(0),
+            id: BodyId(0),
             position: Vector::new(0.0, 0.0, 0.0),
+            velocity: Vector::ZERO,
             mass: 1000.0,
         }];
 
@@ -840,23 +1118,27 @@ mod tests {
         // Test with multiple bodies that create internal nodes
         let multiple_bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(-5.0, -5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(5.0, 5.0, 5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(2),
+                id: BodyId(2),
                 position: Vector::new(-5.0, 5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(3),
+                id: BodyId(3),
                 position: Vector::new(5.0, -5.0, 5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
         ];
@@ -876,8 +1158,9 @@ mod tests {
 
         // Test external node (leaf)
         let single_body = vec![OctreeBody {
-            entity: Entity::from_raw(0),
+            id: BodyId(0),
             position: Vector::new(0.0, 0.0, 0.0),
+            velocity: Vector::ZERO,
             mass: 1000.0,
         }];
 
@@ -888,13 +1171,15 @@ mod tests {
         // Test internal node (not leaf)
         let multiple_bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(-5.0, -5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(5.0, 5.0, 5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
         ];
@@ -914,13 +1199,15 @@ mod tests {
         // Test external node mass calculation
         let bodies_external = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(0.0, 0.0, 0.0),
+                velocity: Vector::ZERO,
                 mass: 500.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(0.1, 0.1, 0.1),
+                velocity: Vector::ZERO,
                 mass: 300.0,
             },
         ];
@@ -933,18 +1220,21 @@ mod tests {
         // Test internal node mass calculation
         let bodies_internal = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(-5.0, -5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(5.0, 5.0, 5.0),
+                velocity: Vector::ZERO,
                 mass: 2000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(2),
+                id: BodyId(2),
                 position: Vector::new(-5.0, 5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 1500.0,
             },
         ];
@@ -957,13 +1247,15 @@ mod tests {
         // Test with zero mass bodies
         let zero_mass_bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(0.0, 0.0, 0.0),
+                velocity: Vector::ZERO,
                 mass: 0.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(1.0, 1.0, 1.0),
+                velocity: Vector::ZERO,
                 mass: 0.0,
             },
         ];
@@ -980,13 +1272,15 @@ mod tests {
         // Test external node center of mass calculation
         let bodies_external = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(0.0, 0.0, 0.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(2.0, 0.0, 0.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
         ];
@@ -1006,18 +1300,21 @@ mod tests {
         // Test internal node center of mass calculation
         let bodies_internal = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(-10.0, -10.0, -10.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(10.0, 10.0, 10.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(2),
+                id: BodyId(2),
                 position: Vector::new(-10.0, 10.0, -10.0),
+                velocity: Vector::ZERO,
                 mass: 2000.0,
             },
         ];
@@ -1040,13 +1337,15 @@ mod tests {
         // Test with zero mass bodies
         let zero_mass_bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(5.0, 5.0, 5.0),
+                velocity: Vector::ZERO,
                 mass: 0.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(-5.0, -5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 0.0,
             },
         ];
@@ -1068,23 +1367,27 @@ mod tests {
         // Create bodies that will force tree subdivision
         let bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(-5.0, -5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(5.0, 5.0, 5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(2),
+                id: BodyId(2),
                 position: Vector::new(-5.0, 5.0, -5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(3),
+                id: BodyId(3),
                 position: Vector::new(5.0, -5.0, 5.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
         ];
@@ -1121,8 +1424,9 @@ mod tests {
 
         // Test with single body (external node)
         let single_body = vec![OctreeBody {
-            entity: Entity::from_raw(0),
+            id: BodyId(0),
             position: Vector::new(0.0, 0.0, 0.0),
+            velocity: Vector::ZERO,
             mass: 1000.0,
         }];
 
@@ -1144,13 +1448,15 @@ mod tests {
         // Test that octree.get_bounds() uses the moved collect_bounds method correctly
         let bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(-3.0, -3.0, -3.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(3.0, 3.0, 3.0),
+                velocity: Vector::ZERO,
                 mass: 1000.0,
             },
         ];
@@ -1199,18 +1505,21 @@ mod tests {
         // Create test bodies
         let bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(0.0, 0.0, 0.0),
+                velocity: Vector::ZERO,
                 mass: 100.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(10.0, 0.0, 0.0),
+                velocity: Vector::ZERO,
                 mass: 200.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(2),
+                id: BodyId(2),
                 position: Vector::new(0.0, 10.0, 0.0),
+                velocity: Vector::ZERO,
                 mass: 300.0,
             },
         ];
@@ -1249,8 +1558,9 @@ mod tests {
 
         // Single body should create one external node
         let single_body = vec![OctreeBody {
-            entity: Entity::from_raw(0),
+            id: BodyId(0),
             position: Vector::new(0.0, 0.0, 0.0),
+            velocity: Vector::ZERO,
             mass: 100.0,
         }];
         octree.build(single_body);
@@ -1262,23 +1572,27 @@ mod tests {
         // Multiple bodies spread out should create internal nodes (with leaf_threshold=1)
         let multiple_bodies = vec![
             OctreeBody {
-                entity: Entity::from_raw(0),
+                id: BodyId(0),
                 position: Vector::new(-10.0, -10.0, -10.0),
+                velocity: Vector::ZERO,
                 mass: 100.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(1),
+                id: BodyId(1),
                 position: Vector::new(10.0, 10.0, 10.0),
+                velocity: Vector::ZERO,
                 mass: 100.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(2),
+                id: BodyId(2),
                 position: Vector::new(-10.0, 10.0, -10.0),
+                velocity: Vector::ZERO,
                 mass: 100.0,
             },
             OctreeBody {
-                entity: Entity::from_raw(3),
+                id: BodyId(3),
                 position: Vector::new(10.0, -10.0, 10.0),
+                velocity: Vector::ZERO,
                 mass: 100.0,
             },
         ];
@@ -1290,4 +1604,33 @@ mod tests {
             "Four bodies should create five nodes (including the root node)"
         );
     }
+
+    #[test]
+    fn test_rebuilding_from_bodies_keeps_leaves() {
+        let bodies: Vec<OctreeBody> = (0..40)
+            .map(|i| {
+                let t = i as Scalar;
+                OctreeBody {
+                    id: BodyId(i),
+                    position: Vector::new(libm::sin(t * 1.3), libm::cos(t * 0.7), t * 0.01) * 10.0,
+                    velocity: Vector::ZERO,
+                    mass: 1.0 + t,
+                }
+            })
+            .collect();
+        let mut octree = Octree::new(0.5, 1.0, 1e4).with_leaf_threshold(2);
+        octree.build(bodies);
+
+        let leaf_bodies = octree.bodies();
+        let mut rebuilt = Octree::new(0.5, 1.0, 1e4).with_leaf_threshold(2);
+        rebuilt.build(leaf_bodies.iter().copied());
+
+        let ids = |bodies: Vec<OctreeBody>| bodies.iter().map(|body| body.id).collect::<Vec<_>>();
+        assert_eq!(leaf_bodies.len(), 40);
+        assert_eq!(ids(rebuilt.bodies()), ids(leaf_bodies));
+        assert_eq!(
+            rebuilt.octree_stats().node_count,
+            octree.octree_stats().node_count
+        );
+    }
 }
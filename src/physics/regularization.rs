@@ -0,0 +1,473 @@
+//! Kustaanheimo-Stiefel regularization of tight bound pairs.
+//!
+//! The octree drops the force between bodies closer than its minimum distance, which lets a
+//! close binary fly apart. Pairs that are both that close and bound are instead taken out of the
+//! integrator for the step: the pair's centre of mass is integrated in their place, while their
+//! relative motion is advanced in KS coordinates. There the Kepler problem becomes a harmonic
+//! oscillator in four dimensions, so the relative orbit can be advanced exactly through any
+//! eccentricity without the singularity at zero separation.
+//!
+//! Perturbations from other bodies act only on the pair's centre of mass. That is accurate for
+//! the tight pairs selected here, and the pair is re-examined every step, so it is released as
+//! soon as it drifts apart.
+
+use super::BodyId;
+use super::integrator::IntegratedBody;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use glam::DVec4;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Iteration limit of the solver for the fictitious time matching a physical time step.
+const MAX_TIME_ITERATIONS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Regularization {
+    /// Pairs closer than this are candidates for regularization.
+    pub distance: Scalar,
+    /// Largest ratio of relative kinetic to potential energy of a regularized pair.
+    ///
+    /// A ratio below 1 means the pair is bound. Keeping away from 1 avoids nearly parabolic
+    /// encounters, where the KS oscillator frequency vanishes.
+    pub max_energy_ratio: Scalar,
+    pub g: Scalar,
+}
+
+/// Relative motion of two bodies in KS coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KsOrbit {
+    /// KS position, whose square norm is the separation.
+    pub u: DVec4,
+    /// Derivative of `u` with respect to the fictitious time `s`, where `dt = r ds`.
+    pub u_prime: DVec4,
+    /// Relative energy per unit reduced mass, negative for bound orbits.
+    pub energy: Scalar,
+}
+
+impl KsOrbit {
+    /// Transforms a relative position and velocity under gravitational parameter `gm`.
+    pub fn from_relative(position: Vector, velocity: Vector, gm: Scalar) -> Self {
+        let r = position.length();
+        let u = if position.x >= 0.0 {
+            let u1 = libm::sqrt(0.5 * (r + position.x));
+            DVec4::new(u1, 0.5 * position.y / u1, 0.5 * position.z / u1, 0.0)
+        } else {
+            let u2 = libm::sqrt(0.5 * (r - position.x));
+            DVec4::new(0.5 * position.y / u2, u2, 0.0, 0.5 * position.z / u2)
+        };
+        let u_prime = 0.5 * transposed_ks_matrix_product(u, velocity);
+
+        Self {
+            u,
+            u_prime,
+            energy: 0.5 * velocity.length_squared() - gm / r,
+        }
+    }
+
+    pub fn relative_position(&self) -> Vector {
+        ks_matrix_product(self.u, self.u)
+    }
+
+    pub fn relative_velocity(&self) -> Vector {
+        ks_matrix_product(self.u, self.u_prime) * (2.0 / self.u.length_squared())
+    }
+
+    /// Advances a bound orbit by the physical time `dt`.
+    ///
+    /// The KS equations of the unperturbed problem reduce to `u'' = (h / 2) u`, so `u` moves on a
+    /// harmonic oscillator of frequency `ω = sqrt(-h / 2)`. Physical time is the integral of
+    /// `|u|²` over the fictitious time, which has a closed form that is inverted numerically.
+    pub fn advance(&mut self, dt: Scalar) {
+        debug_assert!(self.energy < 0.0, "only bound orbits can be advanced");
+
+        let omega = libm::sqrt(-0.5 * self.energy);
+        let a = self.u.length_squared();
+        let b = self.u_prime.length_squared() / (omega * omega);
+        let c = self.u.dot(self.u_prime) / omega;
+
+        let time = |s: Scalar| {
+            let (sin, cos) = libm::sincos(omega * s);
+            0.5 * (a + b) * s + 0.5 * (a - b) * sin * cos / omega + c * sin * sin / omega
+        };
+        let rate = |s: Scalar| {
+            let (sin, cos) = libm::sincos(omega * s);
+            a * cos * cos + b * sin * sin + 2.0 * c * sin * cos
+        };
+
+        // Time grows monotonically with s, at (a + b) / 2 on average plus a bounded oscillation
+        let mut low = 0.0;
+        let mut high = 2.0 * (dt + (0.5 * (a - b).abs() + c.abs()) / omega) / (a + b);
+        let mut s = (dt / a).min(high);
+        for _ in 0..MAX_TIME_ITERATIONS {
+            let error = time(s) - dt;
+            if error.abs() <= Scalar::EPSILON * dt.max(1.0) {
+                break;
+            }
+
+            if error > 0.0 {
+                high = s;
+            } else {
+                low = s;
+            }
+
+            let newton = s - error / rate(s);
+            s = if newton > low && newton < high {
+                newton
+            } else {
+                0.5 * (low + high)
+            };
+        }
+
+        let (sin, cos) = libm::sincos(omega * s);
+        let u = self.u * cos + self.u_prime * (sin / omega);
+        self.u_prime = self.u_prime * cos - self.u * (omega * sin);
+        self.u = u;
+    }
+}
+
+/// A bound pair replaced by its centre of mass for the duration of a step.
+#[derive(Debug, Clone, Copy)]
+pub struct RegularizedPair {
+    pub primary: IntegratedBody,
+    pub secondary: IntegratedBody,
+    pub orbit: KsOrbit,
+}
+
+impl RegularizedPair {
+    pub fn mass(&self) -> Scalar {
+        self.primary.mass + self.secondary.mass
+    }
+
+    /// The pseudo-body standing in for the pair, which keeps the primary's id.
+    pub fn center_of_mass(&self) -> IntegratedBody {
+        let mass = self.mass();
+        IntegratedBody {
+            id: self.primary.id,
+            position: (self.primary.position * self.primary.mass
+                + self.secondary.position * self.secondary.mass)
+                / mass,
+            velocity: (self.primary.velocity * self.primary.mass
+                + self.secondary.velocity * self.secondary.mass)
+                / mass,
+            acceleration: (self.primary.acceleration * self.primary.mass
+                + self.secondary.acceleration * self.secondary.mass)
+                / mass,
+            jerk: (self.primary.jerk * self.primary.mass
+                + self.secondary.jerk * self.secondary.mass)
+                / mass,
+            mass,
+        }
+    }
+
+    /// Places both members around `center_of_mass` according to the current KS orbit.
+    fn members(&self, center_of_mass: &IntegratedBody) -> [IntegratedBody; 2] {
+        let mass = self.mass();
+        let position = self.orbit.relative_position();
+        let velocity = self.orbit.relative_velocity();
+
+        let member = |body: &IntegratedBody, share: Scalar| IntegratedBody {
+            position: center_of_mass.position + position * share,
+            velocity: center_of_mass.velocity + velocity * share,
+            acceleration: center_of_mass.acceleration,
+            jerk: center_of_mass.jerk,
+            ..*body
+        };
+
+        [
+            member(&self.primary, -self.secondary.mass / mass),
+            member(&self.secondary, self.primary.mass / mass),
+        ]
+    }
+}
+
+impl Regularization {
+    /// Whether two bodies are close and bound enough to be regularized.
+    pub fn is_candidate(&self, a: &IntegratedBody, b: &IntegratedBody) -> bool {
+        let separation = a.position.distance(b.position);
+        if separation >= self.distance || separation <= 0.0 {
+            return false;
+        }
+
+        let kinetic = 0.5 * a.velocity.distance_squared(b.velocity);
+        let potential = self.g * (a.mass + b.mass) / separation;
+        kinetic < self.max_energy_ratio * potential
+    }
+
+    /// Removes bound close pairs from `bodies`, leaving their centres of mass in their place.
+    ///
+    /// Candidates are found by sweeping along the x axis, and each body joins at most one pair,
+    /// closest pairs first. The returned pairs must be handed back to [`Self::release_pairs`].
+    pub fn capture_pairs(&self, bodies: &mut Vec<IntegratedBody>) -> Vec<RegularizedPair> {
+        let mut order: Vec<usize> = (0..bodies.len()).collect();
+        order.sort_by(|&a, &b| bodies[a].position.x.total_cmp(&bodies[b].position.x));
+
+        let mut candidates = Vec::new();
+        for (sorted_index, &a) in order.iter().enumerate() {
+            for &b in &order[sorted_index + 1..] {
+                if bodies[b].position.x - bodies[a].position.x >= self.distance {
+                    break;
+                }
+
+                if bodies[a].mass > 0.0
+                    && bodies[b].mass > 0.0
+                    && self.is_candidate(&bodies[a], &bodies[b])
+                {
+                    candidates.push((
+                        bodies[a].position.distance_squared(bodies[b].position),
+                        a,
+                        b,
+                    ));
+                }
+            }
+        }
+
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut paired = vec![false; bodies.len()];
+        let mut pairs = Vec::new();
+        for (_, a, b) in candidates {
+            if paired[a] || paired[b] {
+                continue;
+            }
+            paired[a] = true;
+            paired[b] = true;
+
+            let (primary, secondary) = if bodies[a].mass >= bodies[b].mass {
+                (bodies[a], bodies[b])
+            } else {
+                (bodies[b], bodies[a])
+            };
+            let pair = RegularizedPair {
+                primary,
+                secondary,
+                orbit: KsOrbit::from_relative(
+                    secondary.position - primary.position,
+                    secondary.velocity - primary.velocity,
+                    self.g * (primary.mass + secondary.mass),
+                ),
+            };
+
+            let primary_index = if primary.id == bodies[a].id { a } else { b };
+            bodies[primary_index] = pair.center_of_mass();
+            pairs.push(pair);
+        }
+
+        let secondaries: HashSet<BodyId> = pairs.iter().map(|pair| pair.secondary.id).collect();
+        bodies.retain(|body| !secondaries.contains(&body.id));
+
+        pairs
+    }
+
+    /// Advances each pair's relative orbit by `dt` and replaces its centre of mass in `bodies`
+    /// with both members again.
+    ///
+    /// The members inherit the centre of mass's acceleration, which lacks their mutual
+    /// attraction, so accelerations should be refreshed before they are used again.
+    pub fn release_pairs(
+        &self,
+        bodies: &mut Vec<IntegratedBody>,
+        mut pairs: Vec<RegularizedPair>,
+        dt: Scalar,
+    ) {
+        if pairs.is_empty() {
+            return;
+        }
+
+        let indices: HashMap<BodyId, usize> = bodies
+            .iter()
+            .enumerate()
+            .map(|(index, body)| (body.id, index))
+            .collect();
+
+        for pair in &mut pairs {
+            let Some(&index) = indices.get(&pair.primary.id) else {
+                continue;
+            };
+
+            if dt != 0.0 {
+                pair.orbit.advance(dt);
+            }
+
+            let [primary, secondary] = pair.members(&bodies[index]);
+            bodies[index] = primary;
+            bodies.push(secondary);
+        }
+    }
+}
+
+/// First three components of `L(u) v` for the KS matrix `L(u)`.
+#[inline]
+fn ks_matrix_product(u: DVec4, v: DVec4) -> Vector {
+    Vector::new(
+        u.x * v.x - u.y * v.y - u.z * v.z + u.w * v.w,
+        u.y * v.x + u.x * v.y - u.w * v.z - u.z * v.w,
+        u.z * v.x + u.w * v.y + u.x * v.z + u.y * v.w,
+    )
+}
+
+/// `L(u)ᵀ (v, 0)` for the KS matrix `L(u)`.
+#[inline]
+fn transposed_ks_matrix_product(u: DVec4, v: Vector) -> DVec4 {
+    DVec4::new(
+        u.x * v.x + u.y * v.y + u.z * v.z,
+        -u.y * v.x + u.x * v.y + u.w * v.z,
+        -u.z * v.x - u.w * v.y + u.x * v.z,
+        u.w * v.x - u.z * v.y + u.y * v.z,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::test_support::G;
+    use crate::physics::test_support::body;
+    use core::f64::consts::PI;
+
+    fn regularization() -> Regularization {
+        Regularization {
+            distance: 1.0,
+            max_energy_ratio: 0.9,
+            g: G,
+        }
+    }
+
+    #[test]
+    fn test_ks_transformation_round_trips() {
+        for position in [
+            Vector::new(0.3, -0.2, 0.7),
+            Vector::new(-0.3, 0.2, -0.7),
+            Vector::new(-1.0, 0.0, 0.0),
+        ] {
+            let velocity = Vector::new(0.1, 0.5, -0.4);
+            let orbit = KsOrbit::from_relative(position, velocity, 1.0);
+
+            assert!(orbit.relative_position().distance(position) < 1e-12);
+            assert!(orbit.relative_velocity().distance(velocity) < 1e-12);
+            assert!((orbit.u.length_squared() - position.length()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_ks_orbit_reaches_pericentre_after_half_period() {
+        let gm = 2.0;
+        let eccentricity = 0.99;
+        let apocentre = 1.0 + eccentricity;
+        let speed = libm::sqrt(gm * (1.0 - eccentricity) / apocentre);
+        let mut orbit = KsOrbit::from_relative(
+            Vector::new(apocentre, 0.0, 0.0),
+            Vector::new(0.0, speed, 0.0),
+            gm,
+        );
+        let period = 2.0 * PI / libm::sqrt(gm);
+
+        orbit.advance(0.5 * period);
+        let pericentre = orbit.relative_position();
+        assert!(
+            pericentre.distance(Vector::new(-(1.0 - eccentricity), 0.0, 0.0)) < 1e-8,
+            "expected pericentre, got {pericentre:?}"
+        );
+
+        orbit.advance(0.5 * period);
+        assert!(
+            orbit
+                .relative_position()
+                .distance(Vector::new(apocentre, 0.0, 0.0))
+                < 1e-8
+        );
+        assert!(
+            orbit
+                .relative_velocity()
+                .distance(Vector::new(0.0, speed, 0.0))
+                < 1e-8
+        );
+    }
+
+    #[test]
+    fn test_capture_selects_only_close_bound_pairs() {
+        let mut bodies = vec![
+            // A bound pair well inside the regularization distance
+            body(
+                0,
+                Vector::new(0.0, 0.0, 0.0),
+                Vector::new(0.0, 0.5, 0.0),
+                1.0,
+            ),
+            body(
+                1,
+                Vector::new(0.2, 0.0, 0.0),
+                Vector::new(0.0, -0.5, 0.0),
+                1.0,
+            ),
+            // A close but unbound flyby
+            body(
+                2,
+                Vector::new(10.0, 0.0, 0.0),
+                Vector::new(0.0, 50.0, 0.0),
+                1.0,
+            ),
+            body(
+                3,
+                Vector::new(10.2, 0.0, 0.0),
+                Vector::new(0.0, -50.0, 0.0),
+                1.0,
+            ),
+            // A distant body
+            body(4, Vector::new(20.0, 0.0, 0.0), Vector::ZERO, 1.0),
+        ];
+
+        let pairs = regularization().capture_pairs(&mut bodies);
+
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(bodies.len(), 4);
+        let center_of_mass = bodies
+            .iter()
+            .find(|body| body.id == pairs[0].primary.id)
+            .unwrap();
+        assert_eq!(center_of_mass.mass, 2.0);
+        assert!(center_of_mass.position.distance(Vector::new(0.1, 0.0, 0.0)) < 1e-12);
+        assert!(center_of_mass.velocity.length() < 1e-12);
+    }
+
+    #[test]
+    fn test_release_conserves_momentum_and_energy() {
+        let regularization = regularization();
+        let mut bodies = vec![
+            body(
+                0,
+                Vector::new(1.0, 2.0, 3.0),
+                Vector::new(0.3, 0.8, 0.0),
+                3.0,
+            ),
+            body(
+                1,
+                Vector::new(1.1, 2.05, 3.0),
+                Vector::new(0.1, -1.5, 0.2),
+                1.0,
+            ),
+        ];
+        let momentum = |bodies: &[IntegratedBody]| -> Vector {
+            bodies.iter().map(|body| body.velocity * body.mass).sum()
+        };
+        let energy = |bodies: &[IntegratedBody]| -> Scalar {
+            0.5 * bodies[0].mass * bodies[0].velocity.length_squared()
+                + 0.5 * bodies[1].mass * bodies[1].velocity.length_squared()
+                - G * bodies[0].mass * bodies[1].mass
+                    / bodies[0].position.distance(bodies[1].position)
+        };
+        let initial_momentum = momentum(&bodies);
+        let initial_energy = energy(&bodies);
+
+        let pairs = regularization.capture_pairs(&mut bodies);
+        assert_eq!(pairs.len(), 1);
+        regularization.release_pairs(&mut bodies, pairs, 0.37);
+
+        assert_eq!(bodies.len(), 2);
+        assert!(momentum(&bodies).distance(initial_momentum) < 1e-12);
+        assert!(((energy(&bodies) - initial_energy) / initial_energy).abs() < 1e-10);
+    }
+}
@@ -0,0 +1,469 @@
+//! The gravitational simulation on its own, without Bevy's ECS.
+//!
+//! [`Simulation`] owns its bodies and advances them exactly as the app does, so the physics can
+//! be driven from scripts, bindings and tests without building an `App`. The app keeps its
+//! bodies as entities instead, and copies them into and out of a [`Simulation`] every fixed
+//! step. Collisions, stellar evolution and the other effects that Avian and the app's systems
+//! take care of are not part of the core.
+
+use super::BodyId;
+use super::block_timesteps::BlockTimesteps;
+use super::conservation::ConservedQuantities;
+use super::hermite::BlockHermite;
+use super::integrator;
+use super::integrator::IntegratedBody;
+use super::integrator::IntegratorKind;
+use super::octree::Octree;
+use super::octree::OctreeBody;
+use super::regularization::Regularization;
+use super::wisdom_holman;
+use crate::config::PhysicsConfig;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use log::info;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// An octree with the opening angle, force limits and leaf size from `config`.
+pub fn octree_from_config(config: &PhysicsConfig) -> Octree {
+    Octree::new(
+        config.octree_theta,
+        config.force_calculation_min_distance,
+        config.force_calculation_max_force,
+    )
+    .with_leaf_threshold(config.octree_leaf_threshold)
+}
+
+/// What carries over from one step to the next, apart from the bodies themselves.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stepper {
+    had_regularized_pairs: bool,
+    dominated_by_one_body: bool,
+}
+
+impl Stepper {
+    /// Advances `bodies` by `dt` with the integrator `config` asks for, computing gravity with
+    /// `octree`.
+    ///
+    /// With adaptive timesteps the step is split into block timesteps, using Hermite steps and
+    /// Aarseth's criterion with the Hermite integrator and leapfrog steps otherwise. Bound pairs
+    /// closer than the force calculation minimum distance can be regularized: the integrator then
+    /// only sees their centre of mass, and their relative orbit is advanced in KS coordinates.
+    /// With `wisdom_holman_mass_fraction` set, the Wisdom-Holman map takes over from the
+    /// configured integrator whenever there are at least two bodies and one of them holds that
+    /// fraction of the mass.
+    ///
+    /// Bodies' cached accelerations are reused unless `accelerations_stale` is set, which it has
+    /// to be whenever bodies have come or gone since the last step. Returns the number of bodies
+    /// that started the step on each block timestep level, when adaptive timesteps are used.
+    pub fn step(
+        &mut self,
+        bodies: &mut Vec<IntegratedBody>,
+        dt: Scalar,
+        octree: &mut Octree,
+        config: &PhysicsConfig,
+        accelerations_stale: bool,
+    ) -> Option<Vec<usize>> {
+        let regularization = Regularization {
+            distance: config.force_calculation_min_distance,
+            max_energy_ratio: config.regularization_max_energy_ratio,
+            g: config.gravitational_constant,
+        };
+        let regularized_pairs = if config.regularization {
+            regularization.capture_pairs(bodies)
+        } else {
+            Vec::new()
+        };
+
+        let mut gravity = integrator::OctreeGravity {
+            octree,
+            g: config.gravitational_constant,
+        };
+
+        // Regularized pairs are swapped for or restored from their centre of mass, which
+        // invalidates the cached accelerations as well
+        let has_regularized_pairs = !regularized_pairs.is_empty();
+        if accelerations_stale || has_regularized_pairs || self.had_regularized_pairs {
+            integrator::update_accelerations(&mut gravity, bodies);
+        }
+        self.had_regularized_pairs = has_regularized_pairs;
+
+        let dominated = config.wisdom_holman_mass_fraction.is_some_and(|fraction| {
+            bodies.len() >= 2 && wisdom_holman::dominant_mass_fraction(bodies) >= fraction
+        });
+        if dominated != self.dominated_by_one_body {
+            if dominated {
+                info!("One body dominates the mass; switching to the Wisdom-Holman integrator");
+            } else {
+                info!(
+                    "No body dominates the mass any more; switching back to the configured integrator"
+                );
+            }
+            self.dominated_by_one_body = dominated;
+        }
+
+        let timestep_levels = if dominated {
+            IntegratorKind::WisdomHolman.step(bodies, dt, &mut gravity);
+            None
+        } else if config.adaptive_timesteps && config.integrator == IntegratorKind::Hermite4 {
+            let block_hermite = BlockHermite {
+                min_step: config.adaptive_timestep_min,
+                max_step: config.adaptive_timestep_max,
+                accuracy: config.hermite_accuracy,
+            };
+            Some(block_hermite.step(bodies, dt, &mut gravity))
+        } else if config.adaptive_timesteps {
+            let block_timesteps = BlockTimesteps {
+                min_step: config.adaptive_timestep_min,
+                max_step: config.adaptive_timestep_max,
+                accuracy: config.adaptive_timestep_accuracy,
+                softening: config.force_calculation_min_distance,
+            };
+            Some(block_timesteps.step(bodies, dt, &mut gravity))
+        } else {
+            config.integrator.step(bodies, dt, &mut gravity);
+            None
+        };
+
+        regularization.release_pairs(bodies, regularized_pairs, dt);
+        timestep_levels
+    }
+}
+
+/// The bodies and simulated time of a [`Simulation`] at one moment.
+#[derive(Debug, Clone)]
+pub struct SimulationSnapshot {
+    pub time: Scalar,
+    pub bodies: Vec<OctreeBody>,
+}
+
+/// Bodies moving under their mutual gravitation, configured by a [`PhysicsConfig`].
+///
+/// The Bevy app keeps one as a resource and replaces its bodies with the entities' before every
+/// fixed step, see [`Simulation::sync_bodies`].
+#[derive(Debug)]
+pub struct Simulation {
+    /// Bodies in the order their forces are summed in.
+    bodies: Vec<IntegratedBody>,
+    config: PhysicsConfig,
+    /// Simulated time elapsed since the simulation was created.
+    pub time: Scalar,
+    octree: Octree,
+    stepper: Stepper,
+    next_id: u64,
+    accelerations_stale: bool,
+}
+
+impl Simulation {
+    /// A simulation without bodies.
+    pub fn new(config: PhysicsConfig) -> Self {
+        Self {
+            bodies: Vec::new(),
+            octree: octree_from_config(&config),
+            config,
+            time: 0.0,
+            stepper: Stepper::default(),
+            next_id: 0,
+            accelerations_stale: true,
+        }
+    }
+
+    /// Adds a body and returns its id, which is never given to another body of this simulation.
+    pub fn add_body(&mut self, position: Vector, velocity: Vector, mass: Scalar) -> BodyId {
+        let id = BodyId(self.next_id);
+        self.next_id += 1;
+        self.bodies.push(IntegratedBody {
+            id,
+            position,
+            velocity,
+            acceleration: Vector::ZERO,
+            jerk: Vector::ZERO,
+            mass,
+        });
+        self.accelerations_stale = true;
+        id
+    }
+
+    /// Removes the body with the given id, returning it if there was one.
+    pub fn remove_body(&mut self, id: BodyId) -> Option<IntegratedBody> {
+        let index = self.bodies.iter().position(|body| body.id == id)?;
+        self.accelerations_stale = true;
+        Some(self.bodies.remove(index))
+    }
+
+    pub fn body(&self, id: BodyId) -> Option<&IntegratedBody> {
+        self.bodies.iter().find(|body| body.id == id)
+    }
+
+    /// Bodies in the order their forces are summed in.
+    pub fn bodies(&self) -> &[IntegratedBody] {
+        &self.bodies
+    }
+
+    /// Replaces every body with `bodies`, whose ids are their own.
+    ///
+    /// Bodies that are already part of the simulation keep their place in the order forces are
+    /// summed in, whatever order `bodies` come in, and new ones are added at the end. Cached
+    /// accelerations are reused by the next step, unless bodies have come or gone since the last
+    /// one.
+    pub fn sync_bodies(&mut self, bodies: impl IntoIterator<Item = IntegratedBody>) {
+        let mut incoming: Vec<Option<IntegratedBody>> = bodies.into_iter().map(Some).collect();
+        let indices: HashMap<BodyId, usize> = incoming
+            .iter()
+            .enumerate()
+            .filter_map(|(index, body)| body.map(|body| (body.id, index)))
+            .collect();
+
+        let previous_count = self.bodies.len();
+        self.bodies.retain_mut(|body| {
+            match indices
+                .get(&body.id)
+                .and_then(|&index| incoming[index].take())
+            {
+                Some(synced) => {
+                    *body = synced;
+                    true
+                }
+                None => false,
+            }
+        });
+        let kept_count = self.bodies.len();
+        self.bodies.extend(incoming.into_iter().flatten());
+
+        self.accelerations_stale |= kept_count != previous_count || self.bodies.len() != kept_count;
+    }
+
+    /// Continues from `bodies` and `stepper` as they were saved from [`Simulation::bodies`] and
+    /// [`Simulation::stepper`], reusing the bodies' cached accelerations.
+    pub fn restore(&mut self, bodies: Vec<IntegratedBody>, stepper: Stepper) {
+        self.bodies = bodies;
+        self.stepper = stepper;
+        self.accelerations_stale = false;
+    }
+
+    pub fn config(&self) -> &PhysicsConfig {
+        &self.config
+    }
+
+    /// Replaces the configuration, along with the octree it sets up.
+    pub fn set_config(&mut self, config: PhysicsConfig) {
+        self.octree = octree_from_config(&config);
+        self.config = config;
+        self.accelerations_stale = true;
+    }
+
+    /// The octree gravity was last computed with.
+    ///
+    /// It is rebuilt from the bodies during every step, so it can be rebuilt from other bodies
+    /// in between without affecting the simulation.
+    pub fn octree(&self) -> &Octree {
+        &self.octree
+    }
+
+    pub fn octree_mut(&mut self) -> &mut Octree {
+        &mut self.octree
+    }
+
+    /// What the next step carries over from the previous one, apart from the bodies.
+    pub fn stepper(&self) -> Stepper {
+        self.stepper
+    }
+
+    /// Advances every body by `dt`, if it is positive. Returns the number of bodies that started
+    /// the step on each block timestep level, when adaptive timesteps are used.
+    pub fn step(&mut self, dt: Scalar) -> Option<Vec<usize>> {
+        if self.bodies.is_empty() || dt <= 0.0 {
+            return None;
+        }
+
+        let timestep_levels = self.stepper.step(
+            &mut self.bodies,
+            dt,
+            &mut self.octree,
+            &self.config,
+            self.accelerations_stale,
+        );
+        self.accelerations_stale = false;
+        self.time += dt;
+        timestep_levels
+    }
+
+    pub fn octree_bodies(&self) -> Vec<OctreeBody> {
+        self.bodies
+            .iter()
+            .map(IntegratedBody::as_octree_body)
+            .collect()
+    }
+
+    /// Energy, momentum and angular momentum of all bodies, with the potential energy from a
+    /// Barnes-Hut octree like the forces.
+    pub fn conserved_quantities(&self) -> ConservedQuantities {
+        let bodies = self.octree_bodies();
+        let mut octree = octree_from_config(&self.config);
+        octree.build(bodies.iter().copied());
+        ConservedQuantities::measure(&bodies, &octree, self.config.gravitational_constant)
+    }
+
+    /// Total kinetic and potential energy of all bodies.
+    pub fn energy(&self) -> Scalar {
+        self.conserved_quantities().total_energy()
+    }
+
+    pub fn snapshot(&self) -> SimulationSnapshot {
+        SimulationSnapshot {
+            time: self.time,
+            bodies: self.octree_bodies(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> PhysicsConfig {
+        PhysicsConfig {
+            gravitational_constant: 1.0,
+            octree_theta: 0.5,
+            force_calculation_min_distance: 1e-3,
+            force_calculation_max_force: 1e12,
+            integrator: IntegratorKind::LeapfrogKdk,
+            adaptive_timesteps: false,
+            regularization: false,
+            ..PhysicsConfig::default()
+        }
+    }
+
+    /// Two equal masses on a circular orbit of separation 1 around their centre of mass.
+    fn circular_binary() -> Simulation {
+        let mut simulation = Simulation::new(test_config());
+        let speed = 0.5 * libm::sqrt(2.0);
+        simulation.add_body(
+            Vector::new(0.5, 0.0, 0.0),
+            Vector::new(0.0, speed, 0.0),
+            1.0,
+        );
+        simulation.add_body(
+            Vector::new(-0.5, 0.0, 0.0),
+            Vector::new(0.0, -speed, 0.0),
+            1.0,
+        );
+        simulation
+    }
+
+    #[test]
+    fn test_bodies_keep_their_ids() {
+        let mut simulation = Simulation::new(test_config());
+        let first = simulation.add_body(Vector::ZERO, Vector::ZERO, 1.0);
+        let second = simulation.add_body(Vector::X, Vector::ZERO, 2.0);
+        assert_ne!(first, second);
+
+        let removed = simulation.remove_body(first).unwrap();
+        assert_eq!(removed.id, first);
+        assert!(simulation.remove_body(first).is_none());
+        assert!(simulation.body(first).is_none());
+        assert_eq!(simulation.body(second).unwrap().mass, 2.0);
+
+        let third = simulation.add_body(Vector::Y, Vector::ZERO, 3.0);
+        assert_ne!(third, first);
+        assert_ne!(third, second);
+    }
+
+    #[test]
+    fn test_synced_bodies_keep_their_place() {
+        let mut simulation = circular_binary();
+        let mut bodies = simulation.bodies().to_vec();
+        bodies.reverse();
+        bodies.push(IntegratedBody {
+            id: BodyId(7),
+            ..bodies[0]
+        });
+
+        simulation.sync_bodies(bodies);
+
+        let ids: Vec<BodyId> = simulation.bodies().iter().map(|body| body.id).collect();
+        assert_eq!(ids, vec![BodyId(0), BodyId(1), BodyId(7)]);
+    }
+
+    #[test]
+    fn test_restored_simulation_continues_exactly() {
+        let mut simulation = circular_binary();
+        (0..10).for_each(|_| {
+            simulation.step(0.01);
+        });
+
+        let mut restored = Simulation::new(test_config());
+        restored.time = simulation.time;
+        restored.restore(simulation.bodies().to_vec(), simulation.stepper());
+        (0..10).for_each(|_| {
+            simulation.step(0.01);
+            restored.step(0.01);
+        });
+
+        let state = |simulation: &Simulation| {
+            simulation
+                .bodies()
+                .iter()
+                .map(|body| (body.position, body.velocity, body.acceleration))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(restored.time, simulation.time);
+        assert_eq!(state(&restored), state(&simulation));
+    }
+
+    #[test]
+    fn test_wisdom_holman_takes_over_only_when_enabled() {
+        let star_and_planet = |fraction| {
+            let mut simulation = Simulation::new(PhysicsConfig {
+                wisdom_holman_mass_fraction: fraction,
+                ..test_config()
+            });
+            simulation.add_body(Vector::ZERO, Vector::ZERO, 1000.0);
+            simulation.add_body(Vector::X * 10.0, Vector::Y * 10.0, 1.0);
+            simulation.step(0.01);
+            simulation.stepper().dominated_by_one_body
+        };
+        assert!(!star_and_planet(None));
+        assert!(star_and_planet(Some(0.9)));
+
+        let mut lone_star = Simulation::new(PhysicsConfig {
+            wisdom_holman_mass_fraction: Some(0.9),
+            ..test_config()
+        });
+        lone_star.add_body(Vector::ZERO, Vector::X, 1000.0);
+        lone_star.step(0.5);
+        assert!(!lone_star.stepper().dominated_by_one_body);
+        assert_eq!(lone_star.bodies()[0].position, Vector::X * 0.5);
+    }
+
+    #[test]
+    fn test_circular_binary_conserves_energy() {
+        let mut simulation = circular_binary();
+        let initial_energy = simulation.energy();
+        assert!((initial_energy + 0.5).abs() < 1e-12);
+
+        for _ in 0..1000 {
+            simulation.step(0.01);
+        }
+
+        assert!((simulation.time - 10.0).abs() < 1e-9);
+        assert!((simulation.energy() - initial_energy).abs() < 1e-4 * initial_energy.abs());
+        let separation = simulation.bodies()[0]
+            .position
+            .distance(simulation.bodies()[1].position);
+        assert!((separation - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_snapshot_is_unaffected_by_later_steps() {
+        let mut simulation = circular_binary();
+        simulation.step(0.01);
+        let snapshot = simulation.snapshot();
+        simulation.step(0.01);
+
+        assert_eq!(snapshot.time, 0.01);
+        assert_eq!(snapshot.bodies.len(), 2);
+        assert_ne!(snapshot.bodies[0].position, simulation.bodies()[0].position);
+    }
+}
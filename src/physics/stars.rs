@@ -1,42 +1,52 @@
-use crate::resources;
-use crate::utils;
+//! Stellar structure and evolution in solar units, with ages in Gyr.
+//!
+//! A star's structure follows from its mass and its age relative to its main-sequence lifetime
+//! through simple empirical relations. Stars above eight solar masses become supergiants and
+//! end in a supernova, stars above half a solar mass become giants and then white dwarfs, and
+//! lighter stars stay on the main sequence. Winds shed mass in proportion to `L R / M`, as in
+//! Reimers' law, but never below the mass of the remnant a star will leave.
+
 use avian3d::math::Scalar;
-use avian3d::prelude::*;
-use bevy::prelude::*;
 use libm::pow;
 use rand::Rng;
-use rand_chacha::ChaCha8Rng;
-
-#[derive(Resource, Default)]
-struct StellarParameters {
-    solar_mass: Scalar,
-    solar_radius: Scalar,
-    solar_luminosity: Scalar,
-    solar_temperature: Scalar,
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Initial mass above which a star ends in a supernova.
+const SUPERNOVA_MIN_MASS: Scalar = 8.0;
+/// Initial mass above which a supernova leaves a black hole rather than a neutron star.
+const BLACK_HOLE_MIN_MASS: Scalar = 20.0;
+/// Initial mass above which a star leaves the main sequence within the age of the universe.
+const GIANT_MIN_MASS: Scalar = 0.5;
+const SUPERGIANT_DURATION_GYR: Scalar = 0.1;
+const GIANT_BRANCH_DURATION_GYR: Scalar = 2.0;
+const NEUTRON_STAR_MASS: Scalar = 1.4;
+const WHITE_DWARF_MASS: Scalar = 0.6;
+/// Reimers' wind coefficient, in solar masses per Gyr for a star of one solar luminosity,
+/// radius and mass.
+const REIMERS_COEFFICIENT: Scalar = 4e-4;
+
+/// Solar units expressed in simulation units.
+///
+/// A Sun is given about the mass of a default body of unit density and a radius of a few body
+/// radii, so a stellar population moves on the same time scale as the other scenarios.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StellarParameters {
+    pub solar_mass: Scalar,
+    pub solar_radius: Scalar,
 }
 
-impl StellarParameters {
-    fn new() -> Self {
+impl Default for StellarParameters {
+    fn default() -> Self {
         Self {
-            solar_mass: 1.0,
+            solar_mass: 1e3,
             solar_radius: 10.0,
-            solar_luminosity: 1.0,
-            solar_temperature: 5778.0,
         }
     }
 }
 
-#[derive(Component)]
-struct StellarProperties {
-    mass_solar: Scalar,
-    radius_solar: Scalar,
-    luminosity_solar: Scalar,
-    temperature: Scalar,
-    stellar_class: StellarClass,
-}
-
-#[derive(Debug, Clone)]
-enum StellarClass {
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StellarClass {
     O,
     B,
     A,
@@ -47,7 +57,7 @@ enum StellarClass {
 }
 
 impl StellarClass {
-    fn from_temperature(temp: Scalar) -> Self {
+    pub fn from_temperature(temp: Scalar) -> Self {
         match temp {
             t if t >= 30000.0 => StellarClass::O,
             t if t >= 10000.0 => StellarClass::B,
@@ -60,8 +70,194 @@ impl StellarClass {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StellarPhase {
+    #[default]
+    MainSequence,
+    Giant,
+    Supergiant,
+    WhiteDwarf,
+    NeutronStar,
+    BlackHole,
+}
+
+impl StellarPhase {
+    pub fn is_remnant(self) -> bool {
+        matches!(
+            self,
+            StellarPhase::WhiteDwarf | StellarPhase::NeutronStar | StellarPhase::BlackHole
+        )
+    }
+}
+
+/// The evolutionary state of a star.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Star {
+    pub initial_mass: Scalar,
+    pub mass: Scalar,
+    pub age_gyr: Scalar,
+    pub radius: Scalar,
+    pub luminosity: Scalar,
+    pub temperature: Scalar,
+    pub phase: StellarPhase,
+}
+
+impl Star {
+    /// A star of the given initial mass at the given age, without the mass it would have lost
+    /// to winds on the way.
+    pub fn new(initial_mass: Scalar, age_gyr: Scalar) -> Self {
+        let mut star = Self {
+            initial_mass,
+            mass: initial_mass,
+            age_gyr,
+            radius: 0.0,
+            luminosity: 0.0,
+            temperature: 0.0,
+            phase: StellarPhase::MainSequence,
+        };
+        star.phase = star.phase_at_age();
+        if star.phase.is_remnant() {
+            star.mass = star.remnant_mass();
+        }
+        star.update_structure();
+        star
+    }
+
+    pub fn main_sequence_lifetime(&self) -> Scalar {
+        main_sequence_lifetime(self.initial_mass)
+    }
+
+    pub fn stellar_class(&self) -> StellarClass {
+        StellarClass::from_temperature(self.temperature)
+    }
+
+    /// Mass of the remnant the star leaves, or its current mass if it leaves none.
+    pub fn remnant_mass(&self) -> Scalar {
+        match self.initial_mass {
+            m if m > BLACK_HOLE_MIN_MASS => 0.1 * m,
+            m if m > SUPERNOVA_MIN_MASS => NEUTRON_STAR_MASS,
+            m if m > GIANT_MIN_MASS => WHITE_DWARF_MASS.min(self.mass),
+            _ => self.mass,
+        }
+    }
+
+    /// Ages the star by `dt_gyr`, losing mass to winds with the given Reimers efficiency.
+    ///
+    /// Returns the phase the star left if it moved to a new one. A star that reaches the end of
+    /// its supergiant or giant phase sheds everything but its remnant at once.
+    pub fn evolve(&mut self, dt_gyr: Scalar, wind_efficiency: Scalar) -> Option<StellarPhase> {
+        if dt_gyr <= 0.0 {
+            return None;
+        }
+
+        self.age_gyr += dt_gyr;
+        if !self.phase.is_remnant() {
+            let wind_loss = wind_efficiency * REIMERS_COEFFICIENT * self.luminosity * self.radius
+                / self.mass
+                * dt_gyr;
+            self.mass = (self.mass - wind_loss).max(self.remnant_mass());
+        }
+
+        let previous_phase = self.phase;
+        self.phase = self.phase_at_age();
+        if self.phase.is_remnant() && !previous_phase.is_remnant() {
+            self.mass = self.remnant_mass();
+        }
+        self.update_structure();
+
+        (self.phase != previous_phase).then_some(previous_phase)
+    }
+
+    fn phase_at_age(&self) -> StellarPhase {
+        let post_main_sequence_age = self.age_gyr - self.main_sequence_lifetime();
+        if post_main_sequence_age < 0.0 || self.initial_mass <= GIANT_MIN_MASS {
+            StellarPhase::MainSequence
+        } else if self.initial_mass > SUPERNOVA_MIN_MASS {
+            if post_main_sequence_age < SUPERGIANT_DURATION_GYR {
+                StellarPhase::Supergiant
+            } else if self.initial_mass > BLACK_HOLE_MIN_MASS {
+                StellarPhase::BlackHole
+            } else {
+                StellarPhase::NeutronStar
+            }
+        } else if post_main_sequence_age < GIANT_BRANCH_DURATION_GYR {
+            StellarPhase::Giant
+        } else {
+            StellarPhase::WhiteDwarf
+        }
+    }
+
+    fn update_structure(&mut self) {
+        let (radius, luminosity, temperature) = match self.phase {
+            StellarPhase::MainSequence => {
+                // Stars brighten and swell slowly as they burn through their core hydrogen
+                let progress = (self.age_gyr / self.main_sequence_lifetime()).clamp(0.0, 1.0);
+                let (radius, luminosity, _) = calculate_stellar_properties(self.mass);
+                let radius = radius * (1.0 + 0.3 * progress);
+                let luminosity = luminosity * (1.0 + 0.5 * progress);
+                (
+                    radius,
+                    luminosity,
+                    effective_temperature(luminosity, radius),
+                )
+            }
+            StellarPhase::Giant => {
+                let progress =
+                    (self.age_gyr - self.main_sequence_lifetime()) / GIANT_BRANCH_DURATION_GYR;
+                let (radius, luminosity, temperature) = calculate_stellar_properties(self.mass);
+                (
+                    radius * (1.0 + 20.0 * progress),
+                    luminosity * 2.0,
+                    temperature * 0.7,
+                )
+            }
+            StellarPhase::Supergiant => {
+                let (radius, luminosity, temperature) = calculate_stellar_properties(self.mass);
+                (radius * 50.0, luminosity * 10.0, temperature * 0.6)
+            }
+            StellarPhase::WhiteDwarf => (0.01, 0.001, 10000.0),
+            StellarPhase::NeutronStar | StellarPhase::BlackHole => (0.01, 0.001, 1000.0),
+        };
+
+        self.radius = radius;
+        self.luminosity = luminosity;
+        self.temperature = temperature;
+    }
+}
+
+/// Main-sequence lifetime in Gyr of a star of the given initial mass.
+pub fn main_sequence_lifetime(mass_solar: Scalar) -> Scalar {
+    match mass_solar {
+        m if m > 15.0 => 0.01, // Very massive stars live ~10 Myr
+        m if m > 8.0 => 0.03,  // Massive stars live ~30 Myr
+        m if m > 3.0 => 0.5,   // Medium stars live ~500 Myr
+        m if m > 1.5 => 2.0,   // Sun-like stars live ~2 Gyr
+        m if m > 1.0 => 10.0,  // Lower mass stars live ~10 Gyr
+        _ => 100.0,            // Red dwarfs live >100 Gyr
+    }
+}
+
+/// Distribution of initial stellar masses.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InitialMassFunction {
+    Salpeter,
+    #[default]
+    Kroupa,
+}
+
+impl InitialMassFunction {
+    pub fn sample(self, rng: &mut impl Rng) -> Scalar {
+        match self {
+            InitialMassFunction::Salpeter => sample_stellar_mass_salpeter(rng),
+            InitialMassFunction::Kroupa => sample_stellar_mass_kroupa(rng),
+        }
+    }
+}
+
 // Salpeter Initial Mass Function (simplified)
-fn sample_stellar_mass_salpeter(rng: &mut ChaCha8Rng) -> Scalar {
+pub fn sample_stellar_mass_salpeter(rng: &mut impl Rng) -> Scalar {
     let alpha = 2.35; // Salpeter slope
     let min_mass = 0.1; // Minimum mass in solar masses
     let max_mass = 100.0; // Maximum mass in solar masses
@@ -78,7 +274,7 @@ fn sample_stellar_mass_salpeter(rng: &mut ChaCha8Rng) -> Scalar {
 }
 
 // More realistic Kroupa IMF (broken power law)
-fn sample_stellar_mass_kroupa(rng: &mut ChaCha8Rng) -> Scalar {
+pub fn sample_stellar_mass_kroupa(rng: &mut impl Rng) -> Scalar {
     let u: f64 = rng.random();
 
     // Kroupa IMF has different slopes for different mass ranges
@@ -115,7 +311,7 @@ fn sample_stellar_mass_kroupa(rng: &mut ChaCha8Rng) -> Scalar {
 }
 
 // Calculate stellar properties from mass using empirical relations
-fn calculate_stellar_properties(mass_solar: Scalar) -> (Scalar, Scalar, Scalar) {
+pub fn calculate_stellar_properties(mass_solar: Scalar) -> (Scalar, Scalar, Scalar) {
     // Mass-luminosity relation (main sequence)
     let luminosity_solar = if mass_solar < 0.43 {
         0.23 * pow(mass_solar, 2.3)
@@ -136,133 +332,68 @@ fn calculate_stellar_properties(mass_solar: Scalar) -> (Scalar, Scalar, Scalar)
 
     // Calculate radius from Stefan-Boltzmann law: L = 4πR²σT⁴
     // R = sqrt(L / (4π σ T⁴)) in solar units
-    // let stefan_boltzmann = 5.67e-8; // Not needed for ratio calculation
     let temp_ratio_4th = pow(temperature / 5778.0, 4.0);
     let radius_solar = libm::sqrt(luminosity_solar / temp_ratio_4th);
 
     (radius_solar, luminosity_solar, temperature)
 }
 
-fn apply_stellar_evolution(
-    mass_solar: Scalar,
-    age_gyr: Scalar,
-    rng: &mut ChaCha8Rng,
-) -> (Scalar, Scalar, Scalar, bool) {
-    let main_sequence_lifetime = match mass_solar {
-        m if m > 15.0 => 0.01, // Very massive stars live ~10 Myr
-        m if m > 8.0 => 0.03,  // Massive stars live ~30 Myr
-        m if m > 3.0 => 0.5,   // Medium stars live ~500 Myr
-        m if m > 1.5 => 2.0,   // Sun-like stars live ~2 Gyr
-        m if m > 1.0 => 10.0,  // Lower mass stars live ~10 Gyr
-        _ => 100.0,            // Red dwarfs live >100 Gyr
-    };
+/// Effective temperature of a star with the given luminosity and radius in solar units, from
+/// the Stefan-Boltzmann law.
+fn effective_temperature(luminosity_solar: Scalar, radius_solar: Scalar) -> Scalar {
+    5778.0 * pow(luminosity_solar / (radius_solar * radius_solar), 0.25)
+}
 
-    let is_evolved = age_gyr > main_sequence_lifetime;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if !is_evolved {
-        // Still on main sequence
-        let (radius, luminosity, temperature) = calculate_stellar_properties(mass_solar);
-        (radius, luminosity, temperature, false)
-    } else {
-        // Post-main sequence evolution (simplified)
-        if mass_solar > 8.0 {
-            // Massive stars → supergiants or already exploded
-            let prob_exploded = (age_gyr - main_sequence_lifetime) / 0.1;
-            if rng.random::<f64>() < prob_exploded {
-                // Star has exploded, might be neutron star or black hole
-                (0.01, 0.001, 1000.0, true) // Compact remnant
-            } else {
-                // Red/blue supergiant
-                let (base_radius, base_luminosity, base_temp) =
-                    calculate_stellar_properties(mass_solar);
-                (
-                    base_radius * 50.0,
-                    base_luminosity * 10.0,
-                    base_temp * 0.6,
-                    true,
-                )
-            }
-        } else if mass_solar > 0.5 {
-            // Lower mass stars → red giants → white dwarfs
-            let evolution_phase = (age_gyr - main_sequence_lifetime) / 2.0;
-            if evolution_phase < 1.0 {
-                // Red giant branch
-                let (base_radius, base_luminosity, base_temp) =
-                    calculate_stellar_properties(mass_solar);
-                let expansion_factor = 1.0 + evolution_phase * 20.0;
-                (
-                    base_radius * expansion_factor,
-                    base_luminosity * 2.0,
-                    base_temp * 0.7,
-                    true,
-                )
-            } else {
-                // White dwarf
-                (0.01, 0.001, 10000.0, true)
+    #[test]
+    fn test_massive_star_ends_in_supernova_after_supergiant_phase() {
+        let mut star = Star::new(12.0, 0.0);
+        assert_eq!(star.phase, StellarPhase::MainSequence);
+        let main_sequence_radius = star.radius;
+
+        let mut transitions = Vec::new();
+        for _ in 0..200 {
+            if let Some(previous) = star.evolve(0.001, 0.5) {
+                transitions.push((previous, star.phase));
             }
-        } else {
-            // Very low mass stars don't evolve significantly
-            let (radius, luminosity, temperature) = calculate_stellar_properties(mass_solar);
-            (radius, luminosity, temperature, false)
         }
+
+        assert_eq!(
+            transitions,
+            vec![
+                (StellarPhase::MainSequence, StellarPhase::Supergiant),
+                (StellarPhase::Supergiant, StellarPhase::NeutronStar),
+            ]
+        );
+        assert_eq!(star.mass, NEUTRON_STAR_MASS);
+        assert!(star.radius < main_sequence_radius);
     }
-}
 
-pub(crate) fn spawn_realistic_stellar_bodies(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut rng: ResMut<resources::SharedRng>,
-    body_count: Res<resources::BodyCount>,
-) {
-    let stellar_params = StellarParameters::new();
-    let stellar_population_max_age_gyr = 10.0; // Assume 10 billion year old stellar population
-
-    for _ in 0..**body_count {
-        let body_distribution_sphere_radius =
-            utils::math::min_sphere_radius_for_surface_distribution(**body_count, 1000.0, 0.001);
-        let position = utils::math::random_unit_vector(&mut rng) * body_distribution_sphere_radius;
-        let transform = Transform::from_translation(position.as_vec3());
-
-        let mass_solar = sample_stellar_mass_kroupa(&mut rng);
-
-        let age_gyr = rng.random_range(0.1..stellar_population_max_age_gyr);
-
-        let (radius_solar, luminosity_solar, temperature, is_evolved) =
-            apply_stellar_evolution(mass_solar, age_gyr, &mut rng);
-
-        let radius_sim = radius_solar * stellar_params.solar_radius;
-        let mass_sim = mass_solar * stellar_params.solar_mass;
-
-        let stellar_props = StellarProperties {
-            mass_solar,
-            radius_solar,
-            luminosity_solar,
-            temperature,
-            stellar_class: StellarClass::from_temperature(temperature),
-        };
+    #[test]
+    fn test_winds_shed_mass_without_going_below_remnant() {
+        let mut star = Star::new(3.0, 0.4);
+        let initial_luminosity = star.luminosity;
 
-        let mesh = meshes.add(Sphere::new(radius_sim as f32));
+        star.evolve(0.05, 1.0);
 
-        let bloom_intensity = (luminosity_solar * 100.0).clamp(100.0, 10000.0);
-        let saturation_intensity = if is_evolved { 1.0 } else { 2.0 };
-        let material = utils::color::emissive_material_for_temp(
-            &mut materials,
-            temperature,
-            bloom_intensity,
-            saturation_intensity,
-        );
+        assert!(star.mass < 3.0);
+        assert!(star.mass > star.remnant_mass());
+        assert!(star.luminosity != initial_luminosity);
+
+        while star.evolve(0.5, 1.0) != Some(StellarPhase::Giant) {}
+        assert_eq!(star.phase, StellarPhase::WhiteDwarf);
+        assert_eq!(star.mass, WHITE_DWARF_MASS);
+    }
+
+    #[test]
+    fn test_red_dwarf_stays_on_main_sequence() {
+        let mut star = Star::new(0.3, 5.0);
 
-        commands.spawn((
-            Name::new(format!("Star-{mass_solar:.2}M☉-{temperature:.0}K")),
-            transform,
-            Collider::sphere(radius_sim),
-            GravityScale(0.0),
-            RigidBody::Dynamic,
-            MeshMaterial3d(material.clone()),
-            Mesh3d(mesh),
-            stellar_props,
-            Mass(mass_sim as f32),
-        ));
+        assert_eq!(star.evolve(50.0, 0.5), None);
+        assert_eq!(star.phase, StellarPhase::MainSequence);
+        assert_eq!(star.stellar_class(), StellarClass::M);
     }
 }
@@ -0,0 +1,90 @@
+//! Fixtures shared by the physics tests.
+
+use super::BodyId;
+use super::integrator::GravitySource;
+use super::integrator::IntegratedBody;
+use super::octree::OctreeBody;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+
+/// Gravitational constant the fixtures work with.
+pub const G: Scalar = 1.0;
+
+/// Exact pairwise gravity, for checking the integrators independently of the octree.
+#[derive(Default)]
+pub struct DirectGravity {
+    bodies: Vec<IntegratedBody>,
+}
+
+impl GravitySource for DirectGravity {
+    fn prepare(&mut self, bodies: &[IntegratedBody]) {
+        self.bodies = bodies.to_vec();
+    }
+
+    fn gravitational_constant(&self) -> Scalar {
+        G
+    }
+
+    fn acceleration(&self, body: &IntegratedBody) -> Vector {
+        self.bodies
+            .iter()
+            .filter(|other| other.id != body.id)
+            .map(|other| {
+                let direction = other.position - body.position;
+                let distance = direction.length();
+                direction * (G * other.mass / (distance * distance * distance))
+            })
+            .sum()
+    }
+
+    fn acceleration_and_jerk(&self, body: &IntegratedBody) -> (Vector, Vector) {
+        self.bodies
+            .iter()
+            .filter(|other| other.id != body.id)
+            .map(|other| {
+                let direction = other.position - body.position;
+                let relative_velocity = other.velocity - body.velocity;
+                let distance_squared = direction.length_squared();
+                let strength = G * other.mass / (distance_squared * distance_squared.sqrt());
+                let radial = 3.0 * direction.dot(relative_velocity) / distance_squared;
+                (
+                    direction * strength,
+                    (relative_velocity - direction * radial) * strength,
+                )
+            })
+            .fold((Vector::ZERO, Vector::ZERO), |(a, j), (da, dj)| {
+                (a + da, j + dj)
+            })
+    }
+}
+
+/// A body without cached acceleration or jerk.
+pub fn body(index: u64, position: Vector, velocity: Vector, mass: Scalar) -> IntegratedBody {
+    IntegratedBody {
+        id: BodyId(index),
+        position,
+        velocity,
+        acceleration: Vector::ZERO,
+        jerk: Vector::ZERO,
+        mass,
+    }
+}
+
+pub fn octree_body(index: u64, position: Vector, velocity: Vector, mass: Scalar) -> OctreeBody {
+    body(index, position, velocity, mass).as_octree_body()
+}
+
+/// Kinetic and exact pairwise potential energy of `bodies`.
+pub fn total_energy(bodies: &[IntegratedBody]) -> Scalar {
+    let kinetic: Scalar = bodies
+        .iter()
+        .map(|body| 0.5 * body.mass * body.velocity.length_squared())
+        .sum();
+    let potential: Scalar = bodies
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| bodies[i + 1..].iter().map(move |b| (a, b)))
+        .map(|(a, b)| -G * a.mass * b.mass / a.position.distance(b.position))
+        .sum();
+    kinetic + potential
+}
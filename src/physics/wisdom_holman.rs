@@ -0,0 +1,258 @@
+//! Wisdom-Holman mixed-variable integration for systems with one dominant mass.
+//!
+//! When a single body holds nearly all of the mass, every other body moves on an almost
+//! Keplerian orbit around it. The Wisdom-Holman map splits the Hamiltonian into that Kepler
+//! motion, which is solved exactly, and the small interactions between the other bodies, which
+//! are applied as kicks. Steps can then be a sizable fraction of the shortest orbital period.
+//!
+//! This uses democratic heliocentric coordinates: heliocentric positions with barycentric
+//! velocities. The remaining term of the Hamiltonian, the central body's own momentum, becomes a
+//! linear "jump" of all positions, giving the sequence kick, jump, Kepler drift, jump, kick.
+
+use super::integrator::GravitySource;
+use super::integrator::IntegratedBody;
+use super::integrator::drift;
+use super::integrator::kick;
+use super::integrator::update_accelerations;
+use super::kepler;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use rayon::iter::IntoParallelRefMutIterator;
+use rayon::iter::ParallelIterator;
+
+/// Index of the most massive body.
+pub fn central_body(bodies: &[IntegratedBody]) -> Option<usize> {
+    bodies
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.mass.total_cmp(&b.mass))
+        .map(|(index, _)| index)
+}
+
+/// Fraction of the total mass held by the most massive body.
+pub fn dominant_mass_fraction(bodies: &[IntegratedBody]) -> Scalar {
+    let total_mass: Scalar = bodies.iter().map(|body| body.mass).sum();
+
+    match central_body(bodies) {
+        Some(index) if total_mass > 0.0 => bodies[index].mass / total_mass,
+        _ => 0.0,
+    }
+}
+
+/// Advances all bodies by `dt` with one Wisdom-Holman step around the most massive body.
+///
+/// Interactions between the orbiting bodies come from `source`, which only ever sees those
+/// bodies. As with the other integrators, accelerations are valid again on exit.
+pub fn step(bodies: &mut [IntegratedBody], dt: Scalar, source: &mut impl GravitySource) {
+    let Some(central_index) = central_body(bodies) else {
+        return;
+    };
+
+    let g = source.gravitational_constant();
+    let central = bodies[central_index];
+    let total_mass: Scalar = bodies.iter().map(|body| body.mass).sum();
+    if total_mass <= 0.0 || central.mass <= 0.0 {
+        return;
+    }
+
+    let center_of_mass_position = bodies
+        .iter()
+        .map(|body| body.position * body.mass)
+        .sum::<Vector>()
+        / total_mass;
+    let center_of_mass_velocity = bodies
+        .iter()
+        .map(|body| body.velocity * body.mass)
+        .sum::<Vector>()
+        / total_mass;
+
+    let mut orbiting: Vec<IntegratedBody> = bodies
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| index != central_index)
+        .map(|(_, body)| IntegratedBody {
+            position: body.position - central.position,
+            velocity: body.velocity - center_of_mass_velocity,
+            ..*body
+        })
+        .collect();
+
+    // Pairwise forces only depend on separations, so heliocentric positions serve as well
+    update_accelerations(source, &mut orbiting);
+    kick(&mut orbiting, 0.5 * dt);
+    jump(&mut orbiting, central.mass, 0.5 * dt);
+    kepler_drift(&mut orbiting, g * central.mass, dt);
+    jump(&mut orbiting, central.mass, 0.5 * dt);
+    update_accelerations(source, &mut orbiting);
+    kick(&mut orbiting, 0.5 * dt);
+
+    let mut center_of_mass = [IntegratedBody {
+        position: center_of_mass_position,
+        velocity: center_of_mass_velocity,
+        ..central
+    }];
+    drift(&mut center_of_mass, dt);
+    let [center_of_mass] = center_of_mass;
+
+    let central_position = center_of_mass.position
+        - orbiting
+            .iter()
+            .map(|body| body.position * body.mass)
+            .sum::<Vector>()
+            / total_mass;
+    let central_velocity = center_of_mass.velocity
+        - orbiting
+            .iter()
+            .map(|body| body.velocity * body.mass)
+            .sum::<Vector>()
+            / central.mass;
+
+    let mut central_acceleration = Vector::ZERO;
+    let mut orbiting = orbiting.into_iter();
+    for (index, body) in bodies.iter_mut().enumerate() {
+        if index == central_index {
+            continue;
+        }
+
+        let Some(orbiting_body) = orbiting.next() else {
+            break;
+        };
+        let offset = orbiting_body.position;
+        let distance_cubed = offset.length_squared() * offset.length();
+        let central_pull = if distance_cubed > 0.0 {
+            -offset * (g / distance_cubed)
+        } else {
+            Vector::ZERO
+        };
+        central_acceleration -= central_pull * orbiting_body.mass;
+
+        *body = IntegratedBody {
+            position: central_position + offset,
+            velocity: center_of_mass.velocity + orbiting_body.velocity,
+            acceleration: orbiting_body.acceleration + central_pull * central.mass,
+            ..orbiting_body
+        };
+    }
+
+    bodies[central_index] = IntegratedBody {
+        position: central_position,
+        velocity: central_velocity,
+        acceleration: central_acceleration,
+        ..central
+    };
+}
+
+/// Shifts heliocentric positions by the central body's share of the total momentum.
+fn jump(orbiting: &mut [IntegratedBody], central_mass: Scalar, dt: Scalar) {
+    let momentum: Vector = orbiting.iter().map(|body| body.velocity * body.mass).sum();
+    let shift = momentum * (dt / central_mass);
+
+    orbiting.iter_mut().for_each(|body| body.position += shift);
+}
+
+fn kepler_drift(orbiting: &mut [IntegratedBody], gm: Scalar, dt: Scalar) {
+    orbiting.par_iter_mut().for_each(|body| {
+        (body.position, body.velocity) = kepler::drift(body.position, body.velocity, gm, dt);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::BodyId;
+    use crate::physics::integrator::IntegratorKind;
+    use crate::physics::test_support::DirectGravity;
+    use crate::physics::test_support::G;
+    use crate::physics::test_support::total_energy;
+    use core::f64::consts::PI;
+
+    /// A unit-mass star with two light planets on circular orbits at radii 1 and 1.6.
+    fn planetary_system() -> Vec<IntegratedBody> {
+        let planet = |index: u64, radius: Scalar, mass: Scalar| IntegratedBody {
+            id: BodyId(index),
+            position: Vector::new(radius, 0.0, 0.0),
+            velocity: Vector::new(0.0, libm::sqrt(G / radius), 0.0),
+            acceleration: Vector::ZERO,
+            jerk: Vector::ZERO,
+            mass,
+        };
+        let mut bodies = vec![
+            planet(0, 0.0, 1.0),
+            planet(1, 1.0, 1e-3),
+            planet(2, 1.6, 3e-4),
+        ];
+        bodies[0].velocity = -bodies[1..]
+            .iter()
+            .map(|body| body.velocity * body.mass)
+            .sum::<Vector>();
+        bodies
+    }
+
+    fn relative_energy_error(kind: IntegratorKind, steps: usize, dt: Scalar) -> Scalar {
+        let mut bodies = planetary_system();
+        let mut gravity = DirectGravity::default();
+        update_accelerations(&mut gravity, &mut bodies);
+        let initial_energy = total_energy(&bodies);
+
+        (0..steps).for_each(|_| kind.step(&mut bodies, dt, &mut gravity));
+
+        ((total_energy(&bodies) - initial_energy) / initial_energy).abs()
+    }
+
+    #[test]
+    fn test_dominant_mass_fraction() {
+        let bodies = planetary_system();
+        assert_eq!(central_body(&bodies), Some(0));
+        assert!((dominant_mass_fraction(&bodies) - 1.0 / 1.0013).abs() < 1e-12);
+        assert_eq!(dominant_mass_fraction(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_massless_planet_follows_exact_kepler_orbit() {
+        let mut bodies = planetary_system();
+        bodies.truncate(2);
+        bodies[1].mass = 0.0;
+        bodies[0].velocity = Vector::ZERO;
+        let mut gravity = DirectGravity::default();
+        let initial = bodies.clone();
+
+        // Without planet-planet interactions the map is exact, even at four steps per orbit
+        (0..4).for_each(|_| step(&mut bodies, 0.5 * PI, &mut gravity));
+
+        for (body, initial) in bodies.iter().zip(&initial) {
+            assert!(body.position.distance(initial.position) < 1e-12);
+            assert!(body.velocity.distance(initial.velocity) < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_wisdom_holman_beats_leapfrog_at_large_steps() {
+        // ~10 inner orbits at 20 steps per orbit
+        let dt = 2.0 * PI / 20.0;
+        let leapfrog_error = relative_energy_error(IntegratorKind::LeapfrogKdk, 200, dt);
+        let wisdom_holman_error = relative_energy_error(IntegratorKind::WisdomHolman, 200, dt);
+
+        assert!(
+            wisdom_holman_error < 1e-5,
+            "error too large: {wisdom_holman_error:e}"
+        );
+        assert!(
+            wisdom_holman_error < leapfrog_error / 10.0,
+            "Wisdom-Holman error {wisdom_holman_error:e} should be far below leapfrog error {leapfrog_error:e}"
+        );
+    }
+
+    #[test]
+    fn test_conserves_momentum() {
+        let mut bodies = planetary_system();
+        let mut gravity = DirectGravity::default();
+        let momentum = |bodies: &[IntegratedBody]| -> Vector {
+            bodies.iter().map(|body| body.velocity * body.mass).sum()
+        };
+        let initial_momentum = momentum(&bodies);
+
+        (0..100).for_each(|_| step(&mut bodies, 0.05, &mut gravity));
+
+        assert!(momentum(&bodies).distance(initial_momentum) < 1e-14);
+    }
+}
@@ -1,4 +1,6 @@
+use crate::plugins::simulation_diagnostics::SimulationDiagnosticsPlugin;
 use crate::resources;
+use avian3d::prelude::*;
 use bevy::asset::AssetPath;
 use bevy::asset::io::AssetSourceId;
 use bevy::diagnostic::DiagnosticsStore;
@@ -12,6 +14,56 @@ struct FrameCountTextNode;
 #[derive(Component, Copy, Clone, Default, PartialEq, Debug)]
 struct FpsTextNode;
 
+#[derive(Component, Copy, Clone, Default, PartialEq, Debug)]
+struct SimulatedTimeTextNode;
+
+#[derive(Component, Copy, Clone, Default, PartialEq, Debug)]
+struct TimeScaleTextNode;
+
+#[derive(Component, Copy, Clone, Default, PartialEq, Debug)]
+struct EnergyDriftTextNode;
+
+#[derive(Component, Copy, Clone, Default, PartialEq, Debug)]
+struct VirialRatioTextNode;
+
+#[derive(Component, Copy, Clone, Default, PartialEq, Debug)]
+struct HalfMassRadiusTextNode;
+
+#[derive(Component, Copy, Clone, Default, PartialEq, Debug)]
+struct LagrangianRadiiTextNode;
+
+#[derive(Component, Copy, Clone, Default, PartialEq, Debug)]
+struct GroupCountTextNode;
+
+/// Number of most recent measurements drawn in a sparkline.
+const SPARKLINE_LENGTH: usize = 24;
+
+/// Draws `values` as a line of block characters scaled between their minimum and maximum.
+fn sparkline(values: impl Iterator<Item = f64>) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let values: Vec<f64> = values.collect();
+    let values = &values[values.len().saturating_sub(SPARKLINE_LENGTH)..];
+    let (min, max) = values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(*value), max.max(*value))
+        });
+    let range = max - min;
+
+    values
+        .iter()
+        .map(|value| {
+            let level = if range > 0.0 {
+                ((value - min) / range * (BLOCKS.len() - 1) as f64).round() as usize
+            } else {
+                0
+            };
+            BLOCKS[level.min(BLOCKS.len() - 1)]
+        })
+        .collect()
+}
+
 // TODO: change detection
 #[derive(Resource, Reflect, Debug)]
 #[reflect(Resource, Debug)]
@@ -122,11 +174,87 @@ impl DiagnosticsHudPlugin {
                         ),
                     ],
                 ),
+                (
+                    hud_row_node.clone(),
+                    children![
+                        (Text::new("Simulated time"), bold_text_font.clone()),
+                        (
+                            SimulatedTimeTextNode,
+                            Text::new("-"),
+                            regular_text_font.clone()
+                        ),
+                    ],
+                ),
+                (
+                    hud_row_node.clone(),
+                    children![
+                        (Text::new("Time scale"), bold_text_font.clone()),
+                        (TimeScaleTextNode, Text::new("-"), regular_text_font.clone()),
+                    ],
+                ),
+                (
+                    hud_row_node.clone(),
+                    children![
+                        (Text::new("Energy drift"), bold_text_font.clone()),
+                        (
+                            EnergyDriftTextNode,
+                            Text::new("-"),
+                            regular_text_font.clone()
+                        ),
+                    ],
+                ),
+                (
+                    hud_row_node.clone(),
+                    children![
+                        (Text::new("Virial ratio"), bold_text_font.clone()),
+                        (
+                            VirialRatioTextNode,
+                            Text::new("-"),
+                            regular_text_font.clone()
+                        ),
+                    ],
+                ),
+                (
+                    hud_row_node.clone(),
+                    children![
+                        (Text::new("Half-mass radius"), bold_text_font.clone()),
+                        (
+                            HalfMassRadiusTextNode,
+                            Text::new("-"),
+                            regular_text_font.clone()
+                        ),
+                    ],
+                ),
+                (
+                    hud_row_node.clone(),
+                    children![
+                        (
+                            Text::new("Lagrangian radii (10-90%)"),
+                            bold_text_font.clone()
+                        ),
+                        (
+                            LagrangianRadiiTextNode,
+                            Text::new("-"),
+                            regular_text_font.clone()
+                        ),
+                    ],
+                ),
+                (
+                    hud_row_node.clone(),
+                    children![
+                        (Text::new("Groups"), bold_text_font.clone()),
+                        (
+                            GroupCountTextNode,
+                            Text::new("-"),
+                            regular_text_font.clone()
+                        ),
+                    ],
+                ),
             ],
         ));
     }
 
-    fn advance_refresh_timer(mut state: ResMut<DiagnosticsHudState>, time: Res<Time>) {
+    fn advance_refresh_timer(mut state: ResMut<DiagnosticsHudState>, time: Res<Time<Real>>) {
         state.refresh_timer.tick(time.delta());
     }
 
@@ -135,12 +263,11 @@ impl DiagnosticsHudPlugin {
         mut frame_count_text: Single<&mut Text, With<FrameCountTextNode>>,
         state: ResMut<DiagnosticsHudState>,
     ) {
-        if state.refresh_timer.finished() {
-            if let Some(frame_count) = diagnostics.get(&FrameTimeDiagnosticsPlugin::FRAME_COUNT) {
-                if let Some(fps) = frame_count.smoothed() {
-                    ***frame_count_text = format!("{fps}");
-                }
-            }
+        if state.refresh_timer.finished()
+            && let Some(frame_count) = diagnostics.get(&FrameTimeDiagnosticsPlugin::FRAME_COUNT)
+            && let Some(fps) = frame_count.smoothed()
+        {
+            ***frame_count_text = format!("{fps}");
         }
     }
 
@@ -148,13 +275,121 @@ impl DiagnosticsHudPlugin {
         diagnostics: Res<DiagnosticsStore>,
         mut fps_text: Single<&mut Text, With<FpsTextNode>>,
         state: Res<DiagnosticsHudState>,
+    ) {
+        if state.refresh_timer.finished()
+            && let Some(fps) = diagnostics.get(&FrameTimeDiagnosticsPlugin::FPS)
+            && let Some(fps) = fps.smoothed()
+        {
+            ***fps_text = format!("{fps:.2}");
+        }
+    }
+
+    fn update_simulated_time_text(
+        simulated_time: Res<resources::SimulatedTime>,
+        mut simulated_time_text: Single<&mut Text, With<SimulatedTimeTextNode>>,
+        state: Res<DiagnosticsHudState>,
     ) {
         if state.refresh_timer.finished() {
-            if let Some(fps) = diagnostics.get(&FrameTimeDiagnosticsPlugin::FPS) {
-                if let Some(fps) = fps.smoothed() {
-                    ***fps_text = format!("{fps:.2}");
+            ***simulated_time_text = format!("{:.2}", **simulated_time);
+        }
+    }
+
+    fn update_time_scale_text(
+        physics_time: Res<Time<Physics>>,
+        virtual_time: Res<Time<Virtual>>,
+        replay: Option<Res<resources::Replay>>,
+        mut time_scale_text: Single<&mut Text, With<TimeScaleTextNode>>,
+        state: Res<DiagnosticsHudState>,
+    ) {
+        if state.refresh_timer.finished() {
+            ***time_scale_text = if let Some(replay) = replay {
+                if replay.playing {
+                    format!("{}× (replay)", replay.speed)
+                } else {
+                    format!("{}× (replay paused)", replay.speed)
                 }
-            }
+            } else if physics_time.is_paused() {
+                format!("{}× (paused)", virtual_time.relative_speed_f64())
+            } else {
+                format!("{}×", virtual_time.relative_speed_f64())
+            };
+        }
+    }
+
+    fn update_energy_drift_text(
+        diagnostics: Res<DiagnosticsStore>,
+        mut energy_drift_text: Single<&mut Text, With<EnergyDriftTextNode>>,
+        state: Res<DiagnosticsHudState>,
+    ) {
+        if state.refresh_timer.finished()
+            && let Some(drift) = diagnostics.get(&SimulationDiagnosticsPlugin::ENERGY_DRIFT)
+            && let Some(drift) = drift.value()
+        {
+            ***energy_drift_text = format!("{drift:.2e}");
+        }
+    }
+
+    fn update_virial_ratio_text(
+        diagnostics: Res<DiagnosticsStore>,
+        mut virial_ratio_text: Single<&mut Text, With<VirialRatioTextNode>>,
+        state: Res<DiagnosticsHudState>,
+    ) {
+        if state.refresh_timer.finished()
+            && let Some(virial_ratio) = diagnostics.get(&SimulationDiagnosticsPlugin::VIRIAL_RATIO)
+            && let Some(value) = virial_ratio.value()
+        {
+            ***virial_ratio_text =
+                format!("{} {value:.3}", sparkline(virial_ratio.values().copied()));
+        }
+    }
+
+    fn update_half_mass_radius_text(
+        diagnostics: Res<DiagnosticsStore>,
+        mut half_mass_radius_text: Single<&mut Text, With<HalfMassRadiusTextNode>>,
+        state: Res<DiagnosticsHudState>,
+    ) {
+        if state.refresh_timer.finished()
+            && let Some(radius) = diagnostics.get(&SimulationDiagnosticsPlugin::HALF_MASS_RADIUS)
+            && let Some(value) = radius.value()
+        {
+            ***half_mass_radius_text =
+                format!("{} {value:.1}", sparkline(radius.values().copied()));
+        }
+    }
+
+    fn update_group_count_text(
+        diagnostics: Res<DiagnosticsStore>,
+        mut group_count_text: Single<&mut Text, With<GroupCountTextNode>>,
+        state: Res<DiagnosticsHudState>,
+    ) {
+        if state.refresh_timer.finished()
+            && let Some(group_count) = diagnostics.get(&SimulationDiagnosticsPlugin::GROUP_COUNT)
+            && let Some(value) = group_count.value()
+        {
+            ***group_count_text = format!("{} {value}", sparkline(group_count.values().copied()));
+        }
+    }
+
+    fn update_lagrangian_radii_text(
+        diagnostics: Res<DiagnosticsStore>,
+        mut lagrangian_radii_text: Single<&mut Text, With<LagrangianRadiiTextNode>>,
+        state: Res<DiagnosticsHudState>,
+    ) {
+        if !state.refresh_timer.finished() {
+            return;
+        }
+
+        let radii: Option<Vec<String>> = SimulationDiagnosticsPlugin::LAGRANGIAN_RADIUS_PATHS
+            .iter()
+            .map(|path| {
+                diagnostics
+                    .get(path)
+                    .and_then(|radius| radius.value())
+                    .map(|radius| format!("{radius:.0}"))
+            })
+            .collect();
+        if let Some(radii) = radii {
+            ***lagrangian_radii_text = radii.join(" ");
         }
     }
 }
@@ -170,6 +405,13 @@ impl Plugin for DiagnosticsHudPlugin {
                 Self::advance_refresh_timer,
                 Self::update_frame_count_text,
                 Self::update_fps_text,
+                Self::update_simulated_time_text,
+                Self::update_time_scale_text,
+                Self::update_energy_drift_text,
+                Self::update_virial_ratio_text,
+                Self::update_half_mass_radius_text,
+                Self::update_lagrangian_radii_text,
+                Self::update_group_count_text,
             ),
         );
     }
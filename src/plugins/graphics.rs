@@ -0,0 +1,155 @@
+use crate::plugins::simulation::SimulationSet;
+use crate::states;
+use crate::systems;
+use bevy::prelude::*;
+
+/// Shows the simulation run by [`super::simulation::SimulationPlugin`] in a window: the camera,
+/// body meshes and materials, gizmos, UI, keyboard controls and trajectory replay.
+pub struct SimulationGraphicsPlugin;
+
+impl Plugin for SimulationGraphicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, systems::camera::spawn_camera);
+        app.add_systems(
+            OnEnter(states::AppState::Loading),
+            systems::loading::setup_loading_screen,
+        );
+        app.add_systems(
+            OnEnter(states::LoadingState::SettingUpUI),
+            systems::ui::setup_ui,
+        );
+        app.add_systems(
+            Update,
+            (
+                systems::loading::update_loading_progress
+                    .run_if(in_state(states::AppState::Loading)),
+                systems::loading::complete_loading.run_if(in_state(states::AppState::Running)),
+            )
+                .in_set(SimulationSet::Loading),
+        );
+        app.add_systems(
+            Update,
+            systems::camera::draw_barycenter_gizmo
+                .in_set(SimulationSet::Camera)
+                .run_if(
+                    in_state(states::AppState::Running)
+                        .or(in_state(states::AppState::Paused))
+                        .or(in_state(states::AppState::Replay)),
+                ),
+        );
+        app.add_systems(
+            Update,
+            (
+                systems::input::pause_physics_on_space,
+                systems::input::restart_simulation_on_n,
+                systems::input::toggle_barycenter_gizmo_visibility_on_c,
+                systems::input::toggle_octree_visualization,
+                systems::input::change_time_scale_on_brackets,
+                systems::input::step_simulation_on_period,
+                systems::input::reverse_time_on_r,
+                systems::input::toggle_replay_on_f8,
+                systems::simulation_actions::handle_toggle_octree_visualization_event,
+                systems::simulation_actions::handle_toggle_barycenter_gizmo_visibility_event,
+            )
+                .in_set(SimulationSet::Input)
+                .run_if(
+                    in_state(states::AppState::Running)
+                        .or(in_state(states::AppState::Paused))
+                        .or(in_state(states::AppState::Replay)),
+                ),
+        );
+        app.add_systems(
+            Update,
+            (
+                systems::input::toggle_group_coloring_on_g,
+                systems::input::save_snapshot_on_f5,
+                systems::input::load_snapshot_on_f9,
+                systems::input::export_bodies_on_e,
+                systems::camera::refocus_camera_on_restart,
+                systems::simulation_actions::handle_toggle_group_coloring_event,
+                systems::simulation_actions::handle_start_replay_event,
+            )
+                .in_set(SimulationSet::Input)
+                .run_if(in_state(states::AppState::Running).or(in_state(states::AppState::Paused))),
+        );
+        app.add_systems(
+            OnEnter(states::AppState::Replay),
+            systems::ui::setup_replay_timeline,
+        );
+        app.add_systems(
+            OnExit(states::AppState::Replay),
+            (
+                systems::replay::despawn_replay,
+                systems::ui::despawn_replay_timeline,
+            ),
+        );
+        app.add_systems(
+            Update,
+            (
+                (
+                    systems::replay::handle_replay_playback_events,
+                    systems::ui::handle_replay_timeline_track,
+                    systems::replay::advance_replay,
+                    systems::replay::show_replay_frame,
+                )
+                    .chain(),
+                systems::replay::leave_replay_on_restart,
+            )
+                .in_set(SimulationSet::Input)
+                .run_if(in_state(states::AppState::Replay)),
+        );
+        app.add_systems(
+            Update,
+            (
+                systems::ui::handle_barycenter_gizmo_button,
+                systems::ui::handle_export_button,
+                systems::ui::handle_group_coloring_button,
+                systems::ui::handle_octree_button,
+                systems::ui::handle_pause_button,
+                systems::ui::handle_restart_button,
+                systems::ui::handle_reverse_time_button,
+                systems::ui::handle_slow_down_button,
+                systems::ui::handle_speed_up_button,
+                systems::ui::handle_step_button,
+                systems::ui::update_barycenter_gizmo_button_text,
+                systems::ui::update_group_coloring_button_text,
+                systems::ui::update_octree_button_text,
+                systems::ui::update_pause_button_text,
+            )
+                .in_set(SimulationSet::UI)
+                .run_if(
+                    in_state(states::AppState::Running)
+                        .or(in_state(states::AppState::Paused))
+                        .or(in_state(states::AppState::Replay)),
+                ),
+        );
+        app.add_systems(
+            Update,
+            systems::ui::update_replay_timeline
+                .in_set(SimulationSet::UI)
+                .after(systems::replay::show_replay_frame)
+                .run_if(in_state(states::AppState::Replay)),
+        );
+        app.add_systems(
+            Update,
+            (
+                systems::visualization::visualize_octree.run_if(
+                    in_state(states::AppState::Running)
+                        .or(in_state(states::AppState::Paused))
+                        .or(in_state(states::AppState::Replay)),
+                ),
+                systems::visualization::update_body_appearance
+                    .before(systems::visualization::color_bodies_by_group),
+                systems::visualization::color_bodies_by_group.run_if(
+                    in_state(states::AppState::Running).or(in_state(states::AppState::Paused)),
+                ),
+            )
+                .in_set(SimulationSet::Visualization),
+        );
+
+        app.add_systems(
+            Update,
+            systems::input::quit_on_escape.in_set(SimulationSet::Input),
+        );
+    }
+}
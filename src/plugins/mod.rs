@@ -1,4 +1,9 @@
+#[cfg(feature = "graphics")]
 pub mod diagnostics_hud;
+#[cfg(feature = "graphics")]
 pub mod embedded_assets;
+#[cfg(feature = "graphics")]
+pub mod graphics;
 pub mod simulation;
 pub mod simulation_diagnostics;
+pub mod stardrift_simulation;
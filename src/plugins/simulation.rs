@@ -3,6 +3,7 @@ use crate::physics;
 use crate::resources;
 use crate::states;
 use crate::systems;
+use avian3d::prelude::*;
 #[cfg(feature = "diagnostics")]
 use bevy::ecs::schedule::LogLevel;
 #[cfg(feature = "diagnostics")]
@@ -19,29 +20,64 @@ pub enum SimulationSet {
     Configuration,
 }
 
-pub struct SimulationPlugin;
+/// Runs the simulation: spawning bodies, integrating them, resolving collisions and measuring
+/// the run, with nothing drawn. [`super::graphics::SimulationGraphicsPlugin`] shows it.
+pub struct SimulationPlugin {
+    /// Configuration the simulation starts with.
+    pub config: config::SimulationConfig,
+}
+
+impl Default for SimulationPlugin {
+    fn default() -> Self {
+        Self {
+            config: config::SimulationConfig::load_from_user_config(),
+        }
+    }
+}
 
 impl Plugin for SimulationPlugin {
     fn build(&self, app: &mut App) {
-        let config = config::SimulationConfig::load_from_user_config();
+        let config = self.config.clone();
 
         app.insert_resource(config.clone());
         app.insert_resource(resources::SharedRng::from_optional_seed(
             config.physics.initial_seed,
         ));
+        if config.physics.adaptive_timesteps
+            && !matches!(
+                config.physics.integrator,
+                physics::integrator::IntegratorKind::LeapfrogKdk
+                    | physics::integrator::IntegratorKind::Hermite4
+            )
+        {
+            warn!(
+                "Adaptive timesteps only support leapfrog KDK and Hermite; using leapfrog KDK instead of {:?}",
+                config.physics.integrator
+            );
+        }
+
         app.insert_resource(resources::GravitationalConstant(
             config.physics.gravitational_constant,
         ));
         app.insert_resource(resources::BodyCount(config.physics.body_count));
         app.init_resource::<resources::Barycenter>();
-        app.insert_resource(resources::GravitationalOctree::new(
-            physics::octree::Octree::new(
-                config.physics.octree_theta,
-                config.physics.force_calculation_min_distance,
-                config.physics.force_calculation_max_force,
-            )
-            .with_leaf_threshold(config.physics.octree_leaf_threshold),
-        ));
+        app.init_resource::<resources::TimestepLevelHistogram>();
+        app.init_resource::<resources::SimulatedTime>();
+        app.init_resource::<resources::PendingPhysicsSteps>();
+        app.init_resource::<resources::PeriodicCheckCounters>();
+        app.init_resource::<resources::TrajectoryRecording>();
+        app.init_resource::<resources::StepLimit>();
+        app.init_resource::<resources::TimeReversalProbe>();
+        app.init_resource::<resources::TimeReversed>();
+        app.init_resource::<resources::ConservationMonitor>();
+        app.init_resource::<resources::ClusterStructure>();
+        app.init_resource::<resources::GroupCatalog>();
+        app.init_resource::<resources::GroupColoringSettings>();
+        app.init_resource::<resources::EscaperStatistics>();
+        app.init_resource::<resources::CollisionStatistics>();
+        // Gravity between bodies is integrated by `systems::physics::integrate_bodies`
+        app.insert_resource(Gravity::ZERO);
+        app.insert_resource(resources::GravitySimulation::from_config(&config));
         app.insert_resource(resources::OctreeVisualizationSettings {
             enabled: false,
             ..default()
@@ -53,6 +89,17 @@ impl Plugin for SimulationPlugin {
         app.add_event::<systems::simulation_actions::ToggleOctreeVisualizationEvent>();
         app.add_event::<systems::simulation_actions::ToggleBarycenterGizmoVisibilityEvent>();
         app.add_event::<systems::simulation_actions::TogglePauseSimulationEvent>();
+        app.add_event::<systems::simulation_actions::SpeedUpSimulationEvent>();
+        app.add_event::<systems::simulation_actions::SlowDownSimulationEvent>();
+        app.add_event::<systems::simulation_actions::StepSimulationEvent>();
+        app.add_event::<systems::simulation_actions::ReverseTimeEvent>();
+        app.add_event::<systems::simulation_actions::ToggleGroupColoringEvent>();
+        app.add_event::<systems::simulation_actions::SaveSnapshotEvent>();
+        app.add_event::<systems::simulation_actions::LoadSnapshotEvent>();
+        app.add_event::<systems::simulation_actions::StartReplayEvent>();
+        app.add_event::<systems::simulation_actions::ExportBodiesEvent>();
+        app.add_event::<systems::collisions::TidalDisruptionEvent>();
+        app.add_event::<systems::stars::StellarEvolutionEvent>();
 
         #[cfg(feature = "diagnostics")]
         app.edit_schedule(FixedUpdate, |schedule| {
@@ -65,8 +112,9 @@ impl Plugin for SimulationPlugin {
         app.configure_sets(
             FixedUpdate,
             (
+                systems::physics::PhysicsSet::ResolveCollisions,
                 systems::physics::PhysicsSet::BuildOctree,
-                systems::physics::PhysicsSet::ApplyForces,
+                systems::physics::PhysicsSet::Integrate,
             )
                 .chain(),
         );
@@ -85,93 +133,124 @@ impl Plugin for SimulationPlugin {
                 .chain(),
         );
 
-        app.add_systems(Startup, systems::camera::spawn_camera);
+        app.add_systems(
+            Startup,
+            systems::simulation_actions::apply_configured_time_scale,
+        );
         app.add_systems(
             OnEnter(states::AppState::Loading),
-            (
-                systems::loading::setup_loading_screen,
-                systems::loading::start_loading_process,
-            ),
+            systems::loading::start_loading_process,
         );
         app.add_systems(
             FixedUpdate,
             (
+                (
+                    systems::collisions::fragment_colliding_bodies
+                        .run_if(systems::collisions::fragmentation_enabled),
+                    systems::collisions::merge_colliding_bodies.run_if(
+                        systems::collisions::collision_mode_is(config::CollisionMode::Merge),
+                    ),
+                    (
+                        systems::collisions::disrupt_bodies_within_roche_limit,
+                        systems::collisions::log_tidal_disruptions,
+                    )
+                        .chain()
+                        .run_if(systems::collisions::tidal_disruption_enabled),
+                    systems::collisions::settle_fragments,
+                )
+                    .chain()
+                    .in_set(systems::physics::PhysicsSet::ResolveCollisions)
+                    .run_if(
+                        in_state(states::AppState::Running).or(in_state(states::AppState::Paused)
+                            .and(systems::simulation_actions::has_pending_physics_steps)),
+                    ),
                 systems::physics::rebuild_octree
                     .in_set(systems::physics::PhysicsSet::BuildOctree)
+                    .run_if(in_state(states::AppState::Paused)),
+                (
+                    systems::physics::integrate_bodies,
+                    systems::simulation_actions::track_time_reversal_probe,
+                )
+                    .chain()
+                    .in_set(systems::physics::PhysicsSet::Integrate)
                     .run_if(
-                        in_state(states::AppState::Running).or(in_state(states::AppState::Paused)),
+                        in_state(states::AppState::Running).or(in_state(states::AppState::Paused)
+                            .and(systems::simulation_actions::has_pending_physics_steps)),
+                    ),
+                (
+                    systems::stars::evolve_stars,
+                    systems::stars::log_stellar_evolution,
+                )
+                    .chain()
+                    .run_if(
+                        in_state(states::AppState::Running).or(in_state(states::AppState::Paused)
+                            .and(systems::simulation_actions::has_pending_physics_steps)),
                     ),
-                systems::physics::apply_gravitation_octree
-                    .in_set(systems::physics::PhysicsSet::ApplyForces)
-                    .run_if(in_state(states::AppState::Running)),
                 systems::physics::counteract_barycentric_drift.run_if(
                     in_state(states::AppState::Running).or(in_state(states::AppState::Paused)),
                 ),
+                (
+                    systems::physics::measure_global_quantities,
+                    systems::physics::find_groups,
+                    systems::physics::classify_bound_bodies,
+                )
+                    .chain()
+                    .run_if(
+                        in_state(states::AppState::Running).or(in_state(states::AppState::Paused)
+                            .and(systems::simulation_actions::has_pending_physics_steps)),
+                    ),
+                systems::recording::record_trajectory
+                    .run_if(systems::recording::trajectory_recording_enabled)
+                    .run_if(
+                        in_state(states::AppState::Running).or(in_state(states::AppState::Paused)
+                            .and(systems::simulation_actions::has_pending_physics_steps)),
+                    ),
+                systems::simulation_actions::exit_after_step_limit
+                    .run_if(in_state(states::AppState::Running)),
             )
                 .chain(),
         );
+        app.add_systems(
+            FixedPostUpdate,
+            systems::simulation_actions::consume_pending_physics_steps
+                .after(PhysicsSet::StepSimulation)
+                .run_if(in_state(states::AppState::Paused)),
+        );
+        app.add_systems(Last, systems::recording::finish_trajectory_recording);
+        app.add_systems(
+            FixedPostUpdate,
+            systems::physics::apply_restored_masses
+                .after(PhysicsSet::Prepare)
+                .before(PhysicsSet::StepSimulation),
+        );
         app.add_systems(
             Update,
             (
-                systems::loading::update_loading_progress
-                    .run_if(in_state(states::AppState::Loading)),
                 systems::loading::advance_loading_step
                     .run_if(in_state(states::LoadingState::InitializingConfig)),
                 systems::loading::spawn_bodies_async
                     .run_if(in_state(states::LoadingState::SpawningBodies)),
                 systems::loading::finalize_loading
                     .run_if(in_state(states::LoadingState::BuildingOctree)),
-                systems::loading::setup_ui_after_loading
+                systems::loading::finish_loading
                     .run_if(in_state(states::LoadingState::SettingUpUI)),
-                systems::loading::complete_loading.run_if(in_state(states::AppState::Running)),
             )
                 .in_set(SimulationSet::Loading),
         );
-        app.add_systems(
-            Update,
-            systems::camera::draw_barycenter_gizmo
-                .in_set(SimulationSet::Camera)
-                .run_if(in_state(states::AppState::Running).or(in_state(states::AppState::Paused))),
-        );
         app.add_systems(
             Update,
             (
-                systems::input::pause_physics_on_space,
-                systems::input::restart_simulation_on_n,
-                systems::input::toggle_barycenter_gizmo_visibility_on_c,
-                systems::input::toggle_octree_visualization,
                 systems::simulation_actions::handle_restart_simulation_event,
-                systems::simulation_actions::handle_toggle_octree_visualization_event,
-                systems::simulation_actions::handle_toggle_barycenter_gizmo_visibility_event,
                 systems::simulation_actions::handle_toggle_pause_simulation_event,
+                systems::simulation_actions::handle_time_scale_events,
+                systems::simulation_actions::handle_step_simulation_event,
+                systems::simulation_actions::handle_reverse_time_event,
+                systems::simulation_actions::handle_save_snapshot_event,
+                systems::simulation_actions::handle_load_snapshot_event,
+                systems::simulation_actions::handle_export_bodies_event,
             )
                 .in_set(SimulationSet::Input)
                 .run_if(in_state(states::AppState::Running).or(in_state(states::AppState::Paused))),
         );
-        app.add_systems(
-            Update,
-            (
-                systems::ui::handle_barycenter_gizmo_button,
-                systems::ui::handle_octree_button,
-                systems::ui::handle_pause_button,
-                systems::ui::handle_restart_button,
-                systems::ui::update_barycenter_gizmo_button_text,
-                systems::ui::update_octree_button_text,
-                systems::ui::update_pause_button_text,
-            )
-                .in_set(SimulationSet::UI)
-                .run_if(in_state(states::AppState::Running).or(in_state(states::AppState::Paused))),
-        );
-        app.add_systems(
-            Update,
-            systems::visualization::visualize_octree
-                .in_set(SimulationSet::Visualization)
-                .run_if(in_state(states::AppState::Running).or(in_state(states::AppState::Paused))),
-        );
-
-        app.add_systems(
-            Update,
-            systems::input::quit_on_escape.in_set(SimulationSet::Input),
-        );
     }
 }
@@ -1,7 +1,10 @@
+use crate::config;
+use crate::resources;
 use crate::states;
 use bevy::diagnostic::DEFAULT_MAX_HISTORY_LENGTH;
 use bevy::diagnostic::Diagnostic;
 use bevy::diagnostic::DiagnosticPath;
+use bevy::diagnostic::Diagnostics;
 use bevy::diagnostic::RegisterDiagnostic;
 use bevy::prelude::*;
 use core::time::Duration;
@@ -28,10 +31,110 @@ impl Default for SimulationDiagnosticsPlugin {
 }
 
 impl SimulationDiagnosticsPlugin {
-    const DIAGNOSTIC_PATHS: &'static [DiagnosticPath] = &[];
+    /// Bodies per block timestep level; the last level also counts every deeper one.
+    pub const TIMESTEP_LEVEL_PATHS: &'static [DiagnosticPath] = &[
+        DiagnosticPath::const_new("simulation/timestep_level/0"),
+        DiagnosticPath::const_new("simulation/timestep_level/1"),
+        DiagnosticPath::const_new("simulation/timestep_level/2"),
+        DiagnosticPath::const_new("simulation/timestep_level/3"),
+        DiagnosticPath::const_new("simulation/timestep_level/4"),
+        DiagnosticPath::const_new("simulation/timestep_level/5"),
+        DiagnosticPath::const_new("simulation/timestep_level/6"),
+        DiagnosticPath::const_new("simulation/timestep_level/7"),
+        DiagnosticPath::const_new("simulation/timestep_level/8"),
+        DiagnosticPath::const_new("simulation/timestep_level/9"),
+        DiagnosticPath::const_new("simulation/timestep_level/10"),
+        DiagnosticPath::const_new("simulation/timestep_level/11"),
+        DiagnosticPath::const_new("simulation/timestep_level/12"),
+        DiagnosticPath::const_new("simulation/timestep_level/13"),
+        DiagnosticPath::const_new("simulation/timestep_level/14"),
+        DiagnosticPath::const_new("simulation/timestep_level/15"),
+    ];
+
+    pub const KINETIC_ENERGY: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/conservation/kinetic_energy");
+    pub const POTENTIAL_ENERGY: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/conservation/potential_energy");
+    pub const TOTAL_ENERGY: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/conservation/total_energy");
+    pub const LINEAR_MOMENTUM: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/conservation/linear_momentum");
+    pub const ANGULAR_MOMENTUM: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/conservation/angular_momentum");
+    /// Relative drift since the start of the run, see [`resources::ConservationMonitor`].
+    pub const ENERGY_DRIFT: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/conservation/energy_drift");
+    pub const LINEAR_MOMENTUM_DRIFT: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/conservation/linear_momentum_drift");
+    pub const ANGULAR_MOMENTUM_DRIFT: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/conservation/angular_momentum_drift");
+
+    pub const VIRIAL_RATIO: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/structure/virial_ratio");
+    pub const HALF_MASS_RADIUS: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/structure/half_mass_radius");
+    /// Radii enclosing each of [`crate::physics::cluster::LAGRANGIAN_MASS_FRACTIONS`] of the mass.
+    pub const LAGRANGIAN_RADIUS_PATHS: &'static [DiagnosticPath] = &[
+        DiagnosticPath::const_new("simulation/structure/lagrangian_radius/10"),
+        DiagnosticPath::const_new("simulation/structure/lagrangian_radius/25"),
+        DiagnosticPath::const_new("simulation/structure/lagrangian_radius/50"),
+        DiagnosticPath::const_new("simulation/structure/lagrangian_radius/75"),
+        DiagnosticPath::const_new("simulation/structure/lagrangian_radius/90"),
+    ];
+
+    /// Friends-of-friends groups with at least `group_min_members` members.
+    pub const GROUP_COUNT: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/structure/group_count");
+    pub const LARGEST_GROUP_MASS: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/structure/largest_group_mass");
+
+    pub const UNBOUND_COUNT: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/structure/unbound_count");
+    /// Total mass removed as escapers since the start of the run.
+    pub const REMOVED_MASS: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/structure/removed_mass");
+
+    /// Merges since the start of the run.
+    pub const MERGER_COUNT: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/collisions/merger_count");
+    /// Bodies shattered since the start of the run.
+    pub const SHATTERED_COUNT: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/collisions/shattered_count");
+    pub const TIDAL_DISRUPTION_COUNT: DiagnosticPath =
+        DiagnosticPath::const_new("simulation/collisions/tidal_disruption_count");
+
+    const STRUCTURE_PATHS: &'static [DiagnosticPath] = &[
+        Self::VIRIAL_RATIO,
+        Self::HALF_MASS_RADIUS,
+        Self::GROUP_COUNT,
+        Self::LARGEST_GROUP_MASS,
+        Self::UNBOUND_COUNT,
+        Self::REMOVED_MASS,
+        Self::MERGER_COUNT,
+        Self::SHATTERED_COUNT,
+        Self::TIDAL_DISRUPTION_COUNT,
+    ];
+
+    const CONSERVATION_PATHS: &'static [DiagnosticPath] = &[
+        Self::KINETIC_ENERGY,
+        Self::POTENTIAL_ENERGY,
+        Self::TOTAL_ENERGY,
+        Self::LINEAR_MOMENTUM,
+        Self::ANGULAR_MOMENTUM,
+        Self::ENERGY_DRIFT,
+        Self::LINEAR_MOMENTUM_DRIFT,
+        Self::ANGULAR_MOMENTUM_DRIFT,
+    ];
+
+    const DIAGNOSTIC_PATHS: &'static [&'static [DiagnosticPath]] = &[
+        Self::TIMESTEP_LEVEL_PATHS,
+        Self::CONSERVATION_PATHS,
+        Self::STRUCTURE_PATHS,
+        Self::LAGRANGIAN_RADIUS_PATHS,
+    ];
 
     fn register_diagnostics(&self, app: &mut App) {
-        for path in Self::DIAGNOSTIC_PATHS {
+        for path in Self::DIAGNOSTIC_PATHS.iter().copied().flatten() {
             app.register_diagnostic(
                 Diagnostic::new(path.clone())
                     .with_max_history_length(self.max_history_length)
@@ -40,9 +143,125 @@ impl SimulationDiagnosticsPlugin {
         }
     }
 
-    fn update_timer_ticks(mut state: ResMut<SimulationDiagnosticsState>, time: Res<Time>) {
+    fn update_timer_ticks(mut state: ResMut<SimulationDiagnosticsState>, time: Res<Time<Real>>) {
         state.update_timer.tick(time.delta());
     }
+
+    fn update_timestep_level_diagnostics(
+        mut diagnostics: Diagnostics,
+        histogram: Res<resources::TimestepLevelHistogram>,
+        state: Res<SimulationDiagnosticsState>,
+    ) {
+        if !state.update_timer.just_finished() || histogram.is_empty() {
+            return;
+        }
+
+        let last_level = Self::TIMESTEP_LEVEL_PATHS.len() - 1;
+        let mut counts = vec![0; Self::TIMESTEP_LEVEL_PATHS.len()];
+        histogram
+            .iter()
+            .enumerate()
+            .for_each(|(level, count)| counts[level.min(last_level)] += count);
+
+        Self::TIMESTEP_LEVEL_PATHS
+            .iter()
+            .zip(counts)
+            .for_each(|(path, count)| diagnostics.add_measurement(path, || count as f64));
+    }
+
+    fn update_conservation_diagnostics(
+        mut diagnostics: Diagnostics,
+        monitor: Res<resources::ConservationMonitor>,
+        state: Res<SimulationDiagnosticsState>,
+    ) {
+        if !state.update_timer.just_finished() {
+            return;
+        }
+        let Some(current) = monitor.current else {
+            return;
+        };
+
+        diagnostics.add_measurement(&Self::KINETIC_ENERGY, || current.kinetic_energy);
+        diagnostics.add_measurement(&Self::POTENTIAL_ENERGY, || current.potential_energy);
+        diagnostics.add_measurement(&Self::TOTAL_ENERGY, || current.total_energy());
+        diagnostics.add_measurement(&Self::LINEAR_MOMENTUM, || current.linear_momentum.length());
+        diagnostics.add_measurement(&Self::ANGULAR_MOMENTUM, || {
+            current.angular_momentum.length()
+        });
+        diagnostics.add_measurement(&Self::ENERGY_DRIFT, || monitor.drift.energy);
+        diagnostics.add_measurement(&Self::LINEAR_MOMENTUM_DRIFT, || {
+            monitor.drift.linear_momentum
+        });
+        diagnostics.add_measurement(&Self::ANGULAR_MOMENTUM_DRIFT, || {
+            monitor.drift.angular_momentum
+        });
+    }
+
+    /// Records one sample per measurement rather than per update, so that the history spans
+    /// enough measurements to show how the cluster evolves.
+    fn update_structure_diagnostics(
+        mut diagnostics: Diagnostics,
+        cluster_structure: Res<resources::ClusterStructure>,
+    ) {
+        if !cluster_structure.is_changed() {
+            return;
+        }
+        let Some(structure) = **cluster_structure else {
+            return;
+        };
+
+        diagnostics.add_measurement(&Self::VIRIAL_RATIO, || structure.virial_ratio);
+        diagnostics.add_measurement(&Self::HALF_MASS_RADIUS, || structure.half_mass_radius());
+        Self::LAGRANGIAN_RADIUS_PATHS
+            .iter()
+            .zip(structure.lagrangian_radii)
+            .for_each(|(path, radius)| diagnostics.add_measurement(path, || radius));
+    }
+
+    fn update_group_diagnostics(
+        mut diagnostics: Diagnostics,
+        catalog: Res<resources::GroupCatalog>,
+        config: Res<config::SimulationConfig>,
+    ) {
+        if !catalog.is_changed() {
+            return;
+        }
+
+        let groups = catalog
+            .iter()
+            .filter(|group| group.member_count >= config.physics.group_min_members);
+        diagnostics.add_measurement(&Self::GROUP_COUNT, || groups.clone().count() as f64);
+        diagnostics.add_measurement(&Self::LARGEST_GROUP_MASS, || {
+            groups.clone().map(|group| group.mass).fold(0.0, f64::max)
+        });
+    }
+
+    fn update_escaper_diagnostics(
+        mut diagnostics: Diagnostics,
+        statistics: Res<resources::EscaperStatistics>,
+    ) {
+        if !statistics.is_changed() {
+            return;
+        }
+
+        diagnostics.add_measurement(&Self::UNBOUND_COUNT, || statistics.unbound_count as f64);
+        diagnostics.add_measurement(&Self::REMOVED_MASS, || statistics.removed_mass);
+    }
+
+    fn update_collision_diagnostics(
+        mut diagnostics: Diagnostics,
+        statistics: Res<resources::CollisionStatistics>,
+    ) {
+        if !statistics.is_changed() {
+            return;
+        }
+
+        diagnostics.add_measurement(&Self::MERGER_COUNT, || statistics.merger_count as f64);
+        diagnostics.add_measurement(&Self::SHATTERED_COUNT, || statistics.shattered_count as f64);
+        diagnostics.add_measurement(&Self::TIDAL_DISRUPTION_COUNT, || {
+            statistics.tidal_disruption_count as f64
+        });
+    }
 }
 
 impl Plugin for SimulationDiagnosticsPlugin {
@@ -55,7 +274,19 @@ impl Plugin for SimulationDiagnosticsPlugin {
 
         app.add_systems(
             FixedPostUpdate,
-            Self::update_timer_ticks.run_if(in_state(states::AppState::Running)),
+            (
+                Self::update_timer_ticks,
+                (
+                    Self::update_timestep_level_diagnostics,
+                    Self::update_conservation_diagnostics,
+                    Self::update_structure_diagnostics,
+                    Self::update_group_diagnostics,
+                    Self::update_escaper_diagnostics,
+                    Self::update_collision_diagnostics,
+                ),
+            )
+                .chain()
+                .run_if(in_state(states::AppState::Running)),
         );
     }
 }
@@ -0,0 +1,77 @@
+use crate::config;
+use crate::plugins;
+use crate::states;
+use avian3d::dynamics::integrator::IntegrationSet;
+use avian3d::prelude::*;
+#[cfg(feature = "diagnostics")]
+use bevy::diagnostic::EntityCountDiagnosticsPlugin;
+#[cfg(all(feature = "diagnostics", feature = "graphics"))]
+use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
+#[cfg(feature = "diagnostics")]
+use bevy::diagnostic::SystemInformationDiagnosticsPlugin;
+use bevy::prelude::*;
+#[cfg(feature = "graphics")]
+use bevy_panorbit_camera::PanOrbitCameraPlugin;
+
+/// The whole simulation, for an app that already has Bevy's `DefaultPlugins`, or
+/// `MinimalPlugins` with logging, transforms and states.
+///
+/// Adds Avian with its integration switched off, the simulation and its diagnostics, the app
+/// states and, with `graphics`, everything that shows the simulation in a window.
+pub struct StardriftSimulationPlugin {
+    /// Configuration the simulation starts with.
+    pub config: config::SimulationConfig,
+    /// Show the simulation in a window, which needs `DefaultPlugins` with a primary window.
+    #[cfg(feature = "graphics")]
+    pub graphics: bool,
+}
+
+impl Default for StardriftSimulationPlugin {
+    fn default() -> Self {
+        Self {
+            config: config::SimulationConfig::load_from_user_config(),
+            #[cfg(feature = "graphics")]
+            graphics: true,
+        }
+    }
+}
+
+impl Plugin for StardriftSimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            #[cfg(feature = "diagnostics")]
+            EntityCountDiagnosticsPlugin,
+            PhysicsPlugins::default(),
+            #[cfg(feature = "diagnostics")]
+            plugins::simulation_diagnostics::SimulationDiagnosticsPlugin::default(),
+            #[cfg(feature = "diagnostics")]
+            SystemInformationDiagnosticsPlugin,
+            plugins::simulation::SimulationPlugin {
+                config: self.config.clone(),
+            },
+        ));
+
+        // Avian only resolves collisions; bodies are moved by our own symplectic integrators. The
+        // integrator plugin itself has to stay, as the solver needs the schedule it sets up.
+        app.configure_sets(
+            SubstepSchedule,
+            (IntegrationSet::Velocity, IntegrationSet::Position).run_if(|| false),
+        );
+
+        #[cfg(feature = "graphics")]
+        if self.graphics {
+            app.add_plugins((
+                plugins::embedded_assets::EmbeddedAssetsPlugin,
+                #[cfg(feature = "diagnostics")]
+                plugins::diagnostics_hud::DiagnosticsHudPlugin,
+                #[cfg(feature = "diagnostics")]
+                FrameTimeDiagnosticsPlugin::default(),
+                PanOrbitCameraPlugin,
+                plugins::graphics::SimulationGraphicsPlugin,
+            ));
+        }
+
+        app.init_state::<states::AppState>();
+        app.add_sub_state::<states::LoadingState>();
+    }
+}
@@ -1,9 +1,18 @@
+use crate::config;
 use crate::physics;
+use crate::trajectory;
 use avian3d::math::Scalar;
 use avian3d::math::Vector;
 use bevy::prelude::*;
 use rand_chacha::ChaCha8Rng;
 use rand_chacha::rand_core::SeedableRng;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::num::NonZeroU64;
+use std::path::PathBuf;
 
 #[derive(Resource, Deref, DerefMut, Debug, Clone, PartialEq)]
 pub struct SharedRng(pub ChaCha8Rng);
@@ -48,12 +57,226 @@ impl Default for BodyCount {
 #[derive(Resource, Deref, DerefMut, Copy, Clone, Default, PartialEq, Debug)]
 pub struct Barycenter(pub Option<Vector>);
 
+/// The bodies as the integrator sees them, copied from and back to their entities every fixed
+/// step.
 #[derive(Resource, Deref, DerefMut, Debug)]
-pub struct GravitationalOctree(pub physics::octree::Octree);
+pub struct GravitySimulation(pub physics::simulation::Simulation);
 
-impl GravitationalOctree {
-    pub fn new(octree: physics::octree::Octree) -> Self {
-        Self(octree)
+impl GravitySimulation {
+    pub fn from_config(config: &config::SimulationConfig) -> Self {
+        Self(physics::simulation::Simulation::new(config.physics.clone()))
+    }
+}
+
+/// Number of bodies that started the last fixed step on each block timestep level.
+#[derive(Resource, Deref, DerefMut, Clone, Default, PartialEq, Debug)]
+pub struct TimestepLevelHistogram(pub Vec<usize>);
+
+/// Simulated time elapsed since the bodies were spawned.
+#[derive(Resource, Deref, DerefMut, Copy, Clone, Default, PartialEq, Debug)]
+pub struct SimulatedTime(pub Scalar);
+
+/// Whether time currently runs backwards, after an odd number of time reversals.
+#[derive(Resource, Deref, DerefMut, Copy, Clone, Default, PartialEq, Debug)]
+pub struct TimeReversed(pub bool);
+
+/// Fixed steps since each periodic check last ran, which is `None` before its first run.
+///
+/// They are kept with the run rather than in the systems, so a snapshot restores them and a
+/// loaded run performs its checks on the same steps as the original.
+#[derive(Resource, Serialize, Deserialize, Copy, Clone, Default, PartialEq, Debug)]
+pub struct PeriodicCheckCounters {
+    pub group_finding: Option<u32>,
+    pub bound_classification: Option<u32>,
+    /// Fixed steps and simulated time since the stars last evolved. The time is negative while
+    /// the stars still have time spent running backwards to make up.
+    pub stellar_evolution: (u32, Scalar),
+}
+
+/// Fixed ticks still to be simulated while the simulation is paused.
+#[derive(Resource, Deref, DerefMut, Copy, Clone, Default, PartialEq, Debug)]
+pub struct PendingPhysicsSteps(pub u32);
+
+/// Measures how well the integrator retraces its steps after time is reversed.
+///
+/// The first reversal records every body's position. Once time has been reversed again and as
+/// much simulated time has passed running backwards as running forwards, the bodies should be
+/// back where they were, and the RMS distance from the recorded positions is reported.
+#[derive(Resource, Clone, Default, Debug)]
+pub struct TimeReversalProbe {
+    pub reference_positions: Option<HashMap<Entity, Vector>>,
+    /// Simulated time when the reference positions were recorded.
+    pub reference_time: Scalar,
+    /// Simulated time of the second reversal, once the bodies are on their way back.
+    pub return_time: Option<Scalar>,
+    pub last_rms_deviation: Option<Scalar>,
+}
+
+impl TimeReversalProbe {
+    /// RMS distance of `positions` from the reference positions of the same bodies.
+    pub fn rms_deviation(
+        &self,
+        positions: impl IntoIterator<Item = (Entity, Vector)>,
+    ) -> Option<Scalar> {
+        let reference_positions = self.reference_positions.as_ref()?;
+        let (sum, count) = positions
+            .into_iter()
+            .filter_map(|(entity, position)| {
+                reference_positions
+                    .get(&entity)
+                    .map(|reference| reference.distance_squared(position))
+            })
+            .fold((0.0, 0_usize), |(sum, count), distance_squared| {
+                (sum + distance_squared, count + 1)
+            });
+
+        (count > 0).then(|| libm::sqrt(sum / count as Scalar))
+    }
+}
+
+/// Conserved quantities at the start of the run and at the latest check.
+#[derive(Resource, Serialize, Deserialize, Clone, Default, Debug)]
+pub struct ConservationMonitor {
+    pub initial: Option<physics::conservation::ConservedQuantities>,
+    pub current: Option<physics::conservation::ConservedQuantities>,
+    pub drift: physics::conservation::ConservationDrift,
+    /// Fixed steps since the last check.
+    pub steps_since_check: u32,
+    /// Set once drift has exceeded the threshold, so it is only acted on once per run.
+    pub threshold_exceeded: bool,
+}
+
+/// Virial ratio and Lagrangian radii at the latest conservation check.
+#[derive(Resource, Deref, DerefMut, Copy, Clone, Default, PartialEq, Debug)]
+pub struct ClusterStructure(pub Option<physics::cluster::ClusterStructure>);
+
+/// Friends-of-friends groups from the latest search, ordered by decreasing mass.
+#[derive(Resource, Deref, DerefMut, Clone, Default, PartialEq, Debug)]
+pub struct GroupCatalog(pub Vec<physics::groups::Group>);
+
+#[derive(Resource, Default)]
+pub struct GroupColoringSettings {
+    pub enabled: bool,
+}
+
+/// Bodies unbound at the latest classification, and everything removed as escapers so far.
+#[derive(Resource, Serialize, Deserialize, Copy, Clone, Default, PartialEq, Debug)]
+pub struct EscaperStatistics {
+    pub unbound_count: usize,
+    pub unbound_mass: Scalar,
+    pub removed_count: usize,
+    pub removed_mass: Scalar,
+    pub removed_energy: Scalar,
+}
+
+/// Collision outcomes since the start of the run.
+#[derive(Resource, Serialize, Deserialize, Copy, Clone, Default, PartialEq, Debug)]
+pub struct CollisionStatistics {
+    pub merger_count: usize,
+    /// Bodies shattered by energetic impacts.
+    pub shattered_count: usize,
+    pub fragment_count: usize,
+    pub tidal_disruption_count: usize,
+}
+
+/// The trajectory file being recorded, which is opened on the first recorded step.
+#[derive(Resource, Default)]
+pub struct TrajectoryRecording {
+    pub writer: Option<trajectory::TrajectoryWriter<BufWriter<File>>>,
+    /// File the latest recording went to, kept once it is finished so it can be replayed.
+    pub path: Option<PathBuf>,
+    /// Fixed steps since recording started.
+    pub steps: u64,
+    /// Set once the file could not be opened or written, so recording is not retried.
+    pub failed: bool,
+}
+
+impl TrajectoryRecording {
+    /// Flushes and closes the file being recorded to, so the next recorded step starts over.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        let writer = self.writer.take();
+        self.steps = 0;
+        self.failed = false;
+        writer.map_or(Ok(()), |mut writer| writer.flush())
+    }
+}
+
+/// Fixed steps to run before quitting, when given on the command line.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepLimit {
+    pub steps: Option<NonZeroU64>,
+    /// Export the bodies before quitting.
+    pub export: bool,
+}
+
+/// A recorded trajectory being played back, with a playhead that can sit between frames.
+#[derive(Resource)]
+pub struct Replay {
+    pub header: trajectory::TrajectoryHeader,
+    pub frames: Vec<trajectory::TrajectoryFrame>,
+    /// Position of the playhead as a fractional index into `frames`.
+    pub playhead: Scalar,
+    /// Multiple of the speed the trajectory was recorded at.
+    pub speed: Scalar,
+    pub reversed: bool,
+    pub playing: bool,
+    /// Entity showing each recorded body, by body ID.
+    pub bodies: HashMap<physics::BodyId, Entity>,
+}
+
+impl Replay {
+    pub fn new(
+        header: trajectory::TrajectoryHeader,
+        frames: Vec<trajectory::TrajectoryFrame>,
+    ) -> Self {
+        Self {
+            header,
+            frames,
+            playhead: 0.0,
+            speed: 1.0,
+            reversed: false,
+            playing: true,
+            bodies: HashMap::new(),
+        }
+    }
+
+    fn last_index(&self) -> Scalar {
+        self.frames.len().saturating_sub(1) as Scalar
+    }
+
+    /// Moves the playhead by `frames` in the direction of play, stopping at either end.
+    pub fn advance(&mut self, frames: Scalar) {
+        let frames = if self.reversed { -frames } else { frames };
+        self.playhead = (self.playhead + frames).clamp(0.0, self.last_index());
+        if (self.reversed && self.playhead <= 0.0)
+            || (!self.reversed && self.playhead >= self.last_index())
+        {
+            self.playing = false;
+        }
+    }
+
+    /// Moves the playhead to `progress` of the way through the recording.
+    pub fn seek(&mut self, progress: Scalar) {
+        self.playhead = progress.clamp(0.0, 1.0) * self.last_index();
+    }
+
+    /// How far through the recording the playhead is, from zero to one.
+    pub fn progress(&self) -> Scalar {
+        if self.frames.len() > 1 {
+            self.playhead / self.last_index()
+        } else {
+            0.0
+        }
+    }
+
+    /// The recorded frame at the playhead, interpolated between the frames either side.
+    pub fn frame_at_playhead(&self) -> Option<trajectory::TrajectoryFrame> {
+        let index = self.playhead.floor() as usize;
+        let previous = self.frames.get(index)?;
+        match self.frames.get(index + 1) {
+            Some(next) => Some(previous.interpolate(next, self.playhead - index as Scalar)),
+            None => Some(previous.clone()),
+        }
     }
 }
 
@@ -129,4 +352,64 @@ mod tests {
         // They should be different since they use random seeds
         assert_ne!(value1, value2);
     }
+
+    fn replay(frame_count: u64) -> Replay {
+        let header = trajectory::TrajectoryHeader {
+            config: config::SimulationConfig::default(),
+            seed: None,
+            interval: 10,
+            body_ids: vec![physics::BodyId(1)],
+        };
+        let frames = (0..frame_count)
+            .map(|step| trajectory::TrajectoryFrame {
+                step: step * 10,
+                simulated_time: step as Scalar,
+                bodies: vec![trajectory::BodyState {
+                    id: physics::BodyId(1),
+                    position: Vector::X * step as Scalar,
+                    velocity: Vector::X,
+                    mass: 1.0,
+                    radius: 1.0,
+                }],
+            })
+            .collect();
+        Replay::new(header, frames)
+    }
+
+    #[test]
+    fn test_replay_stops_at_either_end() {
+        let mut replay = replay(5);
+
+        replay.advance(2.5);
+        assert_eq!(replay.playhead, 2.5);
+        assert!(replay.playing);
+
+        replay.advance(10.0);
+        assert_eq!(replay.playhead, 4.0);
+        assert!(!replay.playing);
+
+        replay.reversed = true;
+        replay.playing = true;
+        replay.advance(1.0);
+        assert_eq!(replay.playhead, 3.0);
+        replay.advance(10.0);
+        assert_eq!(replay.playhead, 0.0);
+        assert!(!replay.playing);
+    }
+
+    #[test]
+    fn test_replay_seeks_and_interpolates() {
+        let mut replay = replay(5);
+
+        replay.seek(0.625);
+        assert_eq!(replay.playhead, 2.5);
+        assert_eq!(replay.progress(), 0.625);
+
+        let frame = replay.frame_at_playhead().unwrap();
+        assert_eq!(frame.simulated_time, 2.5);
+        assert!(frame.bodies[0].position.distance(Vector::X * 2.5) < 1e-12);
+
+        replay.seek(2.0);
+        assert_eq!(replay.frame_at_playhead(), replay.frames.last().cloned());
+    }
 }
@@ -0,0 +1,400 @@
+//! Snapshots of the full simulation state, saved to disk so a run can be continued later.
+//!
+//! Floating-point values are written with enough digits to read back exactly, and the shared
+//! random number generator is stored by its seed, stream and position in the stream, so a loaded
+//! run continues bit for bit as the original would have. Bodies are stored in the order their
+//! forces are summed in, and along with them everything else a step carries over to the next:
+//! the integrator's state, the octree the Roche limit is checked with, Avian's contacts and the
+//! statistics of the run.
+
+use crate::config::SimulationConfig;
+use crate::physics::simulation::Stepper;
+use crate::physics::stars::Star;
+use crate::resources;
+use avian3d::collision::contact_types::PackedFeatureId;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use avian3d::math::Vector2;
+use avian3d::prelude::ContactManifold;
+use avian3d::prelude::ContactPair;
+use avian3d::prelude::ContactPairFlags;
+use avian3d::prelude::ContactPoint;
+use bevy::prelude::Entity;
+use rand_chacha::ChaCha8Rng;
+use rand_chacha::rand_core::SeedableRng;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+
+/// Version of the snapshot format; snapshots of any other version are refused.
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// Name of the snapshot file saved next to the user configuration.
+pub const SNAPSHOT_FILE_NAME: &str = "snapshot.json";
+
+/// Position of a ChaCha8 generator in its output.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RngState {
+    pub seed: [u8; 32],
+    pub stream: u64,
+    pub word_pos: u128,
+}
+
+impl RngState {
+    pub fn capture(rng: &ChaCha8Rng) -> Self {
+        Self {
+            seed: rng.get_seed(),
+            stream: rng.get_stream(),
+            word_pos: rng.get_word_pos(),
+        }
+    }
+
+    /// A generator that continues from where the captured one was.
+    pub fn restore(&self) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::from_seed(self.seed);
+        rng.set_stream(self.stream);
+        rng.set_word_pos(self.word_pos);
+        rng
+    }
+}
+
+/// Everything that makes up one body.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BodySnapshot {
+    pub position: [Scalar; 3],
+    /// Orientation quaternion as `[x, y, z, w]`.
+    pub rotation: [Scalar; 4],
+    pub velocity: [Scalar; 3],
+    pub angular_velocity: [Scalar; 3],
+    pub acceleration: [Scalar; 3],
+    pub mass: Scalar,
+    pub density: f32,
+    pub radius: Scalar,
+    pub temperature: Scalar,
+    pub stellar_properties: Option<Star>,
+    /// Fixed steps left before a fragment may merge, shatter or be torn apart again, if it is one.
+    pub fragment_grace_steps: Option<u32>,
+    pub sleeping: bool,
+    /// Time the body has been at rest for, towards falling asleep.
+    pub time_sleeping: Scalar,
+}
+
+/// A body held by the octree, which may have been removed since the octree was built.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OctreeBodySnapshot {
+    /// Index of the body in [`Snapshot::bodies`], unless it has been removed.
+    pub body: Option<usize>,
+    pub position: [Scalar; 3],
+    pub velocity: [Scalar; 3],
+    pub mass: Scalar,
+}
+
+/// One of Avian's contact points, with the impulses its solver starts the next step from.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ContactPointSnapshot {
+    pub local_point1: [Scalar; 3],
+    pub local_point2: [Scalar; 3],
+    pub penetration: Scalar,
+    pub normal_impulse: Scalar,
+    pub tangent_impulse: [Scalar; 2],
+    pub feature_ids: [u32; 2],
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ContactManifoldSnapshot {
+    pub points: Vec<ContactPointSnapshot>,
+    pub normal: [Scalar; 3],
+    pub friction: Scalar,
+    pub restitution: Scalar,
+    pub tangent_velocity: [Scalar; 3],
+    pub index: usize,
+}
+
+/// Avian's contact between two bodies whose bounding boxes overlap.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ContactPairSnapshot {
+    /// Indices of the two bodies in [`Snapshot::bodies`].
+    pub bodies: [usize; 2],
+    pub flags: u8,
+    pub manifolds: Vec<ContactManifoldSnapshot>,
+}
+
+impl ContactPairSnapshot {
+    /// Captures `pair`, unless one of its bodies has no index in the snapshot.
+    pub fn capture(pair: &ContactPair, index_of: impl Fn(Entity) -> Option<usize>) -> Option<Self> {
+        Some(Self {
+            bodies: [index_of(pair.collider1)?, index_of(pair.collider2)?],
+            flags: pair.flags.bits(),
+            manifolds: pair
+                .manifolds
+                .iter()
+                .map(|manifold| ContactManifoldSnapshot {
+                    points: manifold
+                        .points
+                        .iter()
+                        .map(|point| ContactPointSnapshot {
+                            local_point1: point.local_point1.to_array(),
+                            local_point2: point.local_point2.to_array(),
+                            penetration: point.penetration,
+                            normal_impulse: point.normal_impulse,
+                            tangent_impulse: point.tangent_impulse.to_array(),
+                            feature_ids: [point.feature_id1.0, point.feature_id2.0],
+                        })
+                        .collect(),
+                    normal: manifold.normal.to_array(),
+                    friction: manifold.friction,
+                    restitution: manifold.restitution,
+                    tangent_velocity: manifold.tangent_velocity.to_array(),
+                    index: manifold.index,
+                })
+                .collect(),
+        })
+    }
+
+    /// The contact pair between the bodies spawned as `entities`, in the order of
+    /// [`Snapshot::bodies`]. Each body is its own collider.
+    pub fn restore(&self, entities: &[Entity]) -> ContactPair {
+        let [entity1, entity2] = self.bodies.map(|index| entities[index]);
+        ContactPair {
+            collider1: entity1,
+            collider2: entity2,
+            body1: Some(entity1),
+            body2: Some(entity2),
+            manifolds: self
+                .manifolds
+                .iter()
+                .map(|manifold| ContactManifold {
+                    points: manifold
+                        .points
+                        .iter()
+                        .map(|point| ContactPoint {
+                            local_point1: Vector::from_array(point.local_point1),
+                            local_point2: Vector::from_array(point.local_point2),
+                            penetration: point.penetration,
+                            normal_impulse: point.normal_impulse,
+                            tangent_impulse: Vector2::from_array(point.tangent_impulse),
+                            feature_id1: PackedFeatureId(point.feature_ids[0]),
+                            feature_id2: PackedFeatureId(point.feature_ids[1]),
+                        })
+                        .collect(),
+                    normal: Vector::from_array(manifold.normal),
+                    friction: manifold.friction,
+                    restitution: manifold.restitution,
+                    tangent_velocity: Vector::from_array(manifold.tangent_velocity),
+                    index: manifold.index,
+                })
+                .collect(),
+            flags: ContactPairFlags::from_bits_retain(self.flags),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snapshot {
+    pub format_version: u32,
+    pub config: SimulationConfig,
+    pub simulated_time: Scalar,
+    pub time_reversed: bool,
+    pub time_scale: Scalar,
+    pub barycenter: Option<[Scalar; 3]>,
+    pub rng: RngState,
+    pub periodic_check_counters: resources::PeriodicCheckCounters,
+    pub conservation_monitor: resources::ConservationMonitor,
+    pub escaper_statistics: resources::EscaperStatistics,
+    pub collision_statistics: resources::CollisionStatistics,
+    pub stepper: Stepper,
+    pub bodies: Vec<BodySnapshot>,
+    /// Number of bodies at the start of `bodies` that the integrator has stepped before. The
+    /// rest have been spawned since and are taken in by the next step.
+    pub integrated_body_count: usize,
+    /// Bodies in the octree from the previous step, in the order its leaves hold them.
+    pub octree_bodies: Vec<OctreeBodySnapshot>,
+    pub contacts: Vec<ContactPairSnapshot>,
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let snapshot: Self = serde_json::from_str(json)?;
+        if snapshot.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(format!(
+                "Snapshot format version {} is not supported (expected {SNAPSHOT_FORMAT_VERSION})",
+                snapshot.format_version
+            )
+            .into());
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn body(position: [Scalar; 3]) -> BodySnapshot {
+        BodySnapshot {
+            position,
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            velocity: [0.1 + 0.2, 1.0 / 3.0, -5e-324],
+            angular_velocity: [0.0; 3],
+            acceleration: [f64::MIN_POSITIVE, -1.0 / 7.0, 2.0f64.sqrt()],
+            mass: std::f64::consts::PI,
+            density: 0.1,
+            radius: 7.3,
+            temperature: 5772.0,
+            stellar_properties: Some(Star::new(1.3, 4.2)),
+            fragment_grace_steps: Some(3),
+            sleeping: false,
+            time_sleeping: 0.25,
+        }
+    }
+
+    fn snapshot() -> Snapshot {
+        let mut config = SimulationConfig::default();
+        config.physics.initial_seed = Some(u64::MAX - 1);
+
+        Snapshot {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            config,
+            simulated_time: 12.345678901234567,
+            time_reversed: true,
+            time_scale: 0.2,
+            barycenter: Some([1e-17, -2.5, 1e300]),
+            rng: RngState::capture(&resources::SharedRng::from_seed(42)),
+            periodic_check_counters: resources::PeriodicCheckCounters {
+                group_finding: Some(17),
+                bound_classification: None,
+                stellar_evolution: (3, 0.1 + 0.2),
+            },
+            conservation_monitor: resources::ConservationMonitor::default(),
+            escaper_statistics: resources::EscaperStatistics::default(),
+            collision_statistics: resources::CollisionStatistics {
+                merger_count: 2,
+                ..Default::default()
+            },
+            stepper: Stepper::default(),
+            bodies: vec![body([1.0, 2.0, 3.0]), body([-0.1, 1e-9, 6.02e23])],
+            integrated_body_count: 1,
+            octree_bodies: vec![OctreeBodySnapshot {
+                body: None,
+                position: [0.5, -1.0 / 3.0, 0.0],
+                velocity: [0.0; 3],
+                mass: 1e-3,
+            }],
+            contacts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_rng_state_continues_the_sequence() {
+        let mut rng = resources::SharedRng::from_seed(7);
+        (0..13).for_each(|_| {
+            rng.random::<u64>();
+        });
+
+        let mut restored = RngState::capture(&rng).restore();
+        let expected: Vec<u64> = (0..10).map(|_| rng.random()).collect();
+        let actual: Vec<u64> = (0..10).map(|_| restored.random()).collect();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_snapshot_round_trips_exactly() {
+        let snapshot = snapshot();
+        let loaded = Snapshot::from_json(&snapshot.to_json().unwrap()).unwrap();
+
+        assert_eq!(loaded.bodies, snapshot.bodies);
+        assert_eq!(loaded.octree_bodies, snapshot.octree_bodies);
+        assert_eq!(loaded.collision_statistics, snapshot.collision_statistics);
+        assert_eq!(loaded.rng, snapshot.rng);
+        assert_eq!(loaded.barycenter, snapshot.barycenter);
+        assert_eq!(
+            loaded.periodic_check_counters,
+            snapshot.periodic_check_counters
+        );
+        assert_eq!(
+            loaded.simulated_time.to_bits(),
+            snapshot.simulated_time.to_bits()
+        );
+        assert_eq!(loaded.config.physics.initial_seed, Some(u64::MAX - 1));
+        assert_eq!(
+            loaded.bodies[0].velocity[2].to_bits(),
+            snapshot.bodies[0].velocity[2].to_bits()
+        );
+    }
+
+    #[test]
+    fn test_contact_pair_round_trips_to_new_entities() {
+        let old_entities = [Entity::from_raw(5), Entity::from_raw(9)];
+        let new_entities = [Entity::from_raw(1), Entity::from_raw(2)];
+        let mut point = ContactPoint::new(Vector::X, Vector::NEG_X, 1e-3);
+        point.normal_impulse = 0.1 + 0.2;
+        point.tangent_impulse = Vector2::new(-1.0 / 3.0, 5e-324);
+        let mut pair = ContactPair::new(old_entities[0], old_entities[1]);
+        pair.body1 = Some(old_entities[0]);
+        pair.body2 = Some(old_entities[1]);
+        pair.flags = ContactPairFlags::TOUCHING | ContactPairFlags::CONTACT_EVENTS;
+        pair.manifolds
+            .push(ContactManifold::new([point], Vector::Y, 3));
+
+        let captured = ContactPairSnapshot::capture(&pair, |entity| {
+            old_entities.iter().position(|&old| old == entity)
+        })
+        .unwrap();
+        let json = serde_json::to_string(&captured).unwrap();
+        let restored = serde_json::from_str::<ContactPairSnapshot>(&json)
+            .unwrap()
+            .restore(&new_entities);
+
+        assert_eq!(restored.collider1, new_entities[0]);
+        assert_eq!(restored.body2, Some(new_entities[1]));
+        assert_eq!(restored.flags, pair.flags);
+        let (restored_point, point) =
+            (restored.manifolds[0].points[0], pair.manifolds[0].points[0]);
+        assert_eq!(
+            restored_point.normal_impulse.to_bits(),
+            point.normal_impulse.to_bits()
+        );
+        assert_eq!(restored_point.tangent_impulse, point.tangent_impulse);
+        assert_eq!(restored.manifolds[0].index, 3);
+        assert!(ContactPairSnapshot::capture(&pair, |_| None).is_none());
+    }
+
+    #[test]
+    fn test_snapshot_of_other_format_version_refused() {
+        let snapshot = Snapshot {
+            format_version: SNAPSHOT_FORMAT_VERSION + 1,
+            ..snapshot()
+        };
+
+        let error = Snapshot::from_json(&snapshot.to_json().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("not supported"));
+    }
+
+    #[test]
+    fn test_snapshot_save_and_load() {
+        let path = std::path::PathBuf::from("test_snapshot_temp.json");
+        snapshot()
+            .save(&path)
+            .expect("Failed to save test snapshot");
+
+        let loaded = Snapshot::load(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(loaded.unwrap().bodies, snapshot().bodies);
+    }
+}
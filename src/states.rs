@@ -6,6 +6,7 @@ pub enum AppState {
     Loading,
     Running,
     Paused,
+    Replay,
 }
 
 #[derive(SubStates, Debug, Clone, PartialEq, Eq, Hash, Default)]
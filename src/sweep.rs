@@ -0,0 +1,602 @@
+//! Parameter sweeps: the simulation run headless for every combination of configuration values.
+//!
+//! A sweep spec is a TOML file listing values for fields of [`crate::config::PhysicsConfig`],
+//! the seeds every combination is run with and the simulated time each run lasts:
+//!
+//! ```toml
+//! duration = 20.0
+//! seeds = 20
+//! metrics = ["energy_drift", "virial_ratio"]
+//!
+//! [parameters]
+//! octree_theta = [0.3, 0.5, 1.0]
+//! body_count = { start = 100, end = 500, step = 100 }
+//! ```
+//!
+//! Every run is a separate headless app, and runs are spread over a thread pool. The summary has
+//! one row per run, with the swept values, the seed and the chosen metrics. A run that panics is
+//! reported as failed, unless panics abort the process as they do in distribution builds.
+
+use crate::config::ConservationDriftAction;
+use crate::config::SimulationConfig;
+use crate::initial_conditions;
+use crate::physics;
+use crate::resources;
+use crate::states;
+use avian3d::math::Scalar;
+use avian3d::prelude::*;
+use bevy::ecs::schedule::ExecutorKind;
+use bevy::prelude::*;
+use bevy::tasks::ComputeTaskPool;
+use bevy::tasks::TaskPoolBuilder;
+use rayon::iter::IndexedParallelIterator;
+use rayon::iter::IntoParallelRefIterator;
+use rayon::iter::ParallelIterator;
+use serde::Deserialize;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::time::Instant;
+
+/// Most updates a run may take to finish loading before it is given up.
+const MAX_LOADING_UPDATES: usize = 10_000;
+
+/// Values one parameter is swept over.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum ParameterValues {
+    /// Every value listed, in order.
+    List(Vec<toml::Value>),
+    /// `start`, `start + step` and so on up to and including `end`, which are integers if all
+    /// three are.
+    Range {
+        start: toml::Value,
+        end: toml::Value,
+        step: toml::Value,
+    },
+}
+
+impl ParameterValues {
+    pub fn values(&self) -> Result<Vec<toml::Value>, String> {
+        let values = match self {
+            ParameterValues::List(values) => values.clone(),
+            ParameterValues::Range {
+                start: toml::Value::Integer(start),
+                end: toml::Value::Integer(end),
+                step: toml::Value::Integer(step),
+            } if *step > 0 => (*start..=*end)
+                .step_by(*step as usize)
+                .map(toml::Value::Integer)
+                .collect(),
+            ParameterValues::Range { start, end, step } => {
+                let number = |value: &toml::Value| {
+                    value
+                        .as_float()
+                        .or_else(|| value.as_integer().map(|value| value as f64))
+                        .ok_or_else(|| format!("range bounds must be numbers, got `{value}`"))
+                };
+                let (start, end, step) = (number(start)?, number(end)?, number(step)?);
+                if step <= 0.0 || !step.is_finite() {
+                    return Err(format!("range step must be positive, got {step}"));
+                }
+                // Allow for rounding in the step so `end` itself is included
+                let count = libm::floor((end - start) / step + 1e-9) + 1.0;
+                (0..count.max(0.0) as usize)
+                    .map(|index| toml::Value::Float(start + index as f64 * step))
+                    .collect()
+            }
+        };
+
+        if values.is_empty() {
+            return Err("no values to sweep over".to_string());
+        }
+        Ok(values)
+    }
+}
+
+/// Seeds every combination of parameter values is run with.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Seeds {
+    /// Seeds `0` to `count - 1`.
+    Count(u64),
+    List(Vec<u64>),
+}
+
+impl Default for Seeds {
+    fn default() -> Self {
+        Seeds::Count(1)
+    }
+}
+
+impl Seeds {
+    pub fn seeds(&self) -> Vec<u64> {
+        match self {
+            Seeds::Count(count) => (0..*count).collect(),
+            Seeds::List(seeds) => seeds.clone(),
+        }
+    }
+}
+
+/// A column of the sweep summary.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Relative change in total energy over the run, after what escapers carried away.
+    EnergyDrift,
+    /// Seconds the run took.
+    WallTime,
+    /// Virial ratio `2K/|W|` at the end of the run.
+    VirialRatio,
+    /// Collisions that merged two bodies into one.
+    MergerCount,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 4] = [
+        Metric::EnergyDrift,
+        Metric::WallTime,
+        Metric::VirialRatio,
+        Metric::MergerCount,
+    ];
+
+    fn all() -> Vec<Metric> {
+        Self::ALL.to_vec()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::EnergyDrift => "energy_drift",
+            Metric::WallTime => "wall_time",
+            Metric::VirialRatio => "virial_ratio",
+            Metric::MergerCount => "merger_count",
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SweepSpec {
+    /// Simulated time each run lasts.
+    pub duration: Scalar,
+    #[serde(default)]
+    pub seeds: Seeds,
+    /// Metrics in the summary, in the order of its columns.
+    #[serde(default = "Metric::all")]
+    pub metrics: Vec<Metric>,
+    /// Values swept over for each `PhysicsConfig` field, by field name.
+    #[serde(default)]
+    pub parameters: BTreeMap<String, ParameterValues>,
+}
+
+impl SweepSpec {
+    pub fn from_toml(toml: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let spec: Self = toml::from_str(toml)?;
+        if spec.duration <= 0.0 || !spec.duration.is_finite() {
+            return Err(format!("duration must be positive, got {}", spec.duration).into());
+        }
+        Ok(spec)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Every combination of parameter values with every seed.
+    ///
+    /// Runs are ordered by parameters in name order, the last parameter varying fastest, then
+    /// by seed.
+    pub fn runs(&self) -> Result<Vec<SweepRun>, String> {
+        let mut combinations: Vec<Vec<(String, toml::Value)>> = vec![Vec::new()];
+        for (name, values) in &self.parameters {
+            let values = values.values().map_err(|e| format!("`{name}`: {e}"))?;
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push((name.clone(), value.clone()));
+                        combination
+                    })
+                })
+                .collect();
+        }
+
+        let seeds = self.seeds.seeds();
+        if seeds.is_empty() {
+            return Err("no seeds to run with".to_string());
+        }
+        Ok(combinations
+            .into_iter()
+            .flat_map(|parameters| {
+                seeds.iter().map(move |&seed| SweepRun {
+                    parameters: parameters.clone(),
+                    seed,
+                })
+            })
+            .collect())
+    }
+}
+
+/// One configuration of a sweep.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SweepRun {
+    /// Swept `PhysicsConfig` fields and their values.
+    pub parameters: Vec<(String, toml::Value)>,
+    pub seed: u64,
+}
+
+impl SweepRun {
+    /// `base` with this run's values and seed.
+    ///
+    /// Trajectory recording is turned off so runs do not write over each other's files, and
+    /// conservation drift is only reported so no run pauses itself.
+    pub fn config(
+        &self,
+        base: &SimulationConfig,
+    ) -> Result<SimulationConfig, Box<dyn std::error::Error>> {
+        let mut config = base.clone();
+        config.recording.trajectory_recording = false;
+        config.physics.conservation_drift_action = ConservationDriftAction::Ignore;
+        // Replaced first, as a base seed above `i64::MAX` cannot be written as TOML
+        config.physics.initial_seed = Some(self.seed);
+        self.parameters.iter().try_for_each(|(name, value)| {
+            config.set_value(&format!("physics.{name}"), value.clone())
+        })?;
+        Ok(config)
+    }
+}
+
+/// What a run is summarized by.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RunSummary {
+    pub energy_drift: Scalar,
+    pub wall_time: f64,
+    pub virial_ratio: Scalar,
+    pub merger_count: usize,
+}
+
+impl RunSummary {
+    pub fn metric(&self, metric: Metric) -> String {
+        match metric {
+            Metric::EnergyDrift => self.energy_drift.to_string(),
+            Metric::WallTime => self.wall_time.to_string(),
+            Metric::VirialRatio => self.virial_ratio.to_string(),
+            Metric::MergerCount => self.merger_count.to_string(),
+        }
+    }
+}
+
+/// Loads the simulation configured by `config` and runs it for `duration` of simulated time.
+///
+/// A run fails if its bodies cannot be imported, rather than simulating the scenario instead. The
+/// app's systems all run on the calling thread, so the physics spreads its work over the
+/// calling thread's rayon pool and the wall time only counts the run itself, not the loading.
+pub fn run_headless(config: SimulationConfig, duration: Scalar) -> Result<RunSummary, String> {
+    if let Some(path) = &config.physics.initial_conditions_path {
+        initial_conditions::read_bodies(path).map_err(|e| e.to_string())?;
+    }
+    let mut app = crate::headless_app(config);
+    app.world_mut()
+        .resource_mut::<Schedules>()
+        .iter_mut()
+        .for_each(|(_, schedule)| {
+            schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+        });
+
+    let running = |app: &App| {
+        *app.world().resource::<State<states::AppState>>().get() == states::AppState::Running
+    };
+    for _ in 0..MAX_LOADING_UPDATES {
+        if running(&app) {
+            break;
+        }
+        app.update();
+    }
+    if !running(&app) {
+        return Err("the simulation did not finish loading".to_string());
+    }
+
+    let step = app
+        .world()
+        .resource::<Time<Fixed>>()
+        .timestep()
+        .as_secs_f64()
+        * app.world().resource::<Time<Virtual>>().relative_speed_f64();
+    if step <= 0.0 {
+        return Err("simulated time does not advance".to_string());
+    }
+    let started = Instant::now();
+    for _ in 0..libm::ceil(duration / step) as u64 {
+        app.update();
+    }
+    let wall_time = started.elapsed().as_secs_f64();
+
+    let world = app.world_mut();
+    let bodies: Vec<physics::octree::OctreeBody> = world
+        .query_filtered::<(Entity, &Position, &LinearVelocity, &ComputedMass), With<RigidBody>>()
+        .iter(world)
+        .map(
+            |(entity, position, velocity, mass)| physics::octree::OctreeBody {
+                id: entity.into(),
+                position: position.0,
+                velocity: velocity.0,
+                mass: mass.value(),
+            },
+        )
+        .collect();
+    let mut octree =
+        physics::simulation::octree_from_config(&world.resource::<SimulationConfig>().physics);
+    octree.build(bodies.iter().copied());
+    let quantities = physics::conservation::ConservedQuantities::measure(
+        &bodies,
+        &octree,
+        **world.resource::<resources::GravitationalConstant>(),
+    );
+
+    Ok(RunSummary {
+        energy_drift: world
+            .resource::<resources::ConservationMonitor>()
+            .initial
+            .map_or(0.0, |initial| quantities.drift_from(&initial).energy),
+        wall_time,
+        virial_ratio: quantities.virial_ratio(),
+        merger_count: world
+            .resource::<resources::CollisionStatistics>()
+            .merger_count,
+    })
+}
+
+/// The result of `run`, or a failure with the panic message if it panics.
+fn catch_panic(run: impl FnOnce() -> Result<RunSummary, String>) -> Result<RunSummary, String> {
+    std::panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or_else(|panic: Box<dyn Any + Send>| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        Err(format!("the run panicked: {message}"))
+    })
+}
+
+/// Runs every configuration for `duration` on `threads` threads, or one per core if `None`.
+///
+/// `finished` is called with each run's index and result as soon as it is done. The results are
+/// returned in the order of `configs`.
+///
+/// Bevy's compute task pool is given a single thread, unless an app has set it up before, so the
+/// runs' queries do not compete with the runs themselves for the cores.
+pub fn run_all(
+    configs: &[SimulationConfig],
+    duration: Scalar,
+    threads: Option<usize>,
+    finished: impl Fn(usize, &Result<RunSummary, String>) + Sync,
+) -> Result<Vec<Result<RunSummary, String>>, Box<dyn std::error::Error>> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .build()?;
+    ComputeTaskPool::get_or_init(|| TaskPoolBuilder::new().num_threads(1).build());
+    Ok(pool.install(|| {
+        configs
+            .par_iter()
+            .enumerate()
+            .map(|(index, config)| {
+                let result = catch_panic(|| run_headless(config.clone(), duration));
+                finished(index, &result);
+                result
+            })
+            .collect()
+    }))
+}
+
+fn cell(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Writes one row per run, with the swept values, the seed and the spec's metrics. Metrics of
+/// runs that failed are left empty.
+pub fn write_summary_csv(
+    spec: &SweepSpec,
+    runs: &[SweepRun],
+    results: &[Result<RunSummary, String>],
+    writer: impl Write,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+
+    let header = spec
+        .parameters
+        .keys()
+        .map(String::as_str)
+        .chain(["seed"])
+        .chain(spec.metrics.iter().map(|metric| metric.name()));
+    writer.write_record(header)?;
+
+    for (run, result) in runs.iter().zip(results) {
+        let record = run
+            .parameters
+            .iter()
+            .map(|(_, value)| cell(value))
+            .chain([run.seed.to_string()])
+            .chain(spec.metrics.iter().map(|&metric| {
+                result
+                    .as_ref()
+                    .map_or_else(|_| String::new(), |summary| summary.metric(metric))
+            }));
+        writer.write_record(record)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"
+        duration = 2.5
+        seeds = [7, 9]
+        metrics = ["virial_ratio", "merger_count"]
+
+        [parameters]
+        octree_theta = [0.5, 1.0]
+        body_count = { start = 100, end = 300, step = 100 }
+    "#;
+
+    #[test]
+    fn test_spec_from_toml() {
+        let spec = SweepSpec::from_toml(SPEC).unwrap();
+
+        assert_eq!(spec.duration, 2.5);
+        assert_eq!(spec.seeds.seeds(), vec![7, 9]);
+        assert_eq!(spec.metrics, vec![Metric::VirialRatio, Metric::MergerCount]);
+        assert_eq!(
+            spec.parameters["body_count"].values().unwrap(),
+            vec![
+                toml::Value::Integer(100),
+                toml::Value::Integer(200),
+                toml::Value::Integer(300)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spec_defaults() {
+        let spec = SweepSpec::from_toml("duration = 1.0").unwrap();
+
+        assert_eq!(spec.seeds.seeds(), vec![0]);
+        assert_eq!(spec.metrics, Metric::ALL.to_vec());
+        assert_eq!(spec.runs().unwrap().len(), 1);
+
+        assert!(SweepSpec::from_toml("duration = 0.0").is_err());
+        assert!(SweepSpec::from_toml("duration = 1.0\nsteps = 5").is_err());
+    }
+
+    #[test]
+    fn test_float_range_includes_end() {
+        let values = ParameterValues::Range {
+            start: toml::Value::Float(0.1),
+            end: toml::Value::Float(0.3),
+            step: toml::Value::Float(0.1),
+        }
+        .values()
+        .unwrap();
+
+        assert_eq!(values.len(), 3);
+        assert!((values[2].as_float().unwrap() - 0.3).abs() < 1e-12);
+
+        let empty = ParameterValues::Range {
+            start: toml::Value::Integer(5),
+            end: toml::Value::Integer(1),
+            step: toml::Value::Integer(1),
+        };
+        assert!(empty.values().is_err());
+    }
+
+    #[test]
+    fn test_runs_cover_every_combination() {
+        let runs = SweepSpec::from_toml(SPEC).unwrap().runs().unwrap();
+
+        assert_eq!(runs.len(), 3 * 2 * 2);
+        assert_eq!(
+            runs[0],
+            SweepRun {
+                parameters: vec![
+                    ("body_count".to_string(), toml::Value::Integer(100)),
+                    ("octree_theta".to_string(), toml::Value::Float(0.5)),
+                ],
+                seed: 7,
+            }
+        );
+        assert_eq!(runs[1].seed, 9);
+        assert_eq!(runs[2].parameters[1].1, toml::Value::Float(1.0));
+    }
+
+    #[test]
+    fn test_run_config_applies_values() {
+        let runs = SweepSpec::from_toml(SPEC).unwrap().runs().unwrap();
+        let mut base = SimulationConfig::default();
+        base.recording.trajectory_recording = true;
+        base.physics.initial_seed = Some(u64::MAX);
+
+        let config = runs[3].config(&base).unwrap();
+
+        assert_eq!(config.physics.body_count, 100);
+        assert_eq!(config.physics.octree_theta, 1.0);
+        assert_eq!(config.physics.initial_seed, Some(9));
+        assert!(!config.recording.trajectory_recording);
+
+        let unknown = SweepRun {
+            parameters: vec![("nonsense".to_string(), toml::Value::Integer(1))],
+            seed: 0,
+        };
+        assert!(unknown.config(&base).is_err());
+    }
+
+    #[test]
+    fn test_run_with_bad_initial_conditions_fails() {
+        let path = std::env::temp_dir().join(format!(
+            "stardrift_sweep_bad_bodies_{}.csv",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            "mass,radius,position_x,position_y,position_z,velocity_x,velocity_y,velocity_z\n\
+             -1,1,0,0,0,0,0,0\n",
+        )
+        .unwrap();
+        let mut config = SimulationConfig::default();
+        config.physics.initial_conditions_path = Some(path.clone());
+
+        let result = run_headless(config, 1.0);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            result.unwrap_err(),
+            format!("{}:2: `mass` is -1, but must be positive", path.display())
+        );
+    }
+
+    #[test]
+    fn test_panicking_run_fails() {
+        assert_eq!(
+            catch_panic(|| panic!("diverged")),
+            Err("the run panicked: diverged".to_string())
+        );
+        assert_eq!(
+            catch_panic(|| panic!("diverged after {} steps", 3)),
+            Err("the run panicked: diverged after 3 steps".to_string())
+        );
+        assert!(catch_panic(|| Ok(RunSummary::default())).is_ok());
+    }
+
+    #[test]
+    fn test_summary_csv() {
+        let spec = SweepSpec::from_toml(SPEC).unwrap();
+        let runs = &spec.runs().unwrap()[..2];
+        let results = [
+            Ok(RunSummary {
+                energy_drift: 1e-6,
+                wall_time: 0.5,
+                virial_ratio: 0.75,
+                merger_count: 3,
+            }),
+            Err("failed".to_string()),
+        ];
+
+        let mut csv = Vec::new();
+        write_summary_csv(&spec, runs, &results, &mut csv).unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "body_count,octree_theta,seed,virial_ratio,merger_count\n\
+             100,0.5,7,0.75,3\n\
+             100,0.5,9,,\n"
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::config;
 use crate::resources;
+use crate::systems;
 use crate::utils;
 use avian3d::math::Scalar;
 use bevy::color::palettes::css;
@@ -51,21 +52,31 @@ pub fn spawn_camera(
     ));
 }
 
+/// Points the camera back at the origin, where the bodies of a new simulation are spawned.
+pub fn refocus_camera_on_restart(
+    mut restart_events: EventReader<systems::simulation_actions::RestartSimulationEvent>,
+    mut pan_orbit_camera: Single<&mut PanOrbitCamera>,
+) {
+    if restart_events.read().count() > 0 {
+        pan_orbit_camera.target_focus = Vec3::ZERO;
+        pan_orbit_camera.force_update = true;
+    }
+}
+
 pub fn draw_barycenter_gizmo(
     mut gizmos: Gizmos,
     body_count: Res<resources::BodyCount>,
     barycenter_gizmo_visibility: Res<resources::BarycenterGizmoVisibility>,
     barycenter: Res<resources::Barycenter>,
 ) {
-    if barycenter_gizmo_visibility.enabled {
-        if let Some(barycenter) = **barycenter {
-            if barycenter.is_finite() {
-                gizmos.cross(
-                    barycenter.as_vec3(),
-                    libm::cbrt(**body_count as Scalar * **body_count as Scalar / 3.0) as f32,
-                    css::WHITE,
-                );
-            }
-        }
+    if barycenter_gizmo_visibility.enabled
+        && let Some(barycenter) = **barycenter
+        && barycenter.is_finite()
+    {
+        gizmos.cross(
+            barycenter.as_vec3(),
+            libm::cbrt(**body_count as Scalar * **body_count as Scalar / 3.0) as f32,
+            css::WHITE,
+        );
     }
 }
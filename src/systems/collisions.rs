@@ -0,0 +1,454 @@
+use crate::components;
+use crate::config;
+use crate::physics;
+use crate::resources;
+use avian3d::math::Scalar;
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+/// Run condition for systems that only apply in the given collision mode.
+pub fn collision_mode_is(
+    mode: config::CollisionMode,
+) -> impl Fn(Res<config::SimulationConfig>) -> bool + Clone {
+    move |config: Res<config::SimulationConfig>| config.physics.collision_mode == mode
+}
+
+pub fn fragmentation_enabled(config: Res<config::SimulationConfig>) -> bool {
+    config.physics.fragmentation_threshold.is_some()
+}
+
+fn colliding_body(
+    position: &Position,
+    velocity: &LinearVelocity,
+    angular_velocity: &AngularVelocity,
+    mass: &ComputedMass,
+    collider: &Collider,
+    temperature: &components::Temperature,
+) -> physics::collisions::CollidingBody {
+    physics::collisions::CollidingBody {
+        position: position.0,
+        velocity: velocity.0,
+        angular_velocity: angular_velocity.0,
+        mass: mass.value(),
+        radius: collider.shape().as_ball().map_or(0.0, |ball| ball.radius),
+        temperature: **temperature,
+    }
+}
+
+/// Spawns a body for a fragment of a body with the given density.
+fn spawn_fragment<'a>(
+    commands: &'a mut Commands,
+    fragment: &physics::collisions::CollidingBody,
+    density: ColliderDensity,
+    config: &config::SimulationConfig,
+) -> EntityCommands<'a> {
+    commands.spawn((
+        components::BodyBundle::new(
+            fragment.position.as_vec3(),
+            fragment.radius,
+            fragment.temperature,
+            config,
+        ),
+        Position(fragment.position),
+        LinearVelocity(fragment.velocity),
+        AngularVelocity(fragment.angular_velocity),
+        density,
+    ))
+}
+
+/// A body torn apart by the tides of a more massive one.
+#[derive(Event, Copy, Clone, Debug)]
+pub struct TidalDisruptionEvent {
+    pub body: Entity,
+    pub primary: Entity,
+    pub distance: Scalar,
+    pub roche_radius: Scalar,
+    pub fragment_count: usize,
+}
+
+pub fn tidal_disruption_enabled(config: Res<config::SimulationConfig>) -> bool {
+    config.physics.tidal_disruption
+}
+
+/// Which of two colliding bodies the impact shatters, as `(a, b)`.
+///
+/// A body shatters when its share of the impact energy per unit mass exceeds
+/// `fragmentation_threshold` and it is large enough to break into `fragment_count` fragments no
+/// smaller than `fragment_min_radius` on average.
+fn shattered(
+    a: &physics::collisions::CollidingBody,
+    b: &physics::collisions::CollidingBody,
+    config: &config::SimulationConfig,
+) -> (bool, bool) {
+    let Some(threshold) = config.physics.fragmentation_threshold else {
+        return (false, false);
+    };
+
+    let fragment_count = config.physics.fragment_count.max(2) as Scalar;
+    let shatters = |body: &physics::collisions::CollidingBody, specific_energy: Scalar| {
+        specific_energy > threshold
+            && body.radius / fragment_count.cbrt() >= config.physics.fragment_min_radius
+    };
+    let (a_energy, b_energy) = physics::collisions::impact_specific_energies(a, b);
+    (shatters(a, a_energy), shatters(b, b_energy))
+}
+
+/// Shatters bodies in collisions energetic enough to break them apart.
+///
+/// Each shattered body is replaced by fragments of the same density and temperature, ejected at
+/// `fragment_ejecta_speed_ratio` times the impact speed on average. The impact speed is the
+/// relative speed of the pair when the collision event is read, after Avian's contact response,
+/// so with restitution below one it understates the approach speed. Fragments are drawn from
+/// [`resources::SharedRng`], so seeded runs shatter the same way every time. Fragments are
+/// [`components::Fragment`]s for `fragment_grace_steps`, during which their collisions only
+/// bounce.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn fragment_colliding_bodies(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionStarted>,
+    bodies: Query<
+        (
+            &Position,
+            &LinearVelocity,
+            &AngularVelocity,
+            &ComputedMass,
+            &Collider,
+            &ColliderDensity,
+            &components::Temperature,
+        ),
+        (With<RigidBody>, Without<components::Fragment>),
+    >,
+    mut rng: ResMut<resources::SharedRng>,
+    mut statistics: ResMut<resources::CollisionStatistics>,
+    mut shattered_bodies: Local<HashSet<Entity>>,
+    config: Res<config::SimulationConfig>,
+) {
+    shattered_bodies.clear();
+
+    for &CollisionStarted(first, second) in collisions.read() {
+        if first == second
+            || shattered_bodies.contains(&first)
+            || shattered_bodies.contains(&second)
+        {
+            continue;
+        }
+        let Ok([(first_state, first_density), (second_state, second_density)]) =
+            bodies.get_many([first, second]).map(|bodies| {
+                bodies.map(
+                    |(
+                        position,
+                        velocity,
+                        angular_velocity,
+                        mass,
+                        collider,
+                        density,
+                        temperature,
+                    )| {
+                        let state = colliding_body(
+                            position,
+                            velocity,
+                            angular_velocity,
+                            mass,
+                            collider,
+                            temperature,
+                        );
+                        (state, *density)
+                    },
+                )
+            })
+        else {
+            continue;
+        };
+        let impact_speed = first_state.velocity.distance(second_state.velocity);
+        let (first_shatters, second_shatters) = shattered(&first_state, &second_state, &config);
+
+        for (entity, state, density, shatters) in [
+            (first, first_state, first_density, first_shatters),
+            (second, second_state, second_density, second_shatters),
+        ] {
+            if !shatters {
+                continue;
+            }
+
+            let new_fragments = physics::collisions::fragment(
+                &state,
+                config.physics.fragment_count,
+                config.physics.fragment_ejecta_speed_ratio * impact_speed,
+                config.physics.fragment_ejecta_distribution,
+                &mut rng.0,
+            );
+            for fragment in &new_fragments {
+                spawn_fragment(&mut commands, fragment, density, &config)
+                    .insert(components::Fragment(config.physics.fragment_grace_steps));
+            }
+
+            commands.entity(entity).despawn();
+            shattered_bodies.insert(entity);
+            statistics.shattered_count += 1;
+            statistics.fragment_count += new_fragments.len();
+        }
+    }
+}
+
+/// Merges every pair of bodies that started touching into the heavier of the two.
+///
+/// Avian has already resolved the contact by the time its collision event is read, but the
+/// contact conserves momentum, so the merged body still carries the momentum from before. The
+/// survivor's collider, density and temperature are set for the merged body, and its exact mass
+/// is set at once so the next force evaluation sees it, and again once Avian has recomputed it.
+/// A body can absorb several others in the same step. Pairs that shatter are left to
+/// [`fragment_colliding_bodies`], and fragments still in their grace period only bounce.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn merge_colliding_bodies(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionStarted>,
+    mut bodies: Query<
+        (
+            &mut Position,
+            &mut LinearVelocity,
+            &mut AngularVelocity,
+            &mut ComputedMass,
+            &mut Collider,
+            &mut ColliderDensity,
+            &mut components::Temperature,
+        ),
+        (With<RigidBody>, Without<components::Fragment>),
+    >,
+    mut statistics: ResMut<resources::CollisionStatistics>,
+    mut absorbed: Local<HashSet<Entity>>,
+    config: Res<config::SimulationConfig>,
+) {
+    absorbed.clear();
+
+    for &CollisionStarted(first, second) in collisions.read() {
+        if first == second || absorbed.contains(&first) || absorbed.contains(&second) {
+            continue;
+        }
+        let Ok([first_body, second_body]) = bodies.get_many([first, second]).map(|bodies| {
+            bodies.map(
+                |(position, velocity, angular_velocity, mass, collider, _, temperature)| {
+                    colliding_body(
+                        position,
+                        velocity,
+                        angular_velocity,
+                        mass,
+                        collider,
+                        temperature,
+                    )
+                },
+            )
+        }) else {
+            continue;
+        };
+        if shattered(&first_body, &second_body, &config) != (false, false) {
+            continue;
+        }
+
+        let (survivor, absorbed_body) = if first_body.mass >= second_body.mass {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        let merged = physics::collisions::merge(
+            &first_body,
+            &second_body,
+            config.physics.merge_conserves_spin,
+        );
+
+        let Ok((
+            mut position,
+            mut velocity,
+            mut angular_velocity,
+            mut mass,
+            mut collider,
+            mut density,
+            mut temperature,
+        )) = bodies.get_mut(survivor)
+        else {
+            continue;
+        };
+
+        let volume = 4.0 / 3.0 * core::f64::consts::PI * merged.radius.powi(3);
+        position.0 = merged.position;
+        velocity.0 = merged.velocity;
+        angular_velocity.0 = merged.angular_velocity;
+        *mass = ComputedMass::new(merged.mass);
+        *collider = Collider::sphere(merged.radius);
+        *density = ColliderDensity((merged.mass / volume) as f32);
+        **temperature = merged.temperature;
+        // Avian recomputes the mass from the new collider and the `f32` density
+        commands
+            .entity(survivor)
+            .insert(components::RestoredMass(merged.mass));
+
+        commands.entity(absorbed_body).despawn();
+        absorbed.insert(absorbed_body);
+        statistics.merger_count += 1;
+    }
+}
+
+/// Counts down the grace period of every [`components::Fragment`], after which it is an ordinary
+/// body again.
+pub fn settle_fragments(
+    mut commands: Commands,
+    mut fragments: Query<(Entity, &mut components::Fragment)>,
+) {
+    for (entity, mut remaining_steps) in &mut fragments {
+        if **remaining_steps <= 1 {
+            commands.entity(entity).remove::<components::Fragment>();
+        } else {
+            **remaining_steps -= 1;
+        }
+    }
+}
+
+/// Tears apart bodies that are within the Roche limit of a body at least
+/// `tidal_disruption_mass_ratio` times as massive.
+///
+/// Candidate primaries are looked up at their current positions, within the Roche limit that the
+/// most massive body would have, and of those whose Roche limit a body is inside, the one with
+/// the strongest tidal field across it tears it apart. Each disrupted body is replaced by a string
+/// of `tidal_fragment_count` [`components::Fragment`]s along the line to its primary, drawn from
+/// [`resources::SharedRng`], which are not torn apart again during their grace period. Bodies too
+/// small to make fragments of at least `fragment_min_radius` on average are left whole.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn disrupt_bodies_within_roche_limit(
+    mut commands: Commands,
+    bodies: Query<
+        (
+            Entity,
+            &Position,
+            &LinearVelocity,
+            &AngularVelocity,
+            &ComputedMass,
+            &Collider,
+            &ColliderDensity,
+            &components::Temperature,
+            Has<components::Fragment>,
+        ),
+        With<RigidBody>,
+    >,
+    mut rng: ResMut<resources::SharedRng>,
+    mut statistics: ResMut<resources::CollisionStatistics>,
+    mut disruption_events: EventWriter<TidalDisruptionEvent>,
+    config: Res<config::SimulationConfig>,
+) {
+    let state = |entity| {
+        bodies.get(entity).ok().map(
+            |(_, position, velocity, angular_velocity, mass, collider, _, temperature, _)| {
+                colliding_body(
+                    position,
+                    velocity,
+                    angular_velocity,
+                    mass,
+                    collider,
+                    temperature,
+                )
+            },
+        )
+    };
+
+    let mass_ratio = config.physics.tidal_disruption_mass_ratio;
+    let (min_mass, max_mass) = bodies
+        .iter()
+        .map(|(_, _, _, _, mass, ..)| mass.value())
+        .fold(
+            (Scalar::INFINITY, 0.0 as Scalar),
+            |(min_mass, max_mass), mass| (min_mass.min(mass), max_mass.max(mass)),
+        );
+
+    // Only bodies heavy enough to tear apart the lightest one can be primaries
+    let mut primaries = physics::octree::Octree::new(0.0, 0.0, Scalar::INFINITY);
+    primaries.build(
+        bodies
+            .iter()
+            .filter(|(_, _, _, _, mass, ..)| mass.value() >= mass_ratio * min_mass)
+            .map(
+                |(entity, position, velocity, _, mass, ..)| physics::octree::OctreeBody {
+                    id: entity.into(),
+                    position: position.0,
+                    velocity: velocity.0,
+                    mass: mass.value(),
+                },
+            ),
+    );
+    if primaries.root.is_none() {
+        return;
+    }
+
+    let fragment_count = config.physics.tidal_fragment_count.max(2);
+    let mut disrupted = HashSet::new();
+
+    for (entity, _, _, _, mass, _, density, _, is_fragment) in &bodies {
+        if is_fragment || max_mass < mass_ratio * mass.value() {
+            continue;
+        }
+        let Some(body) = state(entity) else {
+            continue;
+        };
+        if body.radius / (fragment_count as Scalar).cbrt() < config.physics.fragment_min_radius {
+            continue;
+        }
+
+        let reach = 2.44 * body.radius * (max_mass / body.mass).cbrt();
+        let mut primary = None;
+        let mut strongest_tide = 0.0;
+        primaries.for_each_body_within(body.position, reach, |candidate| {
+            let candidate_entity = Entity::from(candidate.id);
+            if candidate_entity == entity
+                || disrupted.contains(&candidate_entity)
+                || candidate.mass < mass_ratio * body.mass
+            {
+                return;
+            }
+            let Some(candidate_body) = state(candidate_entity) else {
+                return;
+            };
+            let roche_radius = physics::collisions::roche_radius(&candidate_body, &body);
+            let distance = candidate_body.position.distance(body.position);
+            // The difference in pull across the body grows as M / d³
+            let tide = candidate_body.mass / distance.powi(3);
+            if distance < roche_radius && tide > strongest_tide {
+                strongest_tide = tide;
+                primary = Some((
+                    candidate_entity,
+                    candidate_body.position,
+                    distance,
+                    roche_radius,
+                ));
+            }
+        });
+        let Some((primary, primary_position, distance, roche_radius)) = primary else {
+            continue;
+        };
+
+        let fragments =
+            physics::collisions::tidal_stream(&body, primary_position, fragment_count, &mut rng.0);
+        for fragment in &fragments {
+            spawn_fragment(&mut commands, fragment, *density, &config)
+                .insert(components::Fragment(config.physics.fragment_grace_steps));
+        }
+
+        commands.entity(entity).despawn();
+        disrupted.insert(entity);
+        statistics.tidal_disruption_count += 1;
+        statistics.fragment_count += fragments.len();
+        disruption_events.write(TidalDisruptionEvent {
+            body: entity,
+            primary,
+            distance,
+            roche_radius,
+            fragment_count: fragments.len(),
+        });
+    }
+}
+
+pub fn log_tidal_disruptions(mut disruption_events: EventReader<TidalDisruptionEvent>) {
+    for event in disruption_events.read() {
+        info!(
+            "Body {} torn into {} fragments by {} at {:.1} (Roche limit {:.1})",
+            event.body, event.fragment_count, event.primary, event.distance, event.roche_radius
+        );
+    }
+}
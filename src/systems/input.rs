@@ -1,4 +1,5 @@
 use crate::resources;
+use crate::states;
 use crate::systems;
 use bevy::prelude::*;
 
@@ -17,6 +18,15 @@ pub fn restart_simulation_on_n(
     }
 }
 
+pub fn toggle_group_coloring_on_g(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut group_coloring_events: EventWriter<systems::simulation_actions::ToggleGroupColoringEvent>,
+) {
+    if keys.just_pressed(KeyCode::KeyG) {
+        group_coloring_events.write(systems::simulation_actions::ToggleGroupColoringEvent);
+    }
+}
+
 pub fn pause_physics_on_space(
     keys: Res<ButtonInput<KeyCode>>,
     mut pause_events: EventWriter<systems::simulation_actions::TogglePauseSimulationEvent>,
@@ -64,3 +74,80 @@ pub fn toggle_barycenter_gizmo_visibility_on_c(
         }
     }
 }
+
+pub fn change_time_scale_on_brackets(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut speed_up_events: EventWriter<systems::simulation_actions::SpeedUpSimulationEvent>,
+    mut slow_down_events: EventWriter<systems::simulation_actions::SlowDownSimulationEvent>,
+) {
+    if keys.just_pressed(KeyCode::BracketRight) {
+        speed_up_events.write(systems::simulation_actions::SpeedUpSimulationEvent);
+    }
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        slow_down_events.write(systems::simulation_actions::SlowDownSimulationEvent);
+    }
+}
+
+pub fn step_simulation_on_period(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut step_events: EventWriter<systems::simulation_actions::StepSimulationEvent>,
+) {
+    if keys.just_pressed(KeyCode::Period) {
+        let shifted = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        step_events.write(systems::simulation_actions::StepSimulationEvent(
+            if shifted { 10 } else { 1 },
+        ));
+    }
+}
+
+pub fn reverse_time_on_r(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut reverse_events: EventWriter<systems::simulation_actions::ReverseTimeEvent>,
+) {
+    if keys.just_pressed(KeyCode::KeyR) {
+        reverse_events.write(systems::simulation_actions::ReverseTimeEvent);
+    }
+}
+
+pub fn save_snapshot_on_f5(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut save_events: EventWriter<systems::simulation_actions::SaveSnapshotEvent>,
+) {
+    if keys.just_pressed(KeyCode::F5) {
+        save_events.write(systems::simulation_actions::SaveSnapshotEvent);
+    }
+}
+
+pub fn export_bodies_on_e(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut export_events: EventWriter<systems::simulation_actions::ExportBodiesEvent>,
+) {
+    if keys.just_pressed(KeyCode::KeyE) {
+        export_events.write(systems::simulation_actions::ExportBodiesEvent);
+    }
+}
+
+pub fn load_snapshot_on_f9(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut load_events: EventWriter<systems::simulation_actions::LoadSnapshotEvent>,
+) {
+    if keys.just_pressed(KeyCode::F9) {
+        load_events.write(systems::simulation_actions::LoadSnapshotEvent);
+    }
+}
+
+/// Starts replaying the recorded trajectory, or leaves the replay for a new simulation.
+pub fn toggle_replay_on_f8(
+    keys: Res<ButtonInput<KeyCode>>,
+    current_state: Res<State<states::AppState>>,
+    mut start_replay_events: EventWriter<systems::simulation_actions::StartReplayEvent>,
+    mut restart_events: EventWriter<systems::simulation_actions::RestartSimulationEvent>,
+) {
+    if keys.just_pressed(KeyCode::F8) {
+        if *current_state.get() == states::AppState::Replay {
+            restart_events.write(systems::simulation_actions::RestartSimulationEvent);
+        } else {
+            start_replay_events.write(systems::simulation_actions::StartReplayEvent);
+        }
+    }
+}
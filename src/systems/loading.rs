@@ -4,20 +4,16 @@ use crate::states;
 use crate::systems;
 use bevy::prelude::*;
 
+#[cfg(feature = "graphics")]
 #[derive(Component)]
 pub struct LoadingScreen;
 
+#[cfg(feature = "graphics")]
 #[derive(Component)]
 pub struct LoadingProgressBar;
 
-pub fn setup_loading_screen(
-    mut commands: Commands,
-    mut loading_progress: ResMut<resources::LoadingProgress>,
-) {
-    loading_progress.progress = 0.0;
-    loading_progress.current_message = "Initializing...".to_string();
-    info!("Loading: {}", loading_progress.current_message);
-
+#[cfg(feature = "graphics")]
+pub fn setup_loading_screen(mut commands: Commands) {
     // Create loading screen overlay
     commands
         .spawn((
@@ -87,7 +83,7 @@ pub fn advance_loading_step(
     mut loading_progress: ResMut<resources::LoadingProgress>,
     mut timer: ResMut<resources::LoadingTimer>,
     mut next_state: ResMut<NextState<states::LoadingState>>,
-    time: Res<Time>,
+    time: Res<Time<Real>>,
 ) {
     timer.tick(time.delta());
 
@@ -102,13 +98,10 @@ pub fn advance_loading_step(
     }
 }
 
-#[allow(clippy::too_many_arguments)]
 pub fn spawn_bodies_async(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut rng: ResMut<resources::SharedRng>,
-    body_count: Res<resources::BodyCount>,
+    mut body_count: ResMut<resources::BodyCount>,
     config: Res<config::SimulationConfig>,
     mut loading_progress: ResMut<resources::LoadingProgress>,
     mut next_state: ResMut<NextState<states::LoadingState>>,
@@ -129,16 +122,25 @@ pub fn spawn_bodies_async(
         let bodies_to_spawn =
             (progress.batch_size).min(progress.total_bodies - progress.bodies_spawned);
 
-        for _ in 0..bodies_to_spawn {
-            use crate::components::body::factory;
-
-            let bundle = factory::create_random_body(
-                &mut meshes,
-                &mut materials,
+        if config.physics.scenario != config::Scenario::RandomSphere
+            || config.physics.initial_conditions_path.is_some()
+        {
+            // Other scenarios place their bodies relative to each other, and imported bodies
+            // come from one file, so they are spawned together
+            progress.total_bodies = systems::physics::spawn_simulation_bodies(
+                &mut commands,
                 &mut rng,
-                &config,
                 progress.total_bodies,
+                &config,
             );
+            progress.bodies_spawned = progress.total_bodies;
+            **body_count = progress.total_bodies;
+        }
+
+        for _ in 0..bodies_to_spawn.min(progress.total_bodies - progress.bodies_spawned) {
+            use crate::components::body::factory;
+
+            let bundle = factory::create_random_body(&mut rng, &config, progress.total_bodies);
             commands.spawn(bundle);
             progress.bodies_spawned += 1;
         }
@@ -163,7 +165,7 @@ pub fn spawn_bodies_async(
 pub fn finalize_loading(
     mut loading_progress: ResMut<resources::LoadingProgress>,
     mut next_state: ResMut<NextState<states::LoadingState>>,
-    time: Res<Time>,
+    time: Res<Time<Real>>,
     mut finalize_timer: Local<Option<Timer>>,
 ) {
     if finalize_timer.is_none() {
@@ -190,14 +192,14 @@ pub fn finalize_loading(
     }
 }
 
-pub fn setup_ui_after_loading(
-    commands: Commands,
-    asset_server: Res<AssetServer>,
+/// Starts the simulation once loading is done.
+///
+/// The UI, when there is one, is set up on entering [`states::LoadingState::SettingUpUI`], so
+/// it is in place by the time this runs.
+pub fn finish_loading(
     mut loading_progress: ResMut<resources::LoadingProgress>,
     mut next_app_state: ResMut<NextState<states::AppState>>,
 ) {
-    systems::ui::setup_ui(commands, asset_server);
-
     loading_progress.progress = 1.0;
     loading_progress.current_message = "Loading complete!".to_string();
     info!("Loading: {}", loading_progress.current_message);
@@ -205,6 +207,7 @@ pub fn setup_ui_after_loading(
     next_app_state.set(states::AppState::Running);
 }
 
+#[cfg(feature = "graphics")]
 pub fn update_loading_progress(
     loading_progress: Res<resources::LoadingProgress>,
     mut progress_bar_query: Query<&mut Node, With<LoadingProgressBar>>,
@@ -214,10 +217,11 @@ pub fn update_loading_progress(
     }
 }
 
+#[cfg(feature = "graphics")]
 pub fn complete_loading(
     mut commands: Commands,
     loading_screen_query: Query<Entity, With<LoadingScreen>>,
-    time: Res<Time>,
+    time: Res<Time<Real>>,
     mut completion_timer: Local<Option<Timer>>,
 ) {
     if completion_timer.is_none() {
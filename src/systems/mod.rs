@@ -1,8 +1,17 @@
+#[cfg(feature = "graphics")]
 pub mod camera;
+pub mod collisions;
+#[cfg(feature = "graphics")]
 pub mod input;
 pub mod loading;
 pub mod physics;
+pub mod recording;
+#[cfg(feature = "graphics")]
+pub mod replay;
 pub mod simulation_actions;
+pub mod stars;
+#[cfg(feature = "graphics")]
 #[allow(clippy::type_complexity)]
 pub mod ui;
+#[cfg(feature = "graphics")]
 pub mod visualization;
@@ -1,85 +1,471 @@
+use crate::components;
 use crate::config;
+use crate::initial_conditions;
 use crate::physics;
 use crate::resources;
+use crate::states;
+use crate::systems;
 use avian3d::math::Scalar;
 use avian3d::math::Vector;
 use avian3d::prelude::*;
 use bevy::prelude::*;
 
+impl From<Entity> for physics::BodyId {
+    fn from(entity: Entity) -> Self {
+        Self(entity.to_bits())
+    }
+}
+
+impl From<physics::BodyId> for Entity {
+    fn from(id: physics::BodyId) -> Self {
+        Entity::from_bits(id.0)
+    }
+}
+
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PhysicsSet {
+    ResolveCollisions,
     BuildOctree,
-    ApplyForces,
+    Integrate,
 }
 
+/// Spawns the bodies imported from `initial_conditions_path`, or `body_count` bodies of the
+/// configured scenario, and returns how many were spawned.
 pub fn spawn_simulation_bodies(
     commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
     rng: &mut ResMut<resources::SharedRng>,
     body_count: usize,
     config: &config::SimulationConfig,
-) {
+) -> usize {
     use crate::components::body::factory;
 
-    let spawn_data: Vec<crate::components::BodyBundle> = (0..body_count)
-        .map(|_| factory::create_random_body(meshes, materials, rng, config, body_count))
-        .collect();
+    if let Some(path) = &config.physics.initial_conditions_path {
+        match initial_conditions::read_bodies(path) {
+            Ok(bodies) => {
+                info!("Imported {} bodies from {}", bodies.len(), path.display());
+                commands.spawn_batch(factory::create_imported_bodies(&bodies, config));
+                return bodies.len();
+            }
+            Err(e) => error!(
+                "Failed to import initial conditions: {e}; using the {:?} scenario instead",
+                config.physics.scenario
+            ),
+        }
+    }
 
-    commands.spawn_batch(spawn_data);
+    match config.physics.scenario {
+        config::Scenario::RandomSphere => {
+            let spawn_data: Vec<crate::components::BodyBundle> = (0..body_count)
+                .map(|_| factory::create_random_body(rng, config, body_count))
+                .collect();
+
+            commands.spawn_batch(spawn_data);
+        }
+        config::Scenario::PlanetarySystem => {
+            commands.spawn_batch(factory::create_planetary_system(rng, config, body_count));
+        }
+        config::Scenario::StellarPopulation => {
+            commands.spawn_batch(factory::create_stellar_population(rng, config, body_count));
+        }
+    }
+    body_count
 }
 
 #[allow(clippy::type_complexity)]
 pub fn rebuild_octree(
-    bodies: Query<(Entity, &Transform, &ComputedMass), (With<RigidBody>, Changed<Transform>)>,
-    mut octree: ResMut<resources::GravitationalOctree>,
+    bodies: Query<
+        (Entity, &Position, &LinearVelocity, &ComputedMass),
+        (With<RigidBody>, Changed<Position>),
+    >,
+    mut simulation: ResMut<resources::GravitySimulation>,
 ) {
+    let octree = simulation.octree_mut();
     if bodies.is_empty() {
         return;
     }
 
-    octree.build(
-        bodies
-            .iter()
-            .map(|(entity, transform, mass)| physics::octree::OctreeBody {
-                entity,
-                position: Vector::from(transform.translation),
-                mass: mass.value(),
-            }),
+    octree.build(bodies.iter().map(|(entity, position, velocity, mass)| {
+        physics::octree::OctreeBody {
+            id: entity.into(),
+            position: position.0,
+            velocity: velocity.0,
+            mass: mass.value(),
+        }
+    }));
+}
+
+/// Advances every body by one fixed step with the configured symplectic integrator.
+///
+/// Avian's own integrator is disabled, so this is the only system that moves bodies. Avian
+/// still detects and resolves collisions, which it does by changing `LinearVelocity`. The bodies
+/// are copied out of their components into the [`resources::GravitySimulation`], advanced the
+/// same way a [`physics::simulation::Simulation`] of its own would be, and copied back.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn integrate_bodies(
+    mut bodies: Query<
+        (
+            Entity,
+            &mut Position,
+            &mut LinearVelocity,
+            &mut components::Acceleration,
+            &ComputedMass,
+        ),
+        With<RigidBody>,
+    >,
+    mut simulation: ResMut<resources::GravitySimulation>,
+    mut timestep_levels: ResMut<resources::TimestepLevelHistogram>,
+    mut simulated_time: ResMut<resources::SimulatedTime>,
+    config: Res<config::SimulationConfig>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let dt = fixed_time.delta_secs_f64();
+
+    if bodies.is_empty() || dt <= 0.0 {
+        return;
+    }
+
+    if config.is_changed() && *simulation.config() != config.physics {
+        simulation.set_config(config.physics.clone());
+    }
+    simulation.sync_bodies(bodies.iter().map(
+        |(entity, position, velocity, acceleration, mass)| physics::integrator::IntegratedBody {
+            id: entity.into(),
+            position: position.0,
+            velocity: velocity.0,
+            acceleration: acceleration.0,
+            jerk: Vector::ZERO,
+            mass: mass.value(),
+        },
+    ));
+    simulation.time = **simulated_time;
+
+    if let Some(levels) = simulation.step(dt) {
+        **timestep_levels = levels;
+    }
+    **simulated_time = simulation.time;
+
+    simulation.bodies().iter().for_each(|body| {
+        if let Ok((_, mut position, mut velocity, mut acceleration, _)) =
+            bodies.get_mut(body.id.into())
+        {
+            position.0 = body.position;
+            velocity.0 = body.velocity;
+            acceleration.0 = body.acceleration;
+        }
+    });
+}
+
+/// Measures the conserved quantities and cluster structure every `conservation_check_interval`
+/// fixed steps.
+///
+/// The first measurement of a run becomes the reference that drift is measured from. Drift
+/// beyond `conservation_drift_threshold` is acted on once per run, as configured. Lagrangian
+/// radii are measured from the barycenter, sorting the bodies collected for the octree.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn measure_global_quantities(
+    bodies: Query<(Entity, &Position, &LinearVelocity, &ComputedMass), With<RigidBody>>,
+    mut simulation: ResMut<resources::GravitySimulation>,
+    mut monitor: ResMut<resources::ConservationMonitor>,
+    mut cluster_structure: ResMut<resources::ClusterStructure>,
+    barycenter: Res<resources::Barycenter>,
+    mut octree_bodies: Local<Vec<physics::octree::OctreeBody>>,
+    mut pause_events: EventWriter<systems::simulation_actions::TogglePauseSimulationEvent>,
+    app_state: Res<State<states::AppState>>,
+    g: Res<resources::GravitationalConstant>,
+    config: Res<config::SimulationConfig>,
+) {
+    let octree = simulation.octree_mut();
+    monitor.steps_since_check += 1;
+    if monitor.initial.is_some()
+        && monitor.steps_since_check < config.physics.conservation_check_interval
+    {
+        return;
+    }
+
+    if bodies.is_empty() {
+        return;
+    }
+    monitor.steps_since_check = 0;
+
+    octree_bodies.clear();
+    octree_bodies.extend(bodies.iter().map(|(entity, position, velocity, mass)| {
+        physics::octree::OctreeBody {
+            id: entity.into(),
+            position: position.0,
+            velocity: velocity.0,
+            mass: mass.value(),
+        }
+    }));
+    octree.build(octree_bodies.iter().copied());
+
+    let quantities =
+        physics::conservation::ConservedQuantities::measure(&octree_bodies, octree, **g);
+    let initial = *monitor.initial.get_or_insert(quantities);
+    monitor.current = Some(quantities);
+    monitor.drift = quantities.drift_from(&initial);
+
+    if let Some(center) = barycenter.or_else(|| physics::cluster::center_of_mass(&octree_bodies)) {
+        **cluster_structure = Some(physics::cluster::ClusterStructure {
+            virial_ratio: quantities.virial_ratio(),
+            lagrangian_radii: physics::cluster::lagrangian_radii(&octree_bodies, center),
+        });
+    }
+
+    let threshold = config.physics.conservation_drift_threshold;
+    if monitor.threshold_exceeded || monitor.drift.max() <= threshold {
+        return;
+    }
+    monitor.threshold_exceeded = true;
+
+    let action = config.physics.conservation_drift_action;
+    if action == config::ConservationDriftAction::Ignore {
+        return;
+    }
+    warn!(
+        "Conservation drift exceeds {threshold:e}: energy {:e}, momentum {:e}, angular momentum {:e}",
+        monitor.drift.energy, monitor.drift.linear_momentum, monitor.drift.angular_momentum
     );
+    if action == config::ConservationDriftAction::Pause
+        && *app_state.get() == states::AppState::Running
+    {
+        pause_events.write(systems::simulation_actions::TogglePauseSimulationEvent);
+    }
 }
 
+/// Finds friends-of-friends groups every `group_finding_interval` fixed steps, starting with the
+/// first step that has bodies.
+///
+/// Every body gets a [`components::GroupId`], including bodies alone in their group, and the
+/// group catalog is replaced. Groups with fewer than `group_min_members` members are left out
+/// of the log.
 #[allow(clippy::type_complexity)]
-pub fn apply_gravitation_octree(
-    g: Res<resources::GravitationalConstant>,
-    octree: Res<resources::GravitationalOctree>,
-    mut bodies: Query<
-        (Entity, &Transform, &ComputedMass, &mut ExternalForce),
-        (With<RigidBody>, Changed<Transform>),
+pub fn find_groups(
+    mut commands: Commands,
+    bodies: Query<
+        (
+            Entity,
+            &Position,
+            &LinearVelocity,
+            &ComputedMass,
+            Option<&components::GroupId>,
+        ),
+        With<RigidBody>,
     >,
+    mut simulation: ResMut<resources::GravitySimulation>,
+    mut catalog: ResMut<resources::GroupCatalog>,
+    mut counters: ResMut<resources::PeriodicCheckCounters>,
+    mut octree_bodies: Local<Vec<physics::octree::OctreeBody>>,
+    config: Res<config::SimulationConfig>,
+) {
+    let octree = simulation.octree_mut();
+    if bodies.is_empty() {
+        return;
+    }
+    let steps = counters.group_finding.map_or(u32::MAX, |steps| steps + 1);
+    if steps < config.physics.group_finding_interval {
+        counters.group_finding = Some(steps);
+        return;
+    }
+    counters.group_finding = Some(0);
+
+    octree_bodies.clear();
+    octree_bodies.extend(bodies.iter().map(|(entity, position, velocity, mass, _)| {
+        physics::octree::OctreeBody {
+            id: entity.into(),
+            position: position.0,
+            velocity: velocity.0,
+            mass: mass.value(),
+        }
+    }));
+    octree.build(octree_bodies.iter().copied());
+
+    let groups = physics::groups::friends_of_friends(
+        &octree_bodies,
+        octree,
+        config.physics.group_linking_length,
+    );
+
+    bodies
+        .iter()
+        .zip(&groups.assignments)
+        .for_each(|((entity, _, _, _, group_id), &group)| {
+            if group_id.is_none_or(|group_id| **group_id != group) {
+                commands.entity(entity).insert(components::GroupId(group));
+            }
+        });
+
+    let min_members = config.physics.group_min_members;
+    let significant_groups = groups.with_min_members(min_members).count();
+    let previous_significant_groups = catalog
+        .iter()
+        .filter(|group| group.member_count >= min_members)
+        .count();
+    if significant_groups != previous_significant_groups {
+        info!("Found {significant_groups} groups of at least {min_members} bodies");
+        groups
+            .with_min_members(min_members)
+            .enumerate()
+            .for_each(|(index, group)| {
+                info!(
+                    "Group {index}: {} bodies, mass {:.3e}, centre {:.1}",
+                    group.member_count, group.mass, group.center_of_mass
+                );
+            });
+    }
+
+    **catalog = groups.groups;
+}
+
+/// Classifies bodies as bound or unbound every `bound_classification_interval` fixed steps, and
+/// optionally removes escapers.
+///
+/// Unbound bodies get the [`components::Unbound`] marker. With `escaper_removal` enabled, unbound
+/// bodies further than `escaper_removal_radius` from the barycenter are despawned, since they
+/// would otherwise stretch the octree's root bounds. What they carried away is taken out of the
+/// conservation monitor's reference, and the barycenter is re-measured from the bodies left.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn classify_bound_bodies(
+    mut commands: Commands,
+    bodies: Query<
+        (
+            Entity,
+            &Position,
+            &LinearVelocity,
+            &ComputedMass,
+            Has<components::Unbound>,
+        ),
+        With<RigidBody>,
+    >,
+    mut simulation: ResMut<resources::GravitySimulation>,
+    mut monitor: ResMut<resources::ConservationMonitor>,
+    mut statistics: ResMut<resources::EscaperStatistics>,
+    mut barycenter: ResMut<resources::Barycenter>,
+    mut counters: ResMut<resources::PeriodicCheckCounters>,
+    mut octree_bodies: Local<Vec<physics::octree::OctreeBody>>,
+    g: Res<resources::GravitationalConstant>,
+    config: Res<config::SimulationConfig>,
+) {
+    let octree = simulation.octree_mut();
+    if bodies.is_empty() {
+        return;
+    }
+    let steps = counters
+        .bound_classification
+        .map_or(u32::MAX, |steps| steps + 1);
+    if steps < config.physics.bound_classification_interval {
+        counters.bound_classification = Some(steps);
+        return;
+    }
+    counters.bound_classification = Some(0);
+
+    octree_bodies.clear();
+    octree_bodies.extend(bodies.iter().map(|(entity, position, velocity, mass, _)| {
+        physics::octree::OctreeBody {
+            id: entity.into(),
+            position: position.0,
+            velocity: velocity.0,
+            mass: mass.value(),
+        }
+    }));
+    octree.build(octree_bodies.iter().copied());
+
+    let energies = physics::cluster::binding_energies(&octree_bodies, octree, **g);
+    let Some(center) = barycenter.or_else(|| physics::cluster::center_of_mass(&octree_bodies))
+    else {
+        return;
+    };
+
+    statistics.unbound_count = 0;
+    statistics.unbound_mass = 0.0;
+    let mut escapers = Vec::new();
+    for ((body, &energy), (.., was_unbound)) in octree_bodies.iter().zip(&energies).zip(&bodies) {
+        let unbound = energy > 0.0;
+        if unbound {
+            statistics.unbound_count += 1;
+            statistics.unbound_mass += body.mass;
+        }
+
+        if unbound
+            && config.physics.escaper_removal
+            && body.position.distance(center) > config.physics.escaper_removal_radius
+        {
+            escapers.push(*body);
+            commands.entity(body.id.into()).despawn();
+        } else if unbound && !was_unbound {
+            commands.entity(body.id.into()).insert(components::Unbound);
+        } else if !unbound && was_unbound {
+            commands
+                .entity(body.id.into())
+                .remove::<components::Unbound>();
+        }
+    }
+
+    if escapers.is_empty() {
+        return;
+    }
+
+    // Each escaper takes its full potential energy with it, except for pairs of escapers,
+    // whose potential energy would otherwise be removed twice
+    let escaper_pairs_potential: Scalar = escapers
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| escapers[i + 1..].iter().map(move |b| (a, b)))
+        .map(|(a, b)| {
+            -**g * a.mass * b.mass / a.position.distance(b.position).max(octree.min_distance)
+        })
+        .sum();
+    let removed = escapers
+        .iter()
+        .map(|escaper| {
+            let potential_energy = octree.calculate_potential(escaper, octree.root.as_ref(), **g);
+            physics::conservation::ConservedQuantities::of_body(escaper, potential_energy)
+        })
+        .fold(
+            physics::conservation::ConservedQuantities {
+                potential_energy: -escaper_pairs_potential,
+                ..default()
+            },
+            physics::conservation::ConservedQuantities::combine,
+        );
+
+    statistics.removed_count += escapers.len();
+    statistics.removed_mass += removed.total_mass;
+    statistics.removed_energy += removed.total_energy();
+    info!(
+        "Removed {} escapers beyond {}: mass {:.3e}, energy {:.3e}",
+        escapers.len(),
+        config.physics.escaper_removal_radius,
+        removed.total_mass,
+        removed.total_energy()
+    );
+
+    if let Some(initial) = monitor.initial {
+        monitor.initial = Some(initial.without(&removed));
+    }
+    **barycenter = None;
+}
+
+/// Gives bodies their exact [`components::RestoredMass`], once Avian has computed their mass from
+/// their collider and density.
+pub fn apply_restored_masses(
+    mut commands: Commands,
+    mut bodies: Query<(Entity, &mut ComputedMass, &components::RestoredMass)>,
 ) {
     bodies
-        .par_iter_mut()
-        .for_each(|(entity, transform, mass, mut external_force)| {
-            external_force.set_force(octree.calculate_force(
-                &physics::octree::OctreeBody {
-                    entity,
-                    position: Vector::from(transform.translation),
-                    mass: mass.value(),
-                },
-                octree.root.as_ref(),
-                **g,
-            ));
+        .iter_mut()
+        .for_each(|(entity, mut mass, restored_mass)| {
+            *mass = ComputedMass::new(**restored_mass);
+            commands.entity(entity).remove::<components::RestoredMass>();
         });
 }
 
 pub fn counteract_barycentric_drift(
-    mut bodies: Query<(&mut Transform, &ComputedMass), With<RigidBody>>,
+    mut bodies: Query<(&mut Position, &ComputedMass), With<RigidBody>>,
     mut barycenter: ResMut<resources::Barycenter>,
 ) {
     let (weighted_positions, total_mass): (Vector, Scalar) = bodies
         .iter()
-        .map(|(transform, mass)| (Vector::from(transform.translation), mass.value()))
+        .map(|(position, mass)| (position.0, mass.value()))
         .fold((Vector::ZERO, 0.0), |(pos_acc, mass_acc), (pos, mass)| {
             (pos_acc + pos * mass, mass_acc + mass)
         });
@@ -105,8 +491,8 @@ pub fn counteract_barycentric_drift(
         return;
     }
 
-    bodies.iter_mut().for_each(|(mut transform, _)| {
-        transform.translation += -barycentric_drift.as_vec3();
+    bodies.iter_mut().for_each(|(mut position, _)| {
+        position.0 -= barycentric_drift;
     });
 }
 
@@ -115,6 +501,11 @@ mod tests {
     use super::*;
     use bevy::ecs::system::SystemState;
 
+    type DriftSystemState = SystemState<(
+        Query<'static, 'static, (&'static mut Position, &'static ComputedMass), With<RigidBody>>,
+        ResMut<'static, resources::Barycenter>,
+    )>;
+
     fn create_test_world() -> World {
         let mut world = World::new();
         world.insert_resource(resources::Barycenter::default());
@@ -124,11 +515,11 @@ mod tests {
     fn create_test_body_with_mass_and_position(
         commands: &mut Commands,
         mass: f64,
-        position: Vec3,
+        position: Vector,
     ) -> Entity {
         commands
             .spawn((
-                Transform::from_translation(position),
+                Position(position),
                 ComputedMass::new(mass),
                 RigidBody::Dynamic,
             ))
@@ -138,15 +529,12 @@ mod tests {
     #[test]
     fn test_counteract_barycentric_drift_initial_barycenter_setting() {
         let mut world = create_test_world();
-        let mut system_state: SystemState<(
-            Query<(&mut Transform, &ComputedMass), With<RigidBody>>,
-            ResMut<resources::Barycenter>,
-        )> = SystemState::new(&mut world);
+        let mut system_state: DriftSystemState = SystemState::new(&mut world);
 
         // Create a single body at position (1, 2, 3) with mass 5.0
         {
             let mut commands = world.commands();
-            create_test_body_with_mass_and_position(&mut commands, 5.0, Vec3::new(1.0, 2.0, 3.0));
+            create_test_body_with_mass_and_position(&mut commands, 5.0, Vector::new(1.0, 2.0, 3.0));
         }
         world.flush();
 
@@ -169,17 +557,14 @@ mod tests {
     #[test]
     fn test_counteract_barycentric_drift_multiple_bodies() {
         let mut world = create_test_world();
-        let mut system_state: SystemState<(
-            Query<(&mut Transform, &ComputedMass), With<RigidBody>>,
-            ResMut<resources::Barycenter>,
-        )> = SystemState::new(&mut world);
+        let mut system_state: DriftSystemState = SystemState::new(&mut world);
 
         // Create two bodies: mass 2.0 at (0,0,0) and mass 4.0 at (3,0,0)
         // Expected barycenter: (2*0 + 4*3)/(2+4) = 12/6 = 2.0 on x-axis
         {
             let mut commands = world.commands();
-            create_test_body_with_mass_and_position(&mut commands, 2.0, Vec3::new(0.0, 0.0, 0.0));
-            create_test_body_with_mass_and_position(&mut commands, 4.0, Vec3::new(3.0, 0.0, 0.0));
+            create_test_body_with_mass_and_position(&mut commands, 2.0, Vector::new(0.0, 0.0, 0.0));
+            create_test_body_with_mass_and_position(&mut commands, 4.0, Vector::new(3.0, 0.0, 0.0));
         }
         world.flush();
 
@@ -202,15 +587,12 @@ mod tests {
     #[test]
     fn test_counteract_barycentric_drift_correction() {
         let mut world = create_test_world();
-        let mut system_state: SystemState<(
-            Query<(&mut Transform, &ComputedMass), With<RigidBody>>,
-            ResMut<resources::Barycenter>,
-        )> = SystemState::new(&mut world);
+        let mut system_state: DriftSystemState = SystemState::new(&mut world);
 
         // Create a body and set initial barycenter
         {
             let mut commands = world.commands();
-            create_test_body_with_mass_and_position(&mut commands, 1.0, Vec3::new(0.0, 0.0, 0.0));
+            create_test_body_with_mass_and_position(&mut commands, 1.0, Vector::new(0.0, 0.0, 0.0));
         }
         world.flush();
 
@@ -219,16 +601,16 @@ mod tests {
 
         // Move the body to create drift
         {
-            let mut query = world.query::<&mut Transform>();
-            for mut transform in query.iter_mut(&mut world) {
-                transform.translation = Vec3::new(2.0, 0.0, 0.0);
+            let mut query = world.query::<&mut Position>();
+            for mut position in query.iter_mut(&mut world) {
+                position.0 = Vector::new(2.0, 0.0, 0.0);
             }
         }
 
         // Store original position for comparison
-        let _original_positions: Vec<Vec3> = {
-            let mut query = world.query::<&Transform>();
-            query.iter(&world).map(|t| t.translation).collect()
+        let _original_positions: Vec<Vector> = {
+            let mut query = world.query::<&Position>();
+            query.iter(&world).map(|p| p.0).collect()
         };
 
         // Run the system to correct drift
@@ -236,31 +618,28 @@ mod tests {
         counteract_barycentric_drift(bodies, barycenter);
 
         // Check that bodies were moved back to counteract drift
-        let corrected_positions: Vec<Vec3> = {
-            let mut query = world.query::<&Transform>();
-            query.iter(&world).map(|t| t.translation).collect()
+        let corrected_positions: Vec<Vector> = {
+            let mut query = world.query::<&Position>();
+            query.iter(&world).map(|p| p.0).collect()
         };
 
         // The body should have been moved back by the drift amount
         // Original barycenter was (0,0,0), new would be (2,0,0), so drift is (2,0,0)
         // Body should be moved by -drift = (-2,0,0), so final position should be (0,0,0)
-        assert!((corrected_positions[0].x - 0.0).abs() < f32::EPSILON);
-        assert!((corrected_positions[0].y - 0.0).abs() < f32::EPSILON);
-        assert!((corrected_positions[0].z - 0.0).abs() < f32::EPSILON);
+        assert!((corrected_positions[0].x - 0.0).abs() < Scalar::EPSILON);
+        assert!((corrected_positions[0].y - 0.0).abs() < Scalar::EPSILON);
+        assert!((corrected_positions[0].z - 0.0).abs() < Scalar::EPSILON);
     }
 
     #[test]
     fn test_counteract_barycentric_drift_zero_mass() {
         let mut world = create_test_world();
-        let mut system_state: SystemState<(
-            Query<(&mut Transform, &ComputedMass), With<RigidBody>>,
-            ResMut<resources::Barycenter>,
-        )> = SystemState::new(&mut world);
+        let mut system_state: DriftSystemState = SystemState::new(&mut world);
 
         // Create a body with zero mass
         {
             let mut commands = world.commands();
-            create_test_body_with_mass_and_position(&mut commands, 0.0, Vec3::new(1.0, 2.0, 3.0));
+            create_test_body_with_mass_and_position(&mut commands, 0.0, Vector::new(1.0, 2.0, 3.0));
         }
         world.flush();
 
@@ -278,10 +657,7 @@ mod tests {
     #[test]
     fn test_counteract_barycentric_drift_no_bodies() {
         let mut world = create_test_world();
-        let mut system_state: SystemState<(
-            Query<(&mut Transform, &ComputedMass), With<RigidBody>>,
-            ResMut<resources::Barycenter>,
-        )> = SystemState::new(&mut world);
+        let mut system_state: DriftSystemState = SystemState::new(&mut world);
 
         let original_barycenter = **world.resource::<resources::Barycenter>();
 
@@ -297,15 +673,12 @@ mod tests {
     #[test]
     fn test_counteract_barycentric_drift_small_drift() {
         let mut world = create_test_world();
-        let mut system_state: SystemState<(
-            Query<(&mut Transform, &ComputedMass), With<RigidBody>>,
-            ResMut<resources::Barycenter>,
-        )> = SystemState::new(&mut world);
+        let mut system_state: DriftSystemState = SystemState::new(&mut world);
 
         // Create a body
         {
             let mut commands = world.commands();
-            create_test_body_with_mass_and_position(&mut commands, 1.0, Vec3::new(0.0, 0.0, 0.0));
+            create_test_body_with_mass_and_position(&mut commands, 1.0, Vector::new(0.0, 0.0, 0.0));
         }
         world.flush();
 
@@ -315,15 +688,15 @@ mod tests {
         // Move body by a very small amount (less than epsilon threshold)
         let tiny_offset = Scalar::EPSILON.sqrt() * 0.5; // Much smaller than epsilon
         {
-            let mut query = world.query::<&mut Transform>();
-            for mut transform in query.iter_mut(&mut world) {
-                transform.translation = Vec3::new(tiny_offset as f32, 0.0, 0.0);
+            let mut query = world.query::<&mut Position>();
+            for mut position in query.iter_mut(&mut world) {
+                position.0 = Vector::new(tiny_offset, 0.0, 0.0);
             }
         }
 
-        let original_positions: Vec<Vec3> = {
-            let mut query = world.query::<&Transform>();
-            query.iter(&world).map(|t| t.translation).collect()
+        let original_positions: Vec<Vector> = {
+            let mut query = world.query::<&Position>();
+            query.iter(&world).map(|p| p.0).collect()
         };
 
         // Run the system
@@ -331,9 +704,9 @@ mod tests {
         counteract_barycentric_drift(bodies, barycenter);
 
         // Positions should remain unchanged due to small drift threshold
-        let final_positions: Vec<Vec3> = {
-            let mut query = world.query::<&Transform>();
-            query.iter(&world).map(|t| t.translation).collect()
+        let final_positions: Vec<Vector> = {
+            let mut query = world.query::<&Position>();
+            query.iter(&world).map(|p| p.0).collect()
         };
 
         assert_eq!(original_positions, final_positions);
@@ -342,10 +715,7 @@ mod tests {
     #[test]
     fn test_counteract_barycentric_drift_non_finite_barycenter() {
         let mut world = create_test_world();
-        let mut system_state: SystemState<(
-            Query<(&mut Transform, &ComputedMass), With<RigidBody>>,
-            ResMut<resources::Barycenter>,
-        )> = SystemState::new(&mut world);
+        let mut system_state: DriftSystemState = SystemState::new(&mut world);
 
         // Create a body at a position that would create non-finite barycenter when divided by zero
         // This is tricky to test directly, but we can test the is_finite check by creating
@@ -355,7 +725,7 @@ mod tests {
             create_test_body_with_mass_and_position(
                 &mut commands,
                 f64::INFINITY,
-                Vec3::new(1.0, 0.0, 0.0),
+                Vector::new(1.0, 0.0, 0.0),
             );
         }
         world.flush();
@@ -377,17 +747,22 @@ mod tests {
     #[test]
     fn test_counteract_barycentric_drift_complex_scenario() {
         let mut world = create_test_world();
-        let mut system_state: SystemState<(
-            Query<(&mut Transform, &ComputedMass), With<RigidBody>>,
-            ResMut<resources::Barycenter>,
-        )> = SystemState::new(&mut world);
+        let mut system_state: DriftSystemState = SystemState::new(&mut world);
 
         // Create multiple bodies with different masses and positions
         {
             let mut commands = world.commands();
-            create_test_body_with_mass_and_position(&mut commands, 1.0, Vec3::new(-2.0, 0.0, 0.0));
-            create_test_body_with_mass_and_position(&mut commands, 2.0, Vec3::new(1.0, 0.0, 0.0));
-            create_test_body_with_mass_and_position(&mut commands, 3.0, Vec3::new(2.0, 1.0, -1.0));
+            create_test_body_with_mass_and_position(
+                &mut commands,
+                1.0,
+                Vector::new(-2.0, 0.0, 0.0),
+            );
+            create_test_body_with_mass_and_position(&mut commands, 2.0, Vector::new(1.0, 0.0, 0.0));
+            create_test_body_with_mass_and_position(
+                &mut commands,
+                3.0,
+                Vector::new(2.0, 1.0, -1.0),
+            );
         }
         world.flush();
 
@@ -398,11 +773,11 @@ mod tests {
         let initial_barycenter = world.resource::<resources::Barycenter>().unwrap();
 
         // Move all bodies by the same offset to create uniform drift
-        let drift_offset = Vec3::new(0.5, -0.3, 0.2);
+        let drift_offset = Vector::new(0.5, -0.3, 0.2);
         {
-            let mut query = world.query::<&mut Transform>();
-            for mut transform in query.iter_mut(&mut world) {
-                transform.translation += drift_offset;
+            let mut query = world.query::<&mut Position>();
+            for mut position in query.iter_mut(&mut world) {
+                position.0 += drift_offset;
             }
         }
 
@@ -0,0 +1,138 @@
+use crate::config;
+use crate::resources;
+use crate::trajectory;
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use std::path::PathBuf;
+
+pub fn trajectory_recording_enabled(config: Res<config::SimulationConfig>) -> bool {
+    config.recording.trajectory_recording
+}
+
+/// File new recordings are written to, unless it already exists.
+pub fn trajectory_path(
+    config: &config::SimulationConfig,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    match &config.recording.trajectory_path {
+        Some(path) => Ok(path.clone()),
+        None => Ok(config::SimulationConfig::get_user_file_path(
+            trajectory::TRAJECTORY_FILE_NAME,
+        )?),
+    }
+}
+
+/// Writes every body's position, velocity, mass and radius to the trajectory file every
+/// `trajectory_interval` fixed steps.
+///
+/// The file is created on the first step, with the bodies present then in its header, and
+/// flushed every [`trajectory::KEYFRAME_INTERVAL`] frames. Existing files are never overwritten,
+/// so every recording after the first goes to a new, numbered file next to `trajectory_path`.
+/// Bodies are identified by their [`crate::physics::BodyId`]. If the file cannot be created or
+/// written, a warning is logged and recording stops for the rest of the run.
+#[allow(clippy::type_complexity)]
+pub fn record_trajectory(
+    bodies: Query<(Entity, &Position, &LinearVelocity, &ComputedMass, &Collider), With<RigidBody>>,
+    mut recording: ResMut<resources::TrajectoryRecording>,
+    simulated_time: Res<resources::SimulatedTime>,
+    config: Res<config::SimulationConfig>,
+) {
+    let step = recording.steps;
+    recording.steps += 1;
+    if recording.failed
+        || !step.is_multiple_of(u64::from(config.recording.trajectory_interval.max(1)))
+    {
+        return;
+    }
+
+    if recording.writer.is_none() {
+        let header = trajectory::TrajectoryHeader {
+            config: config.clone(),
+            seed: config.physics.initial_seed,
+            interval: config.recording.trajectory_interval.max(1),
+            body_ids: bodies.iter().map(|(entity, ..)| entity.into()).collect(),
+        };
+        let writer = trajectory_path(&config).and_then(|path| {
+            trajectory::TrajectoryWriter::create(
+                &path,
+                &header,
+                config.recording.trajectory_compression,
+            )
+            .map_err(Into::into)
+        });
+        match writer {
+            Ok((writer, path)) => {
+                info!("Recording trajectory to {}", path.display());
+                recording.writer = Some(writer);
+                recording.path = Some(path);
+            }
+            Err(e) => {
+                warn!("Failed to create trajectory file: {e}");
+                recording.failed = true;
+                return;
+            }
+        }
+    }
+
+    let frame = trajectory::TrajectoryFrame {
+        step,
+        simulated_time: **simulated_time,
+        bodies: bodies
+            .iter()
+            .map(
+                |(entity, position, velocity, mass, collider)| trajectory::BodyState {
+                    id: entity.into(),
+                    position: position.0,
+                    velocity: velocity.0,
+                    mass: mass.value(),
+                    radius: collider.shape().as_ball().map_or(0.0, |ball| ball.radius),
+                },
+            )
+            .collect(),
+    };
+
+    let Some(writer) = recording.writer.as_mut() else {
+        return;
+    };
+    let written = writer.write_frame(&frame).and_then(|()| {
+        if writer
+            .frames_written()
+            .is_multiple_of(trajectory::KEYFRAME_INTERVAL)
+        {
+            writer.flush()
+        } else {
+            Ok(())
+        }
+    });
+    if let Err(e) = written {
+        warn!("Failed to write trajectory frame: {e}");
+        recording.writer = None;
+        recording.failed = true;
+    }
+}
+
+/// The trajectory to replay: `replay_path` if set, otherwise the latest recording, otherwise the
+/// file a recording would have been written to.
+pub fn replay_path(
+    config: &config::SimulationConfig,
+    recording: &resources::TrajectoryRecording,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    match (&config.recording.replay_path, &recording.path) {
+        (Some(path), _) | (None, Some(path)) => Ok(path.clone()),
+        (None, None) => trajectory_path(config),
+    }
+}
+
+/// Flushes the trajectory file when the app exits, which otherwise only happens every
+/// [`trajectory::KEYFRAME_INTERVAL`] frames.
+pub fn finish_trajectory_recording(
+    mut exit_events: EventReader<AppExit>,
+    mut recording: ResMut<resources::TrajectoryRecording>,
+) {
+    if exit_events.read().count() == 0 {
+        return;
+    }
+
+    if let Err(e) = recording.finish() {
+        warn!("Failed to finish trajectory recording: {e}");
+    }
+}
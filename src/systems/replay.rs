@@ -0,0 +1,198 @@
+use crate::components;
+use crate::physics;
+use crate::resources;
+use crate::states;
+use crate::systems;
+use crate::utils;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+/// Moves the playhead on by the real time since the last frame.
+///
+/// At a speed of one the replay runs as fast as the simulation would at a time scale of one: a
+/// fixed timestep of real time for every recorded fixed step.
+pub fn advance_replay(
+    mut replay: ResMut<resources::Replay>,
+    time: Res<Time<Real>>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    if !replay.playing {
+        return;
+    }
+
+    let steps = time.delta_secs_f64() / fixed_time.timestep().as_secs_f64();
+    let frames = steps / replay.header.interval.max(1) as Scalar * replay.speed;
+    replay.advance(frames);
+}
+
+/// Drives the replay with the controls that otherwise drive the simulation.
+///
+/// Pausing pauses playback, and resuming at the end of the recording starts it over. The speed
+/// controls step through the same time scales as the simulation, reversing time plays backwards,
+/// and stepping moves the paused playhead by that many frames.
+pub fn handle_replay_playback_events(
+    mut pause_events: EventReader<systems::simulation_actions::TogglePauseSimulationEvent>,
+    mut speed_up_events: EventReader<systems::simulation_actions::SpeedUpSimulationEvent>,
+    mut slow_down_events: EventReader<systems::simulation_actions::SlowDownSimulationEvent>,
+    mut reverse_events: EventReader<systems::simulation_actions::ReverseTimeEvent>,
+    mut step_events: EventReader<systems::simulation_actions::StepSimulationEvent>,
+    mut replay: ResMut<resources::Replay>,
+) {
+    pause_events.read().for_each(|_| {
+        if !replay.playing {
+            let at_end = if replay.reversed {
+                replay.progress() <= 0.0
+            } else {
+                replay.progress() >= 1.0
+            };
+            if at_end {
+                let start = if replay.reversed { 1.0 } else { 0.0 };
+                replay.seek(start);
+            }
+        }
+        replay.playing = !replay.playing;
+    });
+    speed_up_events.read().for_each(|_| {
+        replay.speed = systems::simulation_actions::next_time_scale(replay.speed, true);
+    });
+    slow_down_events.read().for_each(|_| {
+        replay.speed = systems::simulation_actions::next_time_scale(replay.speed, false);
+    });
+    reverse_events.read().for_each(|_| {
+        replay.reversed = !replay.reversed;
+    });
+    step_events
+        .read()
+        .for_each(|&systems::simulation_actions::StepSimulationEvent(count)| {
+            replay.playing = false;
+            replay.advance(count as Scalar);
+        });
+}
+
+/// Shows the bodies as they were at the playhead.
+///
+/// Bodies are spawned when they first appear in the recording and despawned when they leave it,
+/// and are sized by their recorded radius and coloured by the temperature a body of that radius
+/// is given when spawned. The octree, barycenter and simulated time are set from the same frame,
+/// so the visualizations and HUD show the replay as they would the simulation.
+#[allow(clippy::too_many_arguments)]
+pub fn show_replay_frame(
+    mut commands: Commands,
+    mut replay: ResMut<resources::Replay>,
+    mut transforms: Query<&mut Transform, With<components::ReplayBody>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut unit_sphere: Local<Option<Handle<Mesh>>>,
+    mut simulated_time: ResMut<resources::SimulatedTime>,
+    mut barycenter: ResMut<resources::Barycenter>,
+    mut simulation: ResMut<resources::GravitySimulation>,
+) {
+    let octree = simulation.octree_mut();
+    if !replay.is_changed() {
+        return;
+    }
+    let Some(frame) = replay.frame_at_playhead() else {
+        return;
+    };
+
+    let unit_sphere = unit_sphere
+        .get_or_insert_with(|| components::body::factory::create_detailed_mesh(&mut meshes, 1.0))
+        .clone();
+    let config = replay.header.config.clone();
+    let replay = &mut *replay;
+
+    let mut octree_bodies = Vec::with_capacity(frame.bodies.len());
+    frame.bodies.iter().for_each(|body| {
+        let transform = Transform::from_translation(body.position.as_vec3())
+            .with_scale(Vec3::splat(body.radius as f32));
+        let entity = match replay.bodies.get(&body.id) {
+            Some(&entity) => {
+                if let Ok(mut current) = transforms.get_mut(entity) {
+                    *current = transform;
+                }
+                entity
+            }
+            None => {
+                let temperature =
+                    components::body::factory::calculate_temperature(body.radius, &config);
+                let material = utils::color::emissive_material_for_temp(
+                    &mut materials,
+                    temperature,
+                    config.rendering.bloom_intensity,
+                    config.rendering.saturation_intensity,
+                );
+                let entity = commands
+                    .spawn((
+                        components::ReplayBody(body.id),
+                        transform,
+                        Mesh3d(unit_sphere.clone()),
+                        MeshMaterial3d(material),
+                    ))
+                    .id();
+                replay.bodies.insert(body.id, entity);
+                entity
+            }
+        };
+        octree_bodies.push(physics::octree::OctreeBody {
+            id: entity.into(),
+            position: body.position,
+            velocity: body.velocity,
+            mass: body.mass,
+        });
+    });
+
+    let present: HashSet<physics::BodyId> = frame.bodies.iter().map(|body| body.id).collect();
+    replay.bodies.retain(|id, entity| {
+        let keep = present.contains(id);
+        if !keep {
+            commands.entity(*entity).despawn();
+        }
+        keep
+    });
+
+    let total_mass: Scalar = frame.bodies.iter().map(|body| body.mass).sum();
+    **barycenter = (total_mass > 0.0).then(|| {
+        frame
+            .bodies
+            .iter()
+            .map(|body| body.position * body.mass)
+            .sum::<Vector>()
+            / total_mass
+    });
+    **simulated_time = frame.simulated_time;
+    octree.build(octree_bodies);
+}
+
+/// Despawns the replayed bodies and drops the replay once it is left.
+pub fn despawn_replay(
+    mut commands: Commands,
+    replay_bodies: Query<Entity, With<components::ReplayBody>>,
+    mut simulation: ResMut<resources::GravitySimulation>,
+    mut barycenter: ResMut<resources::Barycenter>,
+) {
+    let octree = simulation.octree_mut();
+    replay_bodies.iter().for_each(|entity| {
+        commands.entity(entity).despawn();
+    });
+    commands.remove_resource::<resources::Replay>();
+    octree.build(vec![]);
+    **barycenter = None;
+}
+
+/// Leaves the replay when a new simulation is asked for.
+///
+/// The restart event is left for the simulation's own restart handler, which runs once the state
+/// is back to running.
+pub fn leave_replay_on_restart(
+    mut restart_events: EventReader<systems::simulation_actions::RestartSimulationEvent>,
+    mut next_state: ResMut<NextState<states::AppState>>,
+    mut time: ResMut<Time<Physics>>,
+) {
+    if restart_events.read().count() > 0 {
+        time.unpause();
+        next_state.set(states::AppState::Running);
+    }
+}
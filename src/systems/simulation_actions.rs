@@ -1,14 +1,57 @@
+use crate::components;
 use crate::config;
+use crate::export;
+use crate::physics;
 use crate::resources;
+use crate::snapshot;
 use crate::states;
 use crate::systems;
+use crate::trajectory;
+use avian3d::math::Quaternion;
+use avian3d::math::Vector;
 use avian3d::prelude::*;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use bevy_panorbit_camera::PanOrbitCamera;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Event)]
 pub struct RestartSimulationEvent;
 
+/// Everything measured over the course of a run, which starts over on restart.
+#[derive(SystemParam)]
+pub struct RunMeasurements<'w> {
+    simulated_time: ResMut<'w, resources::SimulatedTime>,
+    time_reversal_probe: ResMut<'w, resources::TimeReversalProbe>,
+    time_reversed: ResMut<'w, resources::TimeReversed>,
+    conservation_monitor: ResMut<'w, resources::ConservationMonitor>,
+    cluster_structure: ResMut<'w, resources::ClusterStructure>,
+    group_catalog: ResMut<'w, resources::GroupCatalog>,
+    escaper_statistics: ResMut<'w, resources::EscaperStatistics>,
+    collision_statistics: ResMut<'w, resources::CollisionStatistics>,
+    trajectory_recording: ResMut<'w, resources::TrajectoryRecording>,
+    periodic_check_counters: ResMut<'w, resources::PeriodicCheckCounters>,
+}
+
+impl RunMeasurements<'_> {
+    pub fn reset(&mut self) {
+        **self.simulated_time = 0.0;
+        *self.time_reversal_probe = resources::TimeReversalProbe::default();
+        **self.time_reversed = false;
+        *self.conservation_monitor = resources::ConservationMonitor::default();
+        **self.cluster_structure = None;
+        self.group_catalog.clear();
+        *self.escaper_statistics = resources::EscaperStatistics::default();
+        *self.collision_statistics = resources::CollisionStatistics::default();
+        *self.periodic_check_counters = resources::PeriodicCheckCounters::default();
+        if let Err(e) = self.trajectory_recording.finish() {
+            warn!("Failed to finish trajectory recording: {e}");
+        }
+    }
+}
+
 #[derive(Event)]
 pub struct ToggleOctreeVisualizationEvent;
 
@@ -18,18 +61,106 @@ pub struct ToggleBarycenterGizmoVisibilityEvent;
 #[derive(Event)]
 pub struct TogglePauseSimulationEvent;
 
+#[derive(Event)]
+pub struct ToggleGroupColoringEvent;
+
+#[derive(Event)]
+pub struct SpeedUpSimulationEvent;
+
+#[derive(Event)]
+pub struct SlowDownSimulationEvent;
+
+/// Negates every body's velocity, so the simulation runs back the way it came.
+#[derive(Event)]
+pub struct ReverseTimeEvent;
+
+/// Simulates the given number of fixed ticks while the simulation is paused.
+#[derive(Event)]
+pub struct StepSimulationEvent(pub u32);
+
+/// Saves the full simulation state to the snapshot file next to the user configuration.
+#[derive(Event)]
+pub struct SaveSnapshotEvent;
+
+/// Replaces the simulation with the one saved in the snapshot file.
+#[derive(Event)]
+pub struct LoadSnapshotEvent;
+
+/// Stops the simulation and plays back the recorded trajectory file instead.
+#[derive(Event)]
+pub struct StartReplayEvent;
+
+/// Writes every body's state to CSV and JSON Lines files in the export directory.
+#[derive(Event)]
+pub struct ExportBodiesEvent;
+
+/// Time scales offered by the speed controls, from slowest to fastest.
+pub const TIME_SCALES: [f64; 10] = [0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0];
+
+/// The next time scale up from `current`, or down if `faster` is false, within `TIME_SCALES`.
+pub fn next_time_scale(current: f64, faster: bool) -> f64 {
+    let next = if faster {
+        TIME_SCALES
+            .iter()
+            .find(|&&scale| scale > current * (1.0 + 1e-9))
+    } else {
+        TIME_SCALES
+            .iter()
+            .rev()
+            .find(|&&scale| scale < current * (1.0 - 1e-9))
+    };
+
+    next.copied()
+        .unwrap_or(current)
+        .clamp(TIME_SCALES[0], TIME_SCALES[TIME_SCALES.len() - 1])
+}
+
+/// Longest frame whose real time is all simulated, as in Bevy's default.
+const MAX_FRAME_DELTA: Duration = Duration::from_millis(250);
+
+/// Most fixed steps simulated in one frame. When the machine cannot keep up with the time scale,
+/// the simulation runs slower than asked instead of falling further behind.
+const MAX_FIXED_STEPS_PER_FRAME: f64 = 200.0;
+
+/// Sets virtual time to run `time_scale` times as fast as real time, within `TIME_SCALES`.
+///
+/// Every fixed step still simulates one fixed timestep, so a faster time scale runs more steps
+/// each frame rather than longer ones.
+pub fn set_time_scale(time: &mut Time<Virtual>, fixed_time: &Time<Fixed>, time_scale: f64) {
+    let time_scale = time_scale.clamp(TIME_SCALES[0], TIME_SCALES[TIME_SCALES.len() - 1]);
+    time.set_relative_speed_f64(time_scale);
+    time.set_max_delta(
+        fixed_time
+            .timestep()
+            .mul_f64(MAX_FIXED_STEPS_PER_FRAME / time_scale)
+            .min(MAX_FRAME_DELTA),
+    );
+}
+
+pub fn apply_configured_time_scale(
+    mut time: ResMut<Time<Virtual>>,
+    fixed_time: Res<Time<Fixed>>,
+    config: Res<config::SimulationConfig>,
+) {
+    set_time_scale(&mut time, &fixed_time, config.physics.time_scale);
+}
+
+/// Starts a new run from freshly generated bodies.
+///
+/// Everything measured over the old run is reset, including the periodic check counters, so the
+/// new run finds its groups on its first step. Group colouring is turned off again, as it is at
+/// start-up, so the new bodies show their temperatures.
 #[allow(clippy::too_many_arguments)]
 pub fn handle_restart_simulation_event(
     mut restart_events: EventReader<RestartSimulationEvent>,
     mut commands: Commands,
     simulation_bodies: Query<Entity, With<RigidBody>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut rng: ResMut<resources::SharedRng>,
-    body_count: Res<resources::BodyCount>,
+    mut body_count: ResMut<resources::BodyCount>,
     mut barycenter: ResMut<resources::Barycenter>,
-    mut octree: ResMut<resources::GravitationalOctree>,
-    mut pan_orbit_camera: Single<&mut PanOrbitCamera>,
+    mut simulation: ResMut<resources::GravitySimulation>,
+    mut run_measurements: RunMeasurements,
+    mut group_coloring: ResMut<resources::GroupColoringSettings>,
     config: Res<config::SimulationConfig>,
 ) {
     restart_events.read().for_each(|_| {
@@ -38,18 +169,15 @@ pub fn handle_restart_simulation_event(
         });
 
         **barycenter = None;
+        run_measurements.reset();
+        *group_coloring = resources::GroupColoringSettings::default();
 
-        octree.build(vec![]);
-
-        pan_orbit_camera.target_focus = Vec3::ZERO;
-        pan_orbit_camera.force_update = true;
+        **simulation = physics::simulation::Simulation::new(config.physics.clone());
 
         *rng = resources::SharedRng::default();
 
-        systems::physics::spawn_simulation_bodies(
+        **body_count = systems::physics::spawn_simulation_bodies(
             &mut commands,
-            &mut meshes,
-            &mut materials,
             &mut rng,
             **body_count,
             &config,
@@ -75,13 +203,25 @@ pub fn handle_toggle_barycenter_gizmo_visibility_event(
     });
 }
 
+pub fn handle_toggle_group_coloring_event(
+    mut group_coloring_events: EventReader<ToggleGroupColoringEvent>,
+    mut settings: ResMut<resources::GroupColoringSettings>,
+) {
+    group_coloring_events.read().for_each(|_| {
+        settings.enabled = !settings.enabled;
+    });
+}
+
 pub fn handle_toggle_pause_simulation_event(
     mut pause_events: EventReader<TogglePauseSimulationEvent>,
     current_state: Res<State<states::AppState>>,
     mut next_state: ResMut<NextState<states::AppState>>,
     mut time: ResMut<Time<Physics>>,
+    mut pending_steps: ResMut<resources::PendingPhysicsSteps>,
 ) {
     pause_events.read().for_each(|_| {
+        **pending_steps = 0;
+
         match current_state.get() {
             states::AppState::Running => {
                 next_state.set(states::AppState::Paused);
@@ -95,3 +235,617 @@ pub fn handle_toggle_pause_simulation_event(
         }
     });
 }
+
+/// Reverses time and drives the [`resources::TimeReversalProbe`].
+///
+/// Collisions with restitution below one lose energy and cannot be retraced, so round trips are
+/// only meaningful while no bodies touch.
+pub fn handle_reverse_time_event(
+    mut reverse_events: EventReader<ReverseTimeEvent>,
+    mut bodies: Query<(Entity, &Position, &mut LinearVelocity), With<RigidBody>>,
+    mut time_reversal_probe: ResMut<resources::TimeReversalProbe>,
+    mut time_reversed: ResMut<resources::TimeReversed>,
+    simulated_time: Res<resources::SimulatedTime>,
+) {
+    reverse_events.read().for_each(|_| {
+        bodies.iter_mut().for_each(|(_, _, mut velocity)| {
+            velocity.0 = -velocity.0;
+        });
+        **time_reversed = !**time_reversed;
+
+        if time_reversal_probe.reference_positions.is_none() {
+            time_reversal_probe.reference_positions = Some(
+                bodies
+                    .iter()
+                    .map(|(entity, position, _)| (entity, position.0))
+                    .collect(),
+            );
+            time_reversal_probe.reference_time = **simulated_time;
+            time_reversal_probe.return_time = None;
+            info!("Time reversed; reverse again to retrace the steps taken since");
+        } else if time_reversal_probe.return_time.is_none() {
+            time_reversal_probe.return_time = Some(**simulated_time);
+            info!(
+                "Time reversed; returning over {:.3} of simulated time",
+                **simulated_time - time_reversal_probe.reference_time
+            );
+        } else {
+            // Reversed again before arriving; start over from here
+            time_reversal_probe.reference_positions = None;
+            info!("Time reversed; round trip abandoned");
+        }
+    });
+}
+
+/// Reports the RMS position deviation for the [`resources::TimeReversalProbe`] once as much
+/// simulated time has passed since the second reversal as before it.
+pub fn track_time_reversal_probe(
+    bodies: Query<(Entity, &Position), With<RigidBody>>,
+    mut time_reversal_probe: ResMut<resources::TimeReversalProbe>,
+    simulated_time: Res<resources::SimulatedTime>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    if time_reversal_probe.reference_positions.is_none() {
+        return;
+    }
+    let Some(return_time) = time_reversal_probe.return_time else {
+        return;
+    };
+
+    // Within half a step, so rounding in the simulated time cannot take an extra step
+    let remaining =
+        (return_time - time_reversal_probe.reference_time) - (**simulated_time - return_time);
+    if remaining > 0.5 * fixed_time.delta_secs_f64() {
+        return;
+    }
+
+    let deviation = time_reversal_probe
+        .rms_deviation(bodies.iter().map(|(entity, position)| (entity, position.0)));
+    if let Some(deviation) = deviation {
+        info!("Time reversal round trip complete; RMS position deviation {deviation:e}");
+    }
+    *time_reversal_probe = resources::TimeReversalProbe {
+        last_rms_deviation: deviation,
+        ..default()
+    };
+}
+
+pub fn handle_time_scale_events(
+    mut speed_up_events: EventReader<SpeedUpSimulationEvent>,
+    mut slow_down_events: EventReader<SlowDownSimulationEvent>,
+    mut time: ResMut<Time<Virtual>>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    speed_up_events.read().for_each(|_| {
+        let time_scale = next_time_scale(time.relative_speed_f64(), true);
+        set_time_scale(&mut time, &fixed_time, time_scale);
+    });
+    slow_down_events.read().for_each(|_| {
+        let time_scale = next_time_scale(time.relative_speed_f64(), false);
+        set_time_scale(&mut time, &fixed_time, time_scale);
+    });
+}
+
+/// Queues fixed ticks to simulate and lets physics time run until they are done.
+pub fn handle_step_simulation_event(
+    mut step_events: EventReader<StepSimulationEvent>,
+    current_state: Res<State<states::AppState>>,
+    mut pending_steps: ResMut<resources::PendingPhysicsSteps>,
+    mut time: ResMut<Time<Physics>>,
+) {
+    step_events.read().for_each(|StepSimulationEvent(count)| {
+        if *current_state.get() == states::AppState::Paused && *count > 0 {
+            **pending_steps += count;
+            time.unpause();
+        }
+    });
+}
+
+/// Counts down the queued ticks after each physics step and pauses physics time again once
+/// they have all run.
+pub fn consume_pending_physics_steps(
+    mut pending_steps: ResMut<resources::PendingPhysicsSteps>,
+    mut time: ResMut<Time<Physics>>,
+) {
+    if **pending_steps == 0 {
+        return;
+    }
+
+    **pending_steps -= 1;
+    if **pending_steps == 0 {
+        time.pause();
+    }
+}
+
+pub fn has_pending_physics_steps(pending_steps: Res<resources::PendingPhysicsSteps>) -> bool {
+    **pending_steps > 0
+}
+
+fn snapshot_path(config: &config::SimulationConfig) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    match &config.recording.snapshot_path {
+        Some(path) => Ok(path.clone()),
+        None => Ok(config::SimulationConfig::get_user_file_path(
+            snapshot::SNAPSHOT_FILE_NAME,
+        )?),
+    }
+}
+
+/// Saves every body and the state of the run to the snapshot file.
+///
+/// Bodies are saved in the order the integrator sums their forces in, followed by any it has
+/// not stepped yet.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn handle_save_snapshot_event(
+    mut save_events: EventReader<SaveSnapshotEvent>,
+    bodies: Query<
+        (
+            &Position,
+            &Rotation,
+            &LinearVelocity,
+            &AngularVelocity,
+            &components::Acceleration,
+            &ComputedMass,
+            &ColliderDensity,
+            &Collider,
+            &components::Temperature,
+            Option<&components::StellarProperties>,
+            Option<&components::Fragment>,
+            Has<Sleeping>,
+            Option<&TimeSleeping>,
+        ),
+        With<RigidBody>,
+    >,
+    entities: Query<Entity, With<RigidBody>>,
+    simulation: Res<resources::GravitySimulation>,
+    contact_graph: Res<ContactGraph>,
+    run_measurements: RunMeasurements,
+    barycenter: Res<resources::Barycenter>,
+    rng: Res<resources::SharedRng>,
+    config: Res<config::SimulationConfig>,
+    time: Res<Time<Virtual>>,
+) {
+    if save_events.read().count() == 0 {
+        return;
+    }
+
+    let mut order: Vec<Entity> = simulation
+        .bodies()
+        .iter()
+        .map(|body| Entity::from(body.id))
+        .filter(|&entity| entities.contains(entity))
+        .collect();
+    let integrated_body_count = order.len();
+    let integrated: HashSet<Entity> = order.iter().copied().collect();
+    order.extend(
+        entities
+            .iter()
+            .filter(|entity| !integrated.contains(entity)),
+    );
+    let index_of: HashMap<Entity, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(index, &entity)| (entity, index))
+        .collect();
+
+    let snapshot = snapshot::Snapshot {
+        format_version: snapshot::SNAPSHOT_FORMAT_VERSION,
+        config: config.clone(),
+        simulated_time: **run_measurements.simulated_time,
+        time_reversed: **run_measurements.time_reversed,
+        time_scale: time.relative_speed_f64(),
+        barycenter: barycenter.map(|barycenter| barycenter.to_array()),
+        rng: snapshot::RngState::capture(&rng),
+        periodic_check_counters: *run_measurements.periodic_check_counters,
+        conservation_monitor: run_measurements.conservation_monitor.clone(),
+        escaper_statistics: *run_measurements.escaper_statistics,
+        collision_statistics: *run_measurements.collision_statistics,
+        stepper: simulation.stepper(),
+        bodies: bodies
+            .iter_many(&order)
+            .map(
+                |(
+                    position,
+                    rotation,
+                    velocity,
+                    angular_velocity,
+                    acceleration,
+                    mass,
+                    density,
+                    collider,
+                    temperature,
+                    stellar_properties,
+                    fragment,
+                    sleeping,
+                    time_sleeping,
+                )| snapshot::BodySnapshot {
+                    position: position.0.to_array(),
+                    rotation: rotation.0.to_array(),
+                    velocity: velocity.0.to_array(),
+                    angular_velocity: angular_velocity.0.to_array(),
+                    acceleration: acceleration.0.to_array(),
+                    mass: mass.value(),
+                    density: density.0,
+                    radius: collider.shape().as_ball().map_or(0.0, |ball| ball.radius),
+                    temperature: **temperature,
+                    stellar_properties: stellar_properties.map(|star| star.0),
+                    fragment_grace_steps: fragment.map(|fragment| fragment.0),
+                    sleeping,
+                    time_sleeping: time_sleeping.map_or(0.0, |time| time.0),
+                },
+            )
+            .collect(),
+        integrated_body_count,
+        octree_bodies: simulation
+            .octree()
+            .bodies()
+            .iter()
+            .map(|body| snapshot::OctreeBodySnapshot {
+                body: index_of.get(&Entity::from(body.id)).copied(),
+                position: body.position.to_array(),
+                velocity: body.velocity.to_array(),
+                mass: body.mass,
+            })
+            .collect(),
+        contacts: contact_graph
+            .iter()
+            .filter_map(|pair| {
+                snapshot::ContactPairSnapshot::capture(pair, |entity| {
+                    index_of.get(&entity).copied()
+                })
+            })
+            .collect(),
+    };
+
+    match snapshot_path(&config).and_then(|path| snapshot.save(&path).map(|()| path)) {
+        Ok(path) => info!(
+            "Saved snapshot of {} bodies to {}",
+            snapshot.bodies.len(),
+            path.display()
+        ),
+        Err(e) => warn!("Failed to save snapshot: {e}"),
+    }
+}
+
+/// Spawns a body with exactly the state it had when the snapshot was saved.
+fn spawn_snapshot_body(
+    commands: &mut Commands,
+    body: &snapshot::BodySnapshot,
+    config: &config::SimulationConfig,
+) -> Entity {
+    let position = Vector::from_array(body.position);
+
+    let mut bundle =
+        components::BodyBundle::new(position.as_vec3(), body.radius, body.temperature, config);
+    bundle.acceleration = components::Acceleration(Vector::from_array(body.acceleration));
+
+    let mut entity = commands.spawn((
+        bundle,
+        Position(position),
+        Rotation(Quaternion::from_array(body.rotation)),
+        LinearVelocity(Vector::from_array(body.velocity)),
+        AngularVelocity(Vector::from_array(body.angular_velocity)),
+        ColliderDensity(body.density),
+        ComputedMass::new(body.mass),
+        components::RestoredMass(body.mass),
+        TimeSleeping(body.time_sleeping),
+    ));
+    if let Some(star) = body.stellar_properties {
+        entity.insert(components::StellarProperties(star));
+    }
+    if let Some(steps) = body.fragment_grace_steps {
+        entity.insert(components::Fragment(steps));
+    }
+    if body.sleeping {
+        entity.insert(Sleeping);
+    }
+    entity.id()
+}
+
+/// Replaces every body and the state of the run with those saved in the snapshot file.
+///
+/// The snapshot's configuration replaces the one in use. The integrator takes the bodies back in
+/// the order it summed their forces in, with their saved accelerations, and the octree and Avian's
+/// contacts are restored for the new entities, so the run continues bit for bit.
+#[allow(clippy::too_many_arguments)]
+pub fn handle_load_snapshot_event(
+    mut load_events: EventReader<LoadSnapshotEvent>,
+    mut commands: Commands,
+    simulation_bodies: Query<Entity, With<RigidBody>>,
+    mut rng: ResMut<resources::SharedRng>,
+    mut barycenter: ResMut<resources::Barycenter>,
+    mut simulation: ResMut<resources::GravitySimulation>,
+    mut run_measurements: RunMeasurements,
+    mut g: ResMut<resources::GravitationalConstant>,
+    mut body_count: ResMut<resources::BodyCount>,
+    mut config: ResMut<config::SimulationConfig>,
+    mut time: ResMut<Time<Virtual>>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    if load_events.read().count() == 0 {
+        return;
+    }
+
+    let (snapshot, path) = match snapshot_path(&config)
+        .and_then(|path| snapshot::Snapshot::load(&path).map(|snapshot| (snapshot, path)))
+    {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            warn!("Failed to load snapshot: {e}");
+            return;
+        }
+    };
+
+    simulation_bodies.iter().for_each(|entity| {
+        commands.entity(entity).despawn();
+    });
+
+    run_measurements.reset();
+    **run_measurements.simulated_time = snapshot.simulated_time;
+    **run_measurements.time_reversed = snapshot.time_reversed;
+    *run_measurements.conservation_monitor = snapshot.conservation_monitor.clone();
+    *run_measurements.escaper_statistics = snapshot.escaper_statistics;
+    *run_measurements.collision_statistics = snapshot.collision_statistics;
+    **barycenter = snapshot.barycenter.map(Vector::from_array);
+    *rng = resources::SharedRng(snapshot.rng.restore());
+    *run_measurements.periodic_check_counters = snapshot.periodic_check_counters;
+    **g = snapshot.config.physics.gravitational_constant;
+    **body_count = snapshot.config.physics.body_count;
+    set_time_scale(&mut time, &fixed_time, snapshot.time_scale);
+
+    let entities: Vec<Entity> = snapshot
+        .bodies
+        .iter()
+        .map(|body| spawn_snapshot_body(&mut commands, body, &snapshot.config))
+        .collect();
+
+    **simulation = physics::simulation::Simulation::new(snapshot.config.physics.clone());
+    simulation.time = snapshot.simulated_time;
+    simulation.restore(
+        snapshot
+            .bodies
+            .iter()
+            .zip(&entities)
+            .take(snapshot.integrated_body_count)
+            .map(|(body, &entity)| physics::integrator::IntegratedBody {
+                id: entity.into(),
+                position: Vector::from_array(body.position),
+                velocity: Vector::from_array(body.velocity),
+                acceleration: Vector::from_array(body.acceleration),
+                jerk: Vector::ZERO,
+                mass: body.mass,
+            })
+            .collect(),
+        snapshot.stepper,
+    );
+    simulation
+        .octree_mut()
+        .build(snapshot.octree_bodies.iter().map(|body| {
+            physics::octree::OctreeBody {
+                id: body
+                    .body
+                    .map_or(Entity::PLACEHOLDER, |index| entities[index])
+                    .into(),
+                position: Vector::from_array(body.position),
+                velocity: Vector::from_array(body.velocity),
+                mass: body.mass,
+            }
+        }));
+
+    // Contacts are added once the old bodies have been despawned, which removes theirs
+    let contacts: Vec<ContactPair> = snapshot
+        .contacts
+        .iter()
+        .map(|contact| contact.restore(&entities))
+        .collect();
+    commands.queue(move |world: &mut World| {
+        let mut contact_graph = world.resource_mut::<ContactGraph>();
+        contacts
+            .into_iter()
+            .for_each(|contact| contact_graph.add_pair(contact));
+    });
+
+    info!(
+        "Loaded snapshot of {} bodies at simulated time {} from {}",
+        snapshot.bodies.len(),
+        snapshot.simulated_time,
+        path.display()
+    );
+    *config = snapshot.config;
+}
+
+fn export_directory(
+    config: &config::SimulationConfig,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    match &config.recording.export_directory {
+        Some(directory) => Ok(directory.clone()),
+        None => Ok(config::SimulationConfig::get_user_file_path(
+            export::EXPORT_DIRECTORY_NAME,
+        )?),
+    }
+}
+
+/// Exports the table of bodies, named after the simulated time it was taken at.
+///
+/// Potential energies come from the octree of the last fixed step, which holds the bodies where
+/// that step left them. Group columns are filled in once groups have been found.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn handle_export_bodies_event(
+    mut export_events: EventReader<ExportBodiesEvent>,
+    bodies: Query<
+        (
+            Entity,
+            &Position,
+            &LinearVelocity,
+            &ComputedMass,
+            &Collider,
+            &components::Acceleration,
+            &components::Temperature,
+            Option<&components::GroupId>,
+        ),
+        With<RigidBody>,
+    >,
+    simulation: Res<resources::GravitySimulation>,
+    catalog: Res<resources::GroupCatalog>,
+    simulated_time: Res<resources::SimulatedTime>,
+    g: Res<resources::GravitationalConstant>,
+    config: Res<config::SimulationConfig>,
+) {
+    if export_events.read().count() == 0 {
+        return;
+    }
+
+    let octree = simulation.octree();
+
+    let records: Vec<_> = bodies
+        .iter()
+        .map(
+            |(entity, position, velocity, mass, collider, acceleration, temperature, group)| {
+                let body = physics::octree::OctreeBody {
+                    id: entity.into(),
+                    position: position.0,
+                    velocity: velocity.0,
+                    mass: mass.value(),
+                };
+                let force = **acceleration * body.mass;
+                let group = group.map(|&components::GroupId(group)| group);
+                export::BodyRecord {
+                    entity: entity.to_bits(),
+                    mass: body.mass,
+                    radius: collider.shape().as_ball().map_or(0.0, |ball| ball.radius),
+                    position_x: body.position.x,
+                    position_y: body.position.y,
+                    position_z: body.position.z,
+                    velocity_x: body.velocity.x,
+                    velocity_y: body.velocity.y,
+                    velocity_z: body.velocity.z,
+                    force_x: force.x,
+                    force_y: force.y,
+                    force_z: force.z,
+                    temperature: **temperature,
+                    kinetic_energy: 0.5 * body.mass * body.velocity.length_squared(),
+                    potential_energy: octree.calculate_potential(&body, octree.root.as_ref(), **g),
+                    group,
+                    group_member_count: group
+                        .and_then(|group| catalog.get(group))
+                        .map(|group| group.member_count),
+                }
+            },
+        )
+        .collect();
+
+    let name = format!("bodies-t{:.3}", **simulated_time);
+    match export_directory(&config)
+        .and_then(|directory| export::export(&records, &directory, &name))
+    {
+        Ok([csv_path, json_lines_path]) => info!(
+            "Exported {} bodies to {} and {}",
+            records.len(),
+            csv_path.display(),
+            json_lines_path.display()
+        ),
+        Err(e) => warn!("Failed to export bodies: {e}"),
+    }
+}
+
+/// Quits once the simulation has run the number of fixed steps given to `--steps`, exporting the
+/// bodies first when `--export` is given too.
+///
+/// No more fixed steps are run in the frame the limit is reached, however fast time runs, so the
+/// bodies are exported and left as that last step left them.
+pub fn exit_after_step_limit(
+    step_limit: Res<resources::StepLimit>,
+    mut steps: Local<u64>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut export_events: EventWriter<ExportBodiesEvent>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(limit) = step_limit.steps else {
+        return;
+    };
+
+    *steps += 1;
+    if *steps == limit.get() {
+        let overstep = fixed_time.overstep();
+        fixed_time.discard_overstep(overstep);
+        if step_limit.export {
+            export_events.write(ExportBodiesEvent);
+        }
+        exit.write_default();
+    }
+}
+
+/// Replaces the simulation with a replay of `replay_path`, or else of the latest recording.
+///
+/// The recording is finished first so every frame written so far is played back, and the next
+/// recording goes to a new file rather than over the one being replayed. The whole file
+/// is read into memory, and nothing is changed if it cannot be read or holds no frames.
+#[allow(clippy::too_many_arguments)]
+pub fn handle_start_replay_event(
+    mut start_events: EventReader<StartReplayEvent>,
+    mut commands: Commands,
+    simulation_bodies: Query<Entity, With<RigidBody>>,
+    mut run_measurements: RunMeasurements,
+    mut next_state: ResMut<NextState<states::AppState>>,
+    mut time: ResMut<Time<Physics>>,
+    config: Res<config::SimulationConfig>,
+) {
+    if start_events.read().count() == 0 {
+        return;
+    }
+
+    if let Err(e) = run_measurements.trajectory_recording.finish() {
+        warn!("Failed to finish trajectory recording: {e}");
+    }
+
+    let trajectory =
+        systems::recording::replay_path(&config, &run_measurements.trajectory_recording).and_then(
+            |path| {
+                let reader = trajectory::TrajectoryReader::open(&path)?;
+                let header = reader.header().clone();
+                let frames = reader.collect::<std::io::Result<Vec<_>>>()?;
+                Ok((header, frames, path))
+            },
+        );
+    let (header, frames, path) = match trajectory {
+        Ok(trajectory) => trajectory,
+        Err(e) => {
+            warn!("Failed to load trajectory for replay: {e}");
+            return;
+        }
+    };
+    if frames.is_empty() {
+        warn!("Trajectory {} has no frames to replay", path.display());
+        return;
+    }
+
+    simulation_bodies.iter().for_each(|entity| {
+        commands.entity(entity).despawn();
+    });
+    run_measurements.reset();
+    time.pause();
+
+    info!("Replaying {} frames from {}", frames.len(), path.display());
+    commands.insert_resource(resources::Replay::new(header, frames));
+    next_state.set(states::AppState::Replay);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_time_scale_walks_the_ladder() {
+        assert_eq!(next_time_scale(1.0, true), 2.0);
+        assert_eq!(next_time_scale(1.0, false), 0.5);
+        assert_eq!(next_time_scale(3.0, true), 5.0);
+        assert_eq!(next_time_scale(3.0, false), 2.0);
+    }
+
+    #[test]
+    fn test_next_time_scale_stays_within_bounds() {
+        assert_eq!(next_time_scale(100.0, true), 100.0);
+        assert_eq!(next_time_scale(0.1, false), 0.1);
+        assert_eq!(next_time_scale(500.0, false), 100.0);
+        assert_eq!(next_time_scale(0.01, true), 0.1);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::components;
+use crate::config;
+use crate::physics;
+use crate::resources;
+use crate::utils;
+use avian3d::math::Scalar;
+use avian3d::prelude::*;
+use bevy::prelude::*;
+
+/// A star moving from one phase of its evolution to the next.
+#[derive(Event, Copy, Clone, Debug)]
+pub struct StellarEvolutionEvent {
+    pub body: Entity,
+    pub from: physics::stars::StellarPhase,
+    pub to: physics::stars::StellarPhase,
+    pub stellar_class: physics::stars::StellarClass,
+    /// Mass shed in the transition, in simulation units.
+    pub mass_lost: Scalar,
+}
+
+/// Relative change in a star's radius or temperature below which its collider and temperature,
+/// and so its mesh and material, are left as they are.
+const STRUCTURE_UPDATE_TOLERANCE: Scalar = 0.01;
+
+/// Ages every star by the simulated time since the last update, every
+/// `stellar_evolution_interval` fixed steps.
+///
+/// Stars do not age while time runs backwards, and once it runs forwards again they stay as they
+/// are until the time spent running backwards has been retraced.
+///
+/// Mass lost to winds and supernovae leaves the system, so it is taken out of the conservation
+/// monitor's baseline along with the energy and momentum it carries. A supernova remnant also
+/// receives a kick of `supernova_kick_speed` in a direction drawn from
+/// [`resources::SharedRng`], which is added to the baseline as well.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn evolve_stars(
+    mut stars: Query<
+        (
+            Entity,
+            &mut components::StellarProperties,
+            &Position,
+            &mut LinearVelocity,
+            &mut ComputedMass,
+            &mut Collider,
+            &mut ColliderDensity,
+            &mut components::Temperature,
+        ),
+        With<RigidBody>,
+    >,
+    simulation: Res<resources::GravitySimulation>,
+    mut monitor: ResMut<resources::ConservationMonitor>,
+    mut rng: ResMut<resources::SharedRng>,
+    mut evolution_events: EventWriter<StellarEvolutionEvent>,
+    mut commands: Commands,
+    mut counters: ResMut<resources::PeriodicCheckCounters>,
+    g: Res<resources::GravitationalConstant>,
+    config: Res<config::SimulationConfig>,
+    time_reversed: Res<resources::TimeReversed>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let octree = simulation.octree();
+    let (steps, elapsed) = &mut counters.stellar_evolution;
+    let dt = fixed_time.delta_secs_f64();
+    *steps += 1;
+    *elapsed += if **time_reversed { -dt } else { dt };
+    if *steps < config.physics.stellar_evolution_interval {
+        return;
+    }
+    *steps = 0;
+    if *elapsed <= 0.0 {
+        return;
+    }
+    let dt_gyr = *elapsed * config.physics.stellar_time_scale;
+    *elapsed = 0.0;
+
+    let parameters = physics::stars::StellarParameters::default();
+    let mut removed = physics::conservation::ConservedQuantities::default();
+    let mut baseline_changed = false;
+
+    for (
+        entity,
+        mut star,
+        position,
+        mut velocity,
+        mut mass,
+        mut collider,
+        mut density,
+        mut temperature,
+    ) in &mut stars
+    {
+        let previous_phase = star.evolve(dt_gyr, config.physics.stellar_wind_efficiency);
+        let before = physics::octree::OctreeBody {
+            id: entity.into(),
+            position: position.0,
+            velocity: velocity.0,
+            mass: mass.value(),
+        };
+
+        if let Some(from) = previous_phase {
+            if matches!(
+                star.phase,
+                physics::stars::StellarPhase::NeutronStar | physics::stars::StellarPhase::BlackHole
+            ) {
+                velocity.0 +=
+                    utils::math::random_unit_vector(&mut rng) * config.physics.supernova_kick_speed;
+            }
+            evolution_events.write(StellarEvolutionEvent {
+                body: entity,
+                from,
+                to: star.phase,
+                stellar_class: star.stellar_class(),
+                mass_lost: before.mass - star.mass * parameters.solar_mass,
+            });
+        }
+
+        let after = physics::octree::OctreeBody {
+            velocity: velocity.0,
+            mass: star.mass * parameters.solar_mass,
+            ..before
+        };
+        let mass_changed = after.mass != before.mass;
+        if mass_changed || after.velocity != before.velocity {
+            let potential_energy = octree.calculate_potential(&before, octree.root.as_ref(), **g);
+            let lost =
+                physics::conservation::ConservedQuantities::of_body(&before, potential_energy)
+                    .without(&physics::conservation::ConservedQuantities::of_body(
+                        &after,
+                        potential_energy * after.mass / before.mass,
+                    ));
+            removed = removed.combine(lost);
+            baseline_changed = true;
+            *mass = ComputedMass::new(after.mass);
+        }
+
+        let radius = star.radius * parameters.solar_radius;
+        let collider_radius = collider.shape().as_ball().map_or(0.0, |ball| ball.radius);
+        let resized =
+            (radius - collider_radius).abs() > STRUCTURE_UPDATE_TOLERANCE * collider_radius;
+        if resized {
+            *collider = Collider::sphere(radius);
+        }
+        if mass_changed || resized {
+            let collider_radius = if resized { radius } else { collider_radius };
+            let volume = 4.0 / 3.0 * core::f64::consts::PI * collider_radius.powi(3);
+            *density = ColliderDensity((after.mass / volume) as f32);
+            commands
+                .entity(entity)
+                .insert(components::RestoredMass(after.mass));
+        }
+
+        if (star.temperature - **temperature).abs() > STRUCTURE_UPDATE_TOLERANCE * **temperature
+            || previous_phase.is_some()
+        {
+            **temperature = star.temperature;
+        }
+    }
+
+    if baseline_changed && let Some(initial) = monitor.initial {
+        monitor.initial = Some(initial.without(&removed));
+    }
+}
+
+pub fn log_stellar_evolution(mut evolution_events: EventReader<StellarEvolutionEvent>) {
+    for event in evolution_events.read() {
+        info!(
+            "Star {} evolved from {:?} to {:?} (class {:?}), shedding mass {:.3e}",
+            event.body, event.from, event.to, event.stellar_class, event.mass_lost
+        );
+    }
+}
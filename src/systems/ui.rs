@@ -4,6 +4,7 @@ use crate::systems;
 use bevy::asset::AssetPath;
 use bevy::asset::io::AssetSourceId;
 use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
 
 const BUTTON_BORDER_RADIUS_PX: f32 = 5.0;
 const BUTTON_FONT_SIZE_PX: f32 = 12.0;
@@ -11,6 +12,11 @@ const BUTTON_GAP_PX: f32 = 10.0;
 const BUTTON_MARGIN_PX: f32 = 10.0;
 const BUTTON_PADDING_PX: f32 = 5.0;
 
+const TIMELINE_BOTTOM_PX: f32 = 50.0;
+const TIMELINE_HEIGHT_PX: f32 = 12.0;
+const TIMELINE_TRACK_COLOR: Color = Color::srgba(0.1, 0.1, 0.1, 0.8);
+const TIMELINE_FILL_COLOR: Color = Color::srgba(0.6, 0.6, 0.6, 0.9);
+
 const BUTTON_COLOR_NORMAL: Color = Color::srgba(0.1, 0.1, 0.1, 0.8);
 const BUTTON_COLOR_HOVERED: Color = Color::srgba(0.2, 0.2, 0.2, 0.8);
 const BUTTON_COLOR_PRESSED: Color = Color::srgba(0.3, 0.3, 0.3, 0.8);
@@ -27,12 +33,43 @@ pub struct BarycenterGizmoToggleButton;
 #[derive(Component)]
 pub struct PauseButton;
 
+#[derive(Component)]
+pub struct SlowDownButton;
+
+#[derive(Component)]
+pub struct SpeedUpButton;
+
+#[derive(Component)]
+pub struct StepButton;
+
+#[derive(Component)]
+pub struct ReverseTimeButton;
+
+#[derive(Component)]
+pub struct GroupColoringToggleButton;
+
+#[derive(Component)]
+pub struct ExportButton;
+
+#[derive(Component)]
+pub struct ReplayTimeline;
+
+/// The bar of the replay timeline, which moves the playhead while it is pressed.
+#[derive(Component)]
+pub struct ReplayTimelineTrack;
+
+#[derive(Component)]
+pub struct ReplayTimelineFill;
+
+#[derive(Component)]
+pub struct ReplayTimelineText;
+
 pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
     let embedded_asset_source = &AssetSourceId::from("embedded");
     let regular_font_asset_path =
         AssetPath::parse("fonts/BerkeleyMono-Regular").with_source(embedded_asset_source);
     let regular_font = asset_server.load(regular_font_asset_path);
-    let button_text_font = TextFont::from_font(regular_font).with_font_size(BUTTON_FONT_SIZE_PX);
+    let font = TextFont::from_font(regular_font).with_font_size(BUTTON_FONT_SIZE_PX);
 
     // Root UI node
     commands
@@ -56,108 +93,53 @@ pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ..default()
                 })
                 .with_children(|parent| {
-                    // Restart simulation button
-                    parent
-                        .spawn((
-                            Button,
-                            Node {
-                                padding: UiRect::all(Val::Px(BUTTON_PADDING_PX)),
-                                display: Display::Flex,
-                                flex_direction: FlexDirection::Column,
-                                align_items: AlignItems::Center,
-                                justify_content: JustifyContent::Center,
-                                row_gap: Val::Px(1.0),
-                                ..default()
-                            },
-                            BorderRadius::all(Val::Px(BUTTON_BORDER_RADIUS_PX)),
-                            BackgroundColor(BUTTON_COLOR_NORMAL),
-                            RestartSimulationButton,
-                        ))
-                        .with_children(|parent| {
-                            parent.spawn((
-                                Text::new("New Simulation (N)"),
-                                button_text_font.clone(),
-                                TextColor(Color::WHITE),
-                            ));
-                        });
-
-                    // Octree toggle button
-                    parent
-                        .spawn((
-                            Button,
-                            Node {
-                                padding: UiRect::all(Val::Px(BUTTON_PADDING_PX)),
-                                display: Display::Flex,
-                                flex_direction: FlexDirection::Column,
-                                align_items: AlignItems::Center,
-                                justify_content: JustifyContent::Center,
-                                row_gap: Val::Px(1.0),
-                                ..default()
-                            },
-                            BorderRadius::all(Val::Px(BUTTON_BORDER_RADIUS_PX)),
-                            BackgroundColor(BUTTON_COLOR_NORMAL),
-                            OctreeToggleButton,
-                        ))
-                        .with_children(|parent| {
-                            parent.spawn((
-                                Text::new("Show Octree (O)"),
-                                button_text_font.clone(),
-                                TextColor(Color::WHITE),
-                            ));
-                        });
-
-                    // Barycenter gizmo toggle button
-                    parent
-                        .spawn((
-                            Button,
-                            Node {
-                                padding: UiRect::all(Val::Px(BUTTON_PADDING_PX)),
-                                display: Display::Flex,
-                                flex_direction: FlexDirection::Column,
-                                align_items: AlignItems::Center,
-                                justify_content: JustifyContent::Center,
-                                row_gap: Val::Px(1.0),
-                                ..default()
-                            },
-                            BorderRadius::all(Val::Px(BUTTON_BORDER_RADIUS_PX)),
-                            BackgroundColor(BUTTON_COLOR_NORMAL),
-                            BarycenterGizmoToggleButton,
-                        ))
-                        .with_children(|parent| {
-                            parent.spawn((
-                                Text::new("Show Barycenter (C)"),
-                                button_text_font.clone(),
-                                TextColor(Color::WHITE),
-                            ));
-                        });
-
-                    parent
-                        .spawn((
-                            Button,
-                            Node {
-                                padding: UiRect::all(Val::Px(BUTTON_PADDING_PX)),
-                                display: Display::Flex,
-                                flex_direction: FlexDirection::Column,
-                                align_items: AlignItems::Center,
-                                justify_content: JustifyContent::Center,
-                                row_gap: Val::Px(1.0),
-                                ..default()
-                            },
-                            BorderRadius::all(Val::Px(BUTTON_BORDER_RADIUS_PX)),
-                            BackgroundColor(BUTTON_COLOR_NORMAL),
-                            PauseButton,
-                        ))
-                        .with_children(|parent| {
-                            parent.spawn((
-                                Text::new("Pause (Space)"),
-                                button_text_font.clone(),
-                                TextColor(Color::WHITE),
-                            ));
-                        });
+                    spawn_button(parent, RestartSimulationButton, "New Simulation (N)", &font);
+                    spawn_button(parent, OctreeToggleButton, "Show Octree (O)", &font);
+                    spawn_button(parent, GroupColoringToggleButton, "Color Groups (G)", &font);
+                    spawn_button(
+                        parent,
+                        BarycenterGizmoToggleButton,
+                        "Show Barycenter (C)",
+                        &font,
+                    );
+                    spawn_button(parent, PauseButton, "Pause (Space)", &font);
+                    spawn_button(parent, StepButton, "Step (.)", &font);
+                    spawn_button(parent, SlowDownButton, "Slower ([)", &font);
+                    spawn_button(parent, SpeedUpButton, "Faster (])", &font);
+                    spawn_button(parent, ReverseTimeButton, "Reverse Time (R)", &font);
+                    spawn_button(parent, ExportButton, "Export (E)", &font);
                 });
         });
 }
 
+/// Spawns a button of the control bar labelled `label`, marked by `marker`.
+fn spawn_button(
+    parent: &mut ChildSpawnerCommands,
+    marker: impl Component,
+    label: &str,
+    font: &TextFont,
+) {
+    parent
+        .spawn((
+            Button,
+            Node {
+                padding: UiRect::all(Val::Px(BUTTON_PADDING_PX)),
+                display: Display::Flex,
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(1.0),
+                ..default()
+            },
+            BorderRadius::all(Val::Px(BUTTON_BORDER_RADIUS_PX)),
+            BackgroundColor(BUTTON_COLOR_NORMAL),
+            marker,
+        ))
+        .with_children(|parent| {
+            parent.spawn((Text::new(label), font.clone(), TextColor(Color::WHITE)));
+        });
+}
+
 pub fn handle_octree_button(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor),
@@ -207,6 +189,29 @@ pub fn handle_barycenter_gizmo_button(
         });
 }
 
+pub fn handle_group_coloring_button(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<GroupColoringToggleButton>),
+    >,
+    mut group_coloring_events: EventWriter<systems::simulation_actions::ToggleGroupColoringEvent>,
+) {
+    interaction_query
+        .iter_mut()
+        .for_each(|(interaction, mut color)| match *interaction {
+            Interaction::Pressed => {
+                *color = BackgroundColor(BUTTON_COLOR_PRESSED);
+                group_coloring_events.write(systems::simulation_actions::ToggleGroupColoringEvent);
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(BUTTON_COLOR_HOVERED);
+            }
+            Interaction::None => {
+                *color = BackgroundColor(BUTTON_COLOR_NORMAL);
+            }
+        });
+}
+
 pub fn handle_restart_button(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor),
@@ -253,6 +258,121 @@ pub fn handle_pause_button(
         });
 }
 
+pub fn handle_step_button(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<StepButton>),
+    >,
+    mut step_events: EventWriter<systems::simulation_actions::StepSimulationEvent>,
+) {
+    interaction_query
+        .iter_mut()
+        .for_each(|(interaction, mut color)| match *interaction {
+            Interaction::Pressed => {
+                *color = BackgroundColor(BUTTON_COLOR_PRESSED);
+                step_events.write(systems::simulation_actions::StepSimulationEvent(1));
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(BUTTON_COLOR_HOVERED);
+            }
+            Interaction::None => {
+                *color = BackgroundColor(BUTTON_COLOR_NORMAL);
+            }
+        });
+}
+
+pub fn handle_slow_down_button(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<SlowDownButton>),
+    >,
+    mut slow_down_events: EventWriter<systems::simulation_actions::SlowDownSimulationEvent>,
+) {
+    interaction_query
+        .iter_mut()
+        .for_each(|(interaction, mut color)| match *interaction {
+            Interaction::Pressed => {
+                *color = BackgroundColor(BUTTON_COLOR_PRESSED);
+                slow_down_events.write(systems::simulation_actions::SlowDownSimulationEvent);
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(BUTTON_COLOR_HOVERED);
+            }
+            Interaction::None => {
+                *color = BackgroundColor(BUTTON_COLOR_NORMAL);
+            }
+        });
+}
+
+pub fn handle_speed_up_button(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<SpeedUpButton>),
+    >,
+    mut speed_up_events: EventWriter<systems::simulation_actions::SpeedUpSimulationEvent>,
+) {
+    interaction_query
+        .iter_mut()
+        .for_each(|(interaction, mut color)| match *interaction {
+            Interaction::Pressed => {
+                *color = BackgroundColor(BUTTON_COLOR_PRESSED);
+                speed_up_events.write(systems::simulation_actions::SpeedUpSimulationEvent);
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(BUTTON_COLOR_HOVERED);
+            }
+            Interaction::None => {
+                *color = BackgroundColor(BUTTON_COLOR_NORMAL);
+            }
+        });
+}
+
+pub fn handle_reverse_time_button(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ReverseTimeButton>),
+    >,
+    mut reverse_events: EventWriter<systems::simulation_actions::ReverseTimeEvent>,
+) {
+    interaction_query
+        .iter_mut()
+        .for_each(|(interaction, mut color)| match *interaction {
+            Interaction::Pressed => {
+                *color = BackgroundColor(BUTTON_COLOR_PRESSED);
+                reverse_events.write(systems::simulation_actions::ReverseTimeEvent);
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(BUTTON_COLOR_HOVERED);
+            }
+            Interaction::None => {
+                *color = BackgroundColor(BUTTON_COLOR_NORMAL);
+            }
+        });
+}
+
+pub fn handle_export_button(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ExportButton>),
+    >,
+    mut export_events: EventWriter<systems::simulation_actions::ExportBodiesEvent>,
+) {
+    interaction_query
+        .iter_mut()
+        .for_each(|(interaction, mut color)| match *interaction {
+            Interaction::Pressed => {
+                *color = BackgroundColor(BUTTON_COLOR_PRESSED);
+                export_events.write(systems::simulation_actions::ExportBodiesEvent);
+            }
+            Interaction::Hovered => {
+                *color = BackgroundColor(BUTTON_COLOR_HOVERED);
+            }
+            Interaction::None => {
+                *color = BackgroundColor(BUTTON_COLOR_NORMAL);
+            }
+        });
+}
+
 pub fn update_octree_button_text(
     button_query: Query<Entity, With<OctreeToggleButton>>,
     children_query: Query<&Children>,
@@ -303,11 +423,37 @@ pub fn update_barycenter_gizmo_button_text(
     }
 }
 
+pub fn update_group_coloring_button_text(
+    button_query: Query<Entity, With<GroupColoringToggleButton>>,
+    children_query: Query<&Children>,
+    mut text_query: Query<&mut Text>,
+    settings: Res<resources::GroupColoringSettings>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for button_entity in &button_query {
+        if let Ok(children) = children_query.get(button_entity) {
+            for child in children {
+                if let Ok(mut text) = text_query.get_mut(*child) {
+                    text.0 = if settings.enabled {
+                        "Color Temperatures (G)".to_string()
+                    } else {
+                        "Color Groups (G)".to_string()
+                    };
+                }
+            }
+        }
+    }
+}
+
 pub fn update_pause_button_text(
     button_query: Query<Entity, With<PauseButton>>,
     children_query: Query<&Children>,
     mut text_query: Query<&mut Text>,
     current_state: Res<State<states::AppState>>,
+    replay: Option<Res<resources::Replay>>,
 ) {
     for button_entity in &button_query {
         if let Ok(children) = children_query.get(button_entity) {
@@ -316,6 +462,10 @@ pub fn update_pause_button_text(
                     let new_text = match current_state.get() {
                         states::AppState::Running => "Pause (Space)".to_string(),
                         states::AppState::Paused => "Resume (Space)".to_string(),
+                        states::AppState::Replay => match &replay {
+                            Some(replay) if replay.playing => "Pause (Space)".to_string(),
+                            _ => "Play (Space)".to_string(),
+                        },
                         _ => String::new(), // ignore Loading state
                     };
 
@@ -328,6 +478,115 @@ pub fn update_pause_button_text(
     }
 }
 
+/// Spawns the replay timeline above the buttons: a bar filled up to the playhead, and the
+/// simulated time, frame, speed and direction of play.
+pub fn setup_replay_timeline(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let embedded_asset_source = &AssetSourceId::from("embedded");
+    let regular_font_asset_path =
+        AssetPath::parse("fonts/BerkeleyMono-Regular").with_source(embedded_asset_source);
+    let regular_font = asset_server.load(regular_font_asset_path);
+    let text_font = TextFont::from_font(regular_font).with_font_size(BUTTON_FONT_SIZE_PX);
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(TIMELINE_BOTTOM_PX),
+                left: Val::Percent(20.0),
+                width: Val::Percent(60.0),
+                display: Display::Flex,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(BUTTON_PADDING_PX),
+                ..default()
+            },
+            ReplayTimeline,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Button,
+                    Node {
+                        width: Val::Percent(100.0),
+                        height: Val::Px(TIMELINE_HEIGHT_PX),
+                        ..default()
+                    },
+                    BorderRadius::all(Val::Px(BUTTON_BORDER_RADIUS_PX)),
+                    BackgroundColor(TIMELINE_TRACK_COLOR),
+                    RelativeCursorPosition::default(),
+                    ReplayTimelineTrack,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Node {
+                            width: Val::Percent(0.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        BorderRadius::all(Val::Px(BUTTON_BORDER_RADIUS_PX)),
+                        BackgroundColor(TIMELINE_FILL_COLOR),
+                        ReplayTimelineFill,
+                    ));
+                });
+
+            parent.spawn((
+                Text::new(""),
+                text_font,
+                TextColor(Color::WHITE),
+                ReplayTimelineText,
+            ));
+        });
+}
+
+pub fn despawn_replay_timeline(
+    mut commands: Commands,
+    timeline_query: Query<Entity, With<ReplayTimeline>>,
+) {
+    timeline_query.iter().for_each(|entity| {
+        commands.entity(entity).despawn();
+    });
+}
+
+/// Scrubs through the replay by moving the playhead to wherever the timeline is pressed.
+pub fn handle_replay_timeline_track(
+    track_query: Query<(&Interaction, &RelativeCursorPosition), With<ReplayTimelineTrack>>,
+    mut replay: ResMut<resources::Replay>,
+) {
+    track_query
+        .iter()
+        .for_each(|(interaction, cursor_position)| {
+            if *interaction == Interaction::Pressed
+                && let Some(cursor_position) = cursor_position.normalized
+            {
+                replay.seek(cursor_position.x as f64);
+            }
+        });
+}
+
+pub fn update_replay_timeline(
+    replay: Res<resources::Replay>,
+    mut fill: Single<&mut Node, With<ReplayTimelineFill>>,
+    mut text: Single<&mut Text, With<ReplayTimelineText>>,
+    simulated_time: Res<resources::SimulatedTime>,
+) {
+    if !replay.is_changed() {
+        return;
+    }
+
+    fill.width = Val::Percent(replay.progress() as f32 * 100.0);
+    ***text = format!(
+        "t = {:.2}   frame {:.0}/{}   {}× {}",
+        **simulated_time,
+        replay.playhead.floor() + 1.0,
+        replay.frames.len(),
+        replay.speed,
+        if replay.reversed {
+            "reverse"
+        } else {
+            "forward"
+        },
+    );
+}
+
 #[cfg(test)]
 mod ui_tests {
     use super::*;
@@ -1,13 +1,18 @@
+use crate::components;
+use crate::config;
 use crate::physics;
 use crate::resources;
+use crate::utils;
+use avian3d::prelude::*;
 use bevy::color::palettes::css;
 use bevy::prelude::*;
 
 pub fn visualize_octree(
     mut gizmos: Gizmos,
-    octree: Res<resources::GravitationalOctree>,
+    simulation: Res<resources::GravitySimulation>,
     settings: Res<resources::OctreeVisualizationSettings>,
 ) {
+    let octree = simulation.octree();
     if !settings.enabled {
         return;
     }
@@ -19,6 +24,170 @@ pub fn visualize_octree(
     }
 }
 
+/// Gives each body a mesh for its radius and a material for its temperature, and remakes them
+/// when either changes.
+///
+/// The physics only sets a body's collider and temperature, so this is what shows bodies that
+/// were spawned, merged, shattered or evolved. Stars get the material of
+/// [`components::body::factory::create_stellar_material`]. While a body is shown in its group's
+/// colour, a new temperature material is kept in its [`components::TemperatureMaterial`] until
+/// group colouring is turned off.
+#[allow(clippy::type_complexity)]
+pub fn update_body_appearance(
+    mut commands: Commands,
+    mut bodies: Query<
+        (
+            Entity,
+            &Collider,
+            &components::Temperature,
+            Option<&components::StellarProperties>,
+            Option<&mut components::BodyAppearance>,
+            Option<&mut Mesh3d>,
+            Option<&mut MeshMaterial3d<StandardMaterial>>,
+            Option<&mut components::TemperatureMaterial>,
+        ),
+        With<RigidBody>,
+    >,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    config: Res<config::SimulationConfig>,
+) {
+    for (entity, collider, temperature, star, appearance, mesh, material, temperature_material) in
+        &mut bodies
+    {
+        let wanted = components::BodyAppearance {
+            radius: collider.shape().as_ball().map_or(0.0, |ball| ball.radius),
+            temperature: **temperature,
+        };
+        let shown = appearance.as_deref().copied();
+        if shown == Some(wanted) {
+            continue;
+        }
+
+        let mut entity_commands = commands.entity(entity);
+        if shown.is_none_or(|shown| shown.radius != wanted.radius) {
+            let handle =
+                components::body::factory::create_detailed_mesh(&mut meshes, wanted.radius);
+            match mesh {
+                Some(mut mesh) => mesh.0 = handle,
+                None => {
+                    entity_commands.insert(Mesh3d(handle));
+                }
+            }
+        }
+        if shown.is_none_or(|shown| shown.temperature != wanted.temperature) {
+            let handle = match star {
+                Some(star) => {
+                    components::body::factory::create_stellar_material(&mut materials, star)
+                }
+                None => utils::color::emissive_material_for_temp(
+                    &mut materials,
+                    wanted.temperature,
+                    config.rendering.bloom_intensity,
+                    config.rendering.saturation_intensity,
+                ),
+            };
+            match (material, temperature_material) {
+                (Some(_), Some(mut temperature_material)) => temperature_material.0 = handle,
+                (Some(mut material), None) => material.0 = handle,
+                (None, _) => {
+                    entity_commands.insert(MeshMaterial3d(handle));
+                }
+            }
+        }
+        match appearance {
+            Some(mut appearance) => *appearance = wanted,
+            None => {
+                entity_commands.insert(wanted);
+            }
+        }
+    }
+}
+
+/// Shows each body in its group's colour while group colouring is enabled.
+///
+/// Bodies in groups below `group_min_members` are dimmed to grey. A body's own temperature
+/// material is kept in a [`components::TemperatureMaterial`] and restored once group colouring
+/// is turned off.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn color_bodies_by_group(
+    mut commands: Commands,
+    mut bodies: Query<
+        (
+            Entity,
+            Option<&components::GroupId>,
+            &mut MeshMaterial3d<StandardMaterial>,
+            Option<&components::TemperatureMaterial>,
+        ),
+        With<RigidBody>,
+    >,
+    changed_groups: Query<(), Changed<components::GroupId>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut group_materials: Local<Vec<Handle<StandardMaterial>>>,
+    mut ungrouped_material: Local<Option<Handle<StandardMaterial>>>,
+    catalog: Res<resources::GroupCatalog>,
+    settings: Res<resources::GroupColoringSettings>,
+    config: Res<config::SimulationConfig>,
+) {
+    if !settings.is_changed() && (!settings.enabled || changed_groups.is_empty()) {
+        return;
+    }
+
+    if !settings.enabled {
+        bodies
+            .iter_mut()
+            .for_each(|(entity, _, mut material, temperature_material)| {
+                if let Some(temperature_material) = temperature_material {
+                    material.0 = temperature_material.0.clone();
+                    commands
+                        .entity(entity)
+                        .remove::<components::TemperatureMaterial>();
+                }
+            });
+        return;
+    }
+
+    let ungrouped_material = ungrouped_material
+        .get_or_insert_with(|| {
+            materials.add(StandardMaterial {
+                base_color: Color::srgb(0.3, 0.3, 0.3),
+                ..default()
+            })
+        })
+        .clone();
+
+    bodies
+        .iter_mut()
+        .for_each(|(entity, group_id, mut material, temperature_material)| {
+            let Some(&components::GroupId(group)) = group_id else {
+                return;
+            };
+
+            if temperature_material.is_none() {
+                commands
+                    .entity(entity)
+                    .insert(components::TemperatureMaterial(material.0.clone()));
+            }
+
+            let grouped = catalog
+                .get(group)
+                .is_some_and(|group| group.member_count >= config.physics.group_min_members);
+            material.0 = if grouped {
+                while group_materials.len() <= group {
+                    let material = utils::color::emissive_material_for_group(
+                        &mut materials,
+                        group_materials.len(),
+                        config.rendering.bloom_intensity,
+                    );
+                    group_materials.push(material);
+                }
+                group_materials[group].clone()
+            } else {
+                ungrouped_material.clone()
+            };
+        });
+}
+
 pub fn draw_bounding_box_wireframe_gizmo(
     gizmos: &mut Gizmos,
     aabb: &physics::octree::Aabb3d,
@@ -0,0 +1,707 @@
+//! Compact binary recordings of body trajectories, for offline analysis and replay.
+//!
+//! A trajectory file starts with a magic number, a format version, flags and a JSON header with
+//! the configuration, seed and IDs of the bodies present when recording started. Frames follow
+//! as length-prefixed records, each compressed on its own with zstd when the compression flag is
+//! set. A frame stores the f64 positions and velocities of its bodies as the XOR of their bits
+//! with those in the previous frame, which is lossless and leaves mostly zero bytes for the
+//! compressor. Whenever bodies come or go, and every [`KEYFRAME_INTERVAL`] frames regardless, a
+//! key frame lists the body IDs, masses and radii and is stored against zero instead. Masses and
+//! radii are only stored in key frames, so in between a body keeps those of the last key frame.
+
+use crate::config::SimulationConfig;
+use crate::physics::BodyId;
+use avian3d::math::Scalar;
+use avian3d::math::Vector;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+const MAGIC: [u8; 8] = *b"STARDTRJ";
+
+/// Version of the trajectory format; files of any other version are refused.
+pub const TRAJECTORY_FORMAT_VERSION: u32 = 1;
+
+/// Name of the trajectory file written next to the user configuration by default.
+pub const TRAJECTORY_FILE_NAME: &str = "trajectory.strj";
+
+/// Frames between key frames, which a reader can start decoding from.
+pub const KEYFRAME_INTERVAL: u64 = 64;
+
+const FLAG_ZSTD: u32 = 1;
+const FRAME_KIND_DELTA: u8 = 0;
+const FRAME_KIND_KEY: u8 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TrajectoryHeader {
+    pub config: SimulationConfig,
+    pub seed: Option<u64>,
+    /// Fixed steps between recorded frames.
+    pub interval: u32,
+    /// IDs of the bodies present when recording started.
+    pub body_ids: Vec<BodyId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BodyState {
+    pub id: BodyId,
+    pub position: Vector,
+    pub velocity: Vector,
+    pub mass: Scalar,
+    pub radius: Scalar,
+}
+
+impl BodyState {
+    /// The state between this one and `next`, a time `dt` later, at `fraction` of the way.
+    ///
+    /// Positions follow the cubic Hermite curve through both positions and velocities, which is
+    /// exact for uniformly accelerated motion. Velocities are interpolated linearly.
+    pub fn interpolate(&self, next: &Self, dt: Scalar, fraction: Scalar) -> Self {
+        let s = fraction;
+        let s2 = s * s;
+        let s3 = s2 * s;
+        let position = self.position * (2.0 * s3 - 3.0 * s2 + 1.0)
+            + self.velocity * dt * (s3 - 2.0 * s2 + s)
+            + next.position * (3.0 * s2 - 2.0 * s3)
+            + next.velocity * dt * (s3 - s2);
+
+        Self {
+            position,
+            velocity: self.velocity.lerp(next.velocity, s),
+            ..*self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrajectoryFrame {
+    /// Fixed steps since recording started.
+    pub step: u64,
+    pub simulated_time: Scalar,
+    pub bodies: Vec<BodyState>,
+}
+
+impl TrajectoryFrame {
+    /// The frame between this one and `next` at `fraction` of the way.
+    ///
+    /// Bodies missing from `next` stay where they are in this frame, and bodies that only appear
+    /// in `next` are left out until it is reached.
+    pub fn interpolate(&self, next: &Self, fraction: Scalar) -> Self {
+        let dt = next.simulated_time - self.simulated_time;
+        let next_bodies: HashMap<BodyId, &BodyState> =
+            next.bodies.iter().map(|body| (body.id, body)).collect();
+
+        Self {
+            step: self.step,
+            simulated_time: self.simulated_time + dt * fraction,
+            bodies: self
+                .bodies
+                .iter()
+                .map(|body| match next_bodies.get(&body.id) {
+                    Some(next_body) => body.interpolate(next_body, dt, fraction),
+                    None => *body,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn body_values(body: &BodyState) -> [u64; 6] {
+    [
+        body.position.x.to_bits(),
+        body.position.y.to_bits(),
+        body.position.z.to_bits(),
+        body.velocity.x.to_bits(),
+        body.velocity.y.to_bits(),
+        body.velocity.z.to_bits(),
+    ]
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Fills `buffer` unless the reader ends first, and returns how many bytes were read.
+fn read_until_end(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Fails if a `what` of `length` bytes would run past `end`.
+fn check_length(reader: &mut impl Seek, end: u64, length: u32, what: &str) -> io::Result<()> {
+    let remaining = end.saturating_sub(reader.stream_position()?);
+    if u64::from(length) > remaining {
+        return Err(invalid_data(format!(
+            "Trajectory {what} of {length} bytes is longer than the {remaining} bytes left"
+        )));
+    }
+    Ok(())
+}
+
+/// Writes a trajectory frame by frame.
+pub struct TrajectoryWriter<W: Write> {
+    writer: W,
+    compressed: bool,
+    frames_written: u64,
+    previous_ids: Vec<BodyId>,
+    previous_values: Vec<[u64; 6]>,
+    payload: Vec<u8>,
+}
+
+impl TrajectoryWriter<BufWriter<File>> {
+    /// Creates a new trajectory file at `path`, or next to it as `trajectory-1.strj`,
+    /// `trajectory-2.strj` and so on if it exists, and returns the path written to.
+    ///
+    /// Existing files are never overwritten, so a trajectory being replayed stays intact.
+    pub fn create(
+        path: &Path,
+        header: &TrajectoryHeader,
+        compressed: bool,
+    ) -> io::Result<(Self, PathBuf)> {
+        let (file, path) = create_new_numbered(path)?;
+        Ok((Self::new(BufWriter::new(file), header, compressed)?, path))
+    }
+}
+
+/// Creates `path`, or the first numbered path next to it that does not exist yet.
+fn create_new_numbered(path: &Path) -> io::Result<(File, PathBuf)> {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy());
+
+    for number in 0_u32.. {
+        let candidate = match (number, &extension) {
+            (0, _) => path.to_path_buf(),
+            (_, Some(extension)) => path.with_file_name(format!("{stem}-{number}.{extension}")),
+            (_, None) => path.with_file_name(format!("{stem}-{number}")),
+        };
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => return Ok((file, candidate)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("Every numbered file next to {} exists", path.display()),
+    ))
+}
+
+impl<W: Write> TrajectoryWriter<W> {
+    pub fn new(mut writer: W, header: &TrajectoryHeader, compressed: bool) -> io::Result<Self> {
+        let header_json = serde_json::to_vec(header).map_err(io::Error::other)?;
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&TRAJECTORY_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(if compressed { FLAG_ZSTD } else { 0 }).to_le_bytes())?;
+        writer.write_all(&(header_json.len() as u32).to_le_bytes())?;
+        writer.write_all(&header_json)?;
+
+        Ok(Self {
+            writer,
+            compressed,
+            frames_written: 0,
+            previous_ids: header.body_ids.clone(),
+            previous_values: Vec::new(),
+            payload: Vec::new(),
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &TrajectoryFrame) -> io::Result<()> {
+        let key_frame = self.frames_written.is_multiple_of(KEYFRAME_INTERVAL)
+            || self.previous_values.len() != frame.bodies.len()
+            || self
+                .previous_ids
+                .iter()
+                .zip(&frame.bodies)
+                .any(|(&id, body)| id != body.id);
+
+        self.payload.clear();
+        self.payload.push(if key_frame {
+            FRAME_KIND_KEY
+        } else {
+            FRAME_KIND_DELTA
+        });
+        self.payload.extend_from_slice(&frame.step.to_le_bytes());
+        self.payload
+            .extend_from_slice(&frame.simulated_time.to_bits().to_le_bytes());
+
+        if key_frame {
+            self.payload
+                .extend_from_slice(&(frame.bodies.len() as u32).to_le_bytes());
+            frame.bodies.iter().for_each(|body| {
+                self.payload.extend_from_slice(&body.id.0.to_le_bytes());
+                self.payload
+                    .extend_from_slice(&body.mass.to_bits().to_le_bytes());
+                self.payload
+                    .extend_from_slice(&body.radius.to_bits().to_le_bytes());
+            });
+            self.previous_ids.clear();
+            self.previous_ids
+                .extend(frame.bodies.iter().map(|body| body.id));
+            self.previous_values.clear();
+            self.previous_values.resize(frame.bodies.len(), [0; 6]);
+        }
+
+        for (body, previous) in frame.bodies.iter().zip(&mut self.previous_values) {
+            let values = body_values(body);
+            for (value, previous) in values.iter().zip(previous.iter()) {
+                self.payload
+                    .extend_from_slice(&(value ^ previous).to_le_bytes());
+            }
+            *previous = values;
+        }
+
+        if self.compressed {
+            let record = ruzstd::encoding::compress_to_vec(
+                self.payload.as_slice(),
+                ruzstd::encoding::CompressionLevel::Fastest,
+            );
+            self.write_record(&record)?;
+        } else {
+            let payload = std::mem::take(&mut self.payload);
+            self.write_record(&payload)?;
+            self.payload = payload;
+        }
+
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        self.writer
+            .write_all(&(record.len() as u32).to_le_bytes())?;
+        self.writer.write_all(record)
+    }
+
+    pub fn frames_written(&self) -> u64 {
+        self.frames_written
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads a trajectory back frame by frame, as an iterator of frames.
+///
+/// Lengths read from the file are checked against what is left of it before anything is
+/// allocated for them, so a damaged file is reported as such rather than exhausting memory.
+pub struct TrajectoryReader<R: Read + Seek> {
+    reader: R,
+    /// Position of the end of the file.
+    end: u64,
+    header: TrajectoryHeader,
+    compressed: bool,
+    ids: Vec<BodyId>,
+    masses_and_radii: Vec<(Scalar, Scalar)>,
+    values: Vec<[u64; 6]>,
+}
+
+impl TrajectoryReader<BufReader<File>> {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> TrajectoryReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let start = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("Not a trajectory file"));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != TRAJECTORY_FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "Trajectory format version {version} is not supported (expected {TRAJECTORY_FORMAT_VERSION})"
+            )));
+        }
+        let flags = read_u32(&mut reader)?;
+
+        let header_length = read_u32(&mut reader)?;
+        check_length(&mut reader, end, header_length, "header")?;
+        let mut header_json = vec![0; header_length as usize];
+        reader.read_exact(&mut header_json)?;
+        let header: TrajectoryHeader =
+            serde_json::from_slice(&header_json).map_err(io::Error::other)?;
+
+        Ok(Self {
+            reader,
+            end,
+            ids: header.body_ids.clone(),
+            header,
+            compressed: flags & FLAG_ZSTD != 0,
+            masses_and_radii: Vec::new(),
+            values: Vec::new(),
+        })
+    }
+
+    pub fn header(&self) -> &TrajectoryHeader {
+        &self.header
+    }
+
+    /// The next frame, or `None` at the end of the file.
+    ///
+    /// A file that ends partway through a frame, including its length, is an error.
+    pub fn read_frame(&mut self) -> io::Result<Option<TrajectoryFrame>> {
+        let mut length = [0; 4];
+        match read_until_end(&mut self.reader, &mut length)? {
+            0 => return Ok(None),
+            4 => {}
+            _ => {
+                return Err(invalid_data(
+                    "Trajectory ends partway through a frame length",
+                ));
+            }
+        }
+        let length = u32::from_le_bytes(length);
+        check_length(&mut self.reader, self.end, length, "frame")?;
+        let mut record = vec![0; length as usize];
+        self.reader.read_exact(&mut record)?;
+
+        let payload = if self.compressed {
+            let mut decoder = ruzstd::decoding::StreamingDecoder::new(record.as_slice())
+                .map_err(io::Error::other)?;
+            let mut payload = Vec::new();
+            decoder.read_to_end(&mut payload)?;
+            payload
+        } else {
+            record
+        };
+        let mut payload = payload.as_slice();
+
+        let kind = read_u8(&mut payload)?;
+        let step = read_u64(&mut payload)?;
+        let simulated_time = Scalar::from_bits(read_u64(&mut payload)?);
+        match kind {
+            FRAME_KIND_KEY => {
+                let count = read_u32(&mut payload)? as usize;
+                self.ids.clear();
+                self.masses_and_radii.clear();
+                for _ in 0..count {
+                    self.ids.push(BodyId(read_u64(&mut payload)?));
+                    let mass = Scalar::from_bits(read_u64(&mut payload)?);
+                    let radius = Scalar::from_bits(read_u64(&mut payload)?);
+                    self.masses_and_radii.push((mass, radius));
+                }
+                self.values.clear();
+                self.values.resize(count, [0; 6]);
+            }
+            FRAME_KIND_DELTA if self.values.len() == self.ids.len() => {}
+            FRAME_KIND_DELTA => return Err(invalid_data("Delta frame without a key frame")),
+            _ => return Err(invalid_data(format!("Unknown frame kind {kind}"))),
+        }
+
+        let mut bodies = Vec::with_capacity(self.ids.len());
+        for ((&id, &(mass, radius)), values) in self
+            .ids
+            .iter()
+            .zip(&self.masses_and_radii)
+            .zip(&mut self.values)
+        {
+            for value in values.iter_mut() {
+                *value ^= read_u64(&mut payload)?;
+            }
+            let [px, py, pz, vx, vy, vz] = values.map(Scalar::from_bits);
+            bodies.push(BodyState {
+                id,
+                position: Vector::new(px, py, pz),
+                velocity: Vector::new(vx, vy, vz),
+                mass,
+                radius,
+            });
+        }
+
+        Ok(Some(TrajectoryFrame {
+            step,
+            simulated_time,
+            bodies,
+        }))
+    }
+}
+
+impl<R: Read + Seek> Iterator for TrajectoryReader<R> {
+    type Item = io::Result<TrajectoryFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(body_ids: Vec<BodyId>) -> TrajectoryHeader {
+        TrajectoryHeader {
+            config: SimulationConfig::default(),
+            seed: Some(u64::MAX),
+            interval: 10,
+            body_ids,
+        }
+    }
+
+    fn frame(step: u64, ids: &[u64]) -> TrajectoryFrame {
+        let t = step as Scalar * 0.1;
+        TrajectoryFrame {
+            step,
+            simulated_time: t,
+            bodies: ids
+                .iter()
+                .map(|&id| {
+                    let phase = t + id as Scalar;
+                    BodyState {
+                        id: BodyId(id),
+                        position: Vector::new(phase.cos(), phase.sin(), 1.0 / 3.0) * 100.0,
+                        velocity: Vector::new(-phase.sin(), phase.cos(), -5e-324),
+                        mass: id as Scalar + 0.5,
+                        radius: 1.0 / (id as Scalar + 3.0),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    fn round_trip(frames: &[TrajectoryFrame], compressed: bool) -> Vec<TrajectoryFrame> {
+        let mut writer = TrajectoryWriter::new(
+            Vec::new(),
+            &header(vec![BodyId(1), BodyId(2), BodyId(3)]),
+            compressed,
+        )
+        .unwrap();
+        frames
+            .iter()
+            .for_each(|frame| writer.write_frame(frame).unwrap());
+        writer.flush().unwrap();
+
+        let reader = TrajectoryReader::new(io::Cursor::new(writer.writer)).unwrap();
+        assert_eq!(
+            reader.header().body_ids,
+            vec![BodyId(1), BodyId(2), BodyId(3)]
+        );
+        assert_eq!(reader.header().seed, Some(u64::MAX));
+        reader.collect::<io::Result<Vec<_>>>().unwrap()
+    }
+
+    #[test]
+    fn test_frames_round_trip_exactly() {
+        let frames: Vec<_> = (0..150).map(|step| frame(step * 10, &[1, 2, 3])).collect();
+
+        assert_eq!(round_trip(&frames, false), frames);
+        assert_eq!(round_trip(&frames, true), frames);
+    }
+
+    #[test]
+    fn test_frames_round_trip_as_bodies_come_and_go() {
+        let frames = vec![
+            frame(0, &[1, 2, 3]),
+            frame(10, &[1, 2, 3]),
+            frame(20, &[1, 3]),
+            frame(30, &[1, 3, 7, 8]),
+            frame(40, &[1, 3, 7, 8]),
+            frame(50, &[]),
+        ];
+
+        assert_eq!(round_trip(&frames, false), frames);
+        assert_eq!(round_trip(&frames, true), frames);
+    }
+
+    #[test]
+    fn test_compression_shrinks_slowly_changing_frames() {
+        let ids: Vec<u64> = (0..200).collect();
+        let frames: Vec<_> = (0..20).map(|step| frame(step, &ids)).collect();
+        let size = |compressed| {
+            let mut writer = TrajectoryWriter::new(
+                Vec::new(),
+                &header(ids.iter().copied().map(BodyId).collect()),
+                compressed,
+            )
+            .unwrap();
+            frames
+                .iter()
+                .for_each(|frame| writer.write_frame(frame).unwrap());
+            writer.writer.len()
+        };
+
+        assert!(size(true) < size(false));
+    }
+
+    #[test]
+    fn test_reader_refuses_other_files() {
+        let error = TrajectoryReader::new(io::Cursor::new(b"not a trajectory file"))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    fn recorded(frame_count: u64) -> Vec<u8> {
+        let mut writer =
+            TrajectoryWriter::new(Vec::new(), &header(vec![BodyId(1)]), false).unwrap();
+        (0..frame_count).for_each(|step| writer.write_frame(&frame(step, &[1])).unwrap());
+        writer.writer
+    }
+
+    #[test]
+    fn test_reader_refuses_truncated_frame_length() {
+        let mut bytes = recorded(2);
+        bytes.extend_from_slice(&[7, 0]);
+
+        let frames: Vec<_> = TrajectoryReader::new(io::Cursor::new(bytes))
+            .unwrap()
+            .collect();
+        assert_eq!(frames.len(), 3);
+        assert!(frames[..2].iter().all(Result::is_ok));
+        assert_eq!(
+            frames[2].as_ref().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_reader_refuses_frame_longer_than_file() {
+        let mut bytes = recorded(1);
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+
+        let mut reader = TrajectoryReader::new(io::Cursor::new(bytes)).unwrap();
+        assert!(reader.read_frame().unwrap().is_some());
+        let error = reader.read_frame().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("longer than"));
+    }
+
+    #[test]
+    fn test_new_recordings_never_overwrite_existing_files() {
+        let directory = std::env::temp_dir().join("stardrift_test_trajectory_files");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(TRAJECTORY_FILE_NAME);
+
+        let paths: Vec<PathBuf> = [vec![BodyId(1)], vec![BodyId(2)]]
+            .into_iter()
+            .map(|body_ids| {
+                let (mut writer, path) =
+                    TrajectoryWriter::create(&path, &header(body_ids), false).unwrap();
+                writer.flush().unwrap();
+                path
+            })
+            .collect();
+        let first_ids =
+            TrajectoryReader::open(&paths[0]).map(|reader| reader.header().body_ids.clone());
+        let _ = std::fs::remove_dir_all(&directory);
+
+        assert_eq!(paths[0], path);
+        assert_eq!(paths[1], directory.join("trajectory-1.strj"));
+        assert_eq!(first_ids.unwrap(), vec![BodyId(1)]);
+    }
+
+    #[test]
+    fn test_masses_and_radii_change_at_key_frames() {
+        let mut frames: Vec<_> = (0..=KEYFRAME_INTERVAL)
+            .map(|step| frame(step, &[1, 2, 3]))
+            .collect();
+        frames.iter_mut().skip(1).for_each(|frame| {
+            frame.bodies[0].mass = 42.0;
+        });
+
+        let read = round_trip(&frames, true);
+        assert_eq!(read[1].bodies[0].mass, frames[0].bodies[0].mass);
+        assert_eq!(read[KEYFRAME_INTERVAL as usize].bodies[0].mass, 42.0);
+        assert_eq!(read[1].bodies[0].position, frames[1].bodies[0].position);
+    }
+
+    #[test]
+    fn test_interpolation_passes_through_frames() {
+        let previous = frame(0, &[1, 2]);
+        let next = frame(10, &[1, 2]);
+
+        assert_eq!(previous.interpolate(&next, 0.0), previous);
+        let end = previous.interpolate(&next, 1.0);
+        end.bodies.iter().zip(&next.bodies).for_each(|(a, b)| {
+            assert!(a.position.distance(b.position) < 1e-12);
+            assert!(a.velocity.distance(b.velocity) < 1e-12);
+        });
+    }
+
+    #[test]
+    fn test_interpolation_is_exact_for_uniform_acceleration() {
+        let acceleration = Vector::new(0.3, -2.0, 1.5);
+        let state = |t: Scalar| BodyState {
+            id: BodyId(1),
+            position: Vector::new(1.0, 2.0, 3.0) + Vector::X * t + acceleration * t * t / 2.0,
+            velocity: Vector::X + acceleration * t,
+            mass: 1.0,
+            radius: 1.0,
+        };
+
+        let interpolated = state(2.0).interpolate(&state(6.0), 4.0, 0.25);
+        assert!(interpolated.position.distance(state(3.0).position) < 1e-12);
+        assert!(interpolated.velocity.distance(state(3.0).velocity) < 1e-12);
+    }
+
+    #[test]
+    fn test_interpolation_keeps_bodies_missing_from_next_frame() {
+        let previous = frame(0, &[1, 2]);
+        let next = frame(10, &[2, 5]);
+
+        let between = previous.interpolate(&next, 0.5);
+        assert_eq!(
+            between
+                .bodies
+                .iter()
+                .map(|body| body.id)
+                .collect::<Vec<_>>(),
+            vec![BodyId(1), BodyId(2)]
+        );
+        assert_eq!(between.bodies[0], previous.bodies[0]);
+        assert_eq!(between.simulated_time, 0.5);
+    }
+}
@@ -138,6 +138,33 @@ pub(crate) fn emissive_material_for_temp(
     })
 }
 
+/// Creates an emissive material in a distinct hue for the group with the given index.
+///
+/// Hues step around the colour wheel by the golden angle, so consecutive groups, which are
+/// ordered by mass, never look alike.
+pub(crate) fn emissive_material_for_group(
+    materials: &mut Assets<StandardMaterial>,
+    group_index: usize,
+    bloom_intensity: f64,
+) -> Handle<StandardMaterial> {
+    const GOLDEN_ANGLE_DEGREES: f32 = 137.507_77;
+
+    let hue = (group_index as f32 * GOLDEN_ANGLE_DEGREES) % 360.0;
+    let base = LinearRgba::from(Color::hsl(hue, 0.9, 0.55));
+    let (r, g, b) = intensify_for_bloom(
+        (base.red as f64, base.green as f64, base.blue as f64),
+        bloom_intensity,
+    );
+
+    materials.add(StandardMaterial {
+        base_color: base.into(),
+        metallic: 0.0,
+        reflectance: 0.0,
+        emissive: LinearRgba::rgb(r as f32, g as f32, b as f32),
+        ..default()
+    })
+}
+
 /// Enhances the saturation of RGB values by scaling the distance from grayscale.
 ///
 /// This function increases color saturation by pushing RGB values further away
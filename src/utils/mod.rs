@@ -1,2 +1,3 @@
+#[cfg(feature = "graphics")]
 pub mod color;
 pub mod math;
@@ -0,0 +1,376 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use stardrift::components::Fragment;
+use stardrift::components::GroupId;
+use stardrift::components::StellarProperties;
+use stardrift::config::CollisionMode;
+use stardrift::config::Scenario;
+use stardrift::config::SimulationConfig;
+use stardrift::resources::BodyCount;
+use stardrift::resources::CollisionStatistics;
+use stardrift::resources::GravitySimulation;
+use stardrift::resources::GroupColoringSettings;
+use stardrift::resources::PeriodicCheckCounters;
+use stardrift::resources::SimulatedTime;
+use stardrift::resources::StepLimit;
+use stardrift::resources::TimeReversalProbe;
+use stardrift::states::AppState;
+use stardrift::systems::simulation_actions::LoadSnapshotEvent;
+use stardrift::systems::simulation_actions::RestartSimulationEvent;
+use stardrift::systems::simulation_actions::ReverseTimeEvent;
+use stardrift::systems::simulation_actions::SaveSnapshotEvent;
+use std::collections::HashSet;
+use std::num::NonZeroU64;
+
+fn headless_app(body_count: usize) -> App {
+    let mut config = SimulationConfig::default();
+    config.physics.body_count = body_count;
+    config.physics.initial_seed = Some(42);
+    stardrift::headless_app(config)
+}
+
+fn run_until_loaded(app: &mut App) {
+    for _ in 0..1000 {
+        app.update();
+        if *app.world().resource::<State<AppState>>().get() == AppState::Running {
+            break;
+        }
+    }
+    assert_eq!(
+        *app.world().resource::<State<AppState>>().get(),
+        AppState::Running
+    );
+}
+
+/// Positions and velocities of the integrated bodies, in the order their forces are summed in.
+fn integrated_state(app: &App) -> Vec<(Vec<u64>, Vec<u64>)> {
+    app.world()
+        .resource::<GravitySimulation>()
+        .bodies()
+        .iter()
+        .map(|body| {
+            (
+                body.position.to_array().map(f64::to_bits).to_vec(),
+                body.velocity.to_array().map(f64::to_bits).to_vec(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_simulation_loads_and_runs_headless() {
+    let mut app = headless_app(20);
+    run_until_loaded(&mut app);
+
+    let positions = |app: &mut App| {
+        app.world_mut()
+            .query_filtered::<&Position, With<RigidBody>>()
+            .iter(app.world())
+            .map(|position| position.0)
+            .collect::<Vec<_>>()
+    };
+    let before = positions(&mut app);
+    assert_eq!(before.len(), 20);
+
+    for _ in 0..10 {
+        app.update();
+    }
+    let after = positions(&mut app);
+    assert_eq!(after.len(), 20);
+    assert!(after.iter().all(|position| position.is_finite()));
+    assert_ne!(before, after);
+}
+
+#[test]
+fn test_sweep_run_is_summarized() {
+    let mut config = SimulationConfig::default();
+    config.physics.body_count = 20;
+    config.physics.initial_seed = Some(42);
+
+    let summary = stardrift::sweep::run_headless(config, 0.25).unwrap();
+
+    assert!(summary.energy_drift.is_finite());
+    assert!(summary.virial_ratio.is_finite() && summary.virial_ratio >= 0.0);
+    assert!(summary.wall_time > 0.0);
+}
+
+#[test]
+fn test_loaded_snapshot_continues_bit_for_bit() {
+    let path = std::env::temp_dir().join(format!(
+        "stardrift_test_snapshot_{}.json",
+        std::process::id()
+    ));
+    let mut config = SimulationConfig::default();
+    config.physics.body_count = 50;
+    config.physics.initial_seed = Some(7);
+    config.recording.snapshot_path = Some(path.clone());
+
+    let mut original = stardrift::headless_app(config.clone());
+    run_until_loaded(&mut original);
+    for _ in 0..20 {
+        original.update();
+    }
+    original.world_mut().send_event(SaveSnapshotEvent);
+    original.update();
+    for _ in 0..30 {
+        original.update();
+    }
+
+    let mut loaded = stardrift::headless_app(config);
+    run_until_loaded(&mut loaded);
+    loaded.world_mut().send_event(LoadSnapshotEvent);
+    loaded.update();
+    for _ in 0..30 {
+        loaded.update();
+    }
+    let _ = std::fs::remove_file(&path);
+
+    let expected = integrated_state(&original);
+    assert_eq!(expected.len(), 50);
+    assert_eq!(integrated_state(&loaded), expected);
+}
+
+/// A loaded app with two bodies of radius 5 heading at each other along the x axis.
+fn head_on_collision_app(
+    name: &str,
+    masses: [f64; 2],
+    speed: f64,
+    config: SimulationConfig,
+) -> App {
+    let path =
+        std::env::temp_dir().join(format!("stardrift_test_{name}_{}.csv", std::process::id()));
+    std::fs::write(
+        &path,
+        format!(
+            "mass,radius,position_x,position_y,position_z,velocity_x,velocity_y,velocity_z\n\
+             {},5,-12,0,0,{speed},0,0\n\
+             {},5,12,0,0,{},0,0\n",
+            masses[0], masses[1], -speed
+        ),
+    )
+    .unwrap();
+    let mut config = config;
+    config.physics.initial_conditions_path = Some(path.clone());
+    config.physics.initial_seed = Some(3);
+    config.physics.collision_mode = CollisionMode::Merge;
+
+    let mut app = stardrift::headless_app(config);
+    run_until_loaded(&mut app);
+    let _ = std::fs::remove_file(&path);
+    app
+}
+
+#[test]
+fn test_fragments_survive_the_step_after_shattering() {
+    let mut config = SimulationConfig::default();
+    config.physics.fragmentation_threshold = Some(1.0);
+    let mut app = head_on_collision_app("impact", [100.0, 100.0], 60.0, config);
+    let fragments = |app: &mut App| {
+        app.world_mut()
+            .query_filtered::<Entity, With<Fragment>>()
+            .iter(app.world())
+            .collect::<HashSet<_>>()
+    };
+
+    for _ in 0..100 {
+        app.update();
+        if !fragments(&mut app).is_empty() {
+            break;
+        }
+    }
+    let shattered = fragments(&mut app);
+    assert_eq!(shattered.len(), 16);
+
+    app.update();
+    assert_eq!(fragments(&mut app), shattered);
+    assert_eq!(
+        app.world().resource::<CollisionStatistics>().merger_count,
+        0
+    );
+}
+
+#[test]
+fn test_imported_bodies_set_the_body_count() {
+    let app = head_on_collision_app("count", [1.0, 1.0], 0.0, SimulationConfig::default());
+
+    assert_eq!(**app.world().resource::<BodyCount>(), 2);
+}
+
+#[test]
+fn test_merged_body_keeps_its_exact_mass() {
+    let masses = [100.1, 200.3];
+    let mut app = head_on_collision_app("merger", masses, 5.0, SimulationConfig::default());
+
+    for _ in 0..200 {
+        app.update();
+        if app.world().resource::<CollisionStatistics>().merger_count > 0 {
+            break;
+        }
+    }
+    for _ in 0..5 {
+        app.update();
+    }
+
+    let mass = app
+        .world_mut()
+        .query_filtered::<&ComputedMass, With<RigidBody>>()
+        .single(app.world())
+        .unwrap()
+        .value();
+    assert_eq!(mass, masses[0] + masses[1]);
+}
+
+#[test]
+fn test_stars_do_not_age_while_time_runs_backwards() {
+    let mut config = SimulationConfig::default();
+    config.physics.body_count = 10;
+    config.physics.initial_seed = Some(5);
+    config.physics.scenario = Scenario::StellarPopulation;
+    config.physics.stellar_evolution_interval = 1;
+    let mut app = stardrift::headless_app(config);
+    run_until_loaded(&mut app);
+    let ages = |app: &mut App| {
+        app.world_mut()
+            .query::<(Entity, &StellarProperties)>()
+            .iter(app.world())
+            .map(|(entity, star)| (entity, star.age_gyr))
+            .collect::<Vec<_>>()
+    };
+
+    app.world_mut().send_event(ReverseTimeEvent);
+    app.update();
+    let reversed = ages(&mut app);
+    for _ in 0..10 {
+        app.update();
+    }
+    assert_eq!(ages(&mut app), reversed);
+
+    app.world_mut().send_event(ReverseTimeEvent);
+    for _ in 0..10 {
+        app.update();
+    }
+    assert_eq!(ages(&mut app), reversed);
+
+    for _ in 0..5 {
+        app.update();
+    }
+    assert!(
+        ages(&mut app)
+            .iter()
+            .zip(&reversed)
+            .all(|((_, after), (_, before))| after > before)
+    );
+}
+
+#[test]
+fn test_time_scale_runs_more_fixed_steps() {
+    let mut config = SimulationConfig::default();
+    config.physics.body_count = 10;
+    config.physics.initial_seed = Some(42);
+    config.physics.time_scale = 5.0;
+    let mut app = stardrift::headless_app(config);
+    run_until_loaded(&mut app);
+    let timestep = app
+        .world()
+        .resource::<Time<Fixed>>()
+        .timestep()
+        .as_secs_f64();
+    let before = **app.world().resource::<SimulatedTime>();
+
+    app.update();
+
+    assert_eq!(
+        app.world().resource::<Time<Fixed>>().delta_secs_f64(),
+        timestep
+    );
+    let elapsed = **app.world().resource::<SimulatedTime>() - before;
+    assert!((elapsed - 5.0 * timestep).abs() < 1e-9 * timestep);
+}
+
+#[test]
+fn test_step_limit_stops_within_a_frame() {
+    let mut config = SimulationConfig::default();
+    config.physics.body_count = 10;
+    config.physics.initial_seed = Some(42);
+    config.physics.time_scale = 5.0;
+    let mut app = stardrift::headless_app(config);
+    run_until_loaded(&mut app);
+    app.insert_resource(StepLimit {
+        steps: NonZeroU64::new(3),
+        export: false,
+    });
+    let timestep = app
+        .world()
+        .resource::<Time<Fixed>>()
+        .timestep()
+        .as_secs_f64();
+    let before = **app.world().resource::<SimulatedTime>();
+
+    app.update();
+
+    assert!(app.should_exit().is_some());
+    let elapsed = **app.world().resource::<SimulatedTime>() - before;
+    assert!((elapsed - 3.0 * timestep).abs() < 1e-9 * timestep);
+}
+
+#[test]
+fn test_time_reversal_round_trip_returns_to_the_start() {
+    let mut app = head_on_collision_app("reversal", [1.0, 1.0], 1.0, SimulationConfig::default());
+
+    app.world_mut().send_event(ReverseTimeEvent);
+    for _ in 0..10 {
+        app.update();
+    }
+    // Each reversal is handled after the update's fixed step, so the update that handles the
+    // second one still runs forwards
+    app.world_mut().send_event(ReverseTimeEvent);
+    for _ in 0..10 {
+        app.update();
+    }
+    assert!(
+        app.world()
+            .resource::<TimeReversalProbe>()
+            .last_rms_deviation
+            .is_none()
+    );
+    app.update();
+
+    let deviation = app
+        .world()
+        .resource::<TimeReversalProbe>()
+        .last_rms_deviation
+        .unwrap();
+    assert!(deviation < 1e-9, "deviation {deviation:e}");
+}
+
+#[test]
+fn test_restart_finds_groups_for_the_new_bodies_at_once() {
+    let mut app = headless_app(20);
+    run_until_loaded(&mut app);
+    for _ in 0..5 {
+        app.update();
+    }
+    app.world_mut()
+        .resource_mut::<GroupColoringSettings>()
+        .enabled = true;
+
+    app.world_mut().send_event(RestartSimulationEvent);
+    app.update();
+
+    assert_eq!(
+        *app.world().resource::<PeriodicCheckCounters>(),
+        PeriodicCheckCounters::default()
+    );
+    assert!(!app.world().resource::<GroupColoringSettings>().enabled);
+
+    // The new bodies only get their masses in their first physics step
+    app.update();
+    app.update();
+
+    let grouped = app
+        .world_mut()
+        .query_filtered::<(), (With<RigidBody>, With<GroupId>)>()
+        .iter(app.world())
+        .count();
+    assert_eq!(grouped, 20);
+}